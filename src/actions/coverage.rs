@@ -0,0 +1,405 @@
+use crate::{
+    actions::{install::hardware_setups, search::search_many},
+    cli::{new_table, to_json, CommandlinePrint, CoverageActionArguments, GlobalArguments},
+    data::{
+        database::{EntryDecoder, HardwareId},
+        input_file::{HardwareKind, HardwareSetup},
+        remote_index::database_with_remote_index,
+    },
+    detection::AssumedDetector,
+    error::Error,
+};
+use comfy_table::{Attribute, Cell, Color};
+use owo_colors::{
+    OwoColorize,
+    Stream::{Stderr, Stdout},
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    path::PathBuf,
+};
+
+/// The driver options that match one of the assumed hardware IDs, by hardware kind.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HardwareCoverage {
+    pub hardware_id: HardwareId,
+    pub driver_options: BTreeMap<HardwareKind, BTreeSet<String>>,
+}
+
+/// One entry per line of the hardware file, in the order of the file, or one entry per
+/// hardware ID of the database, in order, when no hardware file is given.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct CoverageActionOutput {
+    pub inner: Vec<HardwareCoverage>,
+    /// Print comma-separated values instead of the regular output.
+    #[serde(skip)]
+    pub csv: bool,
+}
+
+impl CoverageActionOutput {
+    /// One `bus,vendor,device,hardware_kind,driver_option` row per driver option, after a header.
+    fn print_csv(&self) {
+        println!("bus,vendor,device,hardware_kind,driver_option");
+        for hardware_coverage in self.inner.iter() {
+            let (bus, vendor, device) = match &hardware_coverage.hardware_id {
+                HardwareId::Pci(pci_id) => ("PCI", pci_id.vendor, pci_id.device),
+                HardwareId::Usb(usb_id) => ("USB", usb_id.vendor, usb_id.device),
+            };
+            for (hardware_kind, driver_options) in hardware_coverage.driver_options.iter() {
+                for driver_option in driver_options.iter() {
+                    println!(
+                        "{},{:04x},{:04x},{},{}",
+                        bus,
+                        vendor,
+                        device,
+                        hardware_kind.name(),
+                        csv_field(driver_option)
+                    );
+                }
+            }
+        }
+    }
+}
+
+/// Quotes a CSV field if it contains a separator, a quote or a line break.
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+impl CommandlinePrint for CoverageActionOutput {
+    fn print(&self) {
+        if self.csv {
+            return self.print_csv();
+        }
+        for hardware_coverage in self.inner.iter() {
+            println!(
+                "{}",
+                hardware_coverage
+                    .hardware_id
+                    .if_supports_color(Stdout, |text| text.bold())
+            );
+            if hardware_coverage.driver_options.is_empty() {
+                println!(
+                    "\t{}",
+                    "No matching driver options".if_supports_color(Stdout, |text| text.red())
+                );
+            }
+            for (hardware_kind, driver_options) in hardware_coverage.driver_options.iter() {
+                println!(
+                    "\t{} {}",
+                    format!("{}:", hardware_kind).if_supports_color(Stdout, |text| text.green()),
+                    driver_options
+                        .iter()
+                        .map(|driver_option| driver_option.as_str())
+                        .collect::<Vec<&str>>()
+                        .join(", ")
+                );
+            }
+        }
+    }
+
+    fn print_json(&self) {
+        println!("{}", to_json(&self).unwrap_or_else(|_| {
+            eprintln!("The output could not be converted to JSON. Please try another output format...");
+            String::from("")
+        }));
+    }
+
+    fn print_plain(&self) {
+        for hardware_coverage in self.inner.iter() {
+            if hardware_coverage.driver_options.is_empty() {
+                println!("{} -", hardware_coverage.hardware_id);
+            }
+            for (hardware_kind, driver_options) in hardware_coverage.driver_options.iter() {
+                for driver_option in driver_options.iter() {
+                    println!(
+                        "{} {} {}",
+                        hardware_coverage.hardware_id,
+                        hardware_kind.name(),
+                        driver_option
+                    );
+                }
+            }
+        }
+    }
+
+    fn print_debug(&self) {
+        self.print();
+    }
+
+    fn print_table(&self) {
+        let mut table = new_table();
+        table.set_header(
+            ["Hardware", "Kind", "Driver options"]
+                .into_iter()
+                .map(|header| Cell::new(header).add_attribute(Attribute::Bold)),
+        );
+        for hardware_coverage in self.inner.iter() {
+            if hardware_coverage.driver_options.is_empty() {
+                table.add_row([
+                    Cell::new(&hardware_coverage.hardware_id),
+                    Cell::new(""),
+                    Cell::new("None").fg(Color::Red),
+                ]);
+            }
+            for (hardware_kind, driver_options) in hardware_coverage.driver_options.iter() {
+                table.add_row([
+                    Cell::new(&hardware_coverage.hardware_id),
+                    Cell::new(hardware_kind),
+                    Cell::new(
+                        driver_options
+                            .iter()
+                            .map(|driver_option| driver_option.as_str())
+                            .collect::<Vec<&str>>()
+                            .join("\n"),
+                    )
+                    .fg(Color::Yellow),
+                ]);
+            }
+        }
+        println!("{table}");
+    }
+
+    fn is_empty_result(&self) -> bool {
+        self.inner
+            .iter()
+            .all(|hardware_coverage| hardware_coverage.driver_options.is_empty())
+    }
+}
+
+/// Matches the database against all the assumed hardware at once, so that setups needing
+/// several devices are covered, then attributes each matching driver option to the
+/// hardware IDs it matched through.
+pub fn coverage_inner(
+    database_filepath: PathBuf,
+    optional_hardware: &Option<HardwareKind>,
+    assumed_detector: &AssumedDetector,
+) -> Result<Vec<HardwareCoverage>, Error> {
+    let driver_options_by_hardware_id = search_many(
+        database_filepath,
+        &assumed_detector.hardware_ids.iter().cloned().collect(),
+        optional_hardware,
+        Vec::new().into_iter(),
+        None,
+    )?;
+    Ok(assumed_detector
+        .hardware_ids
+        .iter()
+        .map(|hardware_id| HardwareCoverage {
+            hardware_id: hardware_id.clone(),
+            driver_options: driver_options_by_hardware_id
+                .get(hardware_id)
+                .into_iter()
+                .flatten()
+                .map(|(hardware_kind, driver_options)| {
+                    (
+                        *hardware_kind,
+                        driver_options
+                            .iter()
+                            .map(|driver_option| driver_option.name.clone())
+                            .collect(),
+                    )
+                })
+                .collect(),
+        })
+        .collect())
+}
+
+/// Every hardware ID listed by the hardware setups, with the driver options it is offered,
+/// which is what the database can match at all. Each hardware ID is attributed the options
+/// of every setup listing it, even setups that also need other devices to be present.
+pub fn database_coverage(
+    hardware_setups: &[HardwareSetup],
+    optional_hardware: &Option<HardwareKind>,
+) -> Vec<HardwareCoverage> {
+    let mut driver_options_by_hardware_id =
+        BTreeMap::<HardwareId, BTreeMap<HardwareKind, BTreeSet<String>>>::new();
+    for hardware_setup in hardware_setups.iter().filter(|hardware_setup| {
+        optional_hardware.is_none_or(|hardware| hardware == hardware_setup.hardware_kind)
+    }) {
+        for hardware_id in hardware_setup.hardware_list.hardware_ids() {
+            driver_options_by_hardware_id
+                .entry(hardware_id)
+                .or_default()
+                .entry(hardware_setup.hardware_kind)
+                .or_default()
+                .extend(
+                    hardware_setup
+                        .driver_options
+                        .iter()
+                        .map(|driver_option| driver_option.name.clone()),
+                );
+        }
+    }
+    driver_options_by_hardware_id
+        .into_iter()
+        .map(|(hardware_id, driver_options)| HardwareCoverage {
+            hardware_id,
+            driver_options,
+        })
+        .collect()
+}
+
+pub fn coverage(
+    coverage_action_arguments: CoverageActionArguments,
+    global_arguments: &GlobalArguments,
+) -> Result<CoverageActionOutput, Error> {
+    let catalog_database = database_with_remote_index(
+        coverage_action_arguments.database_file,
+        global_arguments.offline_flag,
+        global_arguments.verify_checksum,
+    )?;
+    let inner = match coverage_action_arguments.hardware_file {
+        Some(hardware_file) => coverage_inner(
+            catalog_database.path.clone(),
+            &coverage_action_arguments.hardware,
+            &AssumedDetector::from_path(&hardware_file)?,
+        )?,
+        None => {
+            let hardware_setups =
+                hardware_setups(catalog_database.path.clone(), &EntryDecoder::new())?;
+            let usb_vendor_wildcards: BTreeSet<u16> = hardware_setups
+                .iter()
+                .filter(|hardware_setup| {
+                    coverage_action_arguments
+                        .hardware
+                        .is_none_or(|hardware| hardware == hardware_setup.hardware_kind)
+                })
+                .flat_map(|hardware_setup| hardware_setup.hardware_list.usb_vendor_wildcards())
+                .collect();
+            if !usb_vendor_wildcards.is_empty() {
+                eprintln!(
+                    "{} Every device of these USB vendors is matched, which cannot be listed: {}",
+                    "NOTE:".if_supports_color(Stderr, |text| text.yellow()),
+                    usb_vendor_wildcards
+                        .iter()
+                        .map(|vendor| format!("{:04x}", vendor))
+                        .collect::<Vec<String>>()
+                        .join(", ")
+                );
+            }
+            database_coverage(&hardware_setups, &coverage_action_arguments.hardware)
+        }
+    };
+    Ok(CoverageActionOutput {
+        inner,
+        csv: coverage_action_arguments.csv,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::actions::generate_database::generate_database_inner;
+    use crate::test_support::TestDirectory;
+    use std::fs;
+
+    #[test]
+    pub fn coverage_per_assumed_hardware_id() {
+        let directory = TestDirectory::new("coverage");
+        let input_file = directory.join("input_data.yaml");
+        let database_file = directory.join("database.db");
+        let hardware_file = directory.join("hardware.txt");
+        fs::write(
+            &input_file,
+            r#"
+- name: Nvidia dGPU
+  hardware_kind: graphics
+  hardware_list: !pci
+      vendor: 10de
+      devices: [1b80]
+  driver_options:
+    - name: Nvidia
+    - name: Nouveau
+- name: Realtek USB Ethernet
+  hardware_kind: ethernet
+  hardware_list: !usb
+      vendor-id: 0bda
+      device-ids: [8153]
+  driver_options:
+    - name: Realtek r8152
+"#,
+        )
+        .unwrap();
+        generate_database_inner(input_file, database_file.clone(), false).unwrap();
+        fs::write(
+            &hardware_file,
+            "# A desktop\nPCI 10de:1b80\n\nUSB 0bda:8153\nUSB 046d:c52b\n",
+        )
+        .unwrap();
+
+        let assumed_detector = AssumedDetector::from_path(&hardware_file).unwrap();
+        let coverage = coverage_inner(database_file, &None, &assumed_detector).unwrap();
+        assert_eq!(
+            coverage
+                .iter()
+                .map(|hardware_coverage| hardware_coverage.hardware_id.to_string())
+                .collect::<Vec<String>>(),
+            ["PCI 10de:1b80", "USB 0bda:8153", "USB 046d:c52b"]
+        );
+        assert_eq!(
+            coverage[0].driver_options[&HardwareKind::Graphics],
+            BTreeSet::from(["Nvidia".to_string(), "Nouveau".to_string()])
+        );
+        assert_eq!(
+            coverage[1].driver_options.keys().collect::<Vec<_>>(),
+            [&HardwareKind::Ethernet]
+        );
+        assert!(coverage[2].driver_options.is_empty());
+
+        fs::write(&hardware_file, "PCI 10de:1b80\nPCI 10de\n").unwrap();
+        assert!(matches!(
+            AssumedDetector::from_path(&hardware_file),
+            Err(Error::AssumedHardwareParse { line_number: 2, .. })
+        ));
+    }
+
+    #[test]
+    pub fn database_coverage_expands_ranges() {
+        let hardware_setups: Vec<HardwareSetup> = serde_yaml::from_str(
+            r#"
+- name: Nvidia dGPU
+  hardware_kind: graphics
+  hardware_list: !pci
+      vendor: 10de
+      devices: [1b80-1b82]
+  driver_options:
+    - name: Nvidia
+    - name: Nouveau
+- name: Realtek USB
+  hardware_kind: ethernet
+  hardware_list: !usb
+      vendor-id: 0bda
+      device-ids: "*"
+  driver_options:
+    - name: Realtek r8152
+"#,
+        )
+        .unwrap();
+
+        let coverage = database_coverage(&hardware_setups, &None);
+        assert_eq!(
+            coverage
+                .iter()
+                .map(|hardware_coverage| hardware_coverage.hardware_id.to_string())
+                .collect::<Vec<String>>(),
+            ["PCI 10de:1b80", "PCI 10de:1b81", "PCI 10de:1b82"]
+        );
+        assert_eq!(
+            coverage[1].driver_options[&HardwareKind::Graphics],
+            BTreeSet::from(["Nvidia".to_string(), "Nouveau".to_string()])
+        );
+        assert_eq!(
+            hardware_setups[1].hardware_list.usb_vendor_wildcards(),
+            BTreeSet::from([0x0bda])
+        );
+        assert!(database_coverage(&hardware_setups, &Some(HardwareKind::Ethernet)).is_empty());
+        assert_eq!(csv_field("Nvidia, 470xx"), "\"Nvidia, 470xx\"");
+    }
+}
@@ -0,0 +1,164 @@
+use crate::{
+    actions::{list_tags::check_tags_exist, search::search_inner},
+    arch::{package_backend, DependencyTree, PackageBackend},
+    cli::{to_json, CommandlinePrint, DepsActionArguments, GlobalArguments},
+    data::{
+        input_file::{DriverOption, HardwareKind},
+        remote_index::database_with_remote_index,
+    },
+    detection::{hardware_detector, HardwareDetector},
+    error::{DriverOptionNotFoundSnafu, Error, NoDriverOptionSnafu},
+};
+use owo_colors::{OwoColorize, Stream::Stdout};
+use serde::{Deserialize, Serialize};
+use snafu::OptionExt;
+use std::path::PathBuf;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DepsActionOutput {
+    pub driver_option: String,
+    pub dependency_tree: Vec<DependencyTree>,
+}
+
+fn print_dependency_tree(dependency_tree: &DependencyTree, depth: usize) {
+    let marker = if dependency_tree.cycle {
+        " (cycle)"
+    } else if dependency_tree.repeated {
+        " (see above)"
+    } else if dependency_tree.missing {
+        " (not found)"
+    } else {
+        ""
+    };
+    println!(
+        "{}{} {}{}",
+        "  ".repeat(depth + 1),
+        dependency_tree
+            .name
+            .if_supports_color(Stdout, |text| text.yellow()),
+        dependency_tree
+            .version
+            .as_deref()
+            .unwrap_or_default()
+            .if_supports_color(Stdout, |text| text.green()),
+        marker.if_supports_color(Stdout, |text| text.red()),
+    );
+    for dependency in dependency_tree.dependencies.iter() {
+        print_dependency_tree(dependency, depth + 1);
+    }
+}
+
+fn print_dependency_tree_plain(dependency_tree: &DependencyTree, depth: usize) {
+    println!(
+        "{} {} {} {}",
+        depth,
+        dependency_tree.name,
+        dependency_tree.version.as_deref().unwrap_or("-"),
+        if dependency_tree.cycle {
+            "cycle"
+        } else if dependency_tree.repeated {
+            "repeated"
+        } else if dependency_tree.missing {
+            "missing"
+        } else {
+            "ok"
+        },
+    );
+    for dependency in dependency_tree.dependencies.iter() {
+        print_dependency_tree_plain(dependency, depth + 1);
+    }
+}
+
+impl CommandlinePrint for DepsActionOutput {
+    fn print(&self) {
+        println!(
+            "{}",
+            self.driver_option
+                .if_supports_color(Stdout, |text| text.bold())
+        );
+        for dependency_tree in self.dependency_tree.iter() {
+            print_dependency_tree(dependency_tree, 0);
+        }
+    }
+
+    fn print_json(&self) {
+        println!("{}", to_json(&self).unwrap_or_else(|_| {
+            eprintln!("The output could not be converted to JSON. Please try another output format...");
+            String::from("")
+        }));
+    }
+
+    fn print_plain(&self) {
+        for dependency_tree in self.dependency_tree.iter() {
+            print_dependency_tree_plain(dependency_tree, 0);
+        }
+    }
+
+    fn print_debug(&self) {
+        self.print();
+    }
+}
+
+pub fn deps_inner<T: Iterator<Item = String>>(
+    database_filepath: PathBuf,
+    hardware: HardwareKind,
+    optional_driver_name: &Option<String>,
+    tags: T,
+    hardware_detector: &dyn HardwareDetector,
+    package_backend: &dyn PackageBackend,
+) -> Result<DepsActionOutput, Error> {
+    let driver_options = search_inner(
+        database_filepath,
+        &Some(hardware),
+        tags,
+        hardware_detector,
+        None,
+    )?
+    .remove(&hardware)
+    .unwrap_or_default();
+
+    let driver_option: &DriverOption = if let Some(driver_name) = optional_driver_name {
+        driver_options
+            .iter()
+            .find(|driver_option| &driver_option.name == driver_name)
+            .context(DriverOptionNotFoundSnafu { name: driver_name })?
+    } else {
+        driver_options
+            .iter()
+            .next()
+            .context(NoDriverOptionSnafu { hardware })?
+    };
+
+    Ok(DepsActionOutput {
+        driver_option: driver_option.name.clone(),
+        dependency_tree: package_backend.dependency_tree(&driver_option.packages),
+    })
+}
+
+pub fn deps(
+    deps_action_arguments: DepsActionArguments,
+    global_arguments: &GlobalArguments,
+) -> Result<DepsActionOutput, Error> {
+    let catalog_database = database_with_remote_index(
+        deps_action_arguments.database_file,
+        global_arguments.offline_flag,
+        global_arguments.verify_checksum,
+    )?;
+    if global_arguments.strict_tags {
+        check_tags_exist(catalog_database.path.clone(), &deps_action_arguments.tags)?;
+    }
+    deps_inner(
+        catalog_database.path.clone(),
+        deps_action_arguments.hardware,
+        &deps_action_arguments.driver,
+        deps_action_arguments.tags.into_iter(),
+        hardware_detector(
+            global_arguments.offline_flag,
+            global_arguments.assume_hardware.as_deref(),
+            global_arguments.detector,
+            global_arguments.vendor_filter(),
+        )?
+        .as_ref(),
+        package_backend(global_arguments.package_manager)?.as_ref(),
+    )
+}
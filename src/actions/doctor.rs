@@ -0,0 +1,374 @@
+use crate::{
+    arch::{
+        check_alpm_version, package_backend, PackageBackend, PACMAN_CONFIG_PATH,
+        PACMAN_DATABASE_LOCK_PATH,
+    },
+    cli::{new_table, to_json, CommandlinePrint, DoctorActionArguments, GlobalArguments},
+    error::Error,
+    module_signing::MODULES_DIRECTORY,
+    paths::APPLICATION_NAME,
+};
+use comfy_table::{Attribute, Cell, Color};
+use owo_colors::{OwoColorize, Stream::Stdout};
+use serde::{Deserialize, Serialize};
+use std::{
+    fmt, fs,
+    path::{Path, PathBuf},
+    process::ExitCode,
+};
+
+/// The file holding the release of the running kernel, like `uname -r` prints it.
+const KERNEL_RELEASE_PATH: &str = "/proc/sys/kernel/osrelease";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CheckStatus {
+    Pass,
+    Warn,
+    Fail,
+}
+
+impl fmt::Display for CheckStatus {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CheckStatus::Pass => write!(f, "PASS"),
+            CheckStatus::Warn => write!(f, "WARN"),
+            CheckStatus::Fail => write!(f, "FAIL"),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DoctorCheck {
+    pub name: String,
+    pub status: CheckStatus,
+    pub detail: String,
+}
+
+impl DoctorCheck {
+    fn new<S: Into<String>>(name: &str, status: CheckStatus, detail: S) -> Self {
+        DoctorCheck {
+            name: name.to_string(),
+            status,
+            detail: detail.into(),
+        }
+    }
+}
+
+#[derive(Default, Debug, Clone, Serialize, Deserialize)]
+pub struct DoctorActionOutput {
+    pub checks: Vec<DoctorCheck>,
+}
+
+impl DoctorActionOutput {
+    fn has_failures(&self) -> bool {
+        self.checks
+            .iter()
+            .any(|check| check.status == CheckStatus::Fail)
+    }
+}
+
+fn colored_status(status: CheckStatus) -> String {
+    let text = status.to_string();
+    match status {
+        CheckStatus::Pass => text
+            .if_supports_color(Stdout, |text| text.green())
+            .to_string(),
+        CheckStatus::Warn => text
+            .if_supports_color(Stdout, |text| text.yellow())
+            .to_string(),
+        CheckStatus::Fail => text
+            .if_supports_color(Stdout, |text| text.red())
+            .to_string(),
+    }
+}
+
+impl CommandlinePrint for DoctorActionOutput {
+    fn print(&self) {
+        for check in self.checks.iter() {
+            println!(
+                "[{}] {}: {}",
+                colored_status(check.status),
+                check.name.if_supports_color(Stdout, |text| text.bold()),
+                check.detail
+            );
+        }
+    }
+
+    fn print_json(&self) {
+        println!("{}", to_json(&self).unwrap_or_else(|_| {
+            eprintln!("The output could not be converted to JSON. Please try another output format...");
+            String::from("")
+        }));
+    }
+
+    fn print_plain(&self) {
+        for check in self.checks.iter() {
+            println!(
+                "{} {} {}",
+                check.status.to_string().to_lowercase(),
+                check.name,
+                check.detail
+            );
+        }
+    }
+
+    fn print_debug(&self) {
+        self.print();
+    }
+
+    fn print_table(&self) {
+        let mut table = new_table();
+        table.set_header(
+            ["Check", "Status", "Detail"]
+                .into_iter()
+                .map(|header| Cell::new(header).add_attribute(Attribute::Bold)),
+        );
+        for check in self.checks.iter() {
+            let color = match check.status {
+                CheckStatus::Pass => Color::Green,
+                CheckStatus::Warn => Color::Yellow,
+                CheckStatus::Fail => Color::Red,
+            };
+            table.add_row([
+                Cell::new(&check.name),
+                Cell::new(check.status).fg(color),
+                Cell::new(&check.detail),
+            ]);
+        }
+        println!("{table}");
+    }
+
+    fn exit_code(&self, _fail_on_empty: bool) -> ExitCode {
+        if self.has_failures() {
+            ExitCode::FAILURE
+        } else {
+            ExitCode::SUCCESS
+        }
+    }
+}
+
+/// A leftover lock makes every install fail until pacman is done or the lock is removed.
+fn check_pacman_lock(lock_path: &Path) -> DoctorCheck {
+    if lock_path.exists() {
+        DoctorCheck::new(
+            "pacman lock",
+            CheckStatus::Fail,
+            format!(
+                "{} exists: wait for the running package manager to finish, or remove the lock if none is running",
+                lock_path.display()
+            ),
+        )
+    } else {
+        DoctorCheck::new("pacman lock", CheckStatus::Pass, "not locked")
+    }
+}
+
+/// Whether pacman.conf enables the `[multilib]` repository, which has the 32-bit
+/// driver libraries like `lib32-nvidia-utils`.
+pub fn multilib_enabled(pacman_config: &str) -> bool {
+    pacman_config
+        .lines()
+        .any(|line| line.trim() == "[multilib]")
+}
+
+fn check_multilib(pacman_config_path: &Path) -> DoctorCheck {
+    match fs::read_to_string(pacman_config_path) {
+        Ok(pacman_config) if multilib_enabled(&pacman_config) => {
+            DoctorCheck::new("multilib", CheckStatus::Pass, "enabled")
+        }
+        Ok(_) => DoctorCheck::new(
+            "multilib",
+            CheckStatus::Warn,
+            format!(
+                "not enabled in {}, so 32-bit driver libraries cannot be installed",
+                pacman_config_path.display()
+            ),
+        ),
+        Err(error) => DoctorCheck::new(
+            "multilib",
+            CheckStatus::Warn,
+            format!(
+                "{} could not be read: {}",
+                pacman_config_path.display(),
+                error
+            ),
+        ),
+    }
+}
+
+/// After a kernel upgrade the modules of the running kernel are gone, so new kernel
+/// modules like DKMS drivers cannot be loaded until a reboot.
+fn check_kernel(kernel_release: Option<&str>, modules_directory: &Path) -> DoctorCheck {
+    match kernel_release {
+        Some(release) if modules_directory.join(release).is_dir() => DoctorCheck::new(
+            "kernel",
+            CheckStatus::Pass,
+            format!("{} is installed", release),
+        ),
+        Some(release) => DoctorCheck::new(
+            "kernel",
+            CheckStatus::Warn,
+            format!(
+                "the running kernel {} is not installed anymore: reboot before installing drivers",
+                release
+            ),
+        ),
+        None => DoctorCheck::new(
+            "kernel",
+            CheckStatus::Warn,
+            "the release of the running kernel could not be read",
+        ),
+    }
+}
+
+/// Building AUR packages needs `base-devel`.
+fn check_base_devel(package_backend: Result<&dyn PackageBackend, &Error>) -> DoctorCheck {
+    match package_backend {
+        Ok(package_backend) if package_backend.get("base-devel").is_some() => {
+            DoctorCheck::new("base-devel", CheckStatus::Pass, "installed")
+        }
+        Ok(_) => DoctorCheck::new(
+            "base-devel",
+            CheckStatus::Warn,
+            "not installed, so drivers cannot be installed from the AUR",
+        ),
+        Err(error) => DoctorCheck::new(
+            "base-devel",
+            CheckStatus::Warn,
+            format!("could not be checked: {}", error),
+        ),
+    }
+}
+
+/// Whether a file can be written to, or created if it does not exist yet.
+fn is_writable(path: &Path) -> bool {
+    if path.exists() {
+        return fs::OpenOptions::new().append(true).open(path).is_ok();
+    }
+    match path.parent() {
+        Some(directory) if directory.is_dir() => {
+            let probe = directory.join(format!(".{}-{}", APPLICATION_NAME, std::process::id()));
+            let writable = fs::File::create(&probe).is_ok();
+            _ = fs::remove_file(&probe);
+            writable
+        }
+        // The missing directories are created along with the database
+        Some(directory) => is_writable(directory),
+        None => false,
+    }
+}
+
+fn check_database_path(database_path: &Path) -> DoctorCheck {
+    if is_writable(database_path) {
+        DoctorCheck::new(
+            "database",
+            CheckStatus::Pass,
+            format!("{} is writable", database_path.display()),
+        )
+    } else {
+        DoctorCheck::new(
+            "database",
+            CheckStatus::Warn,
+            format!(
+                "{} is not writable, so the database cannot be generated or updated",
+                database_path.display()
+            ),
+        )
+    }
+}
+
+fn check_libalpm(version: &str) -> DoctorCheck {
+    match check_alpm_version(version) {
+        Ok(()) => DoctorCheck::new("libalpm", CheckStatus::Pass, version),
+        Err(error) => DoctorCheck::new("libalpm", CheckStatus::Fail, error.to_string()),
+    }
+}
+
+pub fn doctor_inner(
+    database_path: &Path,
+    kernel_release: Option<&str>,
+    alpm_version: &str,
+    package_backend: Result<&dyn PackageBackend, &Error>,
+) -> DoctorActionOutput {
+    DoctorActionOutput {
+        checks: vec![
+            check_pacman_lock(Path::new(PACMAN_DATABASE_LOCK_PATH)),
+            check_multilib(Path::new(PACMAN_CONFIG_PATH)),
+            check_kernel(kernel_release, Path::new(MODULES_DIRECTORY)),
+            check_base_devel(package_backend),
+            check_database_path(database_path),
+            check_libalpm(alpm_version),
+        ],
+    }
+}
+
+pub fn doctor(
+    doctor_action_arguments: DoctorActionArguments,
+    global_arguments: &GlobalArguments,
+) -> Result<DoctorActionOutput, Error> {
+    let database_path: PathBuf = doctor_action_arguments.database_file;
+    let kernel_release = fs::read_to_string(KERNEL_RELEASE_PATH)
+        .ok()
+        .map(|release| release.trim().to_string());
+    let package_backend = package_backend(global_arguments.package_manager);
+    Ok(doctor_inner(
+        &database_path,
+        kernel_release.as_deref(),
+        alpm::version(),
+        package_backend.as_deref(),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::arch::MockBackend;
+    use crate::test_support::TestDirectory;
+
+    #[test]
+    pub fn environment_checks() {
+        let directory = TestDirectory::new("doctor");
+        fs::create_dir_all(directory.join("modules").join("6.9.1-arch1-1")).unwrap();
+
+        assert!(multilib_enabled(
+            "[core]\nInclude = /etc/pacman.d/mirrorlist\n\n[multilib]\nInclude = /etc/pacman.d/mirrorlist\n"
+        ));
+        assert!(!multilib_enabled(
+            "[core]\nInclude = /etc/pacman.d/mirrorlist\n\n#[multilib]\n#Include = /etc/pacman.d/mirrorlist\n"
+        ));
+
+        let lock_path = directory.join("db.lck");
+        assert_eq!(check_pacman_lock(&lock_path).status, CheckStatus::Pass);
+        fs::write(&lock_path, "").unwrap();
+        assert_eq!(check_pacman_lock(&lock_path).status, CheckStatus::Fail);
+
+        let modules_directory = directory.join("modules");
+        assert_eq!(
+            check_kernel(Some("6.9.1-arch1-1"), &modules_directory).status,
+            CheckStatus::Pass
+        );
+        assert_eq!(
+            check_kernel(Some("6.8.9-arch1-1"), &modules_directory).status,
+            CheckStatus::Warn
+        );
+
+        let package_backend = MockBackend::new([("base-devel", "1-1")]);
+        assert_eq!(
+            check_base_devel(Ok(&package_backend)).status,
+            CheckStatus::Warn
+        );
+        let package_backend = package_backend.with_installed(["base-devel"]);
+        assert_eq!(
+            check_base_devel(Ok(&package_backend)).status,
+            CheckStatus::Pass
+        );
+
+        assert_eq!(
+            check_database_path(&directory.join("database.db")).status,
+            CheckStatus::Pass
+        );
+        assert_eq!(check_libalpm("13.0.2").status, CheckStatus::Pass);
+        assert_eq!(check_libalpm("14.0.0").status, CheckStatus::Fail);
+    }
+}
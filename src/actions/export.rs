@@ -0,0 +1,111 @@
+use crate::{
+    actions::install::hardware_setups,
+    cli::{CommandlinePrint, ExportActionArguments, GlobalArguments},
+    data::{
+        database::EntryDecoder,
+        input_file::{FileFormat, HardwareSetup},
+        remote_index::database_with_remote_index,
+    },
+    error::Error,
+};
+use std::path::PathBuf;
+
+/// The hardware setups of a database, written in the format of an input file.
+#[derive(Debug, Clone)]
+pub struct ExportActionOutput {
+    pub hardware_setups: Vec<HardwareSetup>,
+    pub format: FileFormat,
+}
+
+impl CommandlinePrint for ExportActionOutput {
+    fn print(&self) {
+        print!("{}", self.format.serialize(&self.hardware_setups));
+    }
+
+    fn print_json(&self) {
+        println!("{}", FileFormat::Json.serialize(&self.hardware_setups));
+    }
+
+    fn print_plain(&self) {
+        self.print();
+    }
+
+    fn print_debug(&self) {
+        self.print();
+    }
+
+    fn is_empty_result(&self) -> bool {
+        self.hardware_setups.is_empty()
+    }
+}
+
+pub fn export_inner(
+    database_filepath: PathBuf,
+    format: FileFormat,
+) -> Result<ExportActionOutput, Error> {
+    let mut hardware_setups = hardware_setups(database_filepath, &EntryDecoder::new())?;
+    hardware_setups.sort();
+    Ok(ExportActionOutput {
+        hardware_setups,
+        format,
+    })
+}
+
+pub fn export(
+    export_action_arguments: ExportActionArguments,
+    global_arguments: &GlobalArguments,
+) -> Result<ExportActionOutput, Error> {
+    let catalog_database = database_with_remote_index(
+        export_action_arguments.database_file,
+        global_arguments.offline_flag,
+        global_arguments.verify_checksum,
+    )?;
+    export_inner(
+        catalog_database.path.clone(),
+        export_action_arguments.format,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::TestDirectory;
+    use crate::{
+        actions::generate_database::generate_database_inner, data::input_file::parse_input_file,
+    };
+    use std::{fs, path::Path};
+
+    #[test]
+    pub fn exports_parse_back_to_the_same_hardware_setups() {
+        let directory = TestDirectory::new("export");
+        let database_file = directory.join("database.db");
+        generate_database_inner(
+            PathBuf::from("input_data.yaml"),
+            database_file.clone(),
+            false,
+        )
+        .unwrap();
+        let original = parse_input_file(PathBuf::from("input_data.yaml")).unwrap();
+        assert!(!original.is_empty());
+
+        for (format, file_name) in [
+            (FileFormat::Yaml, "exported.yaml"),
+            (FileFormat::Json, "exported.json"),
+        ] {
+            let exported = export_inner(database_file.clone(), format).unwrap();
+            let exported_file = directory.join(file_name);
+            fs::write(&exported_file, format.serialize(&exported.hardware_setups)).unwrap();
+            assert_eq!(FileFormat::from_path(&exported_file), format);
+            assert_eq!(
+                parse_input_file(exported_file).unwrap(),
+                original,
+                "{:?}",
+                format
+            );
+        }
+        assert_eq!(
+            FileFormat::from_path(Path::new("input_data")),
+            FileFormat::Yaml
+        );
+    }
+}
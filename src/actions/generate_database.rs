@@ -1,30 +1,51 @@
 use crate::{
-    cli::{CommandlinePrint, GenerateDatabaseActionArguments},
+    cli::{to_json, CommandlinePrint, GenerateDatabaseActionArguments},
     data::{
-        database,
-        input_file::{self, HardwareList, HardwareListInner, PciIdList, UsbIdList},
+        backup,
+        database::{self, decode_value, encode_value, PCI_ID_BUCKET, USB_ID_BUCKET},
+        input_file::{self, HardwareSetup},
     },
-    error::{DatabaseSnafu, Error},
+    error::{DatabaseSchemaMismatchSnafu, DatabaseSnafu, Error},
+    paths,
+};
+use jammdb::{Bucket, Tx};
+use owo_colors::{
+    OwoColorize,
+    Stream::{Stderr, Stdout},
 };
-use owo_colors::{OwoColorize, Stream::Stdout};
 use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
 use snafu::ResultExt;
-use speedy::{Readable, Writable};
 use std::{
-    collections::BTreeSet,
-    path::PathBuf,
-    sync::atomic::{AtomicUsize, Ordering},
+    collections::{BTreeMap, BTreeSet},
+    fs,
+    path::{Path, PathBuf},
+    thread,
+    time::{Duration, Instant, SystemTime},
 };
 
 #[derive(Clone, Default, Debug, Serialize, Deserialize)]
 pub struct GenerateDatabaseActionOutput {
     success_message: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    backup_file: Option<PathBuf>,
 }
 
 impl GenerateDatabaseActionOutput {
     pub fn new() -> Self {
         GenerateDatabaseActionOutput {
             success_message: "Database generated successfully...".to_string(),
+            backup_file: None,
+        }
+    }
+
+    pub fn updated(added_count: usize, removed_count: usize, unchanged_count: usize) -> Self {
+        GenerateDatabaseActionOutput {
+            success_message: format!(
+                "Database updated successfully: {} hardware setups added, {} removed, {} unchanged...",
+                added_count, removed_count, unchanged_count
+            ),
+            backup_file: None,
         }
     }
 }
@@ -36,10 +57,16 @@ impl CommandlinePrint for GenerateDatabaseActionOutput {
             self.success_message
                 .if_supports_color(Stdout, |text| text.green())
         );
+        if let Some(backup_file) = &self.backup_file {
+            println!(
+                "The previous database was backed up to {}",
+                backup_file.to_string_lossy()
+            );
+        }
     }
 
     fn print_json(&self) {
-        println!("{}", serde_json::to_string(&self).unwrap_or_else(|_| {
+        println!("{}", to_json(&self).unwrap_or_else(|_| {
             eprintln!("The output could not be converted to JSON. Please try another output format...");
             String::from("")
         }));
@@ -54,191 +81,827 @@ impl CommandlinePrint for GenerateDatabaseActionOutput {
     }
 }
 
-pub fn generate_database_inner(
-    input_file: PathBuf,
-    database_file: PathBuf,
-) -> Result<GenerateDatabaseActionOutput, Error> {
-    let hardware_setups = input_file::parse_input_file(input_file)?;
-    let driver_database = database::DriverDatabase::with_database_path(database_file)?;
-
-    // open a writable transaction so we can make changes
-    let transaction = driver_database.tx(true).context(DatabaseSnafu)?;
+/// Reads a set of IDs stored under a key, or an empty set if there is none.
+fn id_set<K: AsRef<[u8]>>(bucket: &Bucket, key: K) -> BTreeSet<String> {
+    match bucket.get(key) {
+        Some(data) if data.is_kv() => decode_value::<BTreeSet<String>>(data.kv().value()).unwrap(),
+        _ => BTreeSet::<String>::new(),
+    }
+}
 
-    let pci_id_to_hardware_setup_id_bucket = transaction
-        .get_or_create_bucket("pci_id_to_hardware_setup_id_bucket")
+fn add_to_id_set<K: AsRef<[u8]>>(bucket: &Bucket, key: K, id: &str) -> Result<(), Error> {
+    let mut ids = id_set(bucket, &key);
+    ids.insert(id.to_owned());
+    bucket
+        .put(key.as_ref().to_vec(), encode_value(&ids))
         .context(DatabaseSnafu)?;
+    Ok(())
+}
 
-    let usb_id_to_hardware_setup_id_bucket = transaction
-        .get_or_create_bucket("usb_id_to_hardware_setup_id_bucket")
-        .context(DatabaseSnafu)?;
+/// Removes an ID from the set stored under a key, dropping the key once its set is empty.
+fn remove_from_id_set<K: AsRef<[u8]>>(bucket: &Bucket, key: K, id: &str) -> Result<(), Error> {
+    let mut ids = id_set(bucket, &key);
+    if !ids.remove(id) {
+        return Ok(());
+    }
+    if ids.is_empty() {
+        bucket.delete(key).context(DatabaseSnafu)?;
+    } else {
+        bucket
+            .put(key.as_ref().to_vec(), encode_value(&ids))
+            .context(DatabaseSnafu)?;
+    }
+    Ok(())
+}
 
-    let hardware_kind_to_hardware_setup_id_bucket = transaction
-        .get_or_create_bucket("hardware_kind_to_hardware_setup_id_bucket")
-        .context(DatabaseSnafu)?;
+/// A digest of everything stored for a hardware setup, used to tell whether it changed.
+pub fn content_hash(hardware_setup: &HardwareSetup) -> String {
+    hex::encode(Sha256::digest(encode_value(hardware_setup)))
+}
 
-    let hardware_kind_to_driver_option_id_bucket = transaction
-        .get_or_create_bucket("hardware_kind_to_driver_option_id_bucket")
-        .context(DatabaseSnafu)?;
+/// A short digest of a value, used to derive IDs from content.
+fn id_digest<T: Serialize>(value: &T) -> String {
+    hex::encode(&Sha256::digest(encode_value(value))[..8])
+}
 
-    let hardware_setup_id_to_driver_option_id_bucket = transaction
-        .get_or_create_bucket("hardware_setup_id_to_driver_option_id_bucket")
-        .context(DatabaseSnafu)?;
+/// The ID of a hardware setup, derived from what identifies it: its hardware kind, name
+/// and hardware list. Unlike a counter, it does not depend on what else is in the
+/// input, so regenerating from the same input always gives the same IDs.
+fn hardware_setup_base_id(hardware_setup: &HardwareSetup) -> String {
+    id_digest(&(
+        hardware_setup.hardware_kind,
+        &hardware_setup.name,
+        &hardware_setup.hardware_list,
+    ))
+}
 
-    let hardware_setup_id_to_hardware_setup_bucket = transaction
-        .get_or_create_bucket("hardware_setup_id_to_hardware_setup_bucket")
-        .context(DatabaseSnafu)?;
+/// The base ID if no key of a bucket uses it yet, or else the base ID with the first
+/// free numeric suffix.
+fn unique_id(bucket: &Bucket, base_id: String) -> String {
+    if bucket.get(&base_id).is_none() {
+        return base_id;
+    }
+    (2..)
+        .map(|suffix| format!("{}-{}", base_id, suffix))
+        .find(|id| bucket.get(id).is_none())
+        .unwrap()
+}
 
-    let driver_option_id_to_driver_option_bucket = transaction
-        .get_or_create_bucket("driver_option_id_to_driver_option_bucket")
-        .context(DatabaseSnafu)?;
+/// Every bucket written by `generate-database`.
+const BUCKET_NAMES: [&str; 8] = [
+    PCI_ID_BUCKET,
+    USB_ID_BUCKET,
+    "hardware_kind_to_hardware_setup_id_bucket",
+    "hardware_kind_to_driver_option_id_bucket",
+    "hardware_setup_id_to_driver_option_id_bucket",
+    "hardware_setup_id_to_hardware_setup_bucket",
+    "hardware_setup_id_to_content_hash_bucket",
+    "driver_option_id_to_driver_option_bucket",
+];
 
-    static HARDWARE_SETUP_SERIAL: AtomicUsize = AtomicUsize::new(1);
-    let new_hardware_setup_id = || {
-        HARDWARE_SETUP_SERIAL
-            .fetch_add(1, Ordering::SeqCst)
-            .to_string()
-    };
+struct Buckets<'b, 'tx> {
+    pci_id_to_hardware_setup_id: Bucket<'b, 'tx>,
+    usb_id_to_hardware_setup_id: Bucket<'b, 'tx>,
+    hardware_kind_to_hardware_setup_id: Bucket<'b, 'tx>,
+    hardware_kind_to_driver_option_id: Bucket<'b, 'tx>,
+    hardware_setup_id_to_driver_option_id: Bucket<'b, 'tx>,
+    hardware_setup_id_to_hardware_setup: Bucket<'b, 'tx>,
+    hardware_setup_id_to_content_hash: Bucket<'b, 'tx>,
+    driver_option_id_to_driver_option: Bucket<'b, 'tx>,
+}
 
-    static DRIVER_OPTION_SERIAL: AtomicUsize = AtomicUsize::new(1);
-    let new_driver_option_id = || {
-        DRIVER_OPTION_SERIAL
-            .fetch_add(1, Ordering::SeqCst)
-            .to_string()
-    };
+impl<'b, 'tx> Buckets<'b, 'tx> {
+    fn get_or_create(transaction: &'b Tx<'tx>) -> Result<Self, Error> {
+        Ok(Buckets {
+            pci_id_to_hardware_setup_id: transaction
+                .get_or_create_bucket(PCI_ID_BUCKET)
+                .context(DatabaseSnafu)?,
+            usb_id_to_hardware_setup_id: transaction
+                .get_or_create_bucket(USB_ID_BUCKET)
+                .context(DatabaseSnafu)?,
+            hardware_kind_to_hardware_setup_id: transaction
+                .get_or_create_bucket("hardware_kind_to_hardware_setup_id_bucket")
+                .context(DatabaseSnafu)?,
+            hardware_kind_to_driver_option_id: transaction
+                .get_or_create_bucket("hardware_kind_to_driver_option_id_bucket")
+                .context(DatabaseSnafu)?,
+            hardware_setup_id_to_driver_option_id: transaction
+                .get_or_create_bucket("hardware_setup_id_to_driver_option_id_bucket")
+                .context(DatabaseSnafu)?,
+            hardware_setup_id_to_hardware_setup: transaction
+                .get_or_create_bucket("hardware_setup_id_to_hardware_setup_bucket")
+                .context(DatabaseSnafu)?,
+            hardware_setup_id_to_content_hash: transaction
+                .get_or_create_bucket("hardware_setup_id_to_content_hash_bucket")
+                .context(DatabaseSnafu)?,
+            driver_option_id_to_driver_option: transaction
+                .get_or_create_bucket("driver_option_id_to_driver_option_bucket")
+                .context(DatabaseSnafu)?,
+        })
+    }
+
+    /// Drops any existing contents first, so that the database reflects exactly what gets inserted.
+    fn recreate(transaction: &'b Tx<'tx>) -> Result<Self, Error> {
+        for bucket_name in BUCKET_NAMES {
+            if transaction.get_bucket(bucket_name).is_ok() {
+                transaction
+                    .delete_bucket(bucket_name)
+                    .context(DatabaseSnafu)?;
+            }
+        }
+        Self::get_or_create(transaction)
+    }
+
+    fn hardware_id_bucket(&self, bucket_name: &str) -> &Bucket<'b, 'tx> {
+        match bucket_name {
+            PCI_ID_BUCKET => &self.pci_id_to_hardware_setup_id,
+            _ => &self.usb_id_to_hardware_setup_id,
+        }
+    }
+
+    /// Inserts a hardware setup and its driver options under IDs derived from their content.
+    fn insert_hardware_setup(&self, hardware_setup: &HardwareSetup) -> Result<(), Error> {
+        let hardware_setup_id = &unique_id(
+            &self.hardware_setup_id_to_hardware_setup,
+            hardware_setup_base_id(hardware_setup),
+        );
+        let hardware_kind = hardware_setup.hardware_kind.to_string();
+
+        add_to_id_set(
+            &self.hardware_kind_to_hardware_setup_id,
+            hardware_kind.clone(),
+            hardware_setup_id,
+        )?;
+
+        self.hardware_setup_id_to_hardware_setup
+            .put(hardware_setup_id.to_owned(), encode_value(hardware_setup))
+            .context(DatabaseSnafu)?;
+
+        self.hardware_setup_id_to_content_hash
+            .put(hardware_setup_id.to_owned(), content_hash(hardware_setup))
+            .context(DatabaseSnafu)?;
+
+        for (bucket_name, key) in hardware_setup.hardware_list.bucket_and_keys() {
+            add_to_id_set(self.hardware_id_bucket(bucket_name), key, hardware_setup_id)?;
+        }
 
-    hardware_setups.iter().for_each(|hardware_setup| {
-        let hardware_setup_id = new_hardware_setup_id();
         let mut driver_option_ids = BTreeSet::<String>::new();
+        for driver_option in hardware_setup.driver_options.iter() {
+            let driver_option_id = unique_id(
+                &self.driver_option_id_to_driver_option,
+                id_digest(&(hardware_setup_id, &driver_option.name)),
+            );
 
-        {
-            let mut hardware_setup_ids = BTreeSet::<String>::new();
-            if let Some(data) = hardware_kind_to_hardware_setup_id_bucket
-                .get(hardware_setup.hardware_kind.to_string())
-            {
-                if data.is_kv() {
-                    let kv = data.kv();
-                    hardware_setup_ids = BTreeSet::<String>::read_from_buffer(kv.value()).unwrap();
-                }
-            }
-            hardware_setup_ids.insert(hardware_setup_id.clone());
-            hardware_kind_to_hardware_setup_id_bucket
-                .put(
-                    hardware_setup.hardware_kind.to_string(),
-                    hardware_setup_ids.write_to_vec().unwrap(),
-                )
-                .context(DatabaseSnafu)
-                .unwrap();
+            add_to_id_set(
+                &self.hardware_kind_to_driver_option_id,
+                hardware_kind.clone(),
+                &driver_option_id,
+            )?;
+
+            self.driver_option_id_to_driver_option
+                .put(driver_option_id.clone(), encode_value(driver_option))
+                .context(DatabaseSnafu)?;
+            driver_option_ids.insert(driver_option_id);
         }
 
-        hardware_setup_id_to_hardware_setup_bucket
+        self.hardware_setup_id_to_driver_option_id
             .put(
-                hardware_setup_id.clone(),
-                hardware_setup.write_to_vec().unwrap(),
+                hardware_setup_id.to_owned(),
+                encode_value(&driver_option_ids),
             )
-            .context(DatabaseSnafu)
-            .unwrap();
+            .context(DatabaseSnafu)?;
 
-        let process_pci_id_list = |pci_id_list: &PciIdList| {
-            pci_id_list.devices.iter().for_each(|device| {
-                let pci_id = (((pci_id_list.vendor as u32) << 16) | (*device as u32)).to_string();
-                let mut hardware_setup_ids = BTreeSet::<String>::new();
-                if let Some(data) = pci_id_to_hardware_setup_id_bucket.get(&pci_id) {
-                    if data.is_kv() {
-                        let kv = data.kv();
-                        hardware_setup_ids =
-                            BTreeSet::<String>::read_from_buffer(kv.value()).unwrap();
-                    }
-                }
-                hardware_setup_ids.insert(hardware_setup_id.clone());
-                pci_id_to_hardware_setup_id_bucket
-                    .put(pci_id, hardware_setup_ids.write_to_vec().unwrap())
-                    .context(DatabaseSnafu)
-                    .unwrap();
-            })
-        };
+        Ok(())
+    }
+
+    /// Removes a hardware setup, its driver options, and every index entry pointing to them.
+    fn remove_hardware_setup(&self, hardware_setup_id: &str) -> Result<(), Error> {
+        if let Some(data) = self
+            .hardware_setup_id_to_hardware_setup
+            .get(hardware_setup_id)
+        {
+            let hardware_setup = decode_value::<HardwareSetup>(data.kv().value()).unwrap();
+            let hardware_kind = hardware_setup.hardware_kind.to_string();
+
+            remove_from_id_set(
+                &self.hardware_kind_to_hardware_setup_id,
+                hardware_kind.clone(),
+                hardware_setup_id,
+            )?;
 
-        let process_usb_id_list = |usb_id_list: &UsbIdList| {
-            usb_id_list.devices.iter().for_each(|device| {
-                let usb_id = (((usb_id_list.vendor as u32) << 16) | (*device as u32)).to_string();
-                let mut hardware_setup_ids = BTreeSet::<String>::new();
-                if let Some(data) = usb_id_to_hardware_setup_id_bucket.get(&usb_id) {
-                    if data.is_kv() {
-                        let kv = data.kv();
-                        hardware_setup_ids =
-                            BTreeSet::<String>::read_from_buffer(kv.value()).unwrap();
-                    }
+            for (bucket_name, key) in hardware_setup.hardware_list.bucket_and_keys() {
+                remove_from_id_set(self.hardware_id_bucket(bucket_name), key, hardware_setup_id)?;
+            }
+
+            for driver_option_id in id_set(
+                &self.hardware_setup_id_to_driver_option_id,
+                hardware_setup_id,
+            ) {
+                remove_from_id_set(
+                    &self.hardware_kind_to_driver_option_id,
+                    hardware_kind.clone(),
+                    &driver_option_id,
+                )?;
+                if self
+                    .driver_option_id_to_driver_option
+                    .get(&driver_option_id)
+                    .is_some()
+                {
+                    self.driver_option_id_to_driver_option
+                        .delete(&driver_option_id)
+                        .context(DatabaseSnafu)?;
                 }
-                hardware_setup_ids.insert(hardware_setup_id.clone());
-                usb_id_to_hardware_setup_id_bucket
-                    .put(usb_id, hardware_setup_ids.write_to_vec().unwrap())
-                    .context(DatabaseSnafu)
-                    .unwrap();
-            })
-        };
+            }
+
+            self.hardware_setup_id_to_hardware_setup
+                .delete(hardware_setup_id)
+                .context(DatabaseSnafu)?;
+        }
 
-        match &hardware_setup.hardware_list {
-            HardwareList::Each(hardware_lists) => {
-                hardware_lists
-                    .iter()
-                    .for_each(|hardware_list_inner| match hardware_list_inner {
-                        HardwareListInner::Pci(pci_id_list) => process_pci_id_list(pci_id_list),
-                        HardwareListInner::Usb(usb_id_list) => process_usb_id_list(usb_id_list),
-                    })
+        for bucket in [
+            &self.hardware_setup_id_to_driver_option_id,
+            &self.hardware_setup_id_to_content_hash,
+        ] {
+            if bucket.get(hardware_setup_id).is_some() {
+                bucket.delete(hardware_setup_id).context(DatabaseSnafu)?;
             }
-            HardwareList::Pci(pci_id_list) => process_pci_id_list(&pci_id_list),
-            HardwareList::Usb(usb_id_list) => process_usb_id_list(&usb_id_list),
         }
 
-        hardware_setup
-            .driver_options
+        Ok(())
+    }
+}
+
+pub fn generate_database_inner(
+    input_file: PathBuf,
+    database_file: PathBuf,
+    update: bool,
+) -> Result<GenerateDatabaseActionOutput, Error> {
+    let hardware_setups = input_file::parse_input_file(input_file)?;
+    generate_database_from_hardware_setups(hardware_setups, database_file, update)
+}
+
+fn generate_database_from_hardware_setups(
+    hardware_setups: BTreeSet<HardwareSetup>,
+    database_file: PathBuf,
+    update: bool,
+) -> Result<GenerateDatabaseActionOutput, Error> {
+    for warning in hardware_setups
+        .iter()
+        .flat_map(|hardware_setup| hardware_setup.validation_warnings())
+    {
+        eprintln!(
+            "{} {}",
+            "WARNING:".if_supports_color(Stderr, |text| text.yellow()),
+            warning
+        );
+    }
+    let driver_database = database::DriverDatabase::with_database_path(database_file)?;
+
+    // open a writable transaction so we can make changes
+    let transaction = driver_database.tx(true).context(DatabaseSnafu)?;
+    // Entries in another layout cannot be compared or removed one by one
+    let update = update
+        && {
+            let stores_current_layout =
+                database::stored_schema_version(&transaction) == Some(database::SCHEMA_VERSION);
+            if !stores_current_layout {
+                eprintln!(
+                "{} The database was written by another version, so it is regenerated from scratch instead of updated",
+                "WARNING:".if_supports_color(Stderr, |text| text.yellow())
+            );
+            }
+            stores_current_layout
+        };
+    let buckets = if update {
+        Buckets::get_or_create(&transaction)?
+    } else {
+        Buckets::recreate(&transaction)?
+    };
+
+    let output = if update {
+        let stored_content_hashes = buckets
+            .hardware_setup_id_to_hardware_setup
+            .kv_pairs()
+            .filter_map(|kv_pair| String::from_utf8(kv_pair.key().to_vec()).ok())
+            .map(|hardware_setup_id| {
+                let stored_content_hash = buckets
+                    .hardware_setup_id_to_content_hash
+                    .get(&hardware_setup_id)
+                    .map(|data| String::from_utf8_lossy(data.kv().value()).into_owned());
+                (hardware_setup_id, stored_content_hash)
+            })
+            .collect::<Vec<(String, Option<String>)>>();
+
+        let new_content_hashes = hardware_setups
             .iter()
-            .for_each(|driver_option| {
-                let driver_option_id = new_driver_option_id();
+            .map(|hardware_setup| (content_hash(hardware_setup), hardware_setup))
+            .collect::<BTreeMap<String, &HardwareSetup>>();
 
-                {
-                    let mut driver_option_ids = BTreeSet::<String>::new();
-                    if let Some(data) = hardware_kind_to_driver_option_id_bucket
-                        .get(hardware_setup.hardware_kind.to_string())
-                    {
-                        if data.is_kv() {
-                            let kv = data.kv();
-                            driver_option_ids =
-                                BTreeSet::<String>::read_from_buffer(kv.value()).unwrap();
-                        }
-                    }
-                    driver_option_ids.insert(driver_option_id.clone());
-                    hardware_kind_to_driver_option_id_bucket
-                        .put(
-                            hardware_setup.hardware_kind.to_string(),
-                            driver_option_ids.write_to_vec().unwrap(),
-                        )
-                        .context(DatabaseSnafu)
-                        .unwrap();
+        let mut unchanged_content_hashes = BTreeSet::<String>::new();
+        let mut removed_count = 0;
+        for (hardware_setup_id, stored_content_hash) in stored_content_hashes {
+            match stored_content_hash {
+                Some(stored_content_hash)
+                    if new_content_hashes.contains_key(&stored_content_hash)
+                        && unchanged_content_hashes.insert(stored_content_hash.clone()) => {}
+                _ => {
+                    buckets.remove_hardware_setup(&hardware_setup_id)?;
+                    removed_count += 1;
                 }
+            }
+        }
+
+        let mut added_count = 0;
+        for (new_content_hash, hardware_setup) in new_content_hashes.iter() {
+            if !unchanged_content_hashes.contains(new_content_hash) {
+                buckets.insert_hardware_setup(hardware_setup)?;
+                added_count += 1;
+            }
+        }
 
-                driver_option_ids.insert(driver_option_id.clone());
-                driver_option_id_to_driver_option_bucket
-                    .put(driver_option_id, driver_option.write_to_vec().unwrap())
-                    .context(DatabaseSnafu)
-                    .unwrap();
-            });
+        GenerateDatabaseActionOutput::updated(
+            added_count,
+            removed_count,
+            unchanged_content_hashes.len(),
+        )
+    } else {
+        for hardware_setup in hardware_setups.iter() {
+            buckets.insert_hardware_setup(hardware_setup)?;
+        }
+        GenerateDatabaseActionOutput::new()
+    };
 
-        hardware_setup_id_to_driver_option_id_bucket
-            .put(hardware_setup_id, driver_option_ids.write_to_vec().unwrap())
-            .context(DatabaseSnafu)
-            .unwrap();
-    });
+    drop(buckets);
+    database::write_schema_version(&transaction)?;
+    transaction.commit().context(DatabaseSnafu)?;
+
+    Ok(output)
+}
 
+/// Adds hardware setups to an existing database, skipping the ones it already contains.
+/// Returns the number of hardware setups added.
+pub fn merge_hardware_setups(
+    database_file: PathBuf,
+    hardware_setups: &BTreeSet<HardwareSetup>,
+) -> Result<usize, Error> {
+    let driver_database = database::DriverDatabase::with_database_path(database_file.clone())?;
+
+    let transaction = driver_database.tx(true).context(DatabaseSnafu)?;
+    let buckets = Buckets::get_or_create(&transaction)?;
+
+    // Setups in another layout cannot be merged with, only regenerated
+    let found = database::stored_schema_version(&transaction);
+    if found != Some(database::SCHEMA_VERSION)
+        && buckets
+            .hardware_setup_id_to_content_hash
+            .kv_pairs()
+            .next()
+            .is_some()
+    {
+        return DatabaseSchemaMismatchSnafu {
+            path: database_file,
+            found,
+        }
+        .fail();
+    }
+
+    let stored_content_hashes = buckets
+        .hardware_setup_id_to_content_hash
+        .kv_pairs()
+        .map(|kv_pair| String::from_utf8_lossy(kv_pair.value()).into_owned())
+        .collect::<BTreeSet<String>>();
+
+    let mut added_count = 0;
+    for hardware_setup in hardware_setups.iter() {
+        if stored_content_hashes.contains(&content_hash(hardware_setup)) {
+            continue;
+        }
+        buckets.insert_hardware_setup(hardware_setup)?;
+        added_count += 1;
+    }
+
+    drop(buckets);
+    database::write_schema_version(&transaction)?;
     transaction.commit().context(DatabaseSnafu)?;
 
-    Ok(GenerateDatabaseActionOutput::new())
+    Ok(added_count)
+}
+
+/// How often a watched input file is checked for changes.
+const WATCH_POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+/// How long a watched input file has to stay unchanged before the database is regenerated,
+/// so that several saves in quick succession regenerate it once.
+const WATCH_DEBOUNCE: Duration = Duration::from_millis(500);
+
+/// Tells when watched files have settled after changing.
+struct ChangeDebouncer<T> {
+    last_modified: T,
+    changed_at: Option<Instant>,
+    debounce: Duration,
+}
+
+impl<T: PartialEq> ChangeDebouncer<T> {
+    fn new(last_modified: T, debounce: Duration) -> Self {
+        ChangeDebouncer {
+            last_modified,
+            changed_at: None,
+            debounce,
+        }
+    }
+
+    /// Records the modification times of the files seen at `now`, and tells whether they
+    /// changed and have not changed again for the debounce interval since.
+    fn settled(&mut self, modified: T, now: Instant) -> bool {
+        if modified != self.last_modified {
+            self.last_modified = modified;
+            self.changed_at = Some(now);
+            return false;
+        }
+        match self.changed_at {
+            Some(changed_at) if now.duration_since(changed_at) >= self.debounce => {
+                self.changed_at = None;
+                true
+            }
+            _ => false,
+        }
+    }
+}
+
+/// The modification time of a watched input file. A directory changes with the newest of
+/// its entries too, since saving a file in it leaves the time of the directory alone.
+fn modified(path: &Path) -> Option<SystemTime> {
+    let modified = |path: &Path| {
+        fs::metadata(path)
+            .and_then(|metadata| metadata.modified())
+            .ok()
+    };
+    let directory_modified = modified(path);
+    let Ok(entries) = fs::read_dir(path) else {
+        return directory_modified;
+    };
+    entries
+        .filter_map(|entry| modified(&entry.ok()?.path()))
+        .chain(directory_modified)
+        .max()
+}
+
+/// The modification times of watched files, by path.
+fn modified_all(paths: &BTreeSet<PathBuf>) -> BTreeMap<PathBuf, Option<SystemTime>> {
+    paths
+        .iter()
+        .map(|path| (path.clone(), modified(path)))
+        .collect()
+}
+
+/// Regenerates the database whenever the input file or a file it includes changes,
+/// printing the outcome of each run, until the process is interrupted.
+fn watch_input_file(generate_database_action_arguments: &GenerateDatabaseActionArguments) -> ! {
+    let input_file = &generate_database_action_arguments.input_file;
+    let mut seen = modified_all(&BTreeSet::from([input_file.clone()]));
+    loop {
+        let mut watched_files = BTreeSet::from([input_file.clone()]);
+        match generate_database_once(generate_database_action_arguments, &mut watched_files) {
+            Ok(output) => output.print(),
+            Err(error) => eprintln!(
+                "{} {}",
+                "ERROR:".if_supports_color(Stderr, |text| text.red()),
+                error
+            ),
+        }
+        println!(
+            "Watching {} for changes. Press Ctrl-C to stop...",
+            input_file.to_string_lossy()
+        );
+        // Files that were watched before count as changed if they changed during the run
+        let last_modified = watched_files
+            .iter()
+            .map(|path| {
+                let last_modified = seen.get(path).copied().unwrap_or_else(|| modified(path));
+                (path.clone(), last_modified)
+            })
+            .collect();
+        let mut debouncer = ChangeDebouncer::new(last_modified, WATCH_DEBOUNCE);
+        while !debouncer.settled(modified_all(&watched_files), Instant::now()) {
+            thread::sleep(WATCH_POLL_INTERVAL);
+        }
+        seen = debouncer.last_modified;
+    }
 }
 
 pub fn generate_database(
     generate_database_action_arguments: GenerateDatabaseActionArguments,
 ) -> Result<GenerateDatabaseActionOutput, Error> {
-    Ok(generate_database_inner(
-        generate_database_action_arguments.input_file,
-        generate_database_action_arguments.database_file,
-    )?)
+    if generate_database_action_arguments.watch {
+        watch_input_file(&generate_database_action_arguments);
+    }
+    generate_database_once(&generate_database_action_arguments, &mut BTreeSet::new())
+}
+
+/// Generates the database once, adding the files the input pulls in with `!include`
+/// to `included_files`.
+fn generate_database_once(
+    generate_database_action_arguments: &GenerateDatabaseActionArguments,
+    included_files: &mut BTreeSet<PathBuf>,
+) -> Result<GenerateDatabaseActionOutput, Error> {
+    let database_file = generate_database_action_arguments.database_file.clone();
+    // The data directory of the user is created on first use, unlike the system-wide one
+    let data_directory = paths::data_directory();
+    if database_file.starts_with(&data_directory) {
+        _ = fs::create_dir_all(&data_directory);
+    }
+    database::DriverDatabase::ensure_writable(&database_file)?;
+    let input_file = &generate_database_action_arguments.input_file;
+    let hardware_setups = if generate_database_action_arguments.skip_errors && input_file.is_dir() {
+        let (hardware_setups, errors) =
+            input_file::parse_input_directory(input_file, included_files)?;
+        for error in errors.iter() {
+            eprintln!(
+                "{} {}. The file is skipped",
+                "WARNING:".if_supports_color(Stderr, |text| text.yellow()),
+                error
+            );
+        }
+        hardware_setups
+    } else {
+        input_file::parse_input_file_with_includes(input_file.clone(), included_files)?
+    };
+    // Only back up once the input parsed, so broken input never rotates out a good backup
+    let backup_file = if generate_database_action_arguments.no_backup {
+        None
+    } else {
+        let backup_file = backup::backup_database(&database_file)?;
+        backup::prune_database_backups(
+            &database_file,
+            generate_database_action_arguments.keep_backups as usize,
+        )?;
+        backup_file
+    };
+    let mut output = generate_database_from_hardware_setups(
+        hardware_setups,
+        database_file,
+        generate_database_action_arguments.update,
+    )?;
+    database::write_checksum(&generate_database_action_arguments.database_file)?;
+    output.backup_file = backup_file;
+    Ok(output)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::database::{HardwareId, PciId, UsbId};
+    use crate::test_support::TestDirectory;
+    use std::fs;
+
+    const NVIDIA_SETUP: &str = r#"
+- name: Nvidia dGPU
+  hardware_kind: graphics
+  hardware_list: !pci
+      vendor: 10de
+      devices: [1b80]
+  driver_options:
+    - name: Nvidia
+      packages: [nvidia]
+"#;
+
+    const REALTEK_SETUP: &str = r#"
+- name: Realtek USB Ethernet
+  hardware_kind: ethernet
+  hardware_list: !usb
+      vendor-id: 0bda
+      device-ids: [8153]
+  driver_options:
+    - name: Realtek r8152
+      packages: [r8152-dkms]
+"#;
+
+    const REALTEK_SETUP_CHANGED: &str = r#"
+- name: Realtek USB Ethernet
+  hardware_kind: ethernet
+  hardware_list: !usb
+      vendor-id: 0bda
+      device-ids: [8156]
+  driver_options:
+    - name: Realtek r8152
+      packages: [r8152-dkms]
+"#;
+
+    fn stored_keys(database_file: &Path, bucket_name: &str) -> Vec<Vec<u8>> {
+        let driver_database =
+            database::DriverDatabase::with_database_path(database_file.to_path_buf()).unwrap();
+        let transaction = driver_database.tx(false).unwrap();
+        let bucket = transaction.get_bucket(bucket_name).unwrap();
+        let keys = bucket
+            .kv_pairs()
+            .map(|kv_pair| kv_pair.key().to_vec())
+            .collect();
+        keys
+    }
+
+    #[test]
+    pub fn regeneration_replaces_existing_entries() {
+        let directory = TestDirectory::new("regenerate");
+        let input_file = directory.join("input_data.yaml");
+        let database_file = directory.join("database.db");
+
+        fs::write(&input_file, [NVIDIA_SETUP, REALTEK_SETUP].concat()).unwrap();
+        generate_database_inner(input_file.clone(), database_file.clone(), false).unwrap();
+        fs::write(&input_file, REALTEK_SETUP_CHANGED).unwrap();
+        generate_database_inner(input_file, database_file.clone(), false).unwrap();
+
+        assert_eq!(
+            stored_keys(&database_file, "hardware_setup_id_to_hardware_setup_bucket").len(),
+            1
+        );
+        assert_eq!(
+            stored_keys(&database_file, "driver_option_id_to_driver_option_bucket").len(),
+            1
+        );
+        assert!(stored_keys(&database_file, "pci_id_to_hardware_setup_id_bucket").is_empty());
+        assert_eq!(
+            stored_keys(&database_file, "usb_id_to_hardware_setup_id_bucket"),
+            [0x0bda_8156_u32.to_be_bytes()]
+        );
+    }
+
+    #[test]
+    pub fn invalid_input_is_rejected_before_any_backup() {
+        let directory = TestDirectory::new("invalid-input");
+        let input_file = directory.join("input_data.yaml");
+        let database_file = directory.join("database.db");
+        fs::write(&input_file, NVIDIA_SETUP).unwrap();
+        generate_database_inner(input_file.clone(), database_file.clone(), false).unwrap();
+
+        fs::write(&input_file, "- name: [unterminated").unwrap();
+        let arguments = GenerateDatabaseActionArguments {
+            input_file,
+            database_file: database_file.clone(),
+            update: false,
+            no_backup: false,
+            keep_backups: 1,
+            watch: false,
+            skip_errors: false,
+        };
+        assert!(generate_database_once(&arguments, &mut BTreeSet::new()).is_err());
+        assert!(backup::database_backups(&database_file).unwrap().is_empty());
+    }
+
+    #[test]
+    pub fn generated_keys_match_searched_keys() {
+        let directory = TestDirectory::new("binary-keys");
+        let input_file = directory.join("input_data.yaml");
+        let database_file = directory.join("database.db");
+
+        fs::write(&input_file, NVIDIA_SETUP).unwrap();
+        generate_database_inner(input_file, database_file.clone(), false).unwrap();
+
+        let (bucket_name, searched_keys) = HardwareId::Pci(PciId {
+            vendor: 0x10de,
+            device: 0x1b80,
+            revision: None,
+        })
+        .lookup_bucket_and_keys();
+        assert_eq!(searched_keys, [[0x10, 0xde, 0x1b, 0x80]]);
+        assert_eq!(stored_keys(&database_file, bucket_name), searched_keys);
+    }
+
+    #[test]
+    pub fn update_applies_only_changes() {
+        let directory = TestDirectory::new("update");
+        let input_file = directory.join("input_data.yaml");
+        let database_file = directory.join("database.db");
+
+        fs::write(&input_file, [NVIDIA_SETUP, REALTEK_SETUP].concat()).unwrap();
+        generate_database_inner(input_file.clone(), database_file.clone(), false).unwrap();
+        let (pci_bucket, pci_key) = HardwareId::Pci(PciId {
+            vendor: 0x10de,
+            device: 0x1b80,
+            revision: None,
+        })
+        .bucket_and_key();
+        assert_eq!(stored_keys(&database_file, pci_bucket), [pci_key]);
+        let original_setup_ids =
+            stored_keys(&database_file, "hardware_setup_id_to_hardware_setup_bucket");
+        assert_eq!(original_setup_ids.len(), 2);
+
+        fs::write(&input_file, [NVIDIA_SETUP, REALTEK_SETUP_CHANGED].concat()).unwrap();
+        let output =
+            generate_database_inner(input_file.clone(), database_file.clone(), true).unwrap();
+        assert!(output
+            .success_message
+            .contains("1 hardware setups added, 1 removed, 1 unchanged"));
+
+        let updated_setup_ids =
+            stored_keys(&database_file, "hardware_setup_id_to_hardware_setup_bucket");
+        assert_eq!(updated_setup_ids.len(), 2);
+        assert_eq!(
+            original_setup_ids
+                .iter()
+                .filter(|id| updated_setup_ids.contains(id))
+                .count(),
+            1
+        );
+        let (usb_bucket, usb_key) = HardwareId::Usb(UsbId {
+            vendor: 0x0bda,
+            device: 0x8156,
+        })
+        .bucket_and_key();
+        assert_eq!(stored_keys(&database_file, usb_bucket), [usb_key]);
+        assert_eq!(
+            stored_keys(&database_file, "driver_option_id_to_driver_option_bucket").len(),
+            2
+        );
+
+        fs::write(&input_file, NVIDIA_SETUP).unwrap();
+        let output = generate_database_inner(input_file, database_file.clone(), true).unwrap();
+        assert!(output
+            .success_message
+            .contains("0 hardware setups added, 1 removed, 1 unchanged"));
+        assert!(stored_keys(&database_file, "usb_id_to_hardware_setup_id_bucket").is_empty());
+        assert_eq!(
+            stored_keys(&database_file, "hardware_kind_to_hardware_setup_id_bucket"),
+            [b"Graphics"]
+        );
+    }
+
+    #[test]
+    pub fn ids_depend_only_on_content() {
+        let directory = TestDirectory::new("stable-ids");
+        let input_file = directory.join("input_data.yaml");
+        let updated_database = directory.join("updated.db");
+        let fresh_database = directory.join("fresh.db");
+
+        fs::write(&input_file, [NVIDIA_SETUP, REALTEK_SETUP].concat()).unwrap();
+        generate_database_inner(input_file.clone(), updated_database.clone(), false).unwrap();
+        fs::write(&input_file, [REALTEK_SETUP_CHANGED, NVIDIA_SETUP].concat()).unwrap();
+        generate_database_inner(input_file.clone(), updated_database.clone(), true).unwrap();
+        generate_database_inner(input_file, fresh_database.clone(), false).unwrap();
+        for bucket_name in BUCKET_NAMES {
+            assert_eq!(
+                stored_keys(&updated_database, bucket_name),
+                stored_keys(&fresh_database, bucket_name),
+                "{}",
+                bucket_name
+            );
+        }
+
+        // Setups that only differ in their driver options get suffixed IDs
+        let colliding_input_file = directory.join("colliding.yaml");
+        let colliding_database = directory.join("colliding.db");
+        fs::write(
+            &colliding_input_file,
+            [
+                NVIDIA_SETUP,
+                &NVIDIA_SETUP.replace("[nvidia]", "[nvidia-dkms]"),
+            ]
+            .concat(),
+        )
+        .unwrap();
+        generate_database_inner(colliding_input_file, colliding_database.clone(), false).unwrap();
+        let hardware_setup_ids = stored_keys(
+            &colliding_database,
+            "hardware_setup_id_to_hardware_setup_bucket",
+        );
+        assert_eq!(hardware_setup_ids.len(), 2);
+        assert_eq!(
+            hardware_setup_ids[1],
+            [hardware_setup_ids[0].as_slice(), b"-2"].concat()
+        );
+    }
+
+    #[test]
+    pub fn rapid_saves_settle_once() {
+        let start = Instant::now();
+        let at = |milliseconds: u64| start + Duration::from_millis(milliseconds);
+        let saved = |seconds: u64| Some(SystemTime::UNIX_EPOCH + Duration::from_secs(seconds));
+        let mut debouncer = ChangeDebouncer::new(saved(1), Duration::from_millis(500));
+
+        assert!(!debouncer.settled(saved(1), at(0)));
+        assert!(!debouncer.settled(saved(2), at(100)));
+        assert!(!debouncer.settled(saved(3), at(300)));
+        assert!(!debouncer.settled(saved(3), at(700)));
+        assert!(debouncer.settled(saved(3), at(800)));
+        assert!(!debouncer.settled(saved(3), at(2000)));
+        // A deleted file is a change too, once it settles
+        assert!(!debouncer.settled(None, at(2100)));
+        assert!(debouncer.settled(None, at(2600)));
+    }
+
+    #[test]
+    pub fn directories_change_with_their_newest_file() {
+        let directory = TestDirectory::new("watch-directory");
+        let input_file = directory.join("nvidia.yaml");
+        fs::write(&input_file, NVIDIA_SETUP).unwrap();
+        let before = modified(&directory).unwrap();
+
+        let later = before + Duration::from_secs(60);
+        fs::File::options()
+            .write(true)
+            .open(&input_file)
+            .unwrap()
+            .set_modified(later)
+            .unwrap();
+        assert_eq!(modified(&directory), Some(later));
+    }
 }
@@ -0,0 +1,122 @@
+use crate::{
+    cli::{new_table, to_json, CommandlinePrint},
+    data::input_file::HardwareKind,
+    error::Error,
+};
+use comfy_table::{Attribute, Cell, Color};
+use owo_colors::{OwoColorize, Stream::Stdout};
+use serde::{Deserialize, Serialize};
+
+/// The name of a hardware kind and the other names accepted for it.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HardwareKindNames {
+    pub name: String,
+    pub aliases: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct HardwareKindsActionOutput {
+    pub inner: Vec<HardwareKindNames>,
+}
+
+impl CommandlinePrint for HardwareKindsActionOutput {
+    fn print(&self) {
+        for hardware_kind_names in self.inner.iter() {
+            println!(
+                "{} {}",
+                hardware_kind_names
+                    .name
+                    .if_supports_color(Stdout, |text| text.yellow()),
+                format!("(also {})", hardware_kind_names.aliases.join(", "))
+                    .if_supports_color(Stdout, |text| text.dimmed())
+            );
+        }
+    }
+
+    fn print_json(&self) {
+        println!("{}", to_json(&self).unwrap_or_else(|_| {
+            eprintln!("The output could not be converted to JSON. Please try another output format...");
+            String::from("")
+        }));
+    }
+
+    fn print_plain(&self) {
+        for hardware_kind_names in self.inner.iter() {
+            println!(
+                "{} {}",
+                hardware_kind_names.name,
+                hardware_kind_names.aliases.join(" ")
+            );
+        }
+    }
+
+    fn print_debug(&self) {
+        self.print();
+    }
+
+    fn print_table(&self) {
+        let mut table = new_table();
+        table.set_header(
+            ["Hardware kind", "Aliases"]
+                .into_iter()
+                .map(|header| Cell::new(header).add_attribute(Attribute::Bold)),
+        );
+        for hardware_kind_names in self.inner.iter() {
+            table.add_row([
+                Cell::new(&hardware_kind_names.name).fg(Color::Yellow),
+                Cell::new(hardware_kind_names.aliases.join(", ")),
+            ]);
+        }
+        println!("{table}");
+    }
+}
+
+pub fn hardware_kinds() -> Result<HardwareKindsActionOutput, Error> {
+    Ok(HardwareKindsActionOutput {
+        inner: HardwareKind::variants()
+            .iter()
+            .map(|hardware_kind| HardwareKindNames {
+                name: hardware_kind.name().to_string(),
+                aliases: hardware_kind
+                    .aliases()
+                    .iter()
+                    .map(|alias| alias.to_string())
+                    .collect(),
+            })
+            .collect(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    pub fn listed_names_are_accepted_everywhere() {
+        for hardware_kind_names in hardware_kinds().unwrap().inner {
+            let names = std::iter::once(&hardware_kind_names.name)
+                .chain(hardware_kind_names.aliases.iter());
+            for name in names {
+                for written in [name.clone(), name.to_uppercase()] {
+                    let parsed: HardwareKind = written.parse().unwrap();
+                    assert_eq!(parsed.name(), hardware_kind_names.name);
+                    assert_eq!(
+                        serde_yaml::from_str::<HardwareKind>(&written).unwrap(),
+                        parsed
+                    );
+                    assert_eq!(
+                        serde_json::from_value::<HardwareKind>(serde_json::json!(written)).unwrap(),
+                        parsed
+                    );
+                }
+            }
+        }
+        // Stored values keep their capitalized names, which still parse
+        assert_eq!(
+            serde_json::to_value(HardwareKind::Audio).unwrap(),
+            serde_json::json!("Audio")
+        );
+        assert!("speakers".parse::<HardwareKind>().is_err());
+    }
+}
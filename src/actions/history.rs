@@ -0,0 +1,143 @@
+use crate::{
+    actions::list::format_timestamp,
+    cli::{new_table, to_json, CommandlinePrint, HistoryActionArguments},
+    data::{
+        input_file::HardwareKind,
+        install_log::{read_install_log, InstallLogEntry},
+    },
+    error::Error,
+    paths,
+};
+use comfy_table::{Attribute, Cell, Color};
+use owo_colors::{OwoColorize, Stream::Stdout};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+/// The recorded install transactions, oldest first.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct HistoryActionOutput {
+    pub inner: Vec<InstallLogEntry>,
+}
+
+impl CommandlinePrint for HistoryActionOutput {
+    fn print(&self) {
+        for entry in self.inner.iter() {
+            println!(
+                "{} {}: {}",
+                format_timestamp(entry.timestamp).if_supports_color(Stdout, |text| text.bold()),
+                entry.hardware,
+                entry.driver_option
+            );
+            if !entry.packages_installed.is_empty() {
+                println!(
+                    "\t{} {}",
+                    "Installed:".if_supports_color(Stdout, |text| text.green()),
+                    entry.packages_installed.join(", ")
+                );
+            }
+            if !entry.packages_removed.is_empty() {
+                println!(
+                    "\t{} {}",
+                    "Removed:".if_supports_color(Stdout, |text| text.yellow()),
+                    entry.packages_removed.join(", ")
+                );
+            }
+            if let Some(error) = &entry.error {
+                println!(
+                    "\t{} {}",
+                    "Failed:".if_supports_color(Stdout, |text| text.red()),
+                    error
+                );
+            }
+        }
+    }
+
+    fn print_json(&self) {
+        println!("{}", to_json(&self).unwrap_or_else(|_| {
+            eprintln!("The output could not be converted to JSON. Please try another output format...");
+            String::from("")
+        }));
+    }
+
+    fn print_plain(&self) {
+        for entry in self.inner.iter() {
+            println!(
+                "{} {} {} {}",
+                entry.timestamp,
+                entry.hardware.name(),
+                if entry.error.is_some() {
+                    "failed"
+                } else {
+                    "installed"
+                },
+                entry.driver_option
+            );
+        }
+    }
+
+    fn print_debug(&self) {
+        self.print();
+    }
+
+    fn print_table(&self) {
+        let mut table = new_table();
+        table.set_header(
+            [
+                "Time",
+                "Hardware",
+                "Driver option",
+                "Installed",
+                "Removed",
+                "Result",
+            ]
+            .into_iter()
+            .map(|header| Cell::new(header).add_attribute(Attribute::Bold)),
+        );
+        for entry in self.inner.iter() {
+            table.add_row([
+                Cell::new(format_timestamp(entry.timestamp)),
+                Cell::new(entry.hardware),
+                Cell::new(&entry.driver_option).fg(Color::Yellow),
+                Cell::new(entry.packages_installed.join("\n")),
+                Cell::new(entry.packages_removed.join("\n")),
+                match &entry.error {
+                    None => Cell::new("Succeeded").fg(Color::Green),
+                    Some(error) => Cell::new(error).fg(Color::Red),
+                },
+            ]);
+        }
+        println!("{table}");
+    }
+
+    fn is_empty_result(&self) -> bool {
+        self.inner.is_empty()
+    }
+}
+
+/// The entries of the install log, optionally only the ones for some hardware, and
+/// only the last `limit` of them.
+pub fn history_inner(
+    install_log_path: &Path,
+    optional_hardware: &Option<HardwareKind>,
+    limit: Option<usize>,
+) -> Result<HistoryActionOutput, Error> {
+    let mut entries: Vec<InstallLogEntry> = read_install_log(install_log_path)?
+        .into_iter()
+        .filter(|entry| optional_hardware.is_none_or(|hardware| entry.hardware == hardware))
+        .collect();
+    if let Some(limit) = limit {
+        entries.drain(..entries.len().saturating_sub(limit));
+    }
+    Ok(HistoryActionOutput { inner: entries })
+}
+
+pub fn history(
+    history_action_arguments: HistoryActionArguments,
+) -> Result<HistoryActionOutput, Error> {
+    history_inner(
+        Path::new(paths::INSTALL_LOG_PATH),
+        &history_action_arguments.hardware,
+        history_action_arguments.limit,
+    )
+}
@@ -1,80 +1,2086 @@
 use crate::{
-    actions::list::list_inner,
-    actions::search::search_inner,
-    arch::PackageManager,
-    cli::{CommandlinePrint, InstallActionArguments},
-    data::input_file::{DriverOption, HardwareKind},
-    error::Error,
+    actions::{
+        list_tags::check_tags_exist,
+        search::{search_explained_inner, search_inner, specificities, PackageNamesOutput},
+    },
+    arch::{package_backend, PackageBackend},
+    cli::{to_json, CommandlineFlags, CommandlinePrint, GlobalArguments, InstallActionArguments},
+    data::{
+        config::Config,
+        database::{DriverDatabase, EntryDecoder},
+        input_file::{
+            ranked_by_preference, DriverOption, HardwareKind, HardwareSetup, License, PostAction,
+        },
+        install_log::{append_install_log, InstallLogEntry},
+        install_plan::InstallPlan,
+        manifest::Manifest,
+        remote_index::database_with_remote_index,
+    },
+    detection::{hardware_detector, HardwareDetector},
+    error::{
+        DatabaseSnafu, DriverOptionEditSnafu, EditedDriverOptionParseSnafu, EditorFailedSnafu,
+        Error, InstallCancelledSnafu, InstallPlanInvalidSnafu, NoDriverOptionSnafu,
+        OfflineRequiredSnafu, PackageNotFoundSnafu, RankOutOfRangeSnafu,
+    },
+    hooks::{run_hooks, run_verify_command, HookEnvironment, HookOutput, HookPhase, Verification},
+    module_signing::{sign_modules_changed_since, SigningKey, MODULES_DIRECTORY},
+    paths, privileges,
+    services::{change_services, systemd_is_running, ServiceAction, ServiceFailure, SYSTEMCTL},
+};
+use owo_colors::{
+    AnsiColors, OwoColorize,
+    Stream::{Stderr, Stdout},
 };
 use serde::{Deserialize, Serialize};
-use std::collections::BTreeSet;
-use std::path::PathBuf;
+use snafu::{OptionExt, ResultExt};
+use std::{
+    cmp::Ordering,
+    collections::{BTreeMap, BTreeSet},
+    fs,
+    path::{Path, PathBuf},
+    process::Command,
+    time::{Duration, SystemTime},
+};
 
 #[derive(Default, Debug, Clone, Serialize, Deserialize)]
-pub struct InstallActionOutput {}
+pub struct InstallActionOutput {
+    /// The kernel modules signed for Secure Boot after the install.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub signed_modules: Vec<PathBuf>,
+
+    /// What the user has to do for the installed driver to take effect.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub post_install_action: Option<PostAction>,
+
+    /// The driver options considered for the install, when asked to explain the selection.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub selection: Vec<SelectionCandidate>,
+
+    /// The post-install hooks that failed, when asked to continue past them.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub hook_failures: Vec<String>,
+
+    /// The outcome of the verification command of the installed driver option.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub verification: Option<Verification>,
+
+    /// The packages installed, and the repositories they came from.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub package_sources: Vec<PackageSource>,
+
+    /// The selected driver option was already installed at the available versions,
+    /// so nothing was done.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub up_to_date: bool,
+
+    /// The outcome of each entry of an install plan, in the order of the plan.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub planned_installs: Vec<PlannedInstall>,
+
+    /// The services of driver options that could not be enabled or disabled.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub service_failures: Vec<ServiceFailure>,
+
+    /// The packages that the install changed, grouped by what happened to them.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub transaction: Option<TransactionSummary>,
+}
+
+/// The packages that an install changes, grouped like in the summary shown before asking
+/// whether to go ahead.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TransactionSummary {
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub install: Vec<PackageChange>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub reinstall: Vec<PackageChange>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub remove: Vec<PackageChange>,
+}
+
+/// A package in a transaction summary, at the version it is installed or removed at.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PackageChange {
+    pub package: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub version: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub repository: Option<String>,
+    /// The size of the package once installed, in bytes.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub installed_size: Option<i64>,
+}
+
+impl TransactionSummary {
+    /// How much the installed size changes, in bytes, counting only the known sizes.
+    pub fn net_size(&self) -> i64 {
+        let total = |package_changes: &[PackageChange]| -> i64 {
+            package_changes
+                .iter()
+                .filter_map(|package_change| package_change.installed_size)
+                .sum()
+        };
+        total(&self.install) - total(&self.remove)
+    }
+}
+
+/// A package to install, and the repository it comes from.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PackageSource {
+    pub package: String,
+    pub repository: String,
+}
+
+/// A driver option considered by `install`, and whether it was selected.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SelectionCandidate {
+    pub driver_option: String,
+    pub order_of_priority: u32,
+    pub tags: BTreeSet<String>,
+    pub outcome: SelectionOutcome,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum SelectionOutcome {
+    Selected,
+    /// A driver option ranked higher was selected.
+    Outranked,
+    /// The environment variables that do not have the required values.
+    UnmetEnvironment(BTreeMap<String, String>),
+    /// Another rank was asked for with `--rank`.
+    OtherRankRequested,
+}
+
+/// An entry of an install plan, and whether installing it failed.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct PlannedInstall {
+    pub hardware: HardwareKind,
+    pub driver_option: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub post_install_action: Option<PostAction>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub verification: Option<Verification>,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub up_to_date: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl InstallActionOutput {
+    /// The post-install actions of everything installed, without repeats.
+    fn post_install_actions(&self) -> BTreeSet<PostAction> {
+        self.post_install_action
+            .into_iter()
+            .chain(
+                self.planned_installs
+                    .iter()
+                    .filter_map(|planned_install| planned_install.post_install_action),
+            )
+            .collect()
+    }
+
+    /// The outcomes of the verification commands of everything installed.
+    fn verifications(&self) -> impl Iterator<Item = &Verification> {
+        self.verification.iter().chain(
+            self.planned_installs
+                .iter()
+                .filter_map(|planned_install| planned_install.verification.as_ref()),
+        )
+    }
+
+    /// The packages installed, for `--packages`.
+    pub fn package_names(&self) -> PackageNamesOutput {
+        PackageNamesOutput {
+            inner: self
+                .package_sources
+                .iter()
+                .map(|package_source| package_source.package.clone())
+                .collect(),
+        }
+    }
+}
 
 impl CommandlinePrint for InstallActionOutput {
-    fn print(&self) {}
+    fn print(&self) {
+        if self.up_to_date {
+            println!(
+                "{} The selected driver option is already installed",
+                "Up to date:".if_supports_color(Stdout, |text| text.green()),
+            );
+        }
+        for planned_install in self.planned_installs.iter() {
+            match &planned_install.error {
+                None if planned_install.up_to_date => println!(
+                    "{} {}: {}",
+                    "Up to date:".if_supports_color(Stdout, |text| text.green()),
+                    planned_install.hardware,
+                    planned_install.driver_option
+                ),
+                None => println!(
+                    "{} {}: {}",
+                    "Installed:".if_supports_color(Stdout, |text| text.green()),
+                    planned_install.hardware,
+                    planned_install.driver_option
+                ),
+                Some(error) => println!(
+                    "{} {}: {}. {}",
+                    "Failed:".if_supports_color(Stdout, |text| text.red()),
+                    planned_install.hardware,
+                    planned_install.driver_option,
+                    error
+                ),
+            }
+        }
+        for package_source in self.package_sources.iter() {
+            println!(
+                "{} {} from {}",
+                "Package:".if_supports_color(Stdout, |text| text.green()),
+                package_source.package,
+                package_source.repository
+            );
+        }
+        for signed_module in self.signed_modules.iter() {
+            println!(
+                "{} {}",
+                "Signed kernel module:".if_supports_color(Stdout, |text| text.green()),
+                signed_module.to_string_lossy()
+            );
+        }
+        for service_failure in self.service_failures.iter() {
+            println!(
+                "{} Could not {} {}: {}",
+                "Service failed:".if_supports_color(Stdout, |text| text.red()),
+                service_failure.action,
+                service_failure.service,
+                service_failure.status
+            );
+        }
+        for verification in self.verifications() {
+            if verification.passed {
+                println!(
+                    "{} {}",
+                    "Verified:".if_supports_color(Stdout, |text| text.green()),
+                    verification.command
+                );
+            } else {
+                println!(
+                    "{} {} {}",
+                    "Verification failed:".if_supports_color(Stdout, |text| text.red()),
+                    verification.command,
+                    verification.status
+                );
+                for line in verification.output.iter() {
+                    println!("    {}", line);
+                }
+            }
+        }
+        for hint in self
+            .post_install_actions()
+            .iter()
+            .filter_map(|action| action.hint())
+        {
+            println!(
+                "{} {}",
+                "NOTE:".if_supports_color(Stdout, |text| text.yellow()),
+                hint.if_supports_color(Stdout, |text| text.bold())
+            );
+        }
+    }
 
-    fn print_json(&self) {}
+    fn print_json(&self) {
+        println!("{}", to_json(&self).unwrap_or_else(|_| {
+            eprintln!("The output could not be converted to JSON. Please try another output format...");
+            String::from("")
+        }));
+    }
 
-    fn print_plain(&self) {}
+    fn print_plain(&self) {
+        for planned_install in self.planned_installs.iter() {
+            println!(
+                "{} {} {}",
+                planned_install.hardware.name(),
+                if planned_install.error.is_some() {
+                    "failed"
+                } else if planned_install.up_to_date {
+                    "up-to-date"
+                } else {
+                    "installed"
+                },
+                planned_install.driver_option
+            );
+        }
+        for signed_module in self.signed_modules.iter() {
+            println!("{}", signed_module.to_string_lossy());
+        }
+        for service_failure in self.service_failures.iter() {
+            println!(
+                "service-failed {} {}",
+                service_failure.action, service_failure.service
+            );
+        }
+        for verification in self.verifications() {
+            println!(
+                "{} {}",
+                if verification.passed {
+                    "verified"
+                } else {
+                    "unverified"
+                },
+                verification.command
+            );
+        }
+    }
 
     fn print_debug(&self) {}
 }
 
+/// How the packages of the selected driver option are installed.
+#[derive(Clone, Copy, Default, Debug)]
+pub struct InstallOptions {
+    pub enable_aur: bool,
+    pub reinstall: bool,
+    pub parallel_downloads: Option<u32>,
+    /// Install driver options that carry a warning without asking first.
+    pub no_confirm: bool,
+    pub hook_output: HookOutput,
+    /// Append the transaction to the install log.
+    pub record_history: bool,
+    /// Show the ranked driver options and why one was selected before installing.
+    pub explain_selection: bool,
+    /// Only warn about failing post-install hooks instead of failing the install.
+    pub continue_on_script_error: bool,
+    /// Install the optional packages of the selected driver option too.
+    pub with_optional: bool,
+    /// Only consider driver options with this license.
+    pub license_filter: Option<License>,
+    /// Let the user edit the selected driver option before installing it.
+    pub edit: bool,
+    /// How long each hook and verify command may run before it is killed. Without one,
+    /// they may run for as long as they take.
+    pub script_timeout: Option<Duration>,
+    /// Select the driver option at this rank, counting from 1, instead of the highest ranked one.
+    pub rank: Option<usize>,
+    /// Skip the hooks, the verify command and the scripts of the driver option, and only
+    /// install and remove packages.
+    pub no_scripts: bool,
+    /// Show the packages that the install changes, and ask before going ahead.
+    pub confirm_transaction: bool,
+    /// Enable and start the services of the selected driver option after installing it,
+    /// and disable the ones of the driver options it replaces.
+    pub enable_services: bool,
+    /// Run the program again through sudo once there is something to change, unless it
+    /// has superuser privileges already.
+    pub escalate_privileges: bool,
+}
+
+/// Whether an answer to a yes/no question, which defaults to no, is a yes.
+fn is_confirmation(answer: &str) -> bool {
+    matches!(answer.trim().to_lowercase().as_str(), "y" | "yes")
+}
+
+/// Asks a yes/no question, which defaults to no.
+fn confirm(question: &str) -> bool {
+    eprint!("{} [y/N] ", question);
+    let mut answer = String::new();
+    std::io::stdin().read_line(&mut answer).is_ok() && is_confirmation(&answer)
+}
+
+fn print_warning(driver_option: &DriverOption, warning: &str) {
+    eprintln!(
+        "{} {}: {}",
+        "WARNING:".if_supports_color(Stderr, |text| text.red()),
+        driver_option
+            .name
+            .if_supports_color(Stderr, |text| text.bold()),
+        warning.if_supports_color(Stderr, |text| text.bold())
+    );
+}
+
+/// A size in bytes in binary units, the way pacman shows them, like `1.50 MiB`.
+fn format_size(bytes: i64) -> String {
+    let units = ["B", "KiB", "MiB", "GiB", "TiB"];
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size.abs() >= 1024.0 && unit < units.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    format!("{:.2} {}", size, units[unit])
+}
+
+fn print_transaction_summary(transaction: &TransactionSummary) {
+    for (heading, package_changes, color) in [
+        (
+            "Packages to install",
+            &transaction.install,
+            AnsiColors::Green,
+        ),
+        (
+            "Packages to reinstall",
+            &transaction.reinstall,
+            AnsiColors::Yellow,
+        ),
+        ("Packages to remove", &transaction.remove, AnsiColors::Red),
+    ] {
+        if package_changes.is_empty() {
+            continue;
+        }
+        eprintln!(
+            "{} ({})",
+            heading.if_supports_color(Stderr, |text| text.bold()),
+            package_changes.len()
+        );
+        for package_change in package_changes.iter() {
+            // Padded before coloring, since the color codes would count towards the width
+            let package = format!(
+                "{:<40}",
+                format!(
+                    "{} {}",
+                    package_change.package,
+                    package_change.version.as_deref().unwrap_or_default()
+                )
+            );
+            eprintln!(
+                "    {} {:<16} {:>12}",
+                package.if_supports_color(Stderr, |text| text.color(color)),
+                package_change.repository.as_deref().unwrap_or_default(),
+                package_change
+                    .installed_size
+                    .map(format_size)
+                    .unwrap_or_default()
+            );
+        }
+    }
+    eprintln!(
+        "{} {}",
+        "Net installed size:".if_supports_color(Stderr, |text| text.bold()),
+        format_size(transaction.net_size())
+    );
+}
+
 pub fn install_inner<T: Iterator<Item = String>>(
     database_filepath: PathBuf,
     hardware: HardwareKind,
     tags: T,
-    _enable_aur: bool,
+    preferred_tags: &BTreeSet<String>,
+    install_options: InstallOptions,
+    hardware_detector: &dyn HardwareDetector,
+    package_backend: &mut dyn PackageBackend,
 ) -> Result<InstallActionOutput, Error> {
-    let relevant_driver_records = search_inner(database_filepath.clone(), &Some(hardware), tags)?
-        .into_values()
-        .collect::<Vec<BTreeSet<DriverOption>>>()
-        .pop()
-        .unwrap();
+    let mut explained_driver_options = search_explained_inner(
+        database_filepath.clone(),
+        &Some(hardware),
+        tags,
+        hardware_detector,
+        &EntryDecoder::new(),
+        install_options.license_filter,
+    )?;
+    let specificities = specificities(&explained_driver_options)
+        .remove(&hardware)
+        .unwrap_or_default();
+    let relevant_driver_records: BTreeSet<DriverOption> = explained_driver_options
+        .remove(&hardware)
+        .unwrap_or_default()
+        .into_keys()
+        .collect();
 
-    let packages_to_install = relevant_driver_records
+    let ranked_driver_options =
+        ranked_by_preference(&relevant_driver_records, preferred_tags, &specificities);
+    let mut selection = rank_driver_options(ranked_driver_options.iter().copied(), |variable| {
+        std::env::var(variable).ok()
+    });
+    if let Some(rank) = install_options.rank {
+        select_rank(&mut selection, hardware, rank)?;
+    }
+    if install_options.explain_selection {
+        print_selection(hardware, &selection);
+    } else {
+        for candidate in selection.iter() {
+            match &candidate.outcome {
+                SelectionOutcome::UnmetEnvironment(unmet_requirements) => eprintln!(
+                    "{} Skipping the driver option {} because the environment does not match {:?}",
+                    "WARNING:".if_supports_color(Stderr, |text| text.yellow()),
+                    candidate.driver_option,
+                    unmet_requirements
+                ),
+                SelectionOutcome::Selected => break,
+                SelectionOutcome::Outranked | SelectionOutcome::OtherRankRequested => {}
+            }
+        }
+    }
+    let selected_driver_option = ranked_driver_options
         .iter()
-        .next()
-        .expect("Error: Nothing to install")
-        .packages
-        .clone();
-    let packages_to_remove = list_inner(database_filepath.clone(), &Some(hardware), None).map_or(
-        Vec::<String>::new(),
-        |installed_hash_map| {
-            installed_hash_map.into_iter().fold(
-                Vec::<String>::new(),
-                |mut acc, (_hardware_kind, hash_set)| {
-                    acc.append(hash_set.into_iter().fold(
-                        &mut Vec::<String>::new(),
-                        |acc, installed_package| {
-                            if !packages_to_install.contains(&installed_package.name) {
-                                acc.push(installed_package.name);
-                            }
-                            acc
-                        },
-                    ));
-                    acc
-                },
-            )
+        .zip(selection.iter())
+        .find(|(_, candidate)| candidate.outcome == SelectionOutcome::Selected)
+        .map(|(driver_option, _)| *driver_option)
+        .context(NoDriverOptionSnafu { hardware })?;
+    let edited_driver_option;
+    let selected_driver_option = if install_options.edit {
+        edited_driver_option = edit_driver_option(selected_driver_option, &editor())?;
+        &edited_driver_option
+    } else {
+        selected_driver_option
+    };
+
+    let install_action_output = install_driver_option(
+        database_filepath,
+        hardware,
+        selected_driver_option,
+        install_options,
+        package_backend,
+    )?;
+    Ok(InstallActionOutput {
+        selection: if install_options.explain_selection {
+            selection
+        } else {
+            Vec::new()
         },
+        ..install_action_output
+    })
+}
+
+/// The editor command for `--edit`, from `$VISUAL` or `$EDITOR`, falling back to `vi`.
+fn editor() -> String {
+    ["VISUAL", "EDITOR"]
+        .iter()
+        .find_map(|variable| {
+            std::env::var(variable)
+                .ok()
+                .filter(|value| !value.is_empty())
+        })
+        .unwrap_or_else(|| "vi".to_string())
+}
+
+/// Opens a driver option as YAML in an editor command, which can have arguments like
+/// `code --wait`, and reads back the edited driver option.
+fn edit_driver_option(driver_option: &DriverOption, editor: &str) -> Result<DriverOption, Error> {
+    let directory = paths::temp_directory();
+    let path = directory.join(format!("driver-option-{}.yaml", std::process::id()));
+    let contents = serde_yaml::to_string(driver_option).unwrap_or_default();
+    paths::create_private_directory(&directory)
+        .and_then(|_| fs::write(&path, contents))
+        .context(DriverOptionEditSnafu { path: &path })?;
+    let status = Command::new("sh")
+        .arg("-c")
+        .arg(format!("{} \"$1\"", editor))
+        .arg("sh")
+        .arg(&path)
+        .status();
+    let edited = fs::read_to_string(&path);
+    _ = fs::remove_file(&path);
+    let status = status.context(DriverOptionEditSnafu { path: &path })?;
+    if !status.success() {
+        return EditorFailedSnafu {
+            status: status.to_string(),
+        }
+        .fail();
+    }
+    serde_yaml::from_str(&edited.context(DriverOptionEditSnafu { path: &path })?)
+        .context(EditedDriverOptionParseSnafu)
+}
+
+/// Ranks driver options in the order given, usually priority order, and selects the first
+/// one whose environment requirements are met, given a way to look up variables.
+pub(crate) fn rank_driver_options<'a, I, F>(driver_options: I, lookup: F) -> Vec<SelectionCandidate>
+where
+    I: IntoIterator<Item = &'a DriverOption>,
+    F: Fn(&str) -> Option<String>,
+{
+    let mut selected = false;
+    driver_options
+        .into_iter()
+        .map(|driver_option| {
+            let unmet_requirements = driver_option.unmet_environment_requirements(&lookup);
+            let outcome = if !unmet_requirements.is_empty() {
+                SelectionOutcome::UnmetEnvironment(
+                    unmet_requirements
+                        .into_iter()
+                        .map(|(variable, value)| (variable.clone(), value.clone()))
+                        .collect(),
+                )
+            } else if selected {
+                SelectionOutcome::Outranked
+            } else {
+                selected = true;
+                SelectionOutcome::Selected
+            };
+            SelectionCandidate {
+                driver_option: driver_option.name.clone(),
+                order_of_priority: driver_option.order_of_priority,
+                tags: driver_option.tags.clone(),
+                outcome,
+            }
+        })
+        .collect()
+}
+
+/// Selects the driver option at a rank instead of the highest ranked one. Ranks count from 1
+/// and only over the driver options whose environment requirements are met, so that rank 1
+/// is the driver option that would be selected anyway.
+fn select_rank(
+    selection: &mut [SelectionCandidate],
+    hardware: HardwareKind,
+    rank: usize,
+) -> Result<(), Error> {
+    let mut candidates: Vec<&mut SelectionCandidate> = selection
+        .iter_mut()
+        .filter(|candidate| !matches!(candidate.outcome, SelectionOutcome::UnmetEnvironment(_)))
+        .collect();
+    let available = candidates.len();
+    if rank == 0 || rank > available {
+        return RankOutOfRangeSnafu {
+            hardware,
+            rank,
+            available,
+        }
+        .fail();
+    }
+    for (index, candidate) in candidates.iter_mut().enumerate() {
+        candidate.outcome = match (index + 1).cmp(&rank) {
+            Ordering::Less => SelectionOutcome::OtherRankRequested,
+            Ordering::Equal => SelectionOutcome::Selected,
+            Ordering::Greater => SelectionOutcome::Outranked,
+        };
+    }
+    Ok(())
+}
+
+/// Shows the ranked driver options, and why each one was or was not selected.
+fn print_selection(hardware: HardwareKind, selection: &[SelectionCandidate]) {
+    eprintln!(
+        "{}",
+        format!("Driver options for {}, from the highest ranked:", hardware)
+            .if_supports_color(Stderr, |text| text.bold())
+    );
+    for (rank, candidate) in selection.iter().enumerate() {
+        let outcome = match &candidate.outcome {
+            SelectionOutcome::Selected => "selected"
+                .if_supports_color(Stderr, |text| text.green())
+                .to_string(),
+            SelectionOutcome::Outranked => "outranked"
+                .if_supports_color(Stderr, |text| text.yellow())
+                .to_string(),
+            SelectionOutcome::OtherRankRequested => "passed over for the requested rank"
+                .if_supports_color(Stderr, |text| text.yellow())
+                .to_string(),
+            SelectionOutcome::UnmetEnvironment(unmet_requirements) => format!(
+                "skipped, the environment does not match {:?}",
+                unmet_requirements
+            )
+            .if_supports_color(Stderr, |text| text.red())
+            .to_string(),
+        };
+        eprintln!(
+            "\t{}. {} (priority {}, tags: {}): {}",
+            rank + 1,
+            candidate.driver_option,
+            candidate.order_of_priority,
+            candidate
+                .tags
+                .iter()
+                .map(String::as_str)
+                .collect::<Vec<&str>>()
+                .join(", "),
+            outcome
+        );
+    }
+}
+
+/// Every hardware setup in the database.
+pub fn hardware_setups(
+    database_filepath: PathBuf,
+    entry_decoder: &EntryDecoder,
+) -> Result<Vec<HardwareSetup>, Error> {
+    let driver_database = DriverDatabase::cloned_from_database_path(database_filepath)?;
+    let transaction = driver_database.tx(false).context(DatabaseSnafu)?;
+    let hardware_setup_id_to_hardware_setup_bucket = transaction
+        .get_bucket("hardware_setup_id_to_hardware_setup_bucket")
+        .context(DatabaseSnafu)?;
+    Ok(hardware_setup_id_to_hardware_setup_bucket
+        .kv_pairs()
+        .filter_map(|data| {
+            entry_decoder.decode(
+                "hardware_setup_id_to_hardware_setup_bucket",
+                data.key(),
+                data.value(),
+            )
+        })
+        .collect())
+}
+
+/// The services of the installed driver options that the selected one replaces, other
+/// than the ones the selected option has too.
+fn services_to_disable(
+    hardware_setups: &[HardwareSetup],
+    hardware: HardwareKind,
+    selected_driver_option: &DriverOption,
+    package_backend: &dyn PackageBackend,
+) -> Vec<String> {
+    let is_installed = |package: &String| package_backend.get(package).is_some();
+    hardware_setups
+        .iter()
+        .filter(|hardware_setup| {
+            hardware_setup.hardware_kind == hardware
+                && hardware_setup
+                    .driver_options
+                    .iter()
+                    .any(|driver_option| driver_option.name == selected_driver_option.name)
+        })
+        .flat_map(|hardware_setup| hardware_setup.driver_options.iter())
+        .filter(|driver_option| {
+            driver_option.name != selected_driver_option.name
+                && !driver_option.packages.is_empty()
+                && driver_option.packages.iter().all(is_installed)
+        })
+        .flat_map(|driver_option| driver_option.services.iter())
+        .filter(|service| !selected_driver_option.services.contains(service))
+        .cloned()
+        .collect::<BTreeSet<String>>()
+        .into_iter()
+        .collect()
+}
+
+/// The installed packages of the driver options that the selected one replaces, which are
+/// the other options of the hardware setups it belongs to. Packages that are still needed
+/// are left out: the ones of the selected option, the ones of driver options that stay
+/// installed, and the ones that other installed packages depend on.
+fn packages_to_remove(
+    hardware_setups: &[HardwareSetup],
+    hardware: HardwareKind,
+    selected_driver_option: &DriverOption,
+    package_backend: &dyn PackageBackend,
+) -> Vec<String> {
+    // The same driver option can be listed by several hardware setups
+    let replaced_driver_options: BTreeSet<&str> = hardware_setups
+        .iter()
+        .filter(|hardware_setup| {
+            hardware_setup.hardware_kind == hardware
+                && hardware_setup
+                    .driver_options
+                    .iter()
+                    .any(|driver_option| driver_option.name == selected_driver_option.name)
+        })
+        .flat_map(|hardware_setup| {
+            hardware_setup
+                .driver_options
+                .iter()
+                .map(|driver_option| driver_option.name.as_str())
+        })
+        .collect();
+    let is_installed = |package: &String| package_backend.get(package).is_some();
+
+    // Optional packages go along with their driver option, but whether a driver option is
+    // installed only depends on its required packages
+    let mut kept: BTreeSet<&String> = selected_driver_option
+        .packages
+        .iter()
+        .chain(selected_driver_option.optional_packages.iter())
+        .collect();
+    let mut candidates = BTreeSet::<&String>::new();
+    for hardware_setup in hardware_setups.iter() {
+        for driver_option in hardware_setup.driver_options.iter() {
+            let all_packages = driver_option
+                .packages
+                .iter()
+                .chain(driver_option.optional_packages.iter());
+            if hardware_setup.hardware_kind == hardware
+                && replaced_driver_options.contains(driver_option.name.as_str())
+            {
+                candidates.extend(all_packages.filter(|package| is_installed(package)));
+            } else if !driver_option.packages.is_empty()
+                && driver_option.packages.iter().all(is_installed)
+            {
+                kept.extend(all_packages);
+            }
+        }
+    }
+    removable_packages(
+        candidates.difference(&kept).copied().collect(),
+        package_backend,
+    )
+    .into_iter()
+    .cloned()
+    .collect()
+}
+
+/// The repository that each package would be installed from. Fails with `PackageNotFound`
+/// for a package that no sync repository has, since packages are not installed from the
+/// AUR yet.
+fn package_sources(
+    packages: &[String],
+    package_backend: &dyn PackageBackend,
+) -> Result<Vec<PackageSource>, Error> {
+    packages
+        .iter()
+        .map(|package| {
+            let package_info = package_backend
+                .search(package)
+                .context(PackageNotFoundSnafu { name: package })?;
+            Ok(PackageSource {
+                package: package.clone(),
+                repository: package_info.repository.unwrap_or_default(),
+            })
+        })
+        .collect()
+}
+
+/// What installing and removing the packages changes. Packages installed at the available
+/// version are reinstalled, or left alone unless asked to reinstall them.
+fn transaction_summary(
+    packages_to_install: &[String],
+    packages_to_remove: &[String],
+    reinstall: bool,
+    package_backend: &dyn PackageBackend,
+) -> TransactionSummary {
+    let mut transaction = TransactionSummary::default();
+    for package in packages_to_install.iter() {
+        let available = package_backend.search(package);
+        let up_to_date = available.as_ref().is_some_and(|available| {
+            package_backend
+                .get(package)
+                .is_some_and(|installed| installed.version == available.version)
+        });
+        if up_to_date && !reinstall {
+            continue;
+        }
+        let package_change = PackageChange {
+            package: package.clone(),
+            version: available
+                .as_ref()
+                .map(|available| available.version.clone()),
+            repository: available
+                .as_ref()
+                .and_then(|available| available.repository.clone()),
+            installed_size: available.and_then(|available| available.installed_size),
+        };
+        if up_to_date {
+            transaction.reinstall.push(package_change);
+        } else {
+            transaction.install.push(package_change);
+        }
+    }
+    transaction.remove = packages_to_remove
+        .iter()
+        .map(|package| {
+            let installed = package_backend.get(package);
+            PackageChange {
+                package: package.clone(),
+                version: installed
+                    .as_ref()
+                    .map(|installed| installed.version.clone()),
+                repository: None,
+                installed_size: installed.and_then(|installed| installed.installed_size),
+            }
+        })
+        .collect();
+    transaction
+}
+
+/// Whether a repository has packages that are not considered stable yet, like
+/// `extra-testing` or `core-staging`.
+fn is_unstable_repository(repository: &str) -> bool {
+    ["testing", "staging", "unstable"]
+        .iter()
+        .any(|suffix| repository == *suffix || repository.ends_with(&format!("-{}", suffix)))
+}
+
+/// Whether the packages are all installed at the versions available from the repositories.
+fn is_up_to_date(packages: &[String], package_backend: &dyn PackageBackend) -> bool {
+    packages.iter().all(|package_name| {
+        match (
+            package_backend.get(package_name),
+            package_backend.search(package_name),
+        ) {
+            (Some(installed), Some(available)) => installed.version == available.version,
+            // Packages from the AUR are not in the repositories
+            (Some(_), None) => true,
+            (None, _) => false,
+        }
+    })
+}
+
+/// The candidates for removal that no installed package outside the candidates depends
+/// on, directly or through other candidates.
+pub fn removable_packages<'a>(
+    mut candidates: BTreeSet<&'a String>,
+    package_backend: &dyn PackageBackend,
+) -> BTreeSet<&'a String> {
+    // Keeping a package keeps its dependencies too, so repeat until nothing changes
+    loop {
+        let required: Vec<&String> = candidates
+            .iter()
+            .filter(|package| {
+                package_backend
+                    .required_by(package)
+                    .iter()
+                    .any(|dependent| !candidates.contains(dependent))
+            })
+            .copied()
+            .collect();
+        if required.is_empty() {
+            break;
+        }
+        for package in required {
+            candidates.remove(package);
+        }
+    }
+    candidates
+}
+
+/// Installs the packages of a driver option, and removes the packages of the driver
+/// options it replaces, running the install hooks around it.
+pub fn install_driver_option(
+    database_filepath: PathBuf,
+    hardware: HardwareKind,
+    selected_driver_option: &DriverOption,
+    install_options: InstallOptions,
+    package_backend: &mut dyn PackageBackend,
+) -> Result<InstallActionOutput, Error> {
+    let mut packages_to_install = selected_driver_option.packages.clone();
+    if install_options.with_optional {
+        packages_to_install.extend(selected_driver_option.optional_packages.iter().cloned());
+    }
+    // alpm refuses a transaction that adds the same package twice
+    let mut listed_packages = BTreeSet::new();
+    packages_to_install.retain(|package| listed_packages.insert(package.clone()));
+    let hardware_setups = hardware_setups(database_filepath, &EntryDecoder::new())?;
+    let packages_to_remove = packages_to_remove(
+        &hardware_setups,
+        hardware,
+        selected_driver_option,
+        package_backend,
+    );
+    let change_services_of_options = install_options.enable_services && !install_options.no_scripts;
+    let services_to_disable = if change_services_of_options {
+        services_to_disable(
+            &hardware_setups,
+            hardware,
+            selected_driver_option,
+            package_backend,
+        )
+    } else {
+        Vec::new()
+    };
+    // Nothing to do, so do not start a transaction or run the hooks again
+    if !install_options.reinstall
+        && packages_to_remove.is_empty()
+        && is_up_to_date(&packages_to_install, package_backend)
+    {
+        return Ok(InstallActionOutput {
+            up_to_date: true,
+            ..Default::default()
+        });
+    }
+    if install_options.escalate_privileges {
+        privileges::escalate_if_needed()?;
+    }
+    if !install_options.no_scripts {
+        selected_driver_option.check_interpreters(&std::env::var_os("PATH").unwrap_or_default())?;
+    }
+
+    let package_sources = package_sources(&packages_to_install, package_backend)?;
+    for package_source in package_sources.iter() {
+        if is_unstable_repository(&package_source.repository) {
+            eprintln!(
+                "{} {} will be installed from {}",
+                "WARNING:".if_supports_color(Stderr, |text| text.yellow()),
+                package_source.package,
+                package_source.repository
+            );
+        }
+    }
+
+    let transaction = transaction_summary(
+        &packages_to_install,
+        &packages_to_remove,
+        install_options.reinstall,
+        package_backend,
+    );
+    let confirmed = if install_options.confirm_transaction {
+        if let Some(warning) = &selected_driver_option.warning {
+            print_warning(selected_driver_option, warning);
+        }
+        print_transaction_summary(&transaction);
+        confirm("Proceed with the installation?")
+    } else {
+        match &selected_driver_option.warning {
+            Some(warning) if !install_options.no_confirm => {
+                print_warning(selected_driver_option, warning);
+                confirm("Install it anyway?")
+            }
+            _ => true,
+        }
+    };
+    if !confirmed {
+        InstallCancelledSnafu {
+            driver_option: &selected_driver_option.name,
+        }
+        .fail()?;
+    }
+
+    let hooks_directory = paths::hooks_directory();
+    let hook_environment = HookEnvironment {
+        hardware,
+        driver_option: &selected_driver_option.name,
+        packages_to_install: &packages_to_install,
+        packages_to_remove: &packages_to_remove,
+    };
+    if !install_options.no_scripts {
+        run_hooks(
+            &hooks_directory,
+            HookPhase::PreInstall,
+            &hook_environment,
+            install_options.hook_output,
+            install_options.script_timeout,
+            false,
+        )?;
+    }
+    // Stopped before their packages are removed
+    let mut service_failures =
+        change_services(SYSTEMCTL, ServiceAction::Disable, &services_to_disable);
+
+    if let Some(parallel_downloads) = install_options.parallel_downloads {
+        package_backend.set_parallel_downloads(parallel_downloads);
+    }
+    // Packages the user already had are left out, so that pruning never removes them
+    let packages_added: Vec<String> = packages_to_install
+        .iter()
+        .filter(|package| package_backend.get(package).is_none())
+        .cloned()
+        .collect();
+    let result = package_backend.install(
+        &packages_to_install,
+        &packages_to_remove,
+        install_options.reinstall,
     );
-    let mut package_manager = PackageManager::new();
-    package_manager.install(packages_to_install, packages_to_remove)?;
+    if install_options.record_history {
+        let install_log_path = Path::new(paths::INSTALL_LOG_PATH);
+        let entry = InstallLogEntry::now(
+            hardware,
+            &selected_driver_option.name,
+            &packages_to_install,
+            &packages_added,
+            &packages_to_remove,
+            result.as_ref().err().map(|error| error.to_string()),
+        );
+        // Failing to log is not a reason to report the install as failed
+        if let Err(error) = append_install_log(install_log_path, &entry) {
+            eprintln!(
+                "{} {}",
+                "WARNING:".if_supports_color(Stderr, |text| text.yellow()),
+                error
+            );
+        }
+        if result.is_ok() {
+            let manifest_path = Path::new(paths::MANIFEST_PATH);
+            if let Err(error) =
+                Manifest::record(manifest_path, hardware, &selected_driver_option.name)
+            {
+                eprintln!(
+                    "{} {}",
+                    "WARNING:".if_supports_color(Stderr, |text| text.yellow()),
+                    error
+                );
+            }
+        }
+    }
+    result?;
 
-    Ok(InstallActionOutput::default())
+    let (hook_failures, verification) = if install_options.no_scripts {
+        eprintln!(
+            "{} Hooks, scripts and services were skipped because of --no-scripts, so {} may need manual steps to work",
+            "NOTE:".if_supports_color(Stderr, |text| text.yellow()),
+            selected_driver_option.name
+        );
+        (Vec::new(), None)
+    } else {
+        let hook_failures = run_hooks(
+            &hooks_directory,
+            HookPhase::PostInstall,
+            &hook_environment,
+            install_options.hook_output,
+            install_options.script_timeout,
+            install_options.continue_on_script_error,
+        )?;
+        // Started before verifying, since the verify command may rely on them
+        if change_services_of_options {
+            service_failures.extend(change_services(
+                SYSTEMCTL,
+                ServiceAction::Enable,
+                &selected_driver_option.services,
+            ));
+        }
+        let verification = selected_driver_option
+            .verify_command
+            .as_deref()
+            .map(|command| {
+                run_verify_command(
+                    command,
+                    &hook_environment,
+                    install_options.hook_output,
+                    install_options.script_timeout,
+                )
+            });
+        (hook_failures, verification)
+    };
+
+    Ok(InstallActionOutput {
+        post_install_action: selected_driver_option
+            .post_install_action
+            .filter(|action| *action != PostAction::None),
+        hook_failures,
+        verification,
+        package_sources,
+        service_failures,
+        transaction: Some(transaction),
+        ..Default::default()
+    })
+}
+
+/// Looks up the driver option of every entry of an install plan among the ones that
+/// match the detected hardware. All entries are checked, and every problem is reported
+/// at once, before anything is installed.
+fn resolve_install_plan(
+    database_filepath: PathBuf,
+    plan_file: &Path,
+    install_plan: &InstallPlan,
+    hardware_detector: &dyn HardwareDetector,
+    license_filter: Option<License>,
+) -> Result<Vec<(HardwareKind, DriverOption)>, Error> {
+    let mut problems = Vec::<String>::new();
+    let mut listed_hardware = BTreeSet::<HardwareKind>::new();
+    let mut resolved = Vec::<(HardwareKind, DriverOption)>::new();
+    for entry in install_plan.entries.iter() {
+        if !listed_hardware.insert(entry.hardware) {
+            problems.push(format!("{} is listed more than once", entry.hardware));
+            continue;
+        }
+        let driver_option = search_inner(
+            database_filepath.clone(),
+            &Some(entry.hardware),
+            Vec::new().into_iter(),
+            hardware_detector,
+            license_filter,
+        )?
+        .remove(&entry.hardware)
+        .unwrap_or_default()
+        .into_iter()
+        .find(|driver_option| driver_option.name == entry.driver_option);
+        match driver_option {
+            Some(driver_option) => resolved.push((entry.hardware, driver_option)),
+            None => problems.push(format!(
+                "no driver option named {} matches the detected {} hardware",
+                entry.driver_option, entry.hardware
+            )),
+        }
+    }
+    if !problems.is_empty() {
+        return InstallPlanInvalidSnafu {
+            path: plan_file,
+            problems,
+        }
+        .fail();
+    }
+    Ok(resolved)
+}
+
+/// Installs the entries of an install plan in order, without asking for confirmation.
+/// A failed entry stops the plan, unless `keep_going` is set, in which case the failure
+/// is recorded and the rest of the plan is installed.
+pub fn install_plan_inner(
+    database_filepath: PathBuf,
+    plan_file: &Path,
+    install_options: InstallOptions,
+    keep_going: bool,
+    hardware_detector: &dyn HardwareDetector,
+    package_backend: &mut dyn PackageBackend,
+) -> Result<InstallActionOutput, Error> {
+    let install_plan = InstallPlan::load(plan_file)?;
+    let resolved = resolve_install_plan(
+        database_filepath.clone(),
+        plan_file,
+        &install_plan,
+        hardware_detector,
+        install_options.license_filter,
+    )?;
+
+    let install_options = InstallOptions {
+        no_confirm: true,
+        confirm_transaction: false,
+        ..install_options
+    };
+    let mut planned_installs = Vec::<PlannedInstall>::new();
+    let mut hook_failures = Vec::<String>::new();
+    let mut service_failures = Vec::<ServiceFailure>::new();
+    let mut package_sources = Vec::<PackageSource>::new();
+    for (hardware, driver_option) in resolved.iter() {
+        let result = install_driver_option(
+            database_filepath.clone(),
+            *hardware,
+            driver_option,
+            install_options,
+            package_backend,
+        );
+        let planned_install = PlannedInstall {
+            hardware: *hardware,
+            driver_option: driver_option.name.clone(),
+            post_install_action: None,
+            verification: None,
+            up_to_date: false,
+            error: None,
+        };
+        planned_installs.push(match result {
+            Ok(install_action_output) => {
+                hook_failures.extend(install_action_output.hook_failures);
+                package_sources.extend(install_action_output.package_sources);
+                service_failures.extend(install_action_output.service_failures);
+                PlannedInstall {
+                    post_install_action: install_action_output.post_install_action,
+                    verification: install_action_output.verification,
+                    up_to_date: install_action_output.up_to_date,
+                    ..planned_install
+                }
+            }
+            Err(error) if keep_going => PlannedInstall {
+                error: Some(error.to_string()),
+                ..planned_install
+            },
+            Err(error) => return Err(error),
+        });
+    }
+    Ok(InstallActionOutput {
+        planned_installs,
+        hook_failures,
+        package_sources,
+        service_failures,
+        ..Default::default()
+    })
 }
 
 pub fn install(
     install_action_arguments: InstallActionArguments,
+    global_arguments: &GlobalArguments,
 ) -> Result<InstallActionOutput, Error> {
-    sudo::escalate_if_needed().expect("ERROR: Could not get superuser privileges...");
-    Ok(install_inner(
+    if global_arguments.offline_flag && install_action_arguments.enable_aur {
+        OfflineRequiredSnafu {
+            operation: "Installing from the AUR",
+        }
+        .fail()?;
+    }
+    if global_arguments.offline_flag && install_action_arguments.refresh {
+        OfflineRequiredSnafu {
+            operation: "Refreshing the package databases",
+        }
+        .fail()?;
+    }
+    if global_arguments.offline_flag && !install_action_arguments.include_disabled_repos.is_empty()
+    {
+        OfflineRequiredSnafu {
+            operation: "Including disabled repositories",
+        }
+        .fail()?;
+    }
+    let hardware_detector = hardware_detector(
+        global_arguments.offline_flag,
+        global_arguments.assume_hardware.as_deref(),
+        global_arguments.detector,
+        global_arguments.vendor_filter(),
+    )?;
+    let signing_key = match (
+        install_action_arguments.sign_modules,
+        install_action_arguments.mok_key,
+        install_action_arguments.mok_certificate,
+    ) {
+        (true, Some(private_key), Some(certificate)) => {
+            let signing_key = SigningKey {
+                private_key,
+                certificate,
+            };
+            signing_key.check_exists()?;
+            Some(signing_key)
+        }
+        _ => None,
+    };
+    // Refreshing and adding repositories need superuser privileges right away, and an
+    // edit would be asked for again by the run through sudo. Otherwise the install waits
+    // until it knows there is something to change.
+    let escalate_early = install_action_arguments.refresh
+        || install_action_arguments.edit
+        || !install_action_arguments.include_disabled_repos.is_empty();
+    if escalate_early {
+        privileges::escalate_if_needed()?;
+    }
+    let catalog_database = database_with_remote_index(
         install_action_arguments.database_file,
+        global_arguments.offline_flag,
+        global_arguments.verify_checksum,
+    )?;
+    if global_arguments.strict_tags {
+        check_tags_exist(
+            catalog_database.path.clone(),
+            install_action_arguments
+                .tags
+                .iter()
+                .chain(install_action_arguments.prefer.iter()),
+        )?;
+    }
+    let install_options = InstallOptions {
+        enable_aur: install_action_arguments.enable_aur,
+        reinstall: install_action_arguments.reinstall,
+        parallel_downloads: install_action_arguments.parallel_downloads,
+        no_confirm: install_action_arguments.no_confirm,
+        hook_output: global_arguments.output_kind().into(),
+        record_history: true,
+        explain_selection: install_action_arguments.explain_selection,
+        continue_on_script_error: install_action_arguments.continue_on_script_error,
+        with_optional: install_action_arguments.with_optional,
+        license_filter: global_arguments.license_filter(),
+        edit: install_action_arguments.edit,
+        escalate_privileges: !escalate_early,
+        script_timeout: (install_action_arguments.script_timeout > 0)
+            .then(|| Duration::from_secs(install_action_arguments.script_timeout)),
+        rank: install_action_arguments.rank,
+        no_scripts: global_arguments.no_scripts,
+        confirm_transaction: !install_action_arguments.no_confirm,
+        enable_services: install_action_arguments
+            .enable_services
+            .unwrap_or_else(systemd_is_running),
+    };
+    let mut package_backend = package_backend(global_arguments.package_manager)?;
+    if install_action_arguments.refresh {
+        package_backend.refresh_databases()?;
+        eprintln!(
+            "{} The package databases were refreshed. Run a full system upgrade (`pacman -Syu`) soon, since installing packages without one can leave the system partially upgraded",
+            "WARNING:".if_supports_color(Stderr, |text| text.yellow()),
+        );
+    }
+    if !install_action_arguments.include_disabled_repos.is_empty() {
+        let config_file = paths::config_file();
+        let repositories = Config::load(&config_file)?.selected_repositories(
+            &install_action_arguments.include_disabled_repos,
+            &config_file,
+        )?;
+        package_backend.add_repositories(&repositories)?;
+    }
+    let install_started = SystemTime::now();
+    let mut install_action_output = match (
+        &install_action_arguments.from_file,
         install_action_arguments.hardware,
-        install_action_arguments.tags.into_iter(),
-        install_action_arguments.enable_aur,
-    )?)
+    ) {
+        (Some(plan_file), _) => install_plan_inner(
+            catalog_database.path.clone(),
+            plan_file,
+            install_options,
+            install_action_arguments.keep_going,
+            hardware_detector.as_ref(),
+            package_backend.as_mut(),
+        )?,
+        (None, Some(hardware)) => install_inner(
+            catalog_database.path.clone(),
+            hardware,
+            install_action_arguments.tags.into_iter(),
+            &install_action_arguments.prefer.into_iter().collect(),
+            install_options,
+            hardware_detector.as_ref(),
+            package_backend.as_mut(),
+        )?,
+        (None, None) => unreachable!("The hardware is required without --from-file"),
+    };
+    if let Some(signing_key) = signing_key {
+        install_action_output.signed_modules = sign_modules_changed_since(
+            Path::new(MODULES_DIRECTORY),
+            install_started,
+            &signing_key,
+        )?;
+    }
+    Ok(install_action_output)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::TestDirectory;
+    use crate::{
+        actions::generate_database::generate_database_inner,
+        arch::MockBackend,
+        data::database::{HardwareId, PciId, UsbId},
+        detection::MockDetector,
+    };
+    use std::fs;
+
+    const INPUT_DATA: &str = r#"
+- name: Nvidia dGPU
+  hardware_kind: graphics
+  hardware_list: !pci
+      vendor: 10de
+      devices: [1b80]
+  driver_options:
+    - name: Nvidia
+      packages: [nvidia, nvidia-utils]
+    - name: Nouveau
+      packages: [xf86-video-nouveau]
+"#;
+
+    #[test]
+    pub fn warnings_need_an_explicit_yes() {
+        assert!(is_confirmation("y\n"));
+        assert!(is_confirmation(" Yes "));
+        assert!(!is_confirmation("\n"));
+        assert!(!is_confirmation("no"));
+        assert!(!is_confirmation("yep"));
+    }
+
+    #[test]
+    pub fn selection_skips_unmet_environments() {
+        let driver_options: BTreeSet<DriverOption> = serde_yaml::from_str(
+            r#"
+- name: Nvidia Wayland
+  order: 10
+  requires-env:
+    XDG_SESSION_TYPE: wayland
+- name: Nvidia
+  order: 20
+  tags: [nvidia]
+- name: Nouveau
+  order: 30
+"#,
+        )
+        .unwrap();
+        let selection = rank_driver_options(&driver_options, |variable| {
+            (variable == "XDG_SESSION_TYPE").then(|| "x11".to_string())
+        });
+        assert_eq!(
+            selection
+                .iter()
+                .map(|candidate| (candidate.driver_option.as_str(), &candidate.outcome))
+                .collect::<Vec<(&str, &SelectionOutcome)>>(),
+            [
+                (
+                    "Nvidia Wayland",
+                    &SelectionOutcome::UnmetEnvironment(BTreeMap::from([(
+                        "XDG_SESSION_TYPE".to_string(),
+                        "wayland".to_string()
+                    )]))
+                ),
+                ("Nvidia", &SelectionOutcome::Selected),
+                ("Nouveau", &SelectionOutcome::Outranked),
+            ]
+        );
+    }
+
+    #[test]
+    pub fn preferred_tags_only_reorder_equal_priorities() {
+        let driver_options: BTreeSet<DriverOption> = serde_yaml::from_str(
+            r#"
+- name: Nvidia
+  order: 10
+- name: Nvidia Open
+  order: 20
+- name: Nvidia Wayland
+  order: 20
+  tags: [wayland]
+- name: Nouveau
+  order: 30
+  tags: [wayland]
+"#,
+        )
+        .unwrap();
+        let preferred_tags = BTreeSet::from(["wayland".to_string()]);
+        let ranked_driver_options =
+            ranked_by_preference(&driver_options, &preferred_tags, &BTreeMap::new());
+        assert_eq!(
+            ranked_driver_options
+                .iter()
+                .map(|driver_option| driver_option.name.as_str())
+                .collect::<Vec<&str>>(),
+            ["Nvidia", "Nvidia Wayland", "Nvidia Open", "Nouveau"]
+        );
+        // Nothing is left out, so a higher priority still wins over a preferred tag
+        let selection = rank_driver_options(ranked_driver_options.iter().copied(), |_| None);
+        assert_eq!(selection[0].outcome, SelectionOutcome::Selected);
+        assert_eq!(
+            ranked_by_preference(&driver_options, &BTreeSet::new(), &BTreeMap::new()),
+            driver_options.iter().collect::<Vec<&DriverOption>>()
+        );
+    }
+
+    #[test]
+    pub fn ranks_count_only_eligible_driver_options() {
+        let driver_options: BTreeSet<DriverOption> = serde_yaml::from_str(
+            r#"
+- name: Nvidia Wayland
+  order: 10
+  requires-env:
+    XDG_SESSION_TYPE: wayland
+- name: Nvidia
+  order: 20
+- name: Nouveau
+  order: 30
+- name: Vesa
+  order: 40
+"#,
+        )
+        .unwrap();
+        let mut selection = rank_driver_options(&driver_options, |_| None);
+        select_rank(&mut selection, HardwareKind::Graphics, 2).unwrap();
+        assert_eq!(
+            selection
+                .iter()
+                .skip(1)
+                .map(|candidate| (candidate.driver_option.as_str(), &candidate.outcome))
+                .collect::<Vec<(&str, &SelectionOutcome)>>(),
+            [
+                ("Nvidia", &SelectionOutcome::OtherRankRequested),
+                ("Nouveau", &SelectionOutcome::Selected),
+                ("Vesa", &SelectionOutcome::Outranked),
+            ]
+        );
+        assert!(matches!(
+            select_rank(&mut selection, HardwareKind::Graphics, 4),
+            Err(Error::RankOutOfRange { available: 3, .. })
+        ));
+    }
+
+    #[test]
+    pub fn installing_replaces_the_other_driver_options() {
+        let directory = TestDirectory::new("install");
+        let input_file = directory.join("input_data.yaml");
+        let database_file = directory.join("database.db");
+        fs::write(&input_file, INPUT_DATA).unwrap();
+        generate_database_inner(input_file, database_file.clone(), false).unwrap();
+
+        let detector = MockDetector::new([HardwareId::Pci(PciId {
+            vendor: 0x10de,
+            device: 0x1b80,
+            revision: None,
+        })]);
+        let mut package_backend = MockBackend::new([
+            ("nvidia", "550.54-1"),
+            ("nvidia-utils", "550.54-1"),
+            ("xf86-video-nouveau", "1.0.17-3"),
+        ])
+        .with_installed(["nvidia", "nvidia-utils"]);
+        install_inner(
+            database_file,
+            HardwareKind::Graphics,
+            Vec::new().into_iter(),
+            &BTreeSet::new(),
+            InstallOptions::default(),
+            &detector,
+            &mut package_backend,
+        )
+        .unwrap();
+        assert_eq!(
+            package_backend.installed.keys().collect::<Vec<&String>>(),
+            ["xf86-video-nouveau"]
+        );
+    }
+
+    #[test]
+    pub fn driver_options_of_the_most_specific_setup_are_installed() {
+        let directory = TestDirectory::new("install-specificity");
+        let input_file = directory.join("input_data.yaml");
+        let database_file = directory.join("database.db");
+        let input_data = r#"
+- name: Realtek USB Wireless
+  hardware_kind: wireless
+  hardware_list: !usb
+      vendor: 0bda
+      devices: "*"
+  driver_options:
+    - name: Realtek Generic
+      order: 10
+      packages: [rtl88xxau-aircrack-dkms-git]
+- name: Realtek RTL8812BU
+  hardware_kind: wireless
+  hardware_list: !usb
+      vendor: 0bda
+      devices: [b812]
+  driver_options:
+    - name: Realtek RTL8812BU
+      order: 50
+      packages: [rtl88x2bu-dkms-git]
+"#;
+        fs::write(&input_file, input_data).unwrap();
+        generate_database_inner(input_file, database_file.clone(), false).unwrap();
+        let detector = MockDetector::new([HardwareId::Usb(UsbId {
+            vendor: 0x0bda,
+            device: 0xb812,
+        })]);
+
+        let mut package_backend = MockBackend::new([
+            ("rtl88xxau-aircrack-dkms-git", "1"),
+            ("rtl88x2bu-dkms-git", "1"),
+        ]);
+        install_inner(
+            database_file,
+            HardwareKind::Wireless,
+            Vec::new().into_iter(),
+            &BTreeSet::new(),
+            InstallOptions::default(),
+            &detector,
+            &mut package_backend,
+        )
+        .unwrap();
+        assert_eq!(
+            package_backend.installed.keys().collect::<Vec<&String>>(),
+            ["rtl88x2bu-dkms-git"]
+        );
+    }
+
+    #[test]
+    pub fn no_scripts_skips_the_verify_command() {
+        let directory = TestDirectory::new("no-scripts");
+        let input_file = directory.join("input_data.yaml");
+        let database_file = directory.join("database.db");
+        let input_data = r#"
+- name: Nvidia dGPU
+  hardware_kind: graphics
+  hardware_list: !pci
+      vendor: 10de
+      devices: [1b80]
+  driver_options:
+    - name: Nvidia
+      packages: [nvidia]
+      verify-command: "true"
+"#;
+        fs::write(&input_file, input_data).unwrap();
+        generate_database_inner(input_file, database_file.clone(), false).unwrap();
+        let detector = MockDetector::new([HardwareId::Pci(PciId {
+            vendor: 0x10de,
+            device: 0x1b80,
+            revision: None,
+        })]);
+
+        for no_scripts in [false, true] {
+            let mut package_backend = MockBackend::new([("nvidia", "550.54-1")]);
+            let output = install_inner(
+                database_file.clone(),
+                HardwareKind::Graphics,
+                Vec::new().into_iter(),
+                &BTreeSet::new(),
+                InstallOptions {
+                    no_scripts,
+                    ..Default::default()
+                },
+                &detector,
+                &mut package_backend,
+            )
+            .unwrap();
+            assert!(package_backend.installed.contains_key("nvidia"));
+            assert_eq!(output.verification.is_none(), no_scripts);
+        }
+    }
+
+    #[test]
+    pub fn optional_packages_are_only_installed_when_asked_for() {
+        let directory = TestDirectory::new("install-optional");
+        let input_file = directory.join("input_data.yaml");
+        let database_file = directory.join("database.db");
+        let input_data = r#"
+- name: Nvidia dGPU
+  hardware_kind: graphics
+  hardware_list: !pci
+      vendor: 10de
+      devices: [1b80]
+  driver_options:
+    - name: Nvidia
+      order: 10
+      packages: [nvidia]
+      optional-packages: [nvidia-settings]
+    - name: Nouveau
+      order: 20
+      packages: [xf86-video-nouveau]
+"#;
+        fs::write(&input_file, input_data).unwrap();
+        generate_database_inner(input_file.clone(), database_file.clone(), false).unwrap();
+
+        let detector = MockDetector::new([HardwareId::Pci(PciId {
+            vendor: 0x10de,
+            device: 0x1b80,
+            revision: None,
+        })]);
+        let available_packages = [
+            ("nvidia", "550.54-1"),
+            ("nvidia-settings", "550.54-1"),
+            ("xf86-video-nouveau", "1.0.17-3"),
+        ];
+        for (with_optional, installed) in [
+            (false, vec!["nvidia"]),
+            (true, vec!["nvidia", "nvidia-settings"]),
+        ] {
+            let mut package_backend = MockBackend::new(available_packages);
+            install_inner(
+                database_file.clone(),
+                HardwareKind::Graphics,
+                Vec::new().into_iter(),
+                &BTreeSet::new(),
+                InstallOptions {
+                    with_optional,
+                    ..InstallOptions::default()
+                },
+                &detector,
+                &mut package_backend,
+            )
+            .unwrap();
+            assert_eq!(
+                package_backend.installed.keys().collect::<Vec<&String>>(),
+                installed
+            );
+        }
+
+        // A package listed both as required and as optional is installed once
+        fs::write(
+            &input_file,
+            input_data.replace("[nvidia-settings]", "[nvidia-settings, nvidia]"),
+        )
+        .unwrap();
+        generate_database_inner(input_file, database_file.clone(), false).unwrap();
+        let mut package_backend = MockBackend::new(available_packages);
+        install_inner(
+            database_file.clone(),
+            HardwareKind::Graphics,
+            Vec::new().into_iter(),
+            &BTreeSet::new(),
+            InstallOptions {
+                with_optional: true,
+                ..InstallOptions::default()
+            },
+            &detector,
+            &mut package_backend,
+        )
+        .unwrap();
+        assert_eq!(
+            package_backend.installed.keys().collect::<Vec<&String>>(),
+            ["nvidia", "nvidia-settings"]
+        );
+
+        // Replacing a driver option removes its optional packages too
+        let hardware_setups: Vec<HardwareSetup> = serde_yaml::from_str(input_data).unwrap();
+        let package_backend =
+            MockBackend::new(available_packages).with_installed(["nvidia", "nvidia-settings"]);
+        assert_eq!(
+            packages_to_remove(
+                &hardware_setups,
+                HardwareKind::Graphics,
+                hardware_setups[0].driver_options.iter().nth(1).unwrap(),
+                &package_backend,
+            ),
+            ["nvidia", "nvidia-settings"]
+        );
+    }
+
+    #[test]
+    pub fn installing_again_is_a_no_op() {
+        let directory = TestDirectory::new("install-again");
+        let input_file = directory.join("input_data.yaml");
+        let database_file = directory.join("database.db");
+        fs::write(&input_file, INPUT_DATA).unwrap();
+        generate_database_inner(input_file, database_file.clone(), false).unwrap();
+
+        let detector = MockDetector::new([HardwareId::Pci(PciId {
+            vendor: 0x10de,
+            device: 0x1b80,
+            revision: None,
+        })]);
+        let mut package_backend = MockBackend::new([
+            ("nvidia", "550.54-1"),
+            ("nvidia-utils", "550.54-1"),
+            ("xf86-video-nouveau", "1.0.17-3"),
+        ]);
+        let install = |package_backend: &mut MockBackend| {
+            install_inner(
+                database_file.clone(),
+                HardwareKind::Graphics,
+                Vec::new().into_iter(),
+                &BTreeSet::new(),
+                InstallOptions::default(),
+                &detector,
+                package_backend,
+            )
+            .unwrap()
+        };
+        assert!(!install(&mut package_backend).up_to_date);
+        assert!(install(&mut package_backend).up_to_date);
+        assert_eq!(package_backend.transactions, 1);
+
+        // A newer version in the repositories is installed again
+        package_backend
+            .available
+            .insert("xf86-video-nouveau".to_string(), "1.0.18-1".to_string());
+        assert!(!install(&mut package_backend).up_to_date);
+        assert_eq!(package_backend.transactions, 2);
+    }
+
+    #[test]
+    pub fn package_sources_name_their_repository() {
+        let package_backend =
+            MockBackend::new([("nvidia", "550.54-1"), ("nvidia-utils", "550.54-1")])
+                .with_repository("nvidia", "extra")
+                .with_repository("nvidia-utils", "extra-testing");
+        let packages = [
+            "nvidia".to_string(),
+            "nvidia-utils".to_string(),
+            "nvidia-470xx-dkms".to_string(),
+        ];
+        assert_eq!(
+            package_sources(&packages[..2], &package_backend)
+                .unwrap()
+                .iter()
+                .map(|package_source| package_source.repository.as_str())
+                .collect::<Vec<_>>(),
+            ["extra", "extra-testing"]
+        );
+        // Packages only in the AUR are not found until AUR installs are supported
+        assert!(matches!(
+            package_sources(&packages, &package_backend),
+            Err(Error::PackageNotFound { name }) if name == "nvidia-470xx-dkms"
+        ));
+
+        assert!(!is_unstable_repository("extra"));
+        assert!(!is_unstable_repository("multilib"));
+        assert!(is_unstable_repository("testing"));
+        assert!(is_unstable_repository("extra-testing"));
+        assert!(is_unstable_repository("core-staging"));
+    }
+
+    #[test]
+    pub fn services_of_replaced_driver_options_are_disabled() {
+        let hardware_setups: Vec<HardwareSetup> = serde_yaml::from_str(
+            r#"
+- name: Nvidia dGPU
+  hardware_kind: graphics
+  hardware_list: !pci
+      vendor: 10de
+      devices: [1b80]
+  driver_options:
+    - name: Nvidia
+      packages: [nvidia]
+      services: [nvidia-persistenced.service, shared.service]
+    - name: Nvidia Open
+      packages: [nvidia-open]
+      services: [nvidia-persistenced.service]
+    - name: Nouveau
+      packages: [xf86-video-nouveau]
+      services: [shared.service, nouveau-helper.service]
+"#,
+        )
+        .unwrap();
+        let package_backend = MockBackend::new([
+            ("nvidia", "550.54-1"),
+            ("nvidia-open", "550.54-1"),
+            ("xf86-video-nouveau", "1.0.17-3"),
+        ])
+        .with_installed(["nvidia"]);
+        let nouveau = hardware_setups[0]
+            .driver_options
+            .iter()
+            .find(|driver_option| driver_option.name == "Nouveau")
+            .unwrap();
+
+        // Only the installed options are replaced, and shared services stay enabled
+        assert_eq!(
+            services_to_disable(
+                &hardware_setups,
+                HardwareKind::Graphics,
+                nouveau,
+                &package_backend
+            ),
+            ["nvidia-persistenced.service"]
+        );
+    }
+
+    #[test]
+    pub fn transaction_summaries_group_packages_by_change() {
+        let package_backend = MockBackend::new([
+            ("nvidia", "550.54-1"),
+            ("nvidia-utils", "550.54-1"),
+            ("xf86-video-nouveau", "1.0.17-3"),
+        ])
+        .with_repository("nvidia", "extra")
+        .with_installed_size("nvidia", 3 * 1024 * 1024)
+        .with_installed_size("nvidia-utils", 1024 * 1024)
+        .with_installed_size("xf86-video-nouveau", 512 * 1024)
+        .with_installed(["nvidia-utils", "xf86-video-nouveau"]);
+        let packages_to_install = ["nvidia".to_string(), "nvidia-utils".to_string()];
+        let packages_to_remove = ["xf86-video-nouveau".to_string()];
+
+        let transaction = transaction_summary(
+            &packages_to_install,
+            &packages_to_remove,
+            false,
+            &package_backend,
+        );
+        assert_eq!(
+            transaction.install,
+            [PackageChange {
+                package: "nvidia".to_string(),
+                version: Some("550.54-1".to_string()),
+                repository: Some("extra".to_string()),
+                installed_size: Some(3 * 1024 * 1024),
+            }]
+        );
+        assert!(transaction.reinstall.is_empty());
+        assert_eq!(transaction.remove[0].installed_size, Some(512 * 1024));
+        assert_eq!(format_size(transaction.net_size()), "2.50 MiB");
+
+        let transaction = transaction_summary(
+            &packages_to_install,
+            &packages_to_remove,
+            true,
+            &package_backend,
+        );
+        assert_eq!(transaction.reinstall[0].package, "nvidia-utils");
+        assert_eq!(format_size(-300), "-300.00 B");
+    }
+
+    #[test]
+    pub fn edited_driver_options_are_validated() {
+        let driver_option: DriverOption =
+            serde_yaml::from_str("name: Nvidia\npackages: [nvidia]\n").unwrap();
+
+        let edited = edit_driver_option(
+            &driver_option,
+            "sed -i 's/^- nvidia$/- nvidia\\n- nvidia-settings/'",
+        )
+        .unwrap();
+        assert_eq!(edited.name, "Nvidia");
+        assert_eq!(edited.packages, ["nvidia", "nvidia-settings"]);
+
+        assert!(matches!(
+            edit_driver_option(&driver_option, "sh -c 'echo \"packages: [\" > \"$0\"'"),
+            Err(Error::EditedDriverOptionParse { .. })
+        ));
+        assert!(matches!(
+            edit_driver_option(&driver_option, "false"),
+            Err(Error::EditorFailed { .. })
+        ));
+    }
+
+    #[test]
+    pub fn install_plans_are_checked_before_installing() {
+        let directory = TestDirectory::new("install-plan");
+        let input_file = directory.join("input_data.yaml");
+        let database_file = directory.join("database.db");
+        let plan_file = directory.join("plan.yaml");
+        fs::write(&input_file, INPUT_DATA).unwrap();
+        generate_database_inner(input_file, database_file.clone(), false).unwrap();
+
+        let detector = MockDetector::new([HardwareId::Pci(PciId {
+            vendor: 0x10de,
+            device: 0x1b80,
+            revision: None,
+        })]);
+        let mut package_backend = MockBackend::new([
+            ("nvidia", "550.54-1"),
+            ("nvidia-utils", "550.54-1"),
+            ("xf86-video-nouveau", "1.0.17-3"),
+        ]);
+        let install_plan = |plan: &str, package_backend: &mut MockBackend| {
+            fs::write(&plan_file, plan).unwrap();
+            install_plan_inner(
+                database_file.clone(),
+                &plan_file,
+                InstallOptions::default(),
+                false,
+                &detector,
+                package_backend,
+            )
+        };
+
+        match install_plan(
+            "- hardware: graphics\n  driver_option: Nvidia\n- hardware: wireless\n  driver_option: Broadcom wl\n",
+            &mut package_backend,
+        ) {
+            Err(Error::InstallPlanInvalid { problems, .. }) => assert_eq!(
+                problems,
+                ["no driver option named Broadcom wl matches the detected Wireless hardware"]
+            ),
+            result => panic!("expected the plan to be rejected, got {:?}", result),
+        }
+        assert!(package_backend.installed.is_empty());
+
+        let install_action_output = install_plan(
+            "- hardware: graphics\n  driver_option: Nvidia\n",
+            &mut package_backend,
+        )
+        .unwrap();
+        assert!(install_action_output.planned_installs[0].error.is_none());
+        assert_eq!(
+            package_backend.installed.keys().collect::<Vec<&String>>(),
+            ["nvidia", "nvidia-utils"]
+        );
+    }
+
+    #[test]
+    pub fn shared_and_required_packages_are_not_removed() {
+        let hardware_setups: Vec<HardwareSetup> = serde_yaml::from_str(
+            r#"
+- name: Nvidia dGPU
+  hardware_kind: graphics
+  hardware_list: !pci
+      vendor: 10de
+      devices: [1b80]
+  driver_options:
+    - name: Nvidia
+      packages: [nvidia, nvidia-utils]
+    - name: Nouveau
+      packages: [xf86-video-nouveau, mesa]
+- name: Intel iGPU
+  hardware_kind: graphics
+  hardware_list: !pci
+      vendor: 8086
+      devices: [3e92]
+  driver_options:
+    - name: Intel
+      packages: [mesa, vulkan-intel]
+"#,
+        )
+        .unwrap();
+        let option = |name: &str| {
+            hardware_setups
+                .iter()
+                .flat_map(|hardware_setup| hardware_setup.driver_options.iter())
+                .find(|driver_option| driver_option.name == name)
+                .unwrap()
+        };
+        let package_backend = MockBackend::new([
+            ("nvidia", "550.54-1"),
+            ("nvidia-utils", "550.54-1"),
+            ("xf86-video-nouveau", "1.0.17-3"),
+            ("mesa", "24.0.3-1"),
+            ("vulkan-intel", "24.0.3-1"),
+            ("obs-studio", "30.0.2-1"),
+        ])
+        .with_dependency("obs-studio", "nvidia-utils");
+
+        // nvidia-utils is still needed by obs-studio
+        let with_nvidia =
+            package_backend
+                .clone()
+                .with_installed(["nvidia", "nvidia-utils", "obs-studio"]);
+        assert_eq!(
+            packages_to_remove(
+                &hardware_setups,
+                HardwareKind::Graphics,
+                option("Nouveau"),
+                &with_nvidia
+            ),
+            ["nvidia"]
+        );
+
+        // mesa is shared with the installed Intel option
+        let with_nouveau =
+            package_backend.with_installed(["xf86-video-nouveau", "mesa", "vulkan-intel"]);
+        assert_eq!(
+            packages_to_remove(
+                &hardware_setups,
+                HardwareKind::Graphics,
+                option("Nvidia"),
+                &with_nouveau
+            ),
+            ["xf86-video-nouveau"]
+        );
+    }
 }
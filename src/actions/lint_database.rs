@@ -0,0 +1,252 @@
+use crate::{
+    actions::install::hardware_setups,
+    cli::{new_table, to_json, CommandlinePrint, GlobalArguments, LintDatabaseActionArguments},
+    data::{
+        database::{verify_checksum, EntryDecoder},
+        input_file::HardwareSetup,
+    },
+    error::Error,
+};
+use comfy_table::{Attribute, Cell, Color};
+use owo_colors::{OwoColorize, Stream::Stdout};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt,
+    path::PathBuf,
+};
+
+/// The kinds of suspicious entries that `lint-database` looks for.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum LintCategory {
+    /// Driver options that install nothing.
+    NoPackages,
+    /// Hardware setups that no hardware ID is indexed under, so they never match.
+    NoHardwareIds,
+    /// Tags on a single driver option, which are likely typos.
+    RareTag,
+    /// Driver options of a hardware setup that share a priority, so their order is arbitrary.
+    DuplicatePriority,
+}
+
+impl fmt::Display for LintCategory {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            LintCategory::NoPackages => "Driver options without packages",
+            LintCategory::NoHardwareIds => "Hardware setups without hardware IDs",
+            LintCategory::RareTag => "Tags used by a single driver option",
+            LintCategory::DuplicatePriority => "Driver options sharing a priority",
+        })
+    }
+}
+
+/// The findings of `lint-database`, by category.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct LintDatabaseActionOutput {
+    pub findings: BTreeMap<LintCategory, Vec<String>>,
+}
+
+impl CommandlinePrint for LintDatabaseActionOutput {
+    fn print(&self) {
+        if self.findings.is_empty() {
+            println!(
+                "{}",
+                "No problems found".if_supports_color(Stdout, |text| text.green())
+            );
+        }
+        for (category, findings) in self.findings.iter() {
+            println!(
+                "{} ({})",
+                category.if_supports_color(Stdout, |text| text.yellow()),
+                findings.len()
+            );
+            for finding in findings.iter() {
+                println!("\t{}", finding);
+            }
+        }
+    }
+
+    fn print_json(&self) {
+        println!("{}", to_json(&self).unwrap_or_else(|_| {
+            eprintln!("The output could not be converted to JSON. Please try another output format...");
+            String::from("")
+        }));
+    }
+
+    fn print_plain(&self) {
+        for (category, findings) in self.findings.iter() {
+            let category = serde_json::to_value(category).unwrap();
+            for finding in findings.iter() {
+                println!("{}\t{}", category.as_str().unwrap_or_default(), finding);
+            }
+        }
+    }
+
+    fn print_debug(&self) {
+        println!("{:#?}", self);
+    }
+
+    fn print_table(&self) {
+        let mut table = new_table();
+        table.set_header(
+            ["Category", "Finding"]
+                .into_iter()
+                .map(|header| Cell::new(header).add_attribute(Attribute::Bold)),
+        );
+        for (category, findings) in self.findings.iter() {
+            for finding in findings.iter() {
+                table.add_row([Cell::new(category).fg(Color::Yellow), Cell::new(finding)]);
+            }
+        }
+        println!("{table}");
+    }
+
+    fn is_empty_result(&self) -> bool {
+        self.findings.is_empty()
+    }
+}
+
+/// Looks for entries that are valid but most likely not what the author meant.
+pub fn lint_hardware_setups(
+    hardware_setups: &[HardwareSetup],
+) -> BTreeMap<LintCategory, Vec<String>> {
+    let mut findings = BTreeMap::<LintCategory, Vec<String>>::new();
+    let mut driver_options_by_tag = BTreeMap::<&str, BTreeSet<String>>::new();
+
+    for hardware_setup in hardware_setups.iter() {
+        if hardware_setup.hardware_list.bucket_and_keys().is_empty() {
+            findings
+                .entry(LintCategory::NoHardwareIds)
+                .or_default()
+                .push(hardware_setup.name.clone());
+        }
+
+        let mut driver_options_by_priority = BTreeMap::<u32, Vec<&str>>::new();
+        for driver_option in hardware_setup.driver_options.iter() {
+            let driver_option_name = format!("{}: {}", hardware_setup.name, driver_option.name);
+            if driver_option.packages.is_empty() {
+                findings
+                    .entry(LintCategory::NoPackages)
+                    .or_default()
+                    .push(driver_option_name.clone());
+            }
+            for tag in driver_option.tags.iter() {
+                driver_options_by_tag
+                    .entry(tag)
+                    .or_default()
+                    .insert(driver_option_name.clone());
+            }
+            driver_options_by_priority
+                .entry(driver_option.order_of_priority)
+                .or_default()
+                .push(&driver_option.name);
+        }
+
+        for (priority, driver_option_names) in driver_options_by_priority {
+            if driver_option_names.len() > 1 {
+                findings
+                    .entry(LintCategory::DuplicatePriority)
+                    .or_default()
+                    .push(format!(
+                        "{}: {} share priority {}",
+                        hardware_setup.name,
+                        driver_option_names.join(", "),
+                        priority
+                    ));
+            }
+        }
+    }
+
+    for (tag, driver_option_names) in driver_options_by_tag {
+        if let Some(driver_option_name) = driver_option_names
+            .first()
+            .filter(|_| driver_option_names.len() == 1)
+        {
+            findings
+                .entry(LintCategory::RareTag)
+                .or_default()
+                .push(format!("{} on {}", tag, driver_option_name));
+        }
+    }
+
+    findings
+}
+
+pub fn lint_database_inner(database_filepath: PathBuf) -> Result<LintDatabaseActionOutput, Error> {
+    let hardware_setups = hardware_setups(database_filepath, &EntryDecoder::new())?;
+    Ok(LintDatabaseActionOutput {
+        findings: lint_hardware_setups(&hardware_setups),
+    })
+}
+
+pub fn lint_database(
+    lint_database_action_arguments: LintDatabaseActionArguments,
+    global_arguments: &GlobalArguments,
+) -> Result<LintDatabaseActionOutput, Error> {
+    if global_arguments.verify_checksum {
+        verify_checksum(&lint_database_action_arguments.database_file)?;
+    }
+    lint_database_inner(lint_database_action_arguments.database_file)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    pub fn suspicious_entries_are_reported_by_category() {
+        let hardware_setups: Vec<HardwareSetup> = serde_yaml::from_str(
+            r#"
+- name: Nvidia dGPU
+  hardware_kind: graphics
+  hardware_list: !pci
+      vendor: 10de
+      devices: [1b80]
+  driver_options:
+    - name: Nvidia
+      order_of_priority: 1
+      tags: [proprietary, wayland]
+      packages: [nvidia]
+    - name: Nouveau
+      order_of_priority: 1
+      tags: [foss, wayland]
+      packages: [xf86-video-nouveau]
+    - name: Placeholder
+      order_of_priority: 2
+      tags: [foss, waylnd]
+- name: Empty
+  hardware_kind: ethernet
+  hardware_list: !pci
+      vendor: 10ec
+      devices: []
+  driver_options: []
+"#,
+        )
+        .unwrap();
+
+        let findings = lint_hardware_setups(&hardware_setups);
+        assert_eq!(
+            findings,
+            BTreeMap::from([
+                (
+                    LintCategory::NoPackages,
+                    vec!["Nvidia dGPU: Placeholder".to_string()]
+                ),
+                (LintCategory::NoHardwareIds, vec!["Empty".to_string()]),
+                (
+                    LintCategory::RareTag,
+                    vec![
+                        "proprietary on Nvidia dGPU: Nvidia".to_string(),
+                        "waylnd on Nvidia dGPU: Placeholder".to_string()
+                    ]
+                ),
+                (
+                    LintCategory::DuplicatePriority,
+                    vec!["Nvidia dGPU: Nouveau, Nvidia share priority 1".to_string()]
+                ),
+            ])
+        );
+    }
+}
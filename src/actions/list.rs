@@ -1,38 +1,56 @@
-use crate::arch::PackageManager;
+use crate::arch::{package_backend, PackageBackend};
 use crate::data::input_file::{DriverOption, HardwareKind};
 use crate::{
-    cli::{CommandlinePrint, ListActionArguments},
-    data::database::DriverDatabase,
-    error::{DatabaseSnafu, Error},
+    actions::list_tags::check_tags_exist,
+    cli::{new_table, to_json, CommandlinePrint, GlobalArguments, ListActionArguments},
+    data::{
+        database::{DriverDatabase, EntryDecoder},
+        remote_index::database_with_remote_index,
+    },
+    error::{DatabaseSnafu, Error, InvalidSinceSnafu, UnknownDriverOptionSnafu},
 };
+use comfy_table::{Attribute, Cell, Color};
 use owo_colors::{OwoColorize, Stream::Stdout};
 use serde::{Deserialize, Serialize};
-use snafu::ResultExt;
-use speedy::Readable;
+use snafu::{OptionExt, ResultExt};
 use std::collections::BTreeSet;
 use std::fmt::Display;
 use std::path::PathBuf;
+use std::time::{SystemTime, UNIX_EPOCH};
 use std::{
     collections::BTreeMap,
     ops::{Deref, DerefMut},
 };
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
-#[serde(transparent)]
 pub struct ListActionOutput {
+    #[serde(flatten)]
     inner: BTreeMap<HardwareKind, BTreeSet<InstalledPackage>>,
+    /// The database entries skipped because they could not be decoded.
+    #[serde(default, skip_serializing_if = "is_zero")]
+    skipped_entries: usize,
+}
+
+fn is_zero(count: &usize) -> bool {
+    *count == 0
 }
 
 #[derive(Default, Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
 pub struct InstalledPackage {
     pub name: String,
     pub version: String,
+    /// The time of installation, in seconds since the Unix epoch.
+    pub install_date: Option<i64>,
+    /// Whether the package is only an optional package of the driver options.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub optional: bool,
 }
 
 impl ListActionOutput {
     pub fn new() -> Self {
         ListActionOutput {
             inner: BTreeMap::<HardwareKind, BTreeSet<InstalledPackage>>::new(),
+            skipped_entries: 0,
         }
     }
 }
@@ -66,18 +84,19 @@ impl CommandlinePrint for ListActionOutput {
             );
             for package in installed_packages.iter() {
                 println!(
-                    "\t{} {}",
+                    "\t{} {}{}",
                     package.name.if_supports_color(Stdout, |text| text.yellow()),
                     package
                         .version
-                        .if_supports_color(Stdout, |text| text.green())
+                        .if_supports_color(Stdout, |text| text.green()),
+                    if package.optional { " (optional)" } else { "" }
                 );
             }
         }
     }
 
     fn print_json(&self) {
-        println!("{}", serde_json::to_string(&self).unwrap_or_else(|_| {
+        println!("{}", to_json(&self).unwrap_or_else(|_| {
             eprintln!("The output could not be converted to JSON. Please try another output format...");
             String::from("")
         }));
@@ -87,10 +106,11 @@ impl CommandlinePrint for ListActionOutput {
         for (hardware_kind, installed_packages) in self.inner.iter() {
             for package in installed_packages.iter() {
                 println!(
-                    "{} {} {}",
-                    hardware_kind.to_string().to_lowercase(),
+                    "{} {} {}{}",
+                    hardware_kind.name(),
                     package.name,
-                    package.version
+                    package.version,
+                    if package.optional { " optional" } else { "" }
                 );
             }
         }
@@ -98,14 +118,48 @@ impl CommandlinePrint for ListActionOutput {
 
     fn print_debug(&self) {
         self.print();
+        println!(
+            "{} {}",
+            "Skipped database entries:".if_supports_color(Stdout, |text| text.yellow()),
+            self.skipped_entries
+        );
+    }
+
+    fn print_table(&self) {
+        let mut table = new_table();
+        table.set_header(
+            ["Hardware", "Package", "Version", "Optional"]
+                .into_iter()
+                .map(|header| Cell::new(header).add_attribute(Attribute::Bold)),
+        );
+        for (hardware_kind, installed_packages) in self.inner.iter() {
+            for package in installed_packages.iter() {
+                table.add_row([
+                    Cell::new(hardware_kind),
+                    Cell::new(&package.name).fg(Color::Yellow),
+                    Cell::new(&package.version).fg(Color::Green),
+                    Cell::new(if package.optional { "yes" } else { "" }),
+                ]);
+            }
+        }
+        println!("{table}");
+    }
+
+    fn is_empty_result(&self) -> bool {
+        self.inner
+            .values()
+            .all(|installed_packages| installed_packages.is_empty())
     }
 }
 
+/// The packages of the driver options for each kind of hardware, and whether each one is
+/// only ever an optional package.
 fn all_driver_packages(
     optional_hardware: &Option<HardwareKind>,
     filter_tags: &BTreeSet<String>,
     driver_database: &DriverDatabase,
-) -> Result<BTreeMap<HardwareKind, BTreeSet<String>>, Error> {
+    entry_decoder: &EntryDecoder,
+) -> Result<BTreeMap<HardwareKind, BTreeMap<String, bool>>, Error> {
     // Open a read-only transaction to get the data
     let transaction = driver_database.tx(false).context(DatabaseSnafu {})?;
 
@@ -117,81 +171,333 @@ fn all_driver_packages(
         .get_bucket("driver_option_id_to_driver_option_bucket")
         .context(DatabaseSnafu)?;
 
-    let process_hardware_kind = |hardware_kinds: &BTreeSet<HardwareKind>| {
-        hardware_kinds.into_iter().fold(
-            BTreeMap::<HardwareKind, BTreeSet<String>>::new(),
-            |grouped_packages: BTreeMap<HardwareKind, BTreeSet<String>>,
-             hardware_kind: &HardwareKind| {
-                if let Some(data) =
-                    hardware_kind_to_driver_option_id_bucket.get(hardware_kind.to_string())
-                {
-                    let driver_option_ids =
-                        BTreeSet::<String>::read_from_buffer(data.kv().value()).unwrap();
-                    driver_option_ids
-                        .iter()
-                        .filter_map(|driver_option_id| {
-                            if let Some(driver_option_data) =
-                                driver_option_id_to_driver_option_bucket.get(driver_option_id)
-                            {
-                                DriverOption::read_from_buffer(driver_option_data.kv().value()).ok()
-                            } else {
-                                None
-                            }
-                        })
-                        .fold(
-                            grouped_packages,
-                            |mut inner_grouped_packages, driver_option: DriverOption| {
-                                if filter_tags
-                                    .into_iter()
-                                    .all(|tag| driver_option.tags.contains(tag))
-                                {
-                                    inner_grouped_packages
-                                        .entry(hardware_kind.clone())
-                                        .or_default()
-                                        .extend(driver_option.packages.into_iter());
-                                }
-                                inner_grouped_packages
-                            },
-                        )
-                } else {
-                    BTreeMap::<HardwareKind, BTreeSet<String>>::new()
-                }
-            },
-        )
+    let driver_option_ids_by_kind: BTreeMap<HardwareKind, BTreeSet<String>> =
+        match optional_hardware {
+            Some(hardware_kind) => hardware_kind_to_driver_option_id_bucket
+                .get(hardware_kind.to_string())
+                .and_then(|data| {
+                    entry_decoder.decode(
+                        "hardware_kind_to_driver_option_id_bucket",
+                        data.kv().key(),
+                        data.kv().value(),
+                    )
+                })
+                .map(|driver_option_ids| (*hardware_kind, driver_option_ids))
+                .into_iter()
+                .collect(),
+            None => hardware_kind_to_driver_option_id_bucket
+                .kv_pairs()
+                .filter_map(|data| {
+                    let hardware_kind = entry_decoder
+                        .parse_key("hardware_kind_to_driver_option_id_bucket", data.key())?;
+                    let driver_option_ids = entry_decoder.decode(
+                        "hardware_kind_to_driver_option_id_bucket",
+                        data.key(),
+                        data.value(),
+                    )?;
+                    Some((hardware_kind, driver_option_ids))
+                })
+                .collect(),
+        };
+
+    // Every driver option is needed when listing all hardware kinds, so they are read in
+    // one pass over the bucket instead of with a lookup each. For a single hardware kind,
+    // looking up its own driver options reads less.
+    let decode_driver_option = |key: &[u8], value: &[u8]| {
+        entry_decoder.decode::<DriverOption>("driver_option_id_to_driver_option_bucket", key, value)
+    };
+    let driver_options: BTreeMap<Vec<u8>, DriverOption> = match optional_hardware {
+        Some(_) => driver_option_ids_by_kind
+            .values()
+            .flatten()
+            .filter_map(|driver_option_id| {
+                let data = driver_option_id_to_driver_option_bucket.get(driver_option_id)?;
+                let driver_option =
+                    decode_driver_option(driver_option_id.as_bytes(), data.kv().value())?;
+                Some((driver_option_id.as_bytes().to_vec(), driver_option))
+            })
+            .collect(),
+        None => driver_option_id_to_driver_option_bucket
+            .kv_pairs()
+            .filter_map(|data| {
+                let driver_option = decode_driver_option(data.key(), data.value())?;
+                Some((data.key().to_vec(), driver_option))
+            })
+            .collect(),
     };
 
-    if let Some(hardware_kind) = optional_hardware {
-        return Ok(process_hardware_kind(&BTreeSet::from([
-            hardware_kind.clone()
-        ])));
+    let mut grouped_packages = BTreeMap::<HardwareKind, BTreeMap<String, bool>>::new();
+    for (hardware_kind, driver_option_ids) in driver_option_ids_by_kind {
+        for driver_option in driver_option_ids
+            .iter()
+            .filter_map(|driver_option_id| driver_options.get(driver_option_id.as_bytes()))
+            .filter(|driver_option| {
+                filter_tags
+                    .iter()
+                    .all(|tag| driver_option.tags.contains(tag))
+            })
+        {
+            let packages = grouped_packages.entry(hardware_kind).or_default();
+            // Required by one driver option means not optional
+            for package in driver_option.optional_packages.iter() {
+                packages.entry(package.clone()).or_insert(true);
+            }
+            for package in driver_option.packages.iter() {
+                packages.insert(package.clone(), false);
+            }
+        }
+    }
+    Ok(grouped_packages)
+}
+
+/// Parses the argument to `--since` into a cutoff in seconds since the Unix epoch.
+/// Accepts either a duration counted back from now, like `30m`, `12h`, `7d` or `2w`,
+/// or a UTC date like `2023-06-01` or `2023-06-01T18:30:00`.
+pub fn parse_since(value: &str) -> Result<i64, Error> {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |duration| duration.as_secs() as i64);
+    parse_since_at(value, now)
+}
+
+fn parse_since_at(value: &str, now: i64) -> Result<i64, Error> {
+    let value = value.trim();
+    if let Some(unit) = value
+        .chars()
+        .last()
+        .filter(|unit| unit.is_ascii_alphabetic())
+    {
+        let amount: i64 = value[..value.len() - 1]
+            .parse()
+            .ok()
+            .filter(|amount| *amount >= 0)
+            .context(InvalidSinceSnafu { value })?;
+        let unit_seconds = match unit.to_ascii_lowercase() {
+            's' => 1,
+            'm' => 60,
+            'h' => 60 * 60,
+            'd' => 24 * 60 * 60,
+            'w' => 7 * 24 * 60 * 60,
+            _ => return InvalidSinceSnafu { value }.fail(),
+        };
+        return amount
+            .checked_mul(unit_seconds)
+            .and_then(|seconds| now.checked_sub(seconds))
+            .context(InvalidSinceSnafu { value });
+    }
+
+    let (date, time) = value.split_once(['T', ' ']).unwrap_or((value, "00:00:00"));
+    let date_parts = date
+        .split('-')
+        .map(|part| part.parse::<i64>().ok())
+        .collect::<Option<Vec<i64>>>()
+        .context(InvalidSinceSnafu { value })?;
+    let time_parts = time
+        .split(':')
+        .map(|part| part.parse::<i64>().ok())
+        .collect::<Option<Vec<i64>>>()
+        .context(InvalidSinceSnafu { value })?;
+    match (date_parts.as_slice(), time_parts.as_slice()) {
+        ([year @ 0..=9999, month @ 1..=12, day], [hour @ 0..=23, minute @ 0..=59, rest @ ..])
+            if (1..=days_in_month(*year, *month)).contains(day)
+                && rest.len() <= 1
+                && rest.iter().all(|second| (0..=59).contains(second)) =>
+        {
+            let second = rest.first().copied().unwrap_or(0);
+            Ok(days_from_civil(*year, *month, *day) * 24 * 60 * 60
+                + hour * 60 * 60
+                + minute * 60
+                + second)
+        }
+        _ => InvalidSinceSnafu { value }.fail(),
+    }
+}
+
+/// The number of days in a month of the proleptic Gregorian calendar.
+fn days_in_month(year: i64, month: i64) -> i64 {
+    match month {
+        2 if year % 4 == 0 && (year % 100 != 0 || year % 400 == 0) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+/// The number of days between the Unix epoch and a date in the proleptic Gregorian calendar.
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146097 + day_of_era - 719468
+}
+
+/// The date in the proleptic Gregorian calendar that is a number of days after the Unix epoch.
+fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let days = days + 719468;
+    let era = days.div_euclid(146097);
+    let day_of_era = days - era * 146097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 {
+        month_index + 3
     } else {
-        return Ok(process_hardware_kind(
-            &hardware_kind_to_driver_option_id_bucket
-                .kv_pairs()
-                .filter_map(|data| String::from_utf8(data.key().to_vec()).ok())
-                .map(|item| item.parse().unwrap())
-                .collect::<BTreeSet<HardwareKind>>(),
-        ));
+        month_index - 9
+    };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
+
+/// Formats seconds since the Unix epoch like `2023-11-14 22:13:20`, in UTC.
+pub fn format_timestamp(timestamp: i64) -> String {
+    let (year, month, day) = civil_from_days(timestamp.div_euclid(24 * 60 * 60));
+    let seconds_of_day = timestamp.rem_euclid(24 * 60 * 60);
+    format!(
+        "{:04}-{:02}-{:02} {:02}:{:02}:{:02}",
+        year,
+        month,
+        day,
+        seconds_of_day / (60 * 60),
+        seconds_of_day / 60 % 60,
+        seconds_of_day % 60
+    )
+}
+
+/// Every driver option in the database, by hardware kind.
+pub fn driver_options_by_kind(
+    database_filepath: PathBuf,
+    entry_decoder: &EntryDecoder,
+) -> Result<BTreeMap<HardwareKind, BTreeSet<DriverOption>>, Error> {
+    let driver_database = DriverDatabase::cloned_from_database_path(database_filepath)?;
+
+    // Open a read-only transaction to get the data
+    let transaction = driver_database.tx(false).context(DatabaseSnafu {})?;
+
+    let hardware_kind_to_driver_option_id_bucket = transaction
+        .get_bucket("hardware_kind_to_driver_option_id_bucket")
+        .context(DatabaseSnafu)?;
+
+    let driver_option_id_to_driver_option_bucket = transaction
+        .get_bucket("driver_option_id_to_driver_option_bucket")
+        .context(DatabaseSnafu)?;
+
+    Ok(hardware_kind_to_driver_option_id_bucket
+        .kv_pairs()
+        .filter_map(|data| {
+            let hardware_kind: HardwareKind =
+                entry_decoder.parse_key("hardware_kind_to_driver_option_id_bucket", data.key())?;
+            let driver_option_ids: BTreeSet<String> = entry_decoder.decode(
+                "hardware_kind_to_driver_option_id_bucket",
+                data.key(),
+                data.value(),
+            )?;
+            let driver_options = driver_option_ids
+                .iter()
+                .filter_map(|driver_option_id| {
+                    let driver_option_data =
+                        driver_option_id_to_driver_option_bucket.get(driver_option_id)?;
+                    entry_decoder.decode(
+                        "driver_option_id_to_driver_option_bucket",
+                        driver_option_id.as_bytes(),
+                        driver_option_data.kv().value(),
+                    )
+                })
+                .collect();
+            Some((hardware_kind, driver_options))
+        })
+        .collect())
+}
+
+/// How much of a driver option is installed.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum InstallState {
+    Installed,
+    /// Some packages of the driver option are installed, but not all of them.
+    PartiallyInstalled {
+        installed: Vec<String>,
+        missing: Vec<String>,
+    },
+    NotInstalled,
+}
+
+impl InstallState {
+    pub fn of_packages(packages: &[String], package_backend: &dyn PackageBackend) -> Self {
+        let (installed, missing): (Vec<String>, Vec<String>) = packages
+            .iter()
+            .cloned()
+            .partition(|package| package_backend.get(package).is_some());
+        if missing.is_empty() {
+            InstallState::Installed
+        } else if installed.is_empty() {
+            InstallState::NotInstalled
+        } else {
+            InstallState::PartiallyInstalled { installed, missing }
+        }
+    }
+
+    pub fn is_installed(&self) -> bool {
+        *self == InstallState::Installed
     }
 }
 
+/// Whether the packages of a driver option are installed, for status displays that
+/// only need to know about one driver option.
+pub fn is_driver_installed(
+    database_filepath: PathBuf,
+    hardware: HardwareKind,
+    driver_name: &str,
+    package_backend: &dyn PackageBackend,
+) -> Result<InstallState, Error> {
+    let driver_option = driver_options_by_kind(database_filepath, &EntryDecoder::new())?
+        .remove(&hardware)
+        .unwrap_or_default()
+        .into_iter()
+        .find(|driver_option| driver_option.name == driver_name)
+        .context(UnknownDriverOptionSnafu {
+            hardware,
+            name: driver_name,
+        })?;
+    Ok(InstallState::of_packages(
+        &driver_option.packages,
+        package_backend,
+    ))
+}
+
 fn installed_drivers(
-    all_driver_packages: &BTreeMap<HardwareKind, BTreeSet<String>>,
-    package_manager: &PackageManager,
+    all_driver_packages: &BTreeMap<HardwareKind, BTreeMap<String, bool>>,
+    package_backend: &dyn PackageBackend,
+    since: Option<i64>,
 ) -> BTreeMap<HardwareKind, BTreeSet<InstalledPackage>> {
     let mut installed_drivers = BTreeMap::<HardwareKind, BTreeSet<InstalledPackage>>::new();
     for (hardware_kind, package_names) in all_driver_packages {
         installed_drivers
             .entry(hardware_kind.clone())
             .or_default()
-            .extend(package_names.iter().filter_map(|package_name| {
-                package_manager
-                    .get(package_name)
-                    .map(|package| InstalledPackage {
-                        name: package.name().to_owned(),
-                        version: package.version().to_string(),
+            .extend(
+                package_names
+                    .iter()
+                    .filter_map(|(package_name, optional)| {
+                        package_backend
+                            .get(package_name)
+                            .map(|package| InstalledPackage {
+                                name: package.name,
+                                version: package.version,
+                                install_date: package.install_date,
+                                optional: *optional,
+                            })
                     })
-            }));
+                    .filter(|installed_package| {
+                        since.is_none_or(|cutoff| {
+                            installed_package
+                                .install_date
+                                .is_some_and(|install_date| install_date >= cutoff)
+                        })
+                    }),
+            );
     }
     installed_drivers
 }
@@ -200,25 +506,370 @@ pub fn list_inner<T: IntoIterator<Item = String>>(
     database_filepath: PathBuf,
     optional_hardware: &Option<HardwareKind>,
     tags: T,
+    since: Option<i64>,
+    copy_database: bool,
+    entry_decoder: &EntryDecoder,
+    package_backend: &dyn PackageBackend,
 ) -> Result<BTreeMap<HardwareKind, BTreeSet<InstalledPackage>>, Error> {
-    let driver_database = DriverDatabase::cloned_from_database_path(database_filepath)?;
-    let package_manager = PackageManager::new();
+    let driver_database = DriverDatabase::for_reading(database_filepath, copy_database)?;
 
     let all_driver_packages = all_driver_packages(
         optional_hardware,
         &tags.into_iter().collect(),
         &driver_database,
+        entry_decoder,
     )?;
 
-    Ok(installed_drivers(&all_driver_packages, &package_manager))
+    Ok(installed_drivers(
+        &all_driver_packages,
+        package_backend,
+        since,
+    ))
 }
 
-pub fn list(list_action_arguments: ListActionArguments) -> Result<ListActionOutput, Error> {
+pub fn list(
+    list_action_arguments: ListActionArguments,
+    global_arguments: &GlobalArguments,
+) -> Result<ListActionOutput, Error> {
+    let catalog_database = database_with_remote_index(
+        list_action_arguments.database_file,
+        global_arguments.offline_flag,
+        global_arguments.verify_checksum,
+    )?;
+    if global_arguments.strict_tags {
+        check_tags_exist(catalog_database.path.clone(), &list_action_arguments.tags)?;
+    }
+    let entry_decoder = EntryDecoder::new();
     Ok(ListActionOutput {
         inner: list_inner(
-            list_action_arguments.database_file,
+            catalog_database.path.clone(),
             &list_action_arguments.hardware,
             list_action_arguments.tags,
+            list_action_arguments.since,
+            list_action_arguments.copy_database,
+            &entry_decoder,
+            package_backend(global_arguments.package_manager)?.as_ref(),
         )?,
+        skipped_entries: entry_decoder.skipped(),
     })
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::TestDirectory;
+    use crate::{
+        actions::{generate_database::generate_database_inner, search::search_inner},
+        arch::MockBackend,
+        data::{
+            database::{HardwareId, PciId, UsbId},
+            input_file::FIXTURE_INPUT_DATA,
+        },
+        detection::MockDetector,
+    };
+    use std::fs;
+
+    #[test]
+    pub fn parse_since_values() {
+        let now = 1_700_000_000;
+        assert_eq!(parse_since_at("90s", now).unwrap(), now - 90);
+        assert_eq!(parse_since_at("7d", now).unwrap(), now - 7 * 24 * 60 * 60);
+        assert_eq!(parse_since_at("2W", now).unwrap(), now - 14 * 24 * 60 * 60);
+        assert_eq!(parse_since_at("1970-01-02", now).unwrap(), 24 * 60 * 60);
+        assert_eq!(parse_since_at("2023-11-14", now).unwrap(), 1_699_920_000);
+        assert_eq!(
+            parse_since_at("2023-11-14T22:13:20", now).unwrap(),
+            1_700_000_000
+        );
+        assert!(parse_since_at("7y", now).is_err());
+        assert!(parse_since_at("yesterday", now).is_err());
+        assert!(parse_since_at("2023-13-01", now).is_err());
+        assert!(parse_since_at("-7d", now).is_err());
+        assert!(parse_since_at("9223372036854775807w", now).is_err());
+        assert!(parse_since_at("2024-02-31", now).is_err());
+        assert!(parse_since_at("2023-02-29", now).is_err());
+        assert_eq!(parse_since_at("2024-02-29", now).unwrap(), 1_709_164_800);
+        assert_eq!(format_timestamp(1_700_000_000), "2023-11-14 22:13:20");
+        assert_eq!(format_timestamp(0), "1970-01-01 00:00:00");
+    }
+
+    #[test]
+    pub fn driver_install_states() {
+        let directory = TestDirectory::new("install-state");
+        let input_file = directory.join("input_data.yaml");
+        let database_file = directory.join("database.db");
+        fs::write(
+            &input_file,
+            r#"
+- name: Nvidia dGPU
+  hardware_kind: graphics
+  hardware_list: !pci
+      vendor: 10de
+      devices: [1b80]
+  driver_options:
+    - name: Nvidia
+      packages: [nvidia, nvidia-utils]
+    - name: Nouveau
+      packages: [xf86-video-nouveau]
+"#,
+        )
+        .unwrap();
+        generate_database_inner(input_file, database_file.clone(), false).unwrap();
+
+        let package_backend = MockBackend::new([
+            ("nvidia", "550.54-1"),
+            ("nvidia-utils", "550.54-1"),
+            ("xf86-video-nouveau", "1.0.17-3"),
+        ])
+        .with_installed(["nvidia-utils", "xf86-video-nouveau"]);
+        let install_state = |driver_name: &str| {
+            is_driver_installed(
+                database_file.clone(),
+                HardwareKind::Graphics,
+                driver_name,
+                &package_backend,
+            )
+        };
+
+        assert!(install_state("Nouveau").unwrap().is_installed());
+        assert_eq!(
+            install_state("Nvidia").unwrap(),
+            InstallState::PartiallyInstalled {
+                installed: vec!["nvidia-utils".to_string()],
+                missing: vec!["nvidia".to_string()],
+            }
+        );
+        assert!(matches!(
+            install_state("Nvidia Open"),
+            Err(Error::UnknownDriverOption { .. })
+        ));
+        assert_eq!(
+            is_driver_installed(
+                database_file,
+                HardwareKind::Graphics,
+                "Nouveau",
+                &MockBackend::default()
+            )
+            .unwrap(),
+            InstallState::NotInstalled
+        );
+    }
+
+    /// The packages of every driver option, grouped the way `all_driver_packages` groups
+    /// them, but going through `driver_options_by_kind`.
+    fn reference_driver_packages(
+        database_file: PathBuf,
+        optional_hardware: &Option<HardwareKind>,
+        filter_tags: &BTreeSet<String>,
+    ) -> BTreeMap<HardwareKind, BTreeMap<String, bool>> {
+        let mut grouped_packages = BTreeMap::<HardwareKind, BTreeMap<String, bool>>::new();
+        for (hardware_kind, driver_options) in
+            driver_options_by_kind(database_file, &EntryDecoder::new()).unwrap()
+        {
+            if optional_hardware.is_some_and(|hardware| hardware != hardware_kind) {
+                continue;
+            }
+            for driver_option in driver_options
+                .iter()
+                .filter(|driver_option| filter_tags.is_subset(&driver_option.tags))
+            {
+                let packages = grouped_packages.entry(hardware_kind).or_default();
+                for package in driver_option.optional_packages.iter() {
+                    packages.entry(package.clone()).or_insert(true);
+                }
+                for package in driver_option.packages.iter() {
+                    packages.insert(package.clone(), false);
+                }
+            }
+        }
+        grouped_packages
+    }
+
+    /// A database generated for a test, along with the directory that keeps it.
+    fn generated_fixture_database(name: &str) -> (TestDirectory, PathBuf) {
+        let directory = TestDirectory::new(name);
+        let input_file = directory.join("input_data.yaml");
+        let database_file = directory.join("database.db");
+        fs::write(&input_file, FIXTURE_INPUT_DATA).unwrap();
+        generate_database_inner(input_file, database_file.clone(), false).unwrap();
+        (directory, database_file)
+    }
+
+    #[test]
+    pub fn driver_packages_match_the_driver_options() {
+        let (_directory, database_file) = generated_fixture_database("driver-packages");
+        let driver_database = DriverDatabase::for_reading(database_file.clone(), false).unwrap();
+        for optional_hardware in [
+            None,
+            Some(HardwareKind::Graphics),
+            Some(HardwareKind::Audio),
+        ] {
+            for filter_tags in [BTreeSet::new(), BTreeSet::from(["nvidia".to_string()])] {
+                assert_eq!(
+                    all_driver_packages(
+                        &optional_hardware,
+                        &filter_tags,
+                        &driver_database,
+                        &EntryDecoder::new()
+                    )
+                    .unwrap(),
+                    reference_driver_packages(
+                        database_file.clone(),
+                        &optional_hardware,
+                        &filter_tags
+                    )
+                );
+            }
+        }
+    }
+
+    /// Generates the fixture database, searches it for known hardware, and lists what
+    /// is installed of the drivers found, like a first run of the program would.
+    #[test]
+    pub fn generated_databases_are_searched_and_listed() {
+        let (_directory, database_file) = generated_fixture_database("pipeline");
+        let detector = MockDetector::new([
+            HardwareId::Pci(PciId {
+                vendor: 0x10de,
+                device: 0x1b82,
+                revision: None,
+            }),
+            HardwareId::Usb(UsbId {
+                vendor: 0x0bda,
+                device: 0x8153,
+            }),
+        ]);
+        let found = search_inner(
+            database_file.clone(),
+            &None,
+            Vec::new().into_iter(),
+            &detector,
+            None,
+        )
+        .unwrap()
+        .into_iter()
+        .map(|(hardware_kind, driver_options)| {
+            (
+                hardware_kind,
+                driver_options
+                    .into_iter()
+                    .map(|driver_option| driver_option.name)
+                    .collect::<Vec<String>>(),
+            )
+        })
+        .collect::<BTreeMap<HardwareKind, Vec<String>>>();
+        assert_eq!(
+            found,
+            BTreeMap::from([
+                (
+                    HardwareKind::Graphics,
+                    ["Nvidia Wayland", "Nvidia", "Nouveau"]
+                        .map(String::from)
+                        .to_vec()
+                ),
+                (HardwareKind::Ethernet, vec![String::from("Realtek r8152")]),
+                (
+                    HardwareKind::Wireless,
+                    vec![String::from("Realtek rtl88xxau")]
+                ),
+            ])
+        );
+
+        let package_backend = MockBackend::new([
+            ("nvidia", "550.54-1"),
+            ("nvidia-utils", "550.54-1"),
+            ("nvidia-settings", "550.54-1"),
+            ("r8152-dkms", "2.18.1-1"),
+            ("mesa", "24.0.3-1"),
+        ])
+        .with_installed(["nvidia", "nvidia-settings", "r8152-dkms", "mesa"]);
+        let installed = list_inner(
+            database_file,
+            &None,
+            Vec::new(),
+            None,
+            false,
+            &EntryDecoder::new(),
+            &package_backend,
+        )
+        .unwrap()
+        .into_iter()
+        .map(|(hardware_kind, installed_packages)| {
+            (
+                hardware_kind,
+                installed_packages
+                    .into_iter()
+                    .map(|installed_package| (installed_package.name, installed_package.optional))
+                    .collect::<Vec<(String, bool)>>(),
+            )
+        })
+        .collect::<BTreeMap<HardwareKind, Vec<(String, bool)>>>();
+        assert_eq!(
+            installed,
+            BTreeMap::from([
+                (
+                    HardwareKind::Graphics,
+                    vec![
+                        (String::from("mesa"), false),
+                        (String::from("nvidia"), false),
+                        (String::from("nvidia-settings"), true),
+                    ]
+                ),
+                (
+                    HardwareKind::Ethernet,
+                    vec![(String::from("r8152-dkms"), false)]
+                ),
+                // Kinds with driver options are listed even when none of them is installed
+                (HardwareKind::Wireless, Vec::new()),
+            ])
+        );
+    }
+
+    /// Times `list` on a database with many driver options. Run it with
+    /// `cargo test --release list_benchmark -- --ignored --nocapture`.
+    #[test]
+    #[ignore]
+    pub fn list_benchmark() {
+        let directory = TestDirectory::new("list-benchmark");
+        let input_file = directory.join("input_data.yaml");
+        let database_file = directory.join("database.db");
+        let input_data: String = (0..2000)
+            .map(|setup| {
+                format!(
+                    "- name: Setup {setup}\n  hardware_kind: {}\n  hardware_list: !pci\n      vendor: 10de\n      devices: [{:04x}]\n  driver_options:\n{}",
+                    ["graphics", "ethernet", "wireless", "audio"][setup % 4],
+                    setup,
+                    (0..5)
+                        .map(|option| format!(
+                            "    - name: Option {setup}-{option}\n      tags: [tag{option}]\n      packages: [package-{setup}-{option}]\n"
+                        ))
+                        .collect::<String>()
+                )
+            })
+            .collect();
+        fs::write(&input_file, input_data).unwrap();
+        generate_database_inner(input_file, database_file.clone(), false).unwrap();
+
+        let package_backend = MockBackend::default();
+        for optional_hardware in [None, Some(HardwareKind::Graphics)] {
+            let start = std::time::Instant::now();
+            for _ in 0..10 {
+                list_inner(
+                    database_file.clone(),
+                    &optional_hardware,
+                    Vec::new(),
+                    None,
+                    false,
+                    &EntryDecoder::new(),
+                    &package_backend,
+                )
+                .unwrap();
+            }
+            println!(
+                "list {:?}: {:?} per run",
+                optional_hardware,
+                start.elapsed() / 10
+            );
+        }
+    }
+}
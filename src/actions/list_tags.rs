@@ -0,0 +1,219 @@
+use crate::{
+    cli::{new_table, to_json, CommandlinePrint, GlobalArguments, ListTagsActionArguments},
+    data::{
+        database::{decode_value, DriverDatabase},
+        input_file::{DriverOption, HardwareKind},
+        remote_index::database_with_remote_index,
+    },
+    error::{DatabaseSnafu, Error, UnknownTagsSnafu},
+};
+use comfy_table::{Attribute, Cell, Color};
+use owo_colors::{OwoColorize, Stream::Stdout};
+use serde::{Deserialize, Serialize};
+use snafu::ResultExt;
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    path::PathBuf,
+};
+
+/// The tags of the driver options in the database, with the number of driver options carrying each.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct ListTagsActionOutput {
+    pub inner: BTreeMap<String, usize>,
+}
+
+impl CommandlinePrint for ListTagsActionOutput {
+    fn print(&self) {
+        for (tag, count) in self.inner.iter() {
+            println!(
+                "{} ({})",
+                tag.if_supports_color(Stdout, |text| text.yellow()),
+                count
+            );
+        }
+    }
+
+    fn print_json(&self) {
+        println!("{}", to_json(&self).unwrap_or_else(|_| {
+            eprintln!("The output could not be converted to JSON. Please try another output format...");
+            String::from("")
+        }));
+    }
+
+    fn print_plain(&self) {
+        for (tag, count) in self.inner.iter() {
+            println!("{} {}", tag, count);
+        }
+    }
+
+    fn print_debug(&self) {
+        self.print();
+    }
+
+    fn print_table(&self) {
+        let mut table = new_table();
+        table.set_header(
+            ["Tag", "Driver options"]
+                .into_iter()
+                .map(|header| Cell::new(header).add_attribute(Attribute::Bold)),
+        );
+        for (tag, count) in self.inner.iter() {
+            table.add_row([Cell::new(tag).fg(Color::Yellow), Cell::new(count)]);
+        }
+        println!("{table}");
+    }
+
+    fn is_empty_result(&self) -> bool {
+        self.inner.is_empty()
+    }
+}
+
+pub fn list_tags_inner(
+    database_filepath: PathBuf,
+    optional_hardware: &Option<HardwareKind>,
+) -> Result<BTreeMap<String, usize>, Error> {
+    let driver_database = DriverDatabase::cloned_from_database_path(database_filepath)?;
+
+    // Open a read-only transaction to get the data
+    let transaction = driver_database.tx(false).context(DatabaseSnafu {})?;
+
+    let driver_option_id_to_driver_option_bucket = transaction
+        .get_bucket("driver_option_id_to_driver_option_bucket")
+        .context(DatabaseSnafu)?;
+
+    let driver_options: Vec<DriverOption> = if let Some(hardware_kind) = optional_hardware {
+        let hardware_kind_to_driver_option_id_bucket = transaction
+            .get_bucket("hardware_kind_to_driver_option_id_bucket")
+            .context(DatabaseSnafu)?;
+        hardware_kind_to_driver_option_id_bucket
+            .get(hardware_kind.to_string())
+            .and_then(|data| decode_value::<BTreeSet<String>>(data.kv().value()).ok())
+            .unwrap_or_default()
+            .iter()
+            .filter_map(|driver_option_id| {
+                driver_option_id_to_driver_option_bucket
+                    .get(driver_option_id)
+                    .and_then(|data| decode_value::<DriverOption>(data.kv().value()).ok())
+            })
+            .collect()
+    } else {
+        driver_option_id_to_driver_option_bucket
+            .kv_pairs()
+            .filter_map(|data| decode_value::<DriverOption>(data.value()).ok())
+            .collect()
+    };
+
+    Ok(driver_options
+        .iter()
+        .flat_map(|driver_option| driver_option.tags.iter())
+        .fold(BTreeMap::<String, usize>::new(), |mut tag_counts, tag| {
+            *tag_counts.entry(tag.clone()).or_default() += 1;
+            tag_counts
+        }))
+}
+
+/// Fails with `UnknownTags` when any of the tags is not carried by a single driver option in
+/// the database, to tell a misspelled tag apart from tags that only match no hardware.
+pub fn check_tags_exist<'a, I: IntoIterator<Item = &'a String>>(
+    database_filepath: PathBuf,
+    tags: I,
+) -> Result<(), Error> {
+    let known_tags = list_tags_inner(database_filepath, &None)?;
+    let unknown_tags: Vec<String> = tags
+        .into_iter()
+        .filter(|tag| !known_tags.contains_key(*tag))
+        .cloned()
+        .collect::<BTreeSet<String>>()
+        .into_iter()
+        .collect();
+    if !unknown_tags.is_empty() {
+        return UnknownTagsSnafu { tags: unknown_tags }.fail();
+    }
+    Ok(())
+}
+
+pub fn list_tags(
+    list_tags_action_arguments: ListTagsActionArguments,
+    global_arguments: &GlobalArguments,
+) -> Result<ListTagsActionOutput, Error> {
+    let catalog_database = database_with_remote_index(
+        list_tags_action_arguments.database_file,
+        global_arguments.offline_flag,
+        global_arguments.verify_checksum,
+    )?;
+    Ok(ListTagsActionOutput {
+        inner: list_tags_inner(
+            catalog_database.path.clone(),
+            &list_tags_action_arguments.hardware,
+        )?,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::actions::generate_database::generate_database_inner;
+    use crate::test_support::TestDirectory;
+    use std::fs;
+
+    #[test]
+    pub fn tags_are_counted_per_driver_option() {
+        let directory = TestDirectory::new("list-tags");
+        let input_file = directory.join("input_data.yaml");
+        let database_file = directory.join("database.db");
+        fs::write(
+            &input_file,
+            r#"
+- name: Nvidia dGPU
+  hardware_kind: graphics
+  hardware_list: !pci
+      vendor: 10de
+      devices: [1b80]
+  driver_options:
+    - name: Nvidia
+      tags: [nvidia, proprietary]
+    - name: Nouveau
+      tags: [nouveau, free]
+- name: Realtek USB Ethernet
+  hardware_kind: ethernet
+  hardware_list: !usb
+      vendor-id: 0bda
+      device-ids: [8153]
+  driver_options:
+    - name: Realtek r8152
+      tags: [realtek, proprietary]
+"#,
+        )
+        .unwrap();
+        generate_database_inner(input_file, database_file.clone(), false).unwrap();
+
+        let all_tags = list_tags_inner(database_file.clone(), &None).unwrap();
+        assert_eq!(all_tags["proprietary"], 2);
+        assert_eq!(all_tags.len(), 5);
+
+        let graphics_tags =
+            list_tags_inner(database_file.clone(), &Some(HardwareKind::Graphics)).unwrap();
+        assert_eq!(
+            graphics_tags.into_iter().collect::<Vec<_>>(),
+            [
+                ("free".to_string(), 1),
+                ("nouveau".to_string(), 1),
+                ("nvidia".to_string(), 1),
+                ("proprietary".to_string(), 1)
+            ]
+        );
+
+        // Tags of other hardware kinds exist too, even if they match nothing here
+        check_tags_exist(
+            database_file.clone(),
+            &["nvidia".to_string(), "realtek".to_string()],
+        )
+        .unwrap();
+        let requested_tags = ["proprietry", "free", "proprietry"].map(String::from);
+        assert!(matches!(
+            check_tags_exist(database_file, &requested_tags),
+            Err(Error::UnknownTags { tags }) if tags == ["proprietry"]
+        ));
+    }
+}
@@ -1,4 +1,19 @@
+pub mod coverage;
+pub mod deps;
+pub mod doctor;
+pub mod export;
 pub mod generate_database;
+pub mod hardware_kinds;
+pub mod history;
 pub mod install;
+pub mod lint_database;
 pub mod list;
+pub mod list_tags;
+pub mod profile;
+pub mod prune;
+pub mod reapply;
+pub mod restore_database;
 pub mod search;
+#[cfg(feature = "serve")]
+pub mod serve;
+pub mod version;
@@ -0,0 +1,285 @@
+use crate::{
+    actions::{
+        install::{install_driver_option, InstallOptions},
+        list::driver_options_by_kind,
+    },
+    arch::{package_backend, PackageBackend},
+    cli::{
+        to_json, CommandlineFlags, CommandlinePrint, GlobalArguments, ProfileActionArguments,
+        ProfileApplyArguments, ProfileCommand, ProfileSaveArguments,
+    },
+    data::{
+        database::EntryDecoder,
+        input_file::{DriverOption, HardwareKind},
+        profile::{profile_path, Profile},
+        remote_index::database_with_remote_index,
+    },
+    error::{Error, ProfileDriverOptionMissingSnafu},
+    hooks::DEFAULT_SCRIPT_TIMEOUT_SECONDS,
+    paths, privileges,
+};
+use owo_colors::{OwoColorize, Stream::Stdout};
+use serde::{Deserialize, Serialize};
+use snafu::OptionExt;
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProfileActionOutput {
+    pub name: String,
+    pub profile_file: PathBuf,
+    /// Whether the profile was applied, rather than saved.
+    pub applied: bool,
+    pub driver_options: BTreeMap<HardwareKind, String>,
+}
+
+impl CommandlinePrint for ProfileActionOutput {
+    fn print(&self) {
+        let message = if self.applied {
+            format!("Applied the profile {}", self.name)
+        } else {
+            format!(
+                "Saved the profile {} to {}",
+                self.name,
+                self.profile_file.to_string_lossy()
+            )
+        };
+        println!("{}", message.if_supports_color(Stdout, |text| text.green()));
+        for (hardware_kind, driver_option) in self.driver_options.iter() {
+            println!(
+                "\t{} {}",
+                format!("{}:", hardware_kind).if_supports_color(Stdout, |text| text.bold()),
+                driver_option.if_supports_color(Stdout, |text| text.yellow())
+            );
+        }
+    }
+
+    fn print_json(&self) {
+        println!("{}", to_json(&self).unwrap_or_else(|_| {
+            eprintln!("The output could not be converted to JSON. Please try another output format...");
+            String::from("")
+        }));
+    }
+
+    fn print_plain(&self) {
+        for (hardware_kind, driver_option) in self.driver_options.iter() {
+            println!("{} {}", hardware_kind.name(), driver_option);
+        }
+    }
+
+    fn print_debug(&self) {
+        self.print();
+    }
+}
+
+/// The driver options whose packages are all installed, preferring the higher priority
+/// option for each hardware kind when several are.
+fn installed_profile<F: Fn(&str) -> bool>(
+    driver_options_by_kind: &BTreeMap<HardwareKind, BTreeSet<DriverOption>>,
+    is_installed: F,
+) -> Profile {
+    Profile {
+        driver_options: driver_options_by_kind
+            .iter()
+            .filter_map(|(hardware_kind, driver_options)| {
+                driver_options
+                    .iter()
+                    .find(|driver_option| {
+                        !driver_option.packages.is_empty()
+                            && driver_option
+                                .packages
+                                .iter()
+                                .all(|package| is_installed(package))
+                    })
+                    .map(|driver_option| (*hardware_kind, driver_option.name.clone()))
+            })
+            .collect(),
+    }
+}
+
+/// Saves the driver options installed for each hardware kind as a profile.
+pub fn profile_save_inner(
+    database_filepath: PathBuf,
+    profiles_directory: &Path,
+    name: String,
+    package_backend: &dyn PackageBackend,
+) -> Result<ProfileActionOutput, Error> {
+    let profile_file = profile_path(profiles_directory, &name)?;
+    let profile = installed_profile(
+        &driver_options_by_kind(database_filepath, &EntryDecoder::new())?,
+        |package| package_backend.get(package).is_some(),
+    );
+    profile.save(&profile_file)?;
+    Ok(ProfileActionOutput {
+        name,
+        profile_file,
+        applied: false,
+        driver_options: profile.driver_options,
+    })
+}
+
+/// Installs the driver options of a profile. Every option is looked up before anything
+/// is installed, so that a stale profile changes nothing.
+pub fn profile_apply_inner(
+    database_filepath: PathBuf,
+    profiles_directory: &Path,
+    name: String,
+    install_options: InstallOptions,
+    package_backend: &mut dyn PackageBackend,
+) -> Result<ProfileActionOutput, Error> {
+    let profile_file = profile_path(profiles_directory, &name)?;
+    let profile = Profile::load(&profile_file)?;
+    let mut driver_options_by_kind =
+        driver_options_by_kind(database_filepath.clone(), &EntryDecoder::new())?;
+    let selected_driver_options = profile
+        .driver_options
+        .iter()
+        .map(|(hardware_kind, driver_option_name)| {
+            driver_options_by_kind
+                .remove(hardware_kind)
+                .unwrap_or_default()
+                .into_iter()
+                .find(|driver_option| &driver_option.name == driver_option_name)
+                .map(|driver_option| (*hardware_kind, driver_option))
+                .context(ProfileDriverOptionMissingSnafu {
+                    hardware: *hardware_kind,
+                    driver_option: driver_option_name,
+                })
+        })
+        .collect::<Result<Vec<(HardwareKind, DriverOption)>, Error>>()?;
+    for (hardware_kind, driver_option) in selected_driver_options.iter() {
+        install_driver_option(
+            database_filepath.clone(),
+            *hardware_kind,
+            driver_option,
+            install_options,
+            package_backend,
+        )?;
+    }
+    Ok(ProfileActionOutput {
+        name,
+        profile_file,
+        applied: true,
+        driver_options: profile.driver_options,
+    })
+}
+
+pub fn profile(
+    profile_action_arguments: ProfileActionArguments,
+    global_arguments: &GlobalArguments,
+) -> Result<ProfileActionOutput, Error> {
+    // Profiles are kept in the system-wide configuration directory
+    privileges::escalate_if_needed()?;
+    match profile_action_arguments.command {
+        ProfileCommand::Save(ProfileSaveArguments {
+            name,
+            database_file,
+        }) => {
+            let catalog_database = database_with_remote_index(
+                database_file,
+                global_arguments.offline_flag,
+                global_arguments.verify_checksum,
+            )?;
+            profile_save_inner(
+                catalog_database.path.clone(),
+                &paths::profiles_directory(),
+                name,
+                package_backend(global_arguments.package_manager)?.as_ref(),
+            )
+        }
+        ProfileCommand::Apply(ProfileApplyArguments {
+            name,
+            database_file,
+            reinstall,
+            no_confirm,
+        }) => {
+            let catalog_database = database_with_remote_index(
+                database_file,
+                global_arguments.offline_flag,
+                global_arguments.verify_checksum,
+            )?;
+            profile_apply_inner(
+                catalog_database.path.clone(),
+                &paths::profiles_directory(),
+                name,
+                InstallOptions {
+                    reinstall,
+                    no_confirm,
+                    hook_output: global_arguments.output_kind().into(),
+                    record_history: true,
+                    script_timeout: Some(Duration::from_secs(DEFAULT_SCRIPT_TIMEOUT_SECONDS)),
+                    no_scripts: global_arguments.no_scripts,
+                    ..Default::default()
+                },
+                package_backend(global_arguments.package_manager)?.as_mut(),
+            )
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::actions::generate_database::generate_database_inner;
+    use crate::test_support::TestDirectory;
+    use std::fs;
+
+    #[test]
+    pub fn profiles_capture_installed_driver_options() {
+        let directory = TestDirectory::new("profile");
+        let input_file = directory.join("input_data.yaml");
+        let database_file = directory.join("database.db");
+        fs::write(
+            &input_file,
+            r#"
+- name: Nvidia dGPU
+  hardware_kind: graphics
+  hardware_list: !pci
+      vendor: 10de
+      devices: [1b80]
+  driver_options:
+    - name: Nvidia
+      order: 50
+      packages: [nvidia, nvidia-utils]
+    - name: Nvidia Open
+      order: 60
+      packages: [nvidia-open, nvidia-utils]
+    - name: Nouveau
+      order: 100
+      packages: [xf86-video-nouveau]
+- name: Realtek USB Ethernet
+  hardware_kind: ethernet
+  hardware_list: !usb
+      vendor-id: 0bda
+      device-ids: [8153]
+  driver_options:
+    - name: Realtek r8152
+      packages: [r8152-dkms]
+"#,
+        )
+        .unwrap();
+        generate_database_inner(input_file, database_file.clone(), false).unwrap();
+
+        let installed = ["nvidia", "nvidia-open", "nvidia-utils"];
+        let profile = installed_profile(
+            &driver_options_by_kind(database_file, &EntryDecoder::new()).unwrap(),
+            |package| installed.contains(&package),
+        );
+        assert_eq!(
+            profile.driver_options,
+            BTreeMap::from([(HardwareKind::Graphics, "Nvidia".to_string())])
+        );
+
+        let profiles_directory = directory.join("profiles");
+        let profile_file = profile_path(&profiles_directory, "gaming").unwrap();
+        profile.save(&profile_file).unwrap();
+        assert_eq!(Profile::load(&profile_file).unwrap(), profile);
+        assert!(matches!(
+            profile_path(&profiles_directory, "../gaming"),
+            Err(Error::InvalidProfileName { .. })
+        ));
+    }
+}
@@ -0,0 +1,321 @@
+use crate::{
+    actions::{install::removable_packages, list::driver_options_by_kind},
+    arch::{package_backend, PackageBackend},
+    cli::{to_json, CommandlinePrint, GlobalArguments, PruneActionArguments},
+    data::{
+        database::EntryDecoder, install_log::read_install_log,
+        remote_index::database_with_remote_index,
+    },
+    error::Error,
+    paths, privileges,
+};
+use owo_colors::{OwoColorize, Stream::Stdout};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeSet,
+    path::{Path, PathBuf},
+};
+
+#[derive(Default, Debug, Clone, Serialize, Deserialize)]
+pub struct PruneActionOutput {
+    /// The installed packages that were once installed for a driver option, but that no
+    /// driver option in the database lists anymore.
+    pub abandoned_packages: Vec<String>,
+
+    /// The abandoned packages that are kept because other installed packages depend on them.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub required_packages: Vec<String>,
+
+    /// Whether the abandoned packages were removed, or only listed.
+    pub removed: bool,
+}
+
+impl CommandlinePrint for PruneActionOutput {
+    fn print(&self) {
+        let heading = if self.removed {
+            "Removed:"
+        } else {
+            "Abandoned:"
+        };
+        for package in self.abandoned_packages.iter() {
+            println!(
+                "{} {}",
+                heading.if_supports_color(Stdout, |text| text.yellow()),
+                package
+            );
+        }
+        for package in self.required_packages.iter() {
+            println!(
+                "{} {} (other installed packages depend on it)",
+                "Kept:".if_supports_color(Stdout, |text| text.green()),
+                package
+            );
+        }
+        if !self.removed && !self.abandoned_packages.is_empty() {
+            println!(
+                "{} Pass --yes to remove the abandoned packages",
+                "NOTE:".if_supports_color(Stdout, |text| text.yellow()),
+            );
+        }
+    }
+
+    fn print_json(&self) {
+        println!("{}", to_json(&self).unwrap_or_else(|_| {
+            eprintln!("The output could not be converted to JSON. Please try another output format...");
+            String::from("")
+        }));
+    }
+
+    fn print_plain(&self) {
+        for package in self.abandoned_packages.iter() {
+            println!("{}", package);
+        }
+    }
+
+    fn print_debug(&self) {
+        self.print();
+    }
+
+    fn is_empty_result(&self) -> bool {
+        self.abandoned_packages.is_empty()
+    }
+}
+
+/// Finds the installed packages that the install log records as added for a driver
+/// option, but that no driver option in the database lists anymore, and removes them
+/// when asked to. Packages never installed by this tool, including the ones a driver
+/// option listed but that were installed already, are not considered, so unrelated
+/// software is left alone.
+pub fn prune_inner(
+    database_filepath: PathBuf,
+    install_log_path: &Path,
+    remove: bool,
+    package_backend: &mut dyn PackageBackend,
+) -> Result<PruneActionOutput, Error> {
+    let installed_by_driver_options: BTreeSet<String> = read_install_log(install_log_path)?
+        .into_iter()
+        .filter(|entry| entry.error.is_none())
+        .flat_map(|entry| entry.packages_added)
+        .collect();
+    let referenced_packages: BTreeSet<String> =
+        driver_options_by_kind(database_filepath, &EntryDecoder::new())?
+            .into_values()
+            .flatten()
+            // Optional packages are installed with `--with-optional`, and logged like the others
+            .flat_map(|driver_option| {
+                driver_option
+                    .packages
+                    .into_iter()
+                    .chain(driver_option.optional_packages)
+            })
+            .collect();
+
+    let candidates: BTreeSet<&String> = installed_by_driver_options
+        .difference(&referenced_packages)
+        .filter(|package| package_backend.get(package).is_some())
+        .collect();
+    let abandoned_packages: Vec<String> = removable_packages(candidates.clone(), package_backend)
+        .into_iter()
+        .cloned()
+        .collect();
+    let required_packages: Vec<String> = candidates
+        .into_iter()
+        .filter(|package| !abandoned_packages.contains(package))
+        .cloned()
+        .collect();
+
+    let removed = remove && !abandoned_packages.is_empty();
+    if removed {
+        package_backend.remove(&abandoned_packages)?;
+    }
+    Ok(PruneActionOutput {
+        abandoned_packages,
+        required_packages,
+        removed,
+    })
+}
+
+pub fn prune(
+    prune_action_arguments: PruneActionArguments,
+    global_arguments: &GlobalArguments,
+) -> Result<PruneActionOutput, Error> {
+    if prune_action_arguments.yes {
+        privileges::escalate_if_needed()?;
+    }
+    let catalog_database = database_with_remote_index(
+        prune_action_arguments.database_file,
+        global_arguments.offline_flag,
+        global_arguments.verify_checksum,
+    )?;
+    prune_inner(
+        catalog_database.path.clone(),
+        Path::new(paths::INSTALL_LOG_PATH),
+        prune_action_arguments.yes,
+        package_backend(global_arguments.package_manager)?.as_mut(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::TestDirectory;
+    use crate::{
+        actions::generate_database::generate_database_inner,
+        arch::MockBackend,
+        data::{
+            input_file::HardwareKind,
+            install_log::{append_install_log, InstallLogEntry},
+        },
+    };
+    use std::fs;
+
+    #[test]
+    pub fn only_abandoned_driver_packages_are_pruned() {
+        let directory = TestDirectory::new("prune");
+        let input_file = directory.join("input_data.yaml");
+        let database_file = directory.join("database.db");
+        let install_log = directory.join("install.log");
+        fs::write(
+            &input_file,
+            r#"
+- name: Nvidia dGPU
+  hardware_kind: graphics
+  hardware_list: !pci
+      vendor: 10de
+      devices: [1b80]
+  driver_options:
+    - name: Nvidia
+      packages: [nvidia, nvidia-utils]
+"#,
+        )
+        .unwrap();
+        generate_database_inner(input_file, database_file.clone(), false).unwrap();
+
+        let packages = |names: &[&str]| {
+            names
+                .iter()
+                .map(|name| name.to_string())
+                .collect::<Vec<String>>()
+        };
+        let log =
+            |driver_option: &str, installed: &[&str], added: &[&str], error: Option<String>| {
+                append_install_log(
+                    &install_log,
+                    &InstallLogEntry::now(
+                        HardwareKind::Graphics,
+                        driver_option,
+                        &packages(installed),
+                        &packages(added),
+                        &[],
+                        error,
+                    ),
+                )
+                .unwrap()
+            };
+        // The user had nvidia-390xx-settings before this tool installed Nvidia 390xx
+        log(
+            "Nvidia 390xx",
+            &[
+                "nvidia-390xx",
+                "nvidia-390xx-utils",
+                "nvidia-390xx-settings",
+            ],
+            &["nvidia-390xx", "nvidia-390xx-utils"],
+            None,
+        );
+        log(
+            "Nvidia 340xx",
+            &["nvidia-340xx"],
+            &["nvidia-340xx"],
+            Some("failed".to_string()),
+        );
+        log(
+            "Nvidia",
+            &["nvidia", "nvidia-utils"],
+            &["nvidia", "nvidia-utils"],
+            None,
+        );
+
+        let mut package_backend = MockBackend::new([
+            ("nvidia", "1"),
+            ("nvidia-utils", "1"),
+            ("nvidia-390xx", "1"),
+            ("nvidia-390xx-utils", "1"),
+            ("nvidia-390xx-settings", "1"),
+            ("nvidia-340xx", "1"),
+            ("steam", "1"),
+        ])
+        .with_dependency("steam", "nvidia-390xx-utils")
+        .with_installed([
+            "nvidia",
+            "nvidia-utils",
+            "nvidia-390xx",
+            "nvidia-390xx-utils",
+            "nvidia-390xx-settings",
+            "nvidia-340xx",
+            "steam",
+        ]);
+
+        let listed = prune_inner(
+            database_file.clone(),
+            &install_log,
+            false,
+            &mut package_backend,
+        )
+        .unwrap();
+        assert_eq!(listed.abandoned_packages, ["nvidia-390xx"]);
+        assert_eq!(listed.required_packages, ["nvidia-390xx-utils"]);
+        assert!(!listed.removed);
+        assert!(package_backend.get("nvidia-390xx").is_some());
+
+        let pruned = prune_inner(database_file, &install_log, true, &mut package_backend).unwrap();
+        assert!(pruned.removed);
+        assert!(package_backend.get("nvidia-390xx").is_none());
+        assert!(package_backend.get("nvidia-340xx").is_some());
+        assert!(package_backend.get("nvidia-390xx-settings").is_some());
+        assert!(package_backend.get("nvidia").is_some());
+    }
+
+    #[test]
+    pub fn installed_optional_packages_survive_a_prune() {
+        let directory = TestDirectory::new("prune-optional");
+        let input_file = directory.join("input_data.yaml");
+        let database_file = directory.join("database.db");
+        let install_log = directory.join("install.log");
+        fs::write(
+            &input_file,
+            r#"
+- name: Nvidia dGPU
+  hardware_kind: graphics
+  hardware_list: !pci
+      vendor: 10de
+      devices: [1b80]
+  driver_options:
+    - name: Nvidia
+      packages: [nvidia]
+      optional_packages: [nvidia-settings]
+"#,
+        )
+        .unwrap();
+        generate_database_inner(input_file, database_file.clone(), false).unwrap();
+        append_install_log(
+            &install_log,
+            &InstallLogEntry::now(
+                HardwareKind::Graphics,
+                "Nvidia",
+                &["nvidia".to_string(), "nvidia-settings".to_string()],
+                &["nvidia".to_string(), "nvidia-settings".to_string()],
+                &[],
+                None,
+            ),
+        )
+        .unwrap();
+
+        let mut package_backend = MockBackend::new([("nvidia", "1"), ("nvidia-settings", "1")])
+            .with_installed(["nvidia", "nvidia-settings"]);
+        let pruned = prune_inner(database_file, &install_log, true, &mut package_backend).unwrap();
+        assert!(pruned.abandoned_packages.is_empty());
+        assert!(!pruned.removed);
+        assert!(package_backend.get("nvidia-settings").is_some());
+    }
+}
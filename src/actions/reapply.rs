@@ -0,0 +1,298 @@
+use crate::{
+    actions::{
+        install::{install_driver_option, InstallOptions},
+        list::{driver_options_by_kind, InstallState},
+    },
+    arch::{package_backend, PackageBackend},
+    cli::{to_json, CommandlineFlags, CommandlinePrint, GlobalArguments, ReapplyActionArguments},
+    data::{
+        database::EntryDecoder, input_file::HardwareKind, manifest::Manifest,
+        remote_index::database_with_remote_index,
+    },
+    error::Error,
+    hooks::DEFAULT_SCRIPT_TIMEOUT_SECONDS,
+    paths, privileges,
+};
+use owo_colors::{OwoColorize, Stream::Stdout};
+use serde::{Deserialize, Serialize};
+use std::{
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+/// A driver option of the install manifest, and how far the system has drifted from it.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ManifestEntry {
+    pub hardware: HardwareKind,
+    pub driver_option: String,
+    /// How much of the driver option is installed, or `None` when the database does not
+    /// list it anymore, so that it cannot be reapplied.
+    pub install_state: Option<InstallState>,
+}
+
+impl ManifestEntry {
+    fn describe(&self) -> String {
+        match &self.install_state {
+            Some(InstallState::Installed) => String::from("installed"),
+            Some(InstallState::PartiallyInstalled { missing, .. }) => {
+                format!("partially installed, missing {}", missing.join(", "))
+            }
+            Some(InstallState::NotInstalled) => String::from("not installed"),
+            None => String::from("not in the database anymore"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReapplyActionOutput {
+    pub manifest_file: PathBuf,
+    /// The driver options of the manifest, as they were found before reapplying them.
+    pub entries: Vec<ManifestEntry>,
+    /// Whether the driver options were installed again, rather than only checked.
+    pub reapplied: bool,
+}
+
+impl CommandlinePrint for ReapplyActionOutput {
+    fn print(&self) {
+        for entry in self.entries.iter() {
+            let state = entry.describe();
+            println!(
+                "{} {} ({})",
+                format!("{}:", entry.hardware).if_supports_color(Stdout, |text| text.bold()),
+                entry
+                    .driver_option
+                    .if_supports_color(Stdout, |text| text.yellow()),
+                match entry.install_state {
+                    Some(InstallState::Installed) => state
+                        .if_supports_color(Stdout, |text| text.green())
+                        .to_string(),
+                    _ => state
+                        .if_supports_color(Stdout, |text| text.red())
+                        .to_string(),
+                }
+            );
+        }
+        if self.reapplied {
+            println!(
+                "{}",
+                format!(
+                    "Reinstalled the driver options recorded in {}",
+                    self.manifest_file.to_string_lossy()
+                )
+                .if_supports_color(Stdout, |text| text.green())
+            );
+        }
+    }
+
+    fn print_json(&self) {
+        println!("{}", to_json(&self).unwrap_or_else(|_| {
+            eprintln!("The output could not be converted to JSON. Please try another output format...");
+            String::from("")
+        }));
+    }
+
+    fn print_plain(&self) {
+        for entry in self.entries.iter() {
+            println!(
+                "{} {} {}",
+                entry.hardware.name(),
+                entry.driver_option,
+                match entry.install_state {
+                    Some(InstallState::Installed) => "installed",
+                    Some(InstallState::PartiallyInstalled { .. }) => "partially-installed",
+                    Some(InstallState::NotInstalled) => "not-installed",
+                    None => "missing",
+                }
+            );
+        }
+    }
+
+    fn print_debug(&self) {
+        self.print();
+    }
+
+    fn is_empty_result(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
+
+/// Reports how far the installed packages have drifted from the install manifest, and
+/// unless `check_only` is given, installs every driver option of the manifest again that
+/// the database still lists.
+pub fn reapply_inner(
+    database_filepath: PathBuf,
+    manifest_path: &Path,
+    check_only: bool,
+    install_options: InstallOptions,
+    package_backend: &mut dyn PackageBackend,
+) -> Result<ReapplyActionOutput, Error> {
+    let manifest = Manifest::load(manifest_path)?;
+    let mut driver_options_by_kind =
+        driver_options_by_kind(database_filepath.clone(), &EntryDecoder::new())?;
+    let driver_options: Vec<_> = manifest
+        .driver_options
+        .iter()
+        .map(|(hardware_kind, driver_option_name)| {
+            let driver_option = driver_options_by_kind
+                .remove(hardware_kind)
+                .unwrap_or_default()
+                .into_iter()
+                .find(|driver_option| &driver_option.name == driver_option_name);
+            (*hardware_kind, driver_option_name, driver_option)
+        })
+        .collect();
+    let entries = driver_options
+        .iter()
+        .map(
+            |(hardware_kind, driver_option_name, driver_option)| ManifestEntry {
+                hardware: *hardware_kind,
+                driver_option: driver_option_name.to_string(),
+                install_state: driver_option.as_ref().map(|driver_option| {
+                    InstallState::of_packages(&driver_option.packages, &*package_backend)
+                }),
+            },
+        )
+        .collect();
+    if !check_only {
+        for (hardware_kind, _, driver_option) in driver_options.iter() {
+            if let Some(driver_option) = driver_option {
+                install_driver_option(
+                    database_filepath.clone(),
+                    *hardware_kind,
+                    driver_option,
+                    install_options,
+                    package_backend,
+                )?;
+            }
+        }
+    }
+    Ok(ReapplyActionOutput {
+        manifest_file: manifest_path.to_path_buf(),
+        entries,
+        reapplied: !check_only && !driver_options.is_empty(),
+    })
+}
+
+pub fn reapply(
+    reapply_action_arguments: ReapplyActionArguments,
+    global_arguments: &GlobalArguments,
+) -> Result<ReapplyActionOutput, Error> {
+    if !reapply_action_arguments.check {
+        privileges::escalate_if_needed()?;
+    }
+    let catalog_database = database_with_remote_index(
+        reapply_action_arguments.database_file,
+        global_arguments.offline_flag,
+        global_arguments.verify_checksum,
+    )?;
+    reapply_inner(
+        catalog_database.path.clone(),
+        Path::new(paths::MANIFEST_PATH),
+        reapply_action_arguments.check,
+        InstallOptions {
+            // Reinstalling also rebuilds the DKMS modules of the packages, like for a new kernel
+            reinstall: true,
+            no_confirm: reapply_action_arguments.no_confirm,
+            hook_output: global_arguments.output_kind().into(),
+            record_history: true,
+            script_timeout: Some(Duration::from_secs(DEFAULT_SCRIPT_TIMEOUT_SECONDS)),
+            no_scripts: global_arguments.no_scripts,
+            ..Default::default()
+        },
+        package_backend(global_arguments.package_manager)?.as_mut(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::TestDirectory;
+    use crate::{actions::generate_database::generate_database_inner, arch::MockBackend};
+    use std::{collections::BTreeMap, fs};
+
+    #[test]
+    pub fn reapplying_reports_drift_and_reinstalls() {
+        let directory = TestDirectory::new("reapply");
+        let input_file = directory.join("input_data.yaml");
+        let database_file = directory.join("database.db");
+        fs::write(
+            &input_file,
+            r#"
+- name: Nvidia dGPU
+  hardware_kind: graphics
+  hardware_list: !pci
+      vendor: 10de
+      devices: [1b80]
+  driver_options:
+    - name: Nvidia
+      packages: [nvidia, nvidia-utils]
+- name: Realtek USB Ethernet
+  hardware_kind: ethernet
+  hardware_list: !usb
+      vendor-id: 0bda
+      device-ids: [8153]
+  driver_options:
+    - name: Realtek r8152
+      packages: [r8152-dkms]
+"#,
+        )
+        .unwrap();
+        generate_database_inner(input_file, database_file.clone(), false).unwrap();
+        let manifest_path = directory.join("manifest.yaml");
+        Manifest::record(&manifest_path, HardwareKind::Graphics, "Nvidia").unwrap();
+        Manifest::record(&manifest_path, HardwareKind::Ethernet, "Realtek r8152").unwrap();
+        Manifest::record(&manifest_path, HardwareKind::Audio, "Removed option").unwrap();
+        assert_eq!(
+            Manifest::load(&manifest_path).unwrap().driver_options.len(),
+            3
+        );
+
+        let mut package_backend = MockBackend::new([
+            ("nvidia", "550.54-1"),
+            ("nvidia-utils", "550.54-1"),
+            ("r8152-dkms", "2.17.1-1"),
+        ])
+        .with_installed(["nvidia", "r8152-dkms"]);
+        let reapply = |check_only: bool, package_backend: &mut MockBackend| {
+            reapply_inner(
+                database_file.clone(),
+                &manifest_path,
+                check_only,
+                InstallOptions::default(),
+                package_backend,
+            )
+            .unwrap()
+        };
+        let drift = |output: &ReapplyActionOutput| {
+            output
+                .entries
+                .iter()
+                .map(|entry| (entry.hardware, entry.install_state.clone()))
+                .collect::<BTreeMap<HardwareKind, Option<InstallState>>>()
+        };
+
+        let checked = reapply(true, &mut package_backend);
+        assert!(!checked.reapplied);
+        assert_eq!(
+            drift(&checked),
+            BTreeMap::from([
+                (
+                    HardwareKind::Graphics,
+                    Some(InstallState::PartiallyInstalled {
+                        installed: vec!["nvidia".to_string()],
+                        missing: vec!["nvidia-utils".to_string()],
+                    })
+                ),
+                (HardwareKind::Ethernet, Some(InstallState::Installed)),
+                (HardwareKind::Audio, None),
+            ])
+        );
+        assert!(!package_backend.installed.contains_key("nvidia-utils"));
+
+        assert!(reapply(false, &mut package_backend).reapplied);
+        assert_eq!(
+            drift(&reapply(true, &mut package_backend))[&HardwareKind::Graphics],
+            Some(InstallState::Installed)
+        );
+    }
+}
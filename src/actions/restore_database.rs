@@ -0,0 +1,89 @@
+use crate::{
+    cli::{to_json, CommandlinePrint, RestoreDatabaseActionArguments},
+    data::{
+        backup::{backup_database, database_backups},
+        database::write_checksum,
+    },
+    error::{DatabaseBackupSnafu, Error, NoDatabaseBackupSnafu},
+};
+use owo_colors::{OwoColorize, Stream::Stdout};
+use serde::{Deserialize, Serialize};
+use snafu::{OptionExt, ResultExt};
+use std::{fs, path::PathBuf};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RestoreDatabaseActionOutput {
+    pub database_file: PathBuf,
+    pub restored_from: PathBuf,
+    /// Where the database that was replaced has been backed up to, if it existed.
+    pub previous_database_backup: Option<PathBuf>,
+}
+
+impl CommandlinePrint for RestoreDatabaseActionOutput {
+    fn print(&self) {
+        println!(
+            "{} {} {} {}",
+            "Restored".if_supports_color(Stdout, |text| text.green()),
+            self.database_file.to_string_lossy(),
+            "from".if_supports_color(Stdout, |text| text.green()),
+            self.restored_from.to_string_lossy()
+        );
+        if let Some(previous_database_backup) = &self.previous_database_backup {
+            println!(
+                "{} {}",
+                "The replaced database was backed up to"
+                    .if_supports_color(Stdout, |text| text.green()),
+                previous_database_backup.to_string_lossy()
+            );
+        }
+    }
+
+    fn print_json(&self) {
+        println!("{}", to_json(&self).unwrap_or_else(|_| {
+            eprintln!("The output could not be converted to JSON. Please try another output format...");
+            String::from("")
+        }));
+    }
+
+    fn print_plain(&self) {
+        println!("{}", self.restored_from.to_string_lossy());
+    }
+
+    fn print_debug(&self) {
+        self.print();
+    }
+}
+
+pub fn restore_database_inner(
+    database_file: PathBuf,
+    optional_backup: Option<PathBuf>,
+) -> Result<RestoreDatabaseActionOutput, Error> {
+    let restored_from = match optional_backup {
+        Some(backup) => backup,
+        None => database_backups(&database_file)?
+            .pop()
+            .context(NoDatabaseBackupSnafu {
+                path: database_file.clone(),
+            })?,
+    };
+    // Keep the database being replaced, so that a restore can itself be undone
+    let previous_database_backup = backup_database(&database_file)?;
+    fs::copy(&restored_from, &database_file).context(DatabaseBackupSnafu {
+        path: restored_from.clone(),
+    })?;
+    write_checksum(&database_file)?;
+    Ok(RestoreDatabaseActionOutput {
+        database_file,
+        restored_from,
+        previous_database_backup,
+    })
+}
+
+pub fn restore_database(
+    restore_database_action_arguments: RestoreDatabaseActionArguments,
+) -> Result<RestoreDatabaseActionOutput, Error> {
+    restore_database_inner(
+        restore_database_action_arguments.database_file,
+        restore_database_action_arguments.backup,
+    )
+}
@@ -1,18 +1,18 @@
-use crate::data::input_file::HardwareKind;
+use crate::data::input_file::{ranked_by_preference, HardwareKind, License, MatchSpecificity};
 use crate::{
-    cli::{CommandlinePrint, SearchActionArguments},
-    data::database::DriverDatabase,
-    data::{
-        database::{HardwareId, PciId, UsbId},
-        input_file::{DriverOption, HardwareSetup},
-    },
+    actions::{list::InstallState, list_tags::check_tags_exist},
+    arch::{package_backend, PackageBackend},
+    cli::{new_table, to_json, CommandlinePrint, GlobalArguments, SearchActionArguments},
+    data::database::{pci_range_keys, DriverDatabase, EntryDecoder, HardwareId},
+    data::input_file::{DriverOption, HardwareSetup},
+    data::remote_index::database_with_remote_index,
+    detection::{hardware_detector, is_virtual_machine, AssumedDetector, HardwareDetector},
     error::{DatabaseSnafu, Error},
 };
-use devices;
+use comfy_table::{Attribute, Cell, Color};
 use owo_colors::{OwoColorize, Stream::Stdout};
-use serde::{Deserialize, Serialize};
+use serde::{ser::SerializeMap, Deserialize, Serialize, Serializer};
 use snafu::ResultExt;
-use speedy::Readable;
 use std::{
     collections::{BTreeMap, BTreeSet},
     fmt::Display,
@@ -20,20 +20,250 @@ use std::{
     path::PathBuf,
 };
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
-#[serde(transparent)]
+/// A hardware setup that a driver option was found through, and the detected
+/// hardware IDs that made it match.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct SearchMatch {
+    pub hardware_setup: String,
+    pub hardware_ids: BTreeSet<HardwareId>,
+    #[serde(default)]
+    pub specificity: MatchSpecificity,
+}
+
+impl Display for SearchMatch {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} ({})",
+            self.hardware_setup,
+            self.hardware_ids
+                .iter()
+                .map(|hardware_id| hardware_id.to_string())
+                .collect::<Vec<String>>()
+                .join(", ")
+        )
+    }
+}
+
+pub type ExplainedDriverOptions =
+    BTreeMap<HardwareKind, BTreeMap<DriverOption, BTreeSet<SearchMatch>>>;
+
+/// How specific the best hardware setup that each driver option was found through is,
+/// by hardware kind and driver option name, for ranking them.
+pub type Specificities = BTreeMap<HardwareKind, BTreeMap<String, MatchSpecificity>>;
+
+/// The [`Specificities`] of the driver options found by a search.
+pub fn specificities(explained_driver_options: &ExplainedDriverOptions) -> Specificities {
+    explained_driver_options
+        .iter()
+        .map(|(hardware_kind, driver_options)| {
+            (
+                *hardware_kind,
+                driver_options
+                    .iter()
+                    .filter_map(|(driver_option, search_matches)| {
+                        search_matches
+                            .iter()
+                            .map(|search_match| search_match.specificity)
+                            .max()
+                            .map(|specificity| (driver_option.name.clone(), specificity))
+                    })
+                    .collect(),
+            )
+        })
+        .collect()
+}
+
+#[derive(Debug, Clone)]
 pub struct SearchActionOutput {
     inner: BTreeMap<HardwareKind, BTreeSet<DriverOption>>,
+    /// Present when the output should explain why each driver option matched.
+    explanations: Option<ExplainedDriverOptions>,
+    /// The hardware kind listed before the others, when searching every kind.
+    first_kind: Option<HardwareKind>,
+    /// The database entries skipped because they could not be decoded.
+    skipped_entries: usize,
+    /// Present when the output should show how much of each driver option is installed.
+    install_states: Option<InstallStates>,
+    /// The tags of driver options listed ahead of others of the same priority.
+    preferred_tags: BTreeSet<String>,
+    /// How specifically the driver options were matched, to list the closest matches first.
+    specificities: Specificities,
+}
+
+/// How much of each driver option is installed, by hardware kind and driver option name.
+pub type InstallStates = BTreeMap<HardwareKind, BTreeMap<String, InstallState>>;
+
+/// The entries of a map grouped by hardware kind, with the entry of `first_kind` first
+/// and the others in their usual order.
+fn in_display_order<V>(
+    grouped: &BTreeMap<HardwareKind, V>,
+    first_kind: Option<HardwareKind>,
+) -> impl Iterator<Item = (&HardwareKind, &V)> {
+    let first = first_kind.and_then(|kind| grouped.get_key_value(&kind));
+    first.into_iter().chain(
+        grouped
+            .iter()
+            .filter(move |(kind, _)| Some(**kind) != first_kind),
+    )
 }
 
 impl SearchActionOutput {
     pub fn new() -> Self {
         SearchActionOutput {
             inner: BTreeMap::<HardwareKind, BTreeSet<DriverOption>>::new(),
+            explanations: None,
+            first_kind: None,
+            skipped_entries: 0,
+            install_states: None,
+            preferred_tags: BTreeSet::new(),
+            specificities: Specificities::new(),
+        }
+    }
+
+    /// The driver options of a hardware kind in the order they are listed.
+    fn ranked<'a>(
+        &self,
+        hardware_kind: &HardwareKind,
+        driver_options: &'a BTreeSet<DriverOption>,
+    ) -> Vec<&'a DriverOption> {
+        ranked_by_preference(
+            driver_options,
+            &self.preferred_tags,
+            &self
+                .specificities
+                .get(hardware_kind)
+                .cloned()
+                .unwrap_or_default(),
+        )
+    }
+
+    fn in_display_order(&self) -> impl Iterator<Item = (&HardwareKind, &BTreeSet<DriverOption>)> {
+        in_display_order(&self.inner, self.first_kind)
+    }
+
+    fn matches(
+        &self,
+        hardware_kind: &HardwareKind,
+        driver_option: &DriverOption,
+    ) -> Option<&BTreeSet<SearchMatch>> {
+        self.explanations
+            .as_ref()?
+            .get(hardware_kind)?
+            .get(driver_option)
+    }
+
+    fn install_state(
+        &self,
+        hardware_kind: &HardwareKind,
+        driver_option: &DriverOption,
+    ) -> Option<&InstallState> {
+        self.install_states
+            .as_ref()?
+            .get(hardware_kind)?
+            .get(&driver_option.name)
+    }
+
+    /// The packages of every driver option found, for `--packages`.
+    pub fn package_names(&self) -> PackageNamesOutput {
+        PackageNamesOutput {
+            inner: self
+                .inner
+                .values()
+                .flatten()
+                .flat_map(|driver_option| driver_option.packages.iter().cloned())
+                .collect(),
         }
     }
 }
 
+/// Only the names of packages, sorted and each listed once, for passing on to other
+/// programs like `pacman -S $(archlinux-driver-manager search graphics --packages)`.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct PackageNamesOutput {
+    pub inner: BTreeSet<String>,
+}
+
+impl CommandlinePrint for PackageNamesOutput {
+    fn print(&self) {
+        for package in self.inner.iter() {
+            println!("{}", package);
+        }
+    }
+
+    fn print_json(&self) {
+        println!("{}", to_json(&self).unwrap_or_else(|_| {
+            eprintln!("The output could not be converted to JSON. Please try another output format...");
+            String::from("")
+        }));
+    }
+
+    fn print_plain(&self) {
+        self.print();
+    }
+
+    fn print_debug(&self) {
+        self.print();
+    }
+
+    fn is_empty_result(&self) -> bool {
+        self.inner.is_empty()
+    }
+}
+
+#[derive(Serialize)]
+struct AnnotatedDriverOption<'a> {
+    #[serde(flatten)]
+    driver_option: &'a DriverOption,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    matched_by: Option<&'a BTreeSet<SearchMatch>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    install_state: Option<&'a InstallState>,
+}
+
+fn describe_install_state(install_state: &InstallState) -> String {
+    match install_state {
+        InstallState::Installed => String::from("Yes"),
+        InstallState::PartiallyInstalled { missing, .. } => {
+            format!("Partially, missing {}", missing.join(", "))
+        }
+        InstallState::NotInstalled => String::from("No"),
+    }
+}
+
+/// Driver options by hardware kind, along with a `skipped_entries` count when some
+/// database entries could not be decoded.
+impl Serialize for SearchActionOutput {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut map = serializer.serialize_map(None)?;
+        if self.explanations.is_none() && self.install_states.is_none() {
+            for (hardware_kind, driver_options) in self.in_display_order() {
+                map.serialize_entry(hardware_kind, &self.ranked(hardware_kind, driver_options))?;
+            }
+        } else {
+            for (hardware_kind, driver_options) in self.in_display_order() {
+                map.serialize_entry(
+                    hardware_kind,
+                    &self
+                        .ranked(hardware_kind, driver_options)
+                        .into_iter()
+                        .map(|driver_option| AnnotatedDriverOption {
+                            driver_option,
+                            matched_by: self.matches(hardware_kind, driver_option),
+                            install_state: self.install_state(hardware_kind, driver_option),
+                        })
+                        .collect::<Vec<AnnotatedDriverOption>>(),
+                )?;
+            }
+        }
+        if self.skipped_entries > 0 {
+            map.serialize_entry("skipped_entries", &self.skipped_entries)?;
+        }
+        map.end()
+    }
+}
+
 impl Deref for SearchActionOutput {
     type Target = BTreeMap<HardwareKind, BTreeSet<DriverOption>>;
 
@@ -56,13 +286,13 @@ impl Display for SearchActionOutput {
 
 impl CommandlinePrint for SearchActionOutput {
     fn print(&self) {
-        for (hardware_kind, driver_records) in self.inner.iter() {
+        for (hardware_kind, driver_records) in self.in_display_order() {
             println!(
                 "{}",
                 hardware_kind.if_supports_color(Stdout, |text| text.bold())
             );
             println!("");
-            for driver_record in driver_records.iter() {
+            for driver_record in self.ranked(hardware_kind, driver_records) {
                 println!(
                     "\t{}",
                     driver_record
@@ -84,64 +314,240 @@ impl CommandlinePrint for SearchActionOutput {
                     "Packages:".if_supports_color(Stdout, |text| text.green()),
                     driver_record.packages
                 );
+                if !driver_record.optional_packages.is_empty() {
+                    println!(
+                        "\t{} {:?}",
+                        "Optional packages:".if_supports_color(Stdout, |text| text.green()),
+                        driver_record.optional_packages
+                    );
+                }
+                if !driver_record.requires_env.is_empty() {
+                    println!(
+                        "\t{} {:?}",
+                        "Requires environment:".if_supports_color(Stdout, |text| text.green()),
+                        driver_record.requires_env
+                    );
+                }
+                if let Some(warning) = &driver_record.warning {
+                    println!(
+                        "\t{} {}",
+                        "Warning:".if_supports_color(Stdout, |text| text.red()),
+                        warning
+                    );
+                }
+                if let Some(install_state) = self.install_state(hardware_kind, driver_record) {
+                    println!(
+                        "\t{} {}",
+                        "Installed:".if_supports_color(Stdout, |text| text.green()),
+                        describe_install_state(install_state)
+                    );
+                }
+                for search_match in self
+                    .matches(hardware_kind, driver_record)
+                    .into_iter()
+                    .flatten()
+                {
+                    println!(
+                        "\t{} {}",
+                        "Matched by:".if_supports_color(Stdout, |text| text.green()),
+                        search_match
+                    );
+                }
                 println!("");
             }
         }
     }
 
     fn print_json(&self) {
-        println!("{}", serde_json::to_string(&self).unwrap_or_else(|_| {
+        println!("{}", to_json(&self).unwrap_or_else(|_| {
             eprintln!("The output could not be converted to JSON. Please try another output format...");
             String::from("")
         }));
     }
 
     fn print_plain(&self) {
-        for (hardware_kind, driver_records) in self.inner.iter() {
-            for driver_record in driver_records.iter() {
-                println!(
+        for (hardware_kind, driver_records) in self.in_display_order() {
+            for driver_record in self.ranked(hardware_kind, driver_records) {
+                print!(
                     "{} {} {:?} {} {:?}",
-                    hardware_kind.to_string().to_lowercase(),
+                    hardware_kind.name(),
                     driver_record.name,
                     driver_record.tags,
                     driver_record.description,
                     driver_record.packages,
                 );
+                if !driver_record.optional_packages.is_empty() {
+                    print!(" {:?}", driver_record.optional_packages);
+                }
+                match self.install_state(hardware_kind, driver_record) {
+                    Some(InstallState::Installed) => print!(" installed"),
+                    Some(InstallState::PartiallyInstalled { .. }) => print!(" partially-installed"),
+                    Some(InstallState::NotInstalled) => print!(" not-installed"),
+                    None => {}
+                }
+                println!();
             }
         }
     }
 
     fn print_debug(&self) {
         self.print();
+        println!(
+            "{} {}",
+            "Skipped database entries:".if_supports_color(Stdout, |text| text.yellow()),
+            self.skipped_entries
+        );
     }
-}
 
-fn hardware_ids_present() -> BTreeSet<HardwareId> {
-    devices::Devices::get()
-        .expect("Failed to get connected devices")
-        .into_iter()
-        .map(|item| match item.path() {
-            devices::DevicePath::PCI {
-                bus: _,
-                slot: _,
-                function: _,
-            } => HardwareId::Pci(PciId {
-                vendor: item.vendor_id(),
-                device: item.product_id(),
-            }),
-            devices::DevicePath::USB { bus: _, device: _ } => HardwareId::Usb(UsbId {
-                vendor: item.vendor_id(),
-                device: item.product_id(),
-            }),
-        })
-        .collect()
+    fn print_table(&self) {
+        let mut table = new_table();
+        let mut headers = vec![
+            "Hardware",
+            "Driver",
+            "Priority",
+            "Tags",
+            "Packages",
+            "Optional packages",
+        ];
+        if self.explanations.is_some() {
+            headers.push("Matched by");
+        }
+        if self.install_states.is_some() {
+            headers.push("Installed");
+        }
+        table.set_header(
+            headers
+                .into_iter()
+                .map(|header| Cell::new(header).add_attribute(Attribute::Bold)),
+        );
+        for (hardware_kind, driver_records) in self.in_display_order() {
+            for driver_record in self.ranked(hardware_kind, driver_records) {
+                let mut row = vec![
+                    Cell::new(hardware_kind),
+                    Cell::new(&driver_record.name).fg(Color::Yellow),
+                    Cell::new(driver_record.order_of_priority),
+                    Cell::new(
+                        driver_record
+                            .tags
+                            .iter()
+                            .cloned()
+                            .collect::<Vec<String>>()
+                            .join(", "),
+                    ),
+                    Cell::new(driver_record.packages.join(", ")).fg(Color::Green),
+                    Cell::new(driver_record.optional_packages.join(", ")),
+                ];
+                if self.explanations.is_some() {
+                    row.push(Cell::new(
+                        self.matches(hardware_kind, driver_record)
+                            .into_iter()
+                            .flatten()
+                            .map(|search_match| search_match.to_string())
+                            .collect::<Vec<String>>()
+                            .join("\n"),
+                    ));
+                }
+                if self.install_states.is_some() {
+                    row.push(match self.install_state(hardware_kind, driver_record) {
+                        Some(install_state @ InstallState::Installed) => {
+                            Cell::new(describe_install_state(install_state)).fg(Color::Green)
+                        }
+                        Some(install_state) => Cell::new(describe_install_state(install_state)),
+                        None => Cell::new(""),
+                    });
+                }
+                table.add_row(row);
+            }
+        }
+        println!("{table}");
+    }
+
+    fn is_empty_result(&self) -> bool {
+        self.inner
+            .values()
+            .all(|driver_options| driver_options.is_empty())
+    }
 }
 
 pub fn search_inner<T: Iterator<Item = String>>(
     database_filepath: PathBuf,
     optional_hardware: &Option<HardwareKind>,
     tags: T,
+    hardware_detector: &dyn HardwareDetector,
+    license_filter: Option<License>,
 ) -> Result<BTreeMap<HardwareKind, BTreeSet<DriverOption>>, Error> {
+    Ok(search_explained_inner(
+        database_filepath,
+        optional_hardware,
+        tags,
+        hardware_detector,
+        &EntryDecoder::new(),
+        license_filter,
+    )?
+    .into_iter()
+    .map(|(hardware_kind, driver_options)| (hardware_kind, driver_options.into_keys().collect()))
+    .collect())
+}
+
+/// Driver options by hardware kind, for each of several hardware IDs.
+pub type DriverOptionsByHardwareId =
+    BTreeMap<HardwareId, BTreeMap<HardwareKind, BTreeSet<DriverOption>>>;
+
+/// Matches the database against many hardware IDs in one pass, as if they were all
+/// present on one system, so that setups needing several devices are found too. Each
+/// driver option is listed under the hardware IDs it matched through, and every
+/// hardware ID is listed, even without driver options.
+pub fn search_many<T: Iterator<Item = String>>(
+    database_filepath: PathBuf,
+    hardware_ids: &BTreeSet<HardwareId>,
+    optional_hardware: &Option<HardwareKind>,
+    tags: T,
+    license_filter: Option<License>,
+) -> Result<DriverOptionsByHardwareId, Error> {
+    let explained_driver_options = search_explained_inner(
+        database_filepath,
+        optional_hardware,
+        tags,
+        &AssumedDetector {
+            hardware_ids: hardware_ids.iter().cloned().collect(),
+        },
+        &EntryDecoder::new(),
+        license_filter,
+    )?;
+    let mut driver_options_by_hardware_id: DriverOptionsByHardwareId = hardware_ids
+        .iter()
+        .map(|hardware_id| (hardware_id.clone(), BTreeMap::new()))
+        .collect();
+    for (hardware_kind, driver_options) in explained_driver_options {
+        for (driver_option, search_matches) in driver_options {
+            for hardware_id in search_matches
+                .iter()
+                .flat_map(|search_match| search_match.hardware_ids.iter())
+            {
+                if let Some(driver_options_by_kind) =
+                    driver_options_by_hardware_id.get_mut(hardware_id)
+                {
+                    driver_options_by_kind
+                        .entry(hardware_kind)
+                        .or_default()
+                        .insert(driver_option.clone());
+                }
+            }
+        }
+    }
+    Ok(driver_options_by_hardware_id)
+}
+
+/// Like `search_inner`, but keeps track of the hardware setups and detected hardware IDs
+/// that each driver option was found through.
+pub fn search_explained_inner<T: Iterator<Item = String>>(
+    database_filepath: PathBuf,
+    optional_hardware: &Option<HardwareKind>,
+    tags: T,
+    hardware_detector: &dyn HardwareDetector,
+    entry_decoder: &EntryDecoder,
+    license_filter: Option<License>,
+) -> Result<ExplainedDriverOptions, Error> {
     let driver_database = DriverDatabase::cloned_from_database_path(database_filepath)?;
 
     // Open a read-only transaction to get the data
@@ -157,74 +563,1229 @@ pub fn search_inner<T: Iterator<Item = String>>(
         .get_bucket("hardware_setup_id_to_hardware_setup_bucket")
         .context(DatabaseSnafu)?;
 
-    let hardware_ids_present = hardware_ids_present();
+    let hardware_ids_present = hardware_detector.detect();
+    let dmi_identity = hardware_detector.dmi_identity();
+    let modaliases = hardware_detector.modaliases();
+    let is_virtual_machine = is_virtual_machine(&hardware_ids_present, dmi_identity.as_ref());
+
+    // Only the hardware setups indexed under a detected hardware ID can match
+    let mut hardware_setup_ids = BTreeSet::<String>::new();
+    for hardware_id in hardware_ids_present.iter() {
+        let (bucket_name, mut keys) = hardware_id.lookup_bucket_and_keys();
+        let hardware_id_to_hardware_setup_id_bucket =
+            transaction.get_bucket(bucket_name).context(DatabaseSnafu)?;
+        if let HardwareId::Pci(pci_id) = hardware_id {
+            keys.extend(pci_range_keys(
+                &hardware_id_to_hardware_setup_id_bucket,
+                pci_id,
+            ));
+        }
+        for key in keys {
+            if let Some(data) = hardware_id_to_hardware_setup_id_bucket.get(&key) {
+                hardware_setup_ids.extend(
+                    entry_decoder
+                        .decode::<BTreeSet<String>>(bucket_name, &key, data.kv().value())
+                        .unwrap_or_default(),
+                );
+            }
+        }
+    }
 
     if let Some(hardware_kind) = optional_hardware {
-        if let Some(data) = hardware_kind_to_hardware_setup_id_bucket.get(hardware_kind.to_string())
-        {
-            let hardware_setup_ids: BTreeSet<String> =
-                BTreeSet::<String>::read_from_buffer(data.kv().value()).unwrap();
-            return Ok(hardware_setup_ids
-                .iter()
-                .filter_map(|hardware_setup_id| {
-                    if let Some(hardware_setup_data) =
-                        hardware_setup_id_to_hardware_setup_bucket.get(hardware_setup_id)
-                    {
-                        HardwareSetup::read_from_buffer(hardware_setup_data.kv().value()).ok()
-                    } else {
-                        None
-                    }
+        let hardware_setup_ids_of_kind = hardware_kind_to_hardware_setup_id_bucket
+            .get(hardware_kind.to_string())
+            .and_then(|data| {
+                entry_decoder.decode::<BTreeSet<String>>(
+                    "hardware_kind_to_hardware_setup_id_bucket",
+                    data.kv().key(),
+                    data.kv().value(),
+                )
+            })
+            .unwrap_or_default();
+        hardware_setup_ids
+            .retain(|hardware_setup_id| hardware_setup_ids_of_kind.contains(hardware_setup_id));
+    }
+
+    let hardware_setups: Vec<HardwareSetup> = hardware_setup_ids
+        .iter()
+        .filter_map(|hardware_setup_id| {
+            if let Some(hardware_setup_data) =
+                hardware_setup_id_to_hardware_setup_bucket.get(hardware_setup_id)
+            {
+                entry_decoder.decode::<HardwareSetup>(
+                    "hardware_setup_id_to_hardware_setup_bucket",
+                    hardware_setup_id.as_bytes(),
+                    hardware_setup_data.kv().value(),
+                )
+            } else {
+                None
+            }
+        })
+        .collect();
+
+    let mut matching_hardware_setups: Vec<(HardwareSetup, BTreeSet<HardwareId>)> = hardware_setups
+        .into_iter()
+        // Without driver options it has nothing to offer, and must not take precedence
+        // over the hardware setups that do
+        .filter(|hardware_setup| !hardware_setup.driver_options.is_empty())
+        .filter(|hardware_setup| hardware_setup.matches_dmi(dmi_identity.as_ref()))
+        .filter(|hardware_setup| hardware_setup.matches_virtualization(is_virtual_machine))
+        .filter_map(|hardware_setup| {
+            hardware_setup
+                .matching_hardware_ids(&hardware_ids_present, optional_hardware)
+                .and_then(|hardware_ids| {
+                    hardware_setup.matching_modaliases(hardware_ids, modaliases.as_ref())
                 })
-                .fold(
-                    BTreeMap::<HardwareKind, BTreeSet<DriverOption>>::new(),
-                    |mut grouped_driver_options, hardware_setup: HardwareSetup| {
-                        if let Some(more_driver_options) = hardware_setup.matching_driver_options(
-                            &hardware_ids_present,
-                            &optional_hardware,
-                            &filter_tags,
-                        ) {
-                            grouped_driver_options
-                                .entry(hardware_setup.hardware_kind.clone())
-                                .or_default()
-                                .extend(more_driver_options.into_iter().map(|item| item.clone()));
-                        }
-                        grouped_driver_options
-                    },
-                ));
-        } else {
-            return Ok(BTreeMap::<HardwareKind, BTreeSet<DriverOption>>::new());
+                .map(|hardware_ids| (hardware_setup, hardware_ids))
+        })
+        .collect();
+
+    // A hardware setup for a specific revision takes precedence over one that
+    // matches the same devices regardless of their revision
+    let revision_specific_hardware_ids = matching_hardware_setups
+        .iter()
+        .filter(|(hardware_setup, _)| hardware_setup.hardware_list.specifies_revision())
+        .fold(
+            BTreeMap::<HardwareKind, BTreeSet<HardwareId>>::new(),
+            |mut grouped_hardware_ids, (hardware_setup, hardware_ids)| {
+                grouped_hardware_ids
+                    .entry(hardware_setup.hardware_kind)
+                    .or_default()
+                    .extend(hardware_ids.iter().cloned());
+                grouped_hardware_ids
+            },
+        );
+    matching_hardware_setups.retain(|(hardware_setup, hardware_ids)| {
+        hardware_setup.hardware_list.specifies_revision()
+            || !revision_specific_hardware_ids
+                .get(&hardware_setup.hardware_kind)
+                .is_some_and(|revision_specific| hardware_ids.is_subset(revision_specific))
+    });
+
+    let grouped_driver_options = matching_hardware_setups.into_iter().fold(
+        ExplainedDriverOptions::new(),
+        |mut grouped_driver_options, (hardware_setup, hardware_ids)| {
+            let search_match = SearchMatch {
+                hardware_setup: hardware_setup.name.clone(),
+                specificity: hardware_setup
+                    .hardware_list
+                    .match_specificity(&hardware_ids)
+                    .unwrap_or_default(),
+                hardware_ids,
+            };
+            let driver_options = grouped_driver_options
+                .entry(hardware_setup.hardware_kind)
+                .or_default();
+            for driver_option in hardware_setup
+                .driver_options_with_tags(&filter_tags)
+                .into_iter()
+                .filter(|driver_option| {
+                    license_filter.is_none_or(|license| driver_option.license() == Some(license))
+                })
+            {
+                driver_options
+                    .entry(driver_option.clone())
+                    .or_default()
+                    .insert(search_match.clone());
+            }
+            grouped_driver_options
+        },
+    );
+    Ok(grouped_driver_options
+        .into_iter()
+        .map(|(hardware_kind, driver_options)| {
+            (hardware_kind, merge_driver_options(driver_options))
+        })
+        .collect())
+}
+
+/// Merges the driver options of the same name that several matching hardware setups
+/// list, keeping the one from the most specific setup, or the best ranked one among
+/// equally specific setups, along with all the setups that it was found through.
+fn merge_driver_options(
+    driver_options: BTreeMap<DriverOption, BTreeSet<SearchMatch>>,
+) -> BTreeMap<DriverOption, BTreeSet<SearchMatch>> {
+    // Driver options are ordered by priority first, so only a more specific match
+    // replaces the first one of each name
+    let mut merged = BTreeMap::<
+        String,
+        (
+            DriverOption,
+            Option<MatchSpecificity>,
+            BTreeSet<SearchMatch>,
+        ),
+    >::new();
+    for (driver_option, search_matches) in driver_options {
+        let specificity = search_matches
+            .iter()
+            .map(|search_match| search_match.specificity)
+            .max();
+        let (kept_driver_option, kept_specificity, kept_search_matches) = merged
+            .entry(driver_option.name.clone())
+            .or_insert_with(|| (driver_option.clone(), specificity, BTreeSet::new()));
+        if specificity > *kept_specificity {
+            *kept_driver_option = driver_option;
+            *kept_specificity = specificity;
         }
-    } else {
-        return Ok(hardware_setup_id_to_hardware_setup_bucket
-            .kv_pairs()
-            .filter_map(|data| HardwareSetup::read_from_buffer(data.value()).ok())
-            .fold(
-                BTreeMap::<HardwareKind, BTreeSet<DriverOption>>::new(),
-                |mut grouped_driver_options, hardware_setup: HardwareSetup| {
-                    if let Some(more_driver_options) = hardware_setup.matching_driver_options(
-                        &hardware_ids_present,
-                        &optional_hardware,
-                        &filter_tags,
-                    ) {
-                        grouped_driver_options
-                            .entry(hardware_setup.hardware_kind.clone())
-                            .or_default()
-                            .extend(more_driver_options.into_iter().map(|item| item.clone()));
-                    }
-                    grouped_driver_options
-                },
-            ));
+        kept_search_matches.extend(search_matches);
     }
+    merged
+        .into_values()
+        .map(|(driver_option, _, search_matches)| (driver_option, search_matches))
+        .collect()
+}
+
+/// How much of each driver option is installed, going by its required packages.
+pub fn install_states(
+    driver_options: &BTreeMap<HardwareKind, BTreeSet<DriverOption>>,
+    package_backend: &dyn PackageBackend,
+) -> InstallStates {
+    driver_options
+        .iter()
+        .map(|(hardware_kind, driver_options)| {
+            (
+                *hardware_kind,
+                driver_options
+                    .iter()
+                    .map(|driver_option| {
+                        (
+                            driver_option.name.clone(),
+                            InstallState::of_packages(&driver_option.packages, package_backend),
+                        )
+                    })
+                    .collect(),
+            )
+        })
+        .collect()
 }
 
 pub fn search<'a>(
     search_action_arguments: SearchActionArguments,
+    global_arguments: &GlobalArguments,
 ) -> Result<SearchActionOutput, Error> {
+    let catalog_database = database_with_remote_index(
+        search_action_arguments.database_file,
+        global_arguments.offline_flag,
+        global_arguments.verify_checksum,
+    )?;
+    if global_arguments.strict_tags {
+        check_tags_exist(
+            catalog_database.path.clone(),
+            search_action_arguments
+                .tags
+                .iter()
+                .chain(search_action_arguments.prefer.iter()),
+        )?;
+    }
+    let entry_decoder = EntryDecoder::new();
+    // With --all-kinds the hardware argument only decides which kind is listed first
+    let (searched_hardware, first_kind) = if search_action_arguments.all_kinds {
+        (None, search_action_arguments.hardware)
+    } else {
+        (search_action_arguments.hardware, None)
+    };
+    let mut explained_driver_options = search_explained_inner(
+        catalog_database.path.clone(),
+        &searched_hardware,
+        search_action_arguments.tags.into_iter(),
+        hardware_detector(
+            global_arguments.offline_flag,
+            global_arguments.assume_hardware.as_deref(),
+            global_arguments.detector,
+            global_arguments.vendor_filter(),
+        )?
+        .as_ref(),
+        &entry_decoder,
+        global_arguments.license_filter(),
+    )?;
+    // A hardware kind that was asked for is always listed, even without driver options
+    if let Some(hardware_kind) = search_action_arguments.hardware {
+        explained_driver_options.entry(hardware_kind).or_default();
+    }
+    let driver_options: BTreeMap<HardwareKind, BTreeSet<DriverOption>> = explained_driver_options
+        .iter()
+        .map(|(hardware_kind, driver_options)| {
+            (*hardware_kind, driver_options.keys().cloned().collect())
+        })
+        .collect();
+    // Opening the package manager is only worth it when asked to compare
+    let install_states = if search_action_arguments.compare_installed {
+        Some(install_states(
+            &driver_options,
+            package_backend(global_arguments.package_manager)?.as_ref(),
+        ))
+    } else {
+        None
+    };
+    let specificities = specificities(&explained_driver_options);
     Ok(SearchActionOutput {
-        inner: search_inner(
-            search_action_arguments.database_file,
-            &search_action_arguments.hardware,
-            search_action_arguments.tags.into_iter(),
-        )?,
+        inner: driver_options,
+        explanations: search_action_arguments
+            .explain
+            .then_some(explained_driver_options),
+        first_kind,
+        skipped_entries: entry_decoder.skipped(),
+        install_states,
+        preferred_tags: search_action_arguments.prefer.into_iter().collect(),
+        specificities,
     })
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::TestDirectory;
+    use crate::{
+        actions::generate_database::generate_database_inner,
+        arch::MockBackend,
+        data::database::{pci_range_key, HardwareId, PciId, UsbId, PCI_ID_BUCKET},
+        detection::{DmiIdentity, MockDetector},
+    };
+    use std::fs;
+
+    const INPUT_DATA: &str = r#"
+- name: Nvidia dGPU
+  hardware_kind: graphics
+  hardware_list: !pci
+      vendor: 10de
+      devices: [1b80, 1c03]
+  driver_options:
+    - name: Nvidia
+      order: 50
+      tags: [nvidia, proprietary]
+      packages: [nvidia, nvidia-utils]
+    - name: Nouveau
+      order: 100
+      tags: [nouveau, free]
+      packages: [xf86-video-nouveau]
+- name: Realtek USB Ethernet
+  hardware_kind: ethernet
+  hardware_list: !usb
+      vendor-id: 0bda
+      device-ids: [8153]
+  driver_options:
+    - name: Realtek r8152
+      tags: [realtek]
+      packages: [r8152-dkms]
+"#;
+
+    /// A database generated for a test, along with the directory that keeps it.
+    fn generated_database(name: &str) -> (TestDirectory, PathBuf) {
+        let directory = TestDirectory::new(name);
+        let input_file = directory.join("input_data.yaml");
+        let database_file = directory.join("database.db");
+        fs::write(&input_file, INPUT_DATA).unwrap();
+        generate_database_inner(input_file, database_file.clone(), false).unwrap();
+        (directory, database_file)
+    }
+
+    fn option_names(driver_options: &BTreeSet<DriverOption>) -> Vec<&str> {
+        driver_options
+            .iter()
+            .map(|driver_option| driver_option.name.as_str())
+            .collect()
+    }
+
+    #[test]
+    pub fn search_with_mock_detector() {
+        let (_directory, database_file) = generated_database("search");
+        let detector = MockDetector::new([
+            HardwareId::Pci(PciId {
+                vendor: 0x10de,
+                device: 0x1c03,
+                revision: None,
+            }),
+            HardwareId::Usb(UsbId {
+                vendor: 0x046d,
+                device: 0xc52b,
+            }),
+        ]);
+
+        let all_kinds = search_inner(
+            database_file.clone(),
+            &None,
+            Vec::new().into_iter(),
+            &detector,
+            None,
+        )
+        .unwrap();
+        assert_eq!(
+            all_kinds.keys().collect::<Vec<_>>(),
+            [&HardwareKind::Graphics]
+        );
+        assert_eq!(
+            option_names(&all_kinds[&HardwareKind::Graphics]),
+            ["Nvidia", "Nouveau"]
+        );
+
+        let tagged = search_inner(
+            database_file.clone(),
+            &Some(HardwareKind::Graphics),
+            vec!["free".to_string()].into_iter(),
+            &detector,
+            None,
+        )
+        .unwrap();
+        assert_eq!(option_names(&tagged[&HardwareKind::Graphics]), ["Nouveau"]);
+
+        let other_kind = search_inner(
+            database_file.clone(),
+            &Some(HardwareKind::Ethernet),
+            Vec::new().into_iter(),
+            &detector,
+            None,
+        )
+        .unwrap();
+        assert!(other_kind
+            .values()
+            .all(|driver_options| driver_options.is_empty()));
+
+        let nothing_detected = search_inner(
+            database_file,
+            &None,
+            Vec::new().into_iter(),
+            &MockDetector::default(),
+            None,
+        )
+        .unwrap();
+        assert!(nothing_detected.is_empty());
+    }
+
+    #[test]
+    pub fn revision_specific_setups_take_precedence() {
+        let directory = TestDirectory::new("revision");
+        let input_file = directory.join("input_data.yaml");
+        let database_file = directory.join("database.db");
+        fs::write(
+            &input_file,
+            r#"
+- name: Intel iGPU
+  hardware_kind: graphics
+  hardware_list: !pci
+      vendor: 8086
+      devices: [3e92]
+  driver_options:
+    - name: Modesetting
+      packages: [mesa]
+- name: Intel iGPU (early stepping)
+  hardware_kind: graphics
+  hardware_list: !pci
+      vendor: 8086
+      devices: [3e92]
+      revision: 0x02
+  driver_options:
+    - name: Modesetting with quirks
+      packages: [mesa, intel-quirks]
+"#,
+        )
+        .unwrap();
+        generate_database_inner(input_file, database_file.clone(), false).unwrap();
+
+        let graphics_options = |revision: u8| {
+            let detector = MockDetector::new([HardwareId::Pci(PciId {
+                vendor: 0x8086,
+                device: 0x3e92,
+                revision: Some(revision),
+            })]);
+            let driver_options = search_inner(
+                database_file.clone(),
+                &Some(HardwareKind::Graphics),
+                Vec::new().into_iter(),
+                &detector,
+                None,
+            )
+            .unwrap()
+            .remove(&HardwareKind::Graphics)
+            .unwrap_or_default();
+            option_names(&driver_options)
+                .into_iter()
+                .map(String::from)
+                .collect::<Vec<String>>()
+        };
+        assert_eq!(graphics_options(0x02), ["Modesetting with quirks"]);
+        assert_eq!(graphics_options(0x03), ["Modesetting"]);
+    }
+
+    #[test]
+    pub fn hardware_setups_without_driver_options_are_skipped() {
+        let directory = TestDirectory::new("no-options");
+        let input_file = directory.join("input_data.yaml");
+        let database_file = directory.join("database.db");
+        fs::write(
+            &input_file,
+            r#"
+- name: Intel iGPU
+  hardware_kind: graphics
+  hardware_list: !pci
+      vendor: 8086
+      devices: [3e92]
+  driver_options:
+    - name: Modesetting
+      packages: [mesa]
+- name: Intel iGPU (early stepping)
+  hardware_kind: graphics
+  hardware_list: !pci
+      vendor: 8086
+      devices: [3e92]
+      revision: 0x02
+  driver_options: []
+- name: Realtek Ethernet
+  hardware_kind: ethernet
+  hardware_list: !pci
+      vendor: 10ec
+      devices: [8168]
+  driver_options: []
+"#,
+        )
+        .unwrap();
+        let warnings: Vec<String> = crate::data::input_file::parse_input_file(input_file.clone())
+            .unwrap()
+            .iter()
+            .flat_map(|hardware_setup| hardware_setup.validation_warnings())
+            .collect();
+        assert_eq!(warnings.len(), 2);
+        generate_database_inner(input_file, database_file.clone(), false).unwrap();
+
+        let detector = MockDetector::new([
+            HardwareId::Pci(PciId {
+                vendor: 0x8086,
+                device: 0x3e92,
+                revision: Some(0x02),
+            }),
+            HardwareId::Pci(PciId {
+                vendor: 0x10ec,
+                device: 0x8168,
+                revision: None,
+            }),
+        ]);
+        let found = search_inner(
+            database_file,
+            &None,
+            Vec::new().into_iter(),
+            &detector,
+            None,
+        )
+        .unwrap();
+        // The empty revision-specific setup does not hide the general one
+        assert_eq!(
+            option_names(&found[&HardwareKind::Graphics]),
+            ["Modesetting"]
+        );
+        assert!(!found.contains_key(&HardwareKind::Ethernet));
+    }
+
+    #[test]
+    pub fn dmi_restricted_setups_need_a_matching_system() {
+        let directory = TestDirectory::new("dmi");
+        let input_file = directory.join("input_data.yaml");
+        let database_file = directory.join("database.db");
+        fs::write(
+            &input_file,
+            r#"
+- name: Intel Wireless
+  hardware_kind: wireless
+  hardware_list: !pci
+      vendor: 8086
+      devices: [24fd]
+  driver_options:
+    - name: iwlwifi
+      packages: [linux-firmware]
+- name: Intel Wireless (ThinkPad X1 Carbon)
+  hardware_kind: wireless
+  hardware_list: !pci
+      vendor: 8086
+      devices: [24fd]
+  dmi_match:
+    sys_vendor: LENOVO
+    product_name: 20HR
+  driver_options:
+    - name: iwlwifi with firmware quirks
+      packages: [linux-firmware, iwlwifi-quirks]
+"#,
+        )
+        .unwrap();
+        generate_database_inner(input_file, database_file.clone(), false).unwrap();
+
+        let wireless_options = |detector: MockDetector| {
+            let driver_options = search_inner(
+                database_file.clone(),
+                &Some(HardwareKind::Wireless),
+                Vec::new().into_iter(),
+                &detector,
+                None,
+            )
+            .unwrap()
+            .remove(&HardwareKind::Wireless)
+            .unwrap_or_default();
+            option_names(&driver_options)
+                .into_iter()
+                .map(String::from)
+                .collect::<Vec<String>>()
+        };
+        let detector = MockDetector::new([HardwareId::Pci(PciId {
+            vendor: 0x8086,
+            device: 0x24fd,
+            revision: None,
+        })]);
+        assert_eq!(wireless_options(detector.clone()), ["iwlwifi"]);
+        assert_eq!(
+            wireless_options(detector.clone().with_dmi_identity(DmiIdentity {
+                sys_vendor: "Dell Inc.".to_string(),
+                product_name: "XPS 13 9360".to_string(),
+            })),
+            ["iwlwifi"]
+        );
+        assert_eq!(
+            wireless_options(detector.with_dmi_identity(DmiIdentity {
+                sys_vendor: "lenovo".to_string(),
+                product_name: "20HR".to_string(),
+            })),
+            ["iwlwifi", "iwlwifi with firmware quirks"]
+        );
+    }
+
+    #[test]
+    pub fn virtual_machines_get_virtual_gpu_drivers() {
+        let directory = TestDirectory::new("virtual");
+        let input_file = directory.join("input_data.yaml");
+        let database_file = directory.join("database.db");
+        fs::write(
+            &input_file,
+            r#"
+- name: Nvidia dGPU
+  hardware_kind: graphics
+  hardware_list: !pci
+      vendor: 10de
+      devices: [1b80]
+  bare_metal_only: true
+  driver_options:
+    - name: Nvidia
+      packages: [nvidia]
+- name: virtio-gpu
+  hardware_kind: graphics
+  hardware_list: !pci
+      vendor: 1af4
+      devices: [1050]
+  virtual_only: true
+  driver_options:
+    - name: Mesa
+      packages: [mesa]
+"#,
+        )
+        .unwrap();
+        generate_database_inner(input_file, database_file.clone(), false).unwrap();
+
+        let graphics_options = |detector: MockDetector| {
+            let driver_options = search_inner(
+                database_file.clone(),
+                &Some(HardwareKind::Graphics),
+                Vec::new().into_iter(),
+                &detector,
+                None,
+            )
+            .unwrap()
+            .remove(&HardwareKind::Graphics)
+            .unwrap_or_default();
+            option_names(&driver_options)
+                .into_iter()
+                .map(String::from)
+                .collect::<Vec<String>>()
+        };
+        let nvidia = HardwareId::Pci(PciId {
+            vendor: 0x10de,
+            device: 0x1b80,
+            revision: None,
+        });
+        let virtio_gpu = HardwareId::Pci(PciId {
+            vendor: 0x1af4,
+            device: 0x1050,
+            revision: None,
+        });
+        assert_eq!(
+            graphics_options(MockDetector::new([nvidia.clone()])),
+            ["Nvidia"]
+        );
+        assert_eq!(
+            graphics_options(MockDetector::new([nvidia.clone(), virtio_gpu])),
+            ["Mesa"]
+        );
+        let passed_through = MockDetector::new([nvidia]).with_dmi_identity(DmiIdentity {
+            sys_vendor: "QEMU".to_string(),
+            product_name: "Standard PC (Q35 + ICH9, 2009)".to_string(),
+        });
+        assert!(graphics_options(passed_through).is_empty());
+    }
+
+    #[test]
+    pub fn pci_ranges_are_indexed_once() {
+        let directory = TestDirectory::new("pci-ranges");
+        let input_file = directory.join("input_data.yaml");
+        let database_file = directory.join("database.db");
+        let input_data = r#"
+- name: Nvidia Pascal
+  hardware_kind: graphics
+  hardware_list: !pci
+      vendor: 10de
+      devices: [1b80-1b83, 1c03]
+  driver_options:
+    - name: Nvidia
+      packages: [nvidia]
+"#;
+        fs::write(&input_file, input_data).unwrap();
+        generate_database_inner(input_file, database_file.clone(), false).unwrap();
+
+        let hardware_setup: Vec<HardwareSetup> = serde_yaml::from_str(input_data).unwrap();
+        assert_eq!(
+            hardware_setup[0].hardware_list.bucket_and_keys(),
+            BTreeSet::from([
+                (PCI_ID_BUCKET, pci_range_key(0x10de, 0x1b80, 0x1b83)),
+                HardwareId::Pci(PciId {
+                    vendor: 0x10de,
+                    device: 0x1c03,
+                    revision: None,
+                })
+                .bucket_and_key(),
+            ])
+        );
+
+        let graphics_options = |device: u16| {
+            search_inner(
+                database_file.clone(),
+                &Some(HardwareKind::Graphics),
+                Vec::new().into_iter(),
+                &MockDetector::new([HardwareId::Pci(PciId {
+                    vendor: 0x10de,
+                    device,
+                    revision: None,
+                })]),
+                None,
+            )
+            .unwrap()
+            .remove(&HardwareKind::Graphics)
+            .unwrap_or_default()
+            .len()
+        };
+        assert_eq!(graphics_options(0x1b82), 1);
+        assert_eq!(graphics_options(0x1c03), 1);
+        assert_eq!(graphics_options(0x1b84), 0);
+    }
+
+    #[test]
+    pub fn search_explains_matches() {
+        let (_directory, database_file) = generated_database("explain");
+        let detected_id = HardwareId::Pci(PciId {
+            vendor: 0x10de,
+            device: 0x1c03,
+            revision: None,
+        });
+        let detector = MockDetector::new([detected_id.clone()]);
+
+        let explained = search_explained_inner(
+            database_file,
+            &Some(HardwareKind::Graphics),
+            vec!["nvidia".to_string()].into_iter(),
+            &detector,
+            &EntryDecoder::new(),
+            None,
+        )
+        .unwrap();
+        let driver_options = &explained[&HardwareKind::Graphics];
+        assert_eq!(driver_options.len(), 1);
+        let (driver_option, matches) = driver_options.iter().next().unwrap();
+        assert_eq!(driver_option.name, "Nvidia");
+        assert_eq!(
+            matches.iter().collect::<Vec<_>>(),
+            [&SearchMatch {
+                hardware_setup: "Nvidia dGPU".to_string(),
+                hardware_ids: BTreeSet::from([detected_id]),
+                specificity: MatchSpecificity::ExactDevice,
+            }]
+        );
+        assert_eq!(
+            matches.iter().next().unwrap().to_string(),
+            "Nvidia dGPU (PCI 10de:1c03)"
+        );
+    }
+
+    #[test]
+    pub fn search_compares_installed_packages() {
+        let (_directory, database_file) = generated_database("compare-installed");
+        let detector = MockDetector::new([HardwareId::Pci(PciId {
+            vendor: 0x10de,
+            device: 0x1b80,
+            revision: None,
+        })]);
+        let driver_options = search_inner(
+            database_file,
+            &Some(HardwareKind::Graphics),
+            Vec::new().into_iter(),
+            &detector,
+            None,
+        )
+        .unwrap();
+        let package_backend = MockBackend::new([
+            ("nvidia", "550.54-1"),
+            ("nvidia-utils", "550.54-1"),
+            ("xf86-video-nouveau", "1.0.17-3"),
+        ])
+        .with_installed(["nvidia"]);
+
+        let install_states = install_states(&driver_options, &package_backend);
+        let graphics_install_states = &install_states[&HardwareKind::Graphics];
+        assert_eq!(
+            graphics_install_states["Nvidia"],
+            InstallState::PartiallyInstalled {
+                installed: vec!["nvidia".to_string()],
+                missing: vec!["nvidia-utils".to_string()],
+            }
+        );
+        assert_eq!(
+            graphics_install_states["Nouveau"],
+            InstallState::NotInstalled
+        );
+
+        let output = SearchActionOutput {
+            inner: driver_options,
+            install_states: Some(install_states),
+            ..SearchActionOutput::new()
+        };
+        let json = serde_json::to_value(&output).unwrap();
+        assert_eq!(json["Graphics"][0]["name"], "Nvidia");
+        assert_eq!(json["Graphics"][1]["install_state"], "NotInstalled");
+    }
+
+    #[test]
+    pub fn modalias_patterns_narrow_down_hardware_ids() {
+        let directory = TestDirectory::new("modalias");
+        let input_file = directory.join("input_data.yaml");
+        let database_file = directory.join("database.db");
+        fs::write(
+            &input_file,
+            r#"
+- name: Nvidia dGPU
+  hardware_kind: graphics
+  hardware_list: !pci
+      vendor: 10de
+      devices: [1b80]
+  driver_options:
+    - name: Nvidia
+- name: Nvidia dGPU by Gigabyte
+  hardware_kind: graphics
+  hardware_list: !pci
+      vendor: 10de
+      devices: [1b80]
+  modalias: ["pci:v000010DEd00001B80sv00001458sd*"]
+  driver_options:
+    - name: Nvidia with fan quirks
+- name: Nvidia dGPU by MSI
+  hardware_kind: graphics
+  hardware_list: !pci
+      vendor: 10de
+      devices: [1b80]
+  modalias: ["pci:v000010DEd00001B80sv00001462sd*"]
+  driver_options:
+    - name: Nvidia with RGB quirks
+"#,
+        )
+        .unwrap();
+        generate_database_inner(input_file, database_file.clone(), false).unwrap();
+        let detector = MockDetector::new([HardwareId::Pci(PciId {
+            vendor: 0x10de,
+            device: 0x1b80,
+            revision: Some(0xa1),
+        })]);
+        let search = |detector: &MockDetector| {
+            search_inner(
+                database_file.clone(),
+                &Some(HardwareKind::Graphics),
+                Vec::new().into_iter(),
+                detector,
+                None,
+            )
+            .unwrap()
+            .remove(&HardwareKind::Graphics)
+            .unwrap()
+        };
+
+        // Without modaliases, the hardware IDs alone decide
+        assert_eq!(
+            option_names(&search(&detector)),
+            ["Nvidia", "Nvidia with RGB quirks", "Nvidia with fan quirks"]
+        );
+        let detector =
+            detector.with_modaliases(["pci:v000010DEd00001B80sv00001458sd00003702bc03sc00i00"]);
+        assert_eq!(
+            option_names(&search(&detector)),
+            ["Nvidia", "Nvidia with fan quirks"]
+        );
+    }
+
+    #[test]
+    pub fn license_filters() {
+        let (_directory, database_file) = generated_database("license");
+        let detector = MockDetector::new([
+            HardwareId::Pci(PciId {
+                vendor: 0x10de,
+                device: 0x1b80,
+                revision: None,
+            }),
+            HardwareId::Usb(UsbId {
+                vendor: 0x0bda,
+                device: 0x8153,
+            }),
+        ]);
+        let search = |license_filter| {
+            search_inner(
+                database_file.clone(),
+                &None,
+                Vec::new().into_iter(),
+                &detector,
+                license_filter,
+            )
+            .unwrap()
+        };
+
+        let free = search(Some(License::Free));
+        assert_eq!(option_names(&free[&HardwareKind::Graphics]), ["Nouveau"]);
+        // Driver options without a license or a license tag match neither filter
+        assert!(free[&HardwareKind::Ethernet].is_empty());
+        let proprietary = search(Some(License::Proprietary));
+        assert_eq!(
+            option_names(&proprietary[&HardwareKind::Graphics]),
+            ["Nvidia"]
+        );
+        assert_eq!(search(None).len(), 2);
+
+        let driver_option: DriverOption = serde_yaml::from_str(
+            "name: Nvidia Open
+tags: [nvidia, proprietary]
+license: free
+",
+        )
+        .unwrap();
+        assert_eq!(driver_option.license(), Some(License::Free));
+    }
+
+    #[test]
+    pub fn overlapping_hardware_setups_are_merged() {
+        let directory = TestDirectory::new("merge");
+        let input_file = directory.join("input_data.yaml");
+        let database_file = directory.join("database.db");
+        fs::write(
+            &input_file,
+            r#"
+- name: Nvidia dGPU
+  hardware_kind: graphics
+  hardware_list: !pci
+      vendor: 10de
+      devices: [1b80]
+  driver_options:
+    - name: Nvidia
+      order: 50
+      packages: [nvidia, nvidia-utils]
+    - name: Nouveau
+      order: 100
+      packages: [xf86-video-nouveau]
+- name: Intel iGPU
+  hardware_kind: graphics
+  hardware_list: !pci
+      vendor: 8086
+      devices: [3e92]
+  driver_options:
+    - name: Nvidia
+      order: 20
+      packages: [nvidia, nvidia-utils, nvidia-prime]
+    - name: Intel
+      order: 50
+      packages: [mesa]
+"#,
+        )
+        .unwrap();
+        generate_database_inner(input_file, database_file.clone(), false).unwrap();
+        let detector = MockDetector::new([
+            HardwareId::Pci(PciId {
+                vendor: 0x10de,
+                device: 0x1b80,
+                revision: None,
+            }),
+            HardwareId::Pci(PciId {
+                vendor: 0x8086,
+                device: 0x3e92,
+                revision: None,
+            }),
+        ]);
+
+        let explained = search_explained_inner(
+            database_file,
+            &Some(HardwareKind::Graphics),
+            Vec::new().into_iter(),
+            &detector,
+            &EntryDecoder::new(),
+            None,
+        )
+        .unwrap();
+        let driver_options = &explained[&HardwareKind::Graphics];
+        assert_eq!(
+            driver_options
+                .keys()
+                .map(|driver_option| (driver_option.name.as_str(), driver_option.order_of_priority))
+                .collect::<Vec<(&str, u32)>>(),
+            [("Nvidia", 20), ("Intel", 50), ("Nouveau", 100)]
+        );
+        let (_, nvidia_matches) = driver_options.iter().next().unwrap();
+        assert_eq!(
+            nvidia_matches
+                .iter()
+                .map(|search_match| search_match.hardware_setup.as_str())
+                .collect::<Vec<&str>>(),
+            ["Intel iGPU", "Nvidia dGPU"]
+        );
+    }
+
+    #[test]
+    pub fn most_specific_hardware_setups_are_preferred() {
+        let directory = TestDirectory::new("specificity");
+        let input_file = directory.join("input_data.yaml");
+        let database_file = directory.join("database.db");
+        fs::write(
+            &input_file,
+            r#"
+- name: Nvidia Pascal
+  hardware_kind: graphics
+  hardware_list: !pci
+      vendor: 10de
+      devices: [1b80-1b84]
+  driver_options:
+    - name: Nvidia
+      order: 20
+      packages: [nvidia]
+- name: Nvidia GTX 1080
+  hardware_kind: graphics
+  hardware_list: !pci
+      vendor: 10de
+      devices: [1b80]
+  driver_options:
+    - name: Nvidia
+      order: 50
+      packages: [nvidia, nvidia-utils]
+- name: Realtek USB Wireless
+  hardware_kind: wireless
+  hardware_list: !usb
+      vendor: 0bda
+      devices: "*"
+  driver_options:
+    - name: Realtek
+      packages: [rtl88xxau-aircrack-dkms-git]
+    - name: Realtek Generic
+      order: 0
+      packages: [rtl8xxxu-dkms-git]
+- name: Realtek RTL8812BU
+  hardware_kind: wireless
+  hardware_list: !usb
+      vendor: 0bda
+      devices: [b812]
+  driver_options:
+    - name: Realtek
+      packages: [rtl88x2bu-dkms-git]
+"#,
+        )
+        .unwrap();
+        generate_database_inner(input_file.clone(), database_file.clone(), false).unwrap();
+        let detected_ids = BTreeSet::from([
+            HardwareId::Pci(PciId {
+                vendor: 0x10de,
+                device: 0x1b80,
+                revision: None,
+            }),
+            HardwareId::Usb(UsbId {
+                vendor: 0x0bda,
+                device: 0xb812,
+            }),
+        ]);
+
+        let hardware_setups: Vec<HardwareSetup> =
+            serde_yaml::from_str(&fs::read_to_string(input_file).unwrap()).unwrap();
+        assert_eq!(
+            hardware_setups
+                .iter()
+                .map(|hardware_setup| hardware_setup
+                    .hardware_list
+                    .match_specificity(&detected_ids))
+                .collect::<Vec<_>>(),
+            [
+                Some(MatchSpecificity::Range),
+                Some(MatchSpecificity::ExactDevice),
+                Some(MatchSpecificity::VendorWildcard),
+                Some(MatchSpecificity::ExactDevice),
+            ]
+        );
+        assert!(MatchSpecificity::ExactDevice > MatchSpecificity::Range);
+        assert!(MatchSpecificity::Range > MatchSpecificity::VendorWildcard);
+
+        let explained = search_explained_inner(
+            database_file,
+            &None,
+            Vec::new().into_iter(),
+            &MockDetector::new(detected_ids),
+            &EntryDecoder::new(),
+            None,
+        )
+        .unwrap();
+        let specificities = specificities(&explained);
+        let packages = |hardware_kind: HardwareKind| {
+            let driver_options: BTreeSet<DriverOption> =
+                explained[&hardware_kind].keys().cloned().collect();
+            ranked_by_preference(
+                &driver_options,
+                &BTreeSet::new(),
+                &specificities[&hardware_kind],
+            )
+            .into_iter()
+            .map(|driver_option| driver_option.packages.join(" "))
+            .collect::<Vec<String>>()
+        };
+        assert_eq!(packages(HardwareKind::Graphics), ["nvidia nvidia-utils"]);
+        // The exact device outranks the vendor wildcard, despite its lower priority
+        assert_eq!(
+            packages(HardwareKind::Wireless),
+            ["rtl88x2bu-dkms-git", "rtl8xxxu-dkms-git"]
+        );
+    }
+
+    #[test]
+    pub fn many_hardware_ids_are_searched_at_once() {
+        let directory = TestDirectory::new("search-many");
+        let input_file = directory.join("input_data.yaml");
+        let database_file = directory.join("database.db");
+        fs::write(
+            &input_file,
+            format!(
+                "{}{}",
+                INPUT_DATA,
+                r#"- name: Nvidia dGPU + Intel iGPU
+  hardware_kind: graphics
+  hardware_list: !each
+      - !pci
+          vendor: 10de
+          devices: [1b80]
+      - !pci
+          vendor: 8086
+          devices: [3e92]
+  driver_options:
+    - name: Nvidia Prime
+      packages: [nvidia-prime]
+"#
+            ),
+        )
+        .unwrap();
+        generate_database_inner(input_file, database_file.clone(), false).unwrap();
+        let nvidia = HardwareId::Pci(PciId {
+            vendor: 0x10de,
+            device: 0x1b80,
+            revision: None,
+        });
+        let intel = HardwareId::Pci(PciId {
+            vendor: 0x8086,
+            device: 0x3e92,
+            revision: None,
+        });
+        let realtek = HardwareId::Usb(UsbId {
+            vendor: 0x0bda,
+            device: 0x8153,
+        });
+        let logitech = HardwareId::Usb(UsbId {
+            vendor: 0x046d,
+            device: 0xc52b,
+        });
+
+        let found = search_many(
+            database_file,
+            &BTreeSet::from([
+                nvidia.clone(),
+                intel.clone(),
+                realtek.clone(),
+                logitech.clone(),
+            ]),
+            &None,
+            Vec::new().into_iter(),
+            None,
+        )
+        .unwrap();
+        let names = |hardware_id: &HardwareId, hardware_kind: HardwareKind| {
+            found[hardware_id]
+                .get(&hardware_kind)
+                .map(option_names)
+                .unwrap_or_default()
+        };
+        assert_eq!(
+            names(&nvidia, HardwareKind::Graphics),
+            ["Nvidia Prime", "Nvidia", "Nouveau"]
+        );
+        // Setups needing several devices are found through each of them
+        assert_eq!(names(&intel, HardwareKind::Graphics), ["Nvidia Prime"]);
+        assert_eq!(names(&realtek, HardwareKind::Ethernet), ["Realtek r8152"]);
+        assert!(found[&logitech].is_empty());
+    }
+
+    #[test]
+    pub fn first_kind_is_listed_first() {
+        let grouped = BTreeMap::from([
+            (HardwareKind::Graphics, 1),
+            (HardwareKind::Ethernet, 2),
+            (HardwareKind::Wireless, 3),
+        ]);
+        let kinds = |first_kind| {
+            in_display_order(&grouped, first_kind)
+                .map(|(kind, _)| *kind)
+                .collect::<Vec<HardwareKind>>()
+        };
+        assert_eq!(
+            kinds(None),
+            [
+                HardwareKind::Graphics,
+                HardwareKind::Ethernet,
+                HardwareKind::Wireless
+            ]
+        );
+        assert_eq!(
+            kinds(Some(HardwareKind::Wireless)),
+            [
+                HardwareKind::Wireless,
+                HardwareKind::Graphics,
+                HardwareKind::Ethernet
+            ]
+        );
+    }
+
+    #[test]
+    pub fn package_names_are_sorted_and_listed_once() {
+        let driver_option = |yaml: &str| serde_yaml::from_str::<DriverOption>(yaml).unwrap();
+        let mut search_action_output = SearchActionOutput::new();
+        search_action_output.insert(
+            HardwareKind::Graphics,
+            BTreeSet::from([
+                driver_option("{name: nvidia, packages: [nvidia-utils, nvidia]}"),
+                driver_option("{name: nouveau, packages: [mesa, xf86-video-nouveau]}"),
+            ]),
+        );
+        search_action_output.insert(
+            HardwareKind::Ethernet,
+            BTreeSet::from([driver_option("{name: r8168, packages: [r8168, mesa]}")]),
+        );
+        assert_eq!(
+            search_action_output
+                .package_names()
+                .inner
+                .into_iter()
+                .collect::<Vec<String>>(),
+            [
+                "mesa",
+                "nvidia",
+                "nvidia-utils",
+                "r8168",
+                "xf86-video-nouveau"
+            ]
+        );
+        assert!(SearchActionOutput::new().package_names().is_empty_result());
+    }
+}
@@ -0,0 +1,396 @@
+use crate::{
+    actions::{
+        install::{rank_driver_options, SelectionCandidate, SelectionOutcome},
+        list::list_inner,
+        search::search_explained_inner,
+    },
+    arch::{package_backend, PackageBackend},
+    cli::{to_json, CommandlinePrint, GlobalArguments, ServeActionArguments},
+    data::{
+        database::{EntryDecoder, HardwareId},
+        input_file::{DriverOption, HardwareKind, License},
+        remote_index::database_with_remote_index,
+    },
+    detection::{hardware_detector, DmiIdentity, HardwareDetector},
+    error::{Error, ServeSocketSnafu},
+};
+use owo_colors::{OwoColorize, Stream::Stdout};
+use serde::{Deserialize, Serialize};
+use snafu::ResultExt;
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fs,
+    io::{BufRead, BufReader, Write},
+    os::unix::net::{UnixListener, UnixStream},
+    path::{Path, PathBuf},
+};
+
+/// A request read from the socket, one JSON object per line, like
+/// `{"action": "search", "hardware": "graphics"}`.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "action", rename_all = "kebab-case")]
+pub enum ServeRequest {
+    Search {
+        #[serde(default)]
+        hardware: Option<HardwareKind>,
+        #[serde(default)]
+        tags: Vec<String>,
+    },
+    List {
+        #[serde(default)]
+        hardware: Option<HardwareKind>,
+        #[serde(default)]
+        tags: Vec<String>,
+    },
+    Detect,
+    /// The driver option that `install` would select, without installing anything.
+    /// Installing needs superuser privileges and a confirmation, so it is left to
+    /// the `install` subcommand.
+    InstallPlan {
+        hardware: HardwareKind,
+        #[serde(default)]
+        tags: Vec<String>,
+    },
+    /// Stops the server after answering.
+    Shutdown,
+}
+
+/// The answer to a request, written as one JSON line: `{"ok": ...}` or `{"error": "..."}`.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ServeResponse {
+    Ok(serde_json::Value),
+    Error(String),
+}
+
+/// What `install` would do for a hardware kind.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct InstallPreview {
+    pub hardware: HardwareKind,
+    pub driver_option: Option<String>,
+    pub packages: Vec<String>,
+    pub selection: Vec<SelectionCandidate>,
+}
+
+/// The hardware detected once when the server starts, so that requests do not
+/// enumerate the devices again.
+#[derive(Clone, Default, Debug)]
+pub struct DetectedHardware {
+    pub hardware_ids: BTreeSet<HardwareId>,
+    pub dmi_identity: Option<DmiIdentity>,
+    pub modaliases: Option<BTreeSet<String>>,
+}
+
+impl DetectedHardware {
+    pub fn from_detector(hardware_detector: &dyn HardwareDetector) -> Self {
+        DetectedHardware {
+            hardware_ids: hardware_detector.detect(),
+            dmi_identity: hardware_detector.dmi_identity(),
+            modaliases: hardware_detector.modaliases(),
+        }
+    }
+}
+
+impl HardwareDetector for DetectedHardware {
+    fn detect(&self) -> BTreeSet<HardwareId> {
+        self.hardware_ids.clone()
+    }
+
+    fn dmi_identity(&self) -> Option<DmiIdentity> {
+        self.dmi_identity.clone()
+    }
+
+    fn modaliases(&self) -> Option<BTreeSet<String>> {
+        self.modaliases.clone()
+    }
+}
+
+/// Everything the requests are answered from.
+pub struct ServeState {
+    pub database_path: PathBuf,
+    pub detected_hardware: DetectedHardware,
+    pub license_filter: Option<License>,
+    /// Opens the package backend for each request, so that packages installed while
+    /// serving are seen.
+    pub package_backend: Box<dyn Fn() -> Result<Box<dyn PackageBackend>, Error>>,
+}
+
+fn to_value<T: Serialize>(value: &T) -> serde_json::Value {
+    // The outputs only have string keys, so they always convert
+    serde_json::to_value(value).expect("The output could not be converted to JSON")
+}
+
+fn answer(request: ServeRequest, state: &ServeState) -> Result<serde_json::Value, Error> {
+    match request {
+        ServeRequest::Search { hardware, tags } => {
+            let driver_options: BTreeMap<HardwareKind, Vec<DriverOption>> = search_explained_inner(
+                state.database_path.clone(),
+                &hardware,
+                tags.into_iter(),
+                &state.detected_hardware,
+                &EntryDecoder::new(),
+                state.license_filter,
+            )?
+            .into_iter()
+            .map(|(hardware_kind, driver_options)| {
+                (hardware_kind, driver_options.into_keys().collect())
+            })
+            .collect();
+            Ok(to_value(&driver_options))
+        }
+        ServeRequest::List { hardware, tags } => Ok(to_value(&list_inner(
+            state.database_path.clone(),
+            &hardware,
+            tags,
+            None,
+            false,
+            &EntryDecoder::new(),
+            (state.package_backend)()?.as_ref(),
+        )?)),
+        ServeRequest::Detect => Ok(to_value(&state.detected_hardware.hardware_ids)),
+        ServeRequest::InstallPlan { hardware, tags } => {
+            let driver_options = search_explained_inner(
+                state.database_path.clone(),
+                &Some(hardware),
+                tags.into_iter(),
+                &state.detected_hardware,
+                &EntryDecoder::new(),
+                state.license_filter,
+            )?
+            .remove(&hardware)
+            .map(|driver_options| driver_options.into_keys().collect::<BTreeSet<_>>())
+            .unwrap_or_default();
+            let selection =
+                rank_driver_options(&driver_options, |variable| std::env::var(variable).ok());
+            let selected = driver_options
+                .iter()
+                .zip(selection.iter())
+                .find(|(_, candidate)| candidate.outcome == SelectionOutcome::Selected)
+                .map(|(driver_option, _)| driver_option);
+            Ok(to_value(&InstallPreview {
+                hardware,
+                driver_option: selected.map(|driver_option| driver_option.name.clone()),
+                packages: selected
+                    .map(|driver_option| driver_option.packages.clone())
+                    .unwrap_or_default(),
+                selection,
+            }))
+        }
+        ServeRequest::Shutdown => Ok(serde_json::Value::Null),
+    }
+}
+
+/// Answers one request line. Malformed requests are answered with an error instead of
+/// closing the connection.
+pub fn handle_request(request_line: &str, state: &ServeState) -> ServeResponse {
+    match serde_json::from_str::<ServeRequest>(request_line) {
+        Ok(request) => match answer(request, state) {
+            Ok(value) => ServeResponse::Ok(value),
+            Err(error) => ServeResponse::Error(error.to_string()),
+        },
+        Err(error) => ServeResponse::Error(format!("The request is not valid: {}", error)),
+    }
+}
+
+/// Answers the requests of one connection until it is closed. Returns the number of
+/// requests answered, and whether the server was asked to shut down.
+fn serve_connection(stream: UnixStream, state: &ServeState) -> (usize, bool) {
+    let mut writer = match stream.try_clone() {
+        Ok(writer) => writer,
+        Err(_) => return (0, false),
+    };
+    let mut requests_served = 0;
+    for line in BufReader::new(stream).lines() {
+        let Ok(line) = line else { break };
+        if line.trim().is_empty() {
+            continue;
+        }
+        let shutdown = matches!(
+            serde_json::from_str::<ServeRequest>(&line),
+            Ok(ServeRequest::Shutdown)
+        );
+        let response = serde_json::to_string(&handle_request(&line, state))
+            .expect("The response could not be converted to JSON");
+        requests_served += 1;
+        if writeln!(writer, "{}", response).is_err() || shutdown {
+            return (requests_served, shutdown);
+        }
+    }
+    (requests_served, false)
+}
+
+/// Listens on a Unix socket and answers connections one after another until a
+/// `shutdown` request.
+pub fn serve_inner(socket_path: &Path, state: &ServeState) -> Result<ServeActionOutput, Error> {
+    // A socket left behind by a server that was killed keeps the new one from binding
+    if socket_path.exists() {
+        fs::remove_file(socket_path).context(ServeSocketSnafu { path: socket_path })?;
+    }
+    if let Some(directory) = socket_path.parent() {
+        fs::create_dir_all(directory).context(ServeSocketSnafu { path: socket_path })?;
+    }
+    let listener =
+        UnixListener::bind(socket_path).context(ServeSocketSnafu { path: socket_path })?;
+
+    let mut requests_served = 0;
+    for stream in listener.incoming() {
+        let Ok(stream) = stream else { continue };
+        let (served, shutdown) = serve_connection(stream, state);
+        requests_served += served;
+        if shutdown {
+            break;
+        }
+    }
+    _ = fs::remove_file(socket_path);
+    Ok(ServeActionOutput {
+        socket: socket_path.to_path_buf(),
+        requests_served,
+    })
+}
+
+#[derive(Default, Debug, Clone, Serialize, Deserialize)]
+pub struct ServeActionOutput {
+    pub socket: PathBuf,
+    pub requests_served: usize,
+}
+
+impl CommandlinePrint for ServeActionOutput {
+    fn print(&self) {
+        println!(
+            "{} {} requests on {}",
+            "Served".if_supports_color(Stdout, |text| text.green()),
+            self.requests_served,
+            self.socket.display()
+        );
+    }
+
+    fn print_json(&self) {
+        println!("{}", to_json(&self).unwrap_or_else(|_| {
+            eprintln!("The output could not be converted to JSON. Please try another output format...");
+            String::from("")
+        }));
+    }
+
+    fn print_plain(&self) {
+        println!("{} {}", self.requests_served, self.socket.display());
+    }
+
+    fn print_debug(&self) {
+        println!("{:#?}", self);
+    }
+}
+
+pub fn serve(
+    serve_action_arguments: ServeActionArguments,
+    global_arguments: &GlobalArguments,
+) -> Result<ServeActionOutput, Error> {
+    let catalog_database = database_with_remote_index(
+        serve_action_arguments.database_file,
+        global_arguments.offline_flag,
+        global_arguments.verify_checksum,
+    )?;
+    let detected_hardware = DetectedHardware::from_detector(
+        hardware_detector(
+            global_arguments.offline_flag,
+            global_arguments.assume_hardware.as_deref(),
+            global_arguments.detector,
+            global_arguments.vendor_filter(),
+        )?
+        .as_ref(),
+    );
+    let package_manager = global_arguments.package_manager;
+    let state = ServeState {
+        database_path: catalog_database.path.clone(),
+        detected_hardware,
+        license_filter: global_arguments.license_filter(),
+        package_backend: Box::new(move || package_backend(package_manager)),
+    };
+    eprintln!("Listening on {}", serve_action_arguments.socket.display());
+    serve_inner(&serve_action_arguments.socket, &state)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::TestDirectory;
+    use crate::{
+        actions::generate_database::generate_database_inner,
+        arch::MockBackend,
+        data::{database::PciId, input_file::FIXTURE_INPUT_DATA},
+    };
+    use std::thread;
+
+    #[test]
+    pub fn requests_are_answered_over_the_socket() {
+        let directory = TestDirectory::new("serve");
+        let input_file = directory.join("input_data.yaml");
+        let database_file = directory.join("database.db");
+        fs::write(&input_file, FIXTURE_INPUT_DATA).unwrap();
+        generate_database_inner(input_file, database_file.clone(), false).unwrap();
+
+        let state = ServeState {
+            database_path: database_file,
+            detected_hardware: DetectedHardware {
+                hardware_ids: [HardwareId::Pci(PciId {
+                    vendor: 0x10de,
+                    device: 0x1e82,
+                    revision: None,
+                })]
+                .into(),
+                dmi_identity: None,
+                modaliases: None,
+            },
+            license_filter: None,
+            package_backend: Box::new(|| {
+                Ok(Box::new(
+                    MockBackend::new([("nvidia", "550.54-1"), ("nvidia-utils", "550.54-1")])
+                        .with_installed(["nvidia"]),
+                ))
+            }),
+        };
+
+        match handle_request(
+            r#"{"action": "install-plan", "hardware": "graphics"}"#,
+            &state,
+        ) {
+            ServeResponse::Ok(value) => {
+                let preview: InstallPreview = serde_json::from_value(value).unwrap();
+                assert_eq!(preview.driver_option.as_deref(), Some("Nvidia"));
+            }
+            response => panic!("expected an install preview, got {:?}", response),
+        }
+        assert!(matches!(
+            handle_request(r#"{"action": "install", "hardware": "graphics"}"#, &state),
+            ServeResponse::Error(_)
+        ));
+
+        let socket_path = directory.join("serve.sock");
+        let client = {
+            let socket_path = socket_path.clone();
+            thread::spawn(move || {
+                let stream = loop {
+                    if let Ok(stream) = UnixStream::connect(&socket_path) {
+                        break stream;
+                    }
+                    thread::sleep(std::time::Duration::from_millis(10));
+                };
+                let mut writer = stream.try_clone().unwrap();
+                writeln!(writer, r#"{{"action": "detect"}}"#).unwrap();
+                writeln!(writer, r#"{{"action": "list"}}"#).unwrap();
+                writeln!(writer, r#"{{"action": "shutdown"}}"#).unwrap();
+                BufReader::new(stream)
+                    .lines()
+                    .map(|line| serde_json::from_str::<ServeResponse>(&line.unwrap()).unwrap())
+                    .collect::<Vec<_>>()
+            })
+        };
+        let output = serve_inner(&socket_path, &state).unwrap();
+        let responses = client.join().unwrap();
+        assert_eq!(output.requests_served, 3);
+        assert_eq!(responses.len(), 3);
+        assert!(responses
+            .iter()
+            .all(|response| matches!(response, ServeResponse::Ok(_))));
+        assert!(!socket_path.exists());
+    }
+}
@@ -0,0 +1,60 @@
+use crate::{
+    cli::{to_json, CommandlinePrint},
+    data::database::SCHEMA_VERSION,
+    error::Error,
+};
+use owo_colors::{OwoColorize, Stream::Stdout};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct VersionActionOutput {
+    pub version: String,
+    pub libalpm_version: String,
+    pub database_schema_version: u32,
+}
+
+impl CommandlinePrint for VersionActionOutput {
+    fn print(&self) {
+        println!(
+            "{} {}",
+            "archlinux-driver-manager".if_supports_color(Stdout, |text| text.bold()),
+            self.version.if_supports_color(Stdout, |text| text.green())
+        );
+        println!(
+            "\t{} {}",
+            "libalpm:".if_supports_color(Stdout, |text| text.yellow()),
+            self.libalpm_version
+        );
+        println!(
+            "\t{} {}",
+            "Database schema:".if_supports_color(Stdout, |text| text.yellow()),
+            self.database_schema_version
+        );
+    }
+
+    fn print_json(&self) {
+        println!("{}", to_json(&self).unwrap_or_else(|_| {
+            eprintln!("The output could not be converted to JSON. Please try another output format...");
+            String::from("")
+        }));
+    }
+
+    fn print_plain(&self) {
+        println!(
+            "{} {} {}",
+            self.version, self.libalpm_version, self.database_schema_version
+        );
+    }
+
+    fn print_debug(&self) {
+        self.print();
+    }
+}
+
+pub fn version() -> Result<VersionActionOutput, Error> {
+    Ok(VersionActionOutput {
+        version: env!("CARGO_PKG_VERSION").to_string(),
+        libalpm_version: alpm::version().to_string(),
+        database_schema_version: SCHEMA_VERSION,
+    })
+}
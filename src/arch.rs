@@ -1,49 +1,404 @@
-use crate::error::{Error, PackageNotFoundSnafu};
-use alpm::{Alpm, Package, TransFlag};
+use crate::error::{
+    DatabaseRefreshSnafu, Error, FileConflictSnafu, InvalidRepositoryServerSnafu,
+    PackageNotFoundSnafu, RepositoryDatabaseExistsSnafu, RepositoryDatabaseWriteSnafu,
+    RepositoryDownloadSnafu, RepositoryRegisterSnafu, TransactionSnafu, UnsupportedAlpmSnafu,
+};
+use alpm::{Alpm, CommitResult, FileConflictType, Package, SigLevel, TransFlag};
 use alpm_utils::alpm_with_conf;
+use clap::ValueEnum;
 use pacmanconf::Config;
+use reqwest::{blocking::Client, Url};
+use serde::{Deserialize, Serialize};
+#[cfg(test)]
+use snafu::OptionExt;
+use snafu::ResultExt;
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fs,
+    io::{ErrorKind, Write},
+    ops::RangeInclusive,
+    path::{Path, PathBuf},
+};
 
 pub const PACMAN_CONFIG_PATH: &str = "/etc/pacman.conf";
 
-pub struct PackageManager {
+/// The lock pacman holds on its databases while a transaction runs.
+pub const PACMAN_DATABASE_LOCK_PATH: &str = "/var/lib/pacman/db.lck";
+
+/// The major versions of libalpm that the alpm bindings this program is built with can drive.
+pub const SUPPORTED_ALPM_MAJOR_VERSIONS: RangeInclusive<u32> = 13..=13;
+
+/// Fails with `UnsupportedAlpm` unless a libalpm version is in the supported range, so that
+/// an incompatible libalpm is reported up front instead of failing inside a transaction.
+pub fn check_alpm_version(found: &str) -> Result<(), Error> {
+    let supported = found
+        .split('.')
+        .next()
+        .and_then(|major| major.parse::<u32>().ok())
+        .is_some_and(|major| SUPPORTED_ALPM_MAJOR_VERSIONS.contains(&major));
+    if !supported {
+        return UnsupportedAlpmSnafu {
+            found,
+            supported: format!(
+                "{}.x to {}.x",
+                SUPPORTED_ALPM_MAJOR_VERSIONS.start(),
+                SUPPORTED_ALPM_MAJOR_VERSIONS.end()
+            ),
+        }
+        .fail();
+    }
+    Ok(())
+}
+
+/// An installed package, or one available from the repositories.
+#[derive(Clone, Default, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct PackageInfo {
+    pub name: String,
+    pub version: String,
+    /// The time of installation, in seconds since the Unix epoch, for installed packages.
+    pub install_date: Option<i64>,
+    /// The repository that an available package comes from, like `extra`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub repository: Option<String>,
+    /// The size of the package once installed, in bytes.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub installed_size: Option<i64>,
+}
+
+/// A package manager that drivers are installed with. The actions only go through this
+/// trait, so that the install and remove logic is testable without touching the system,
+/// and so that derivative distributions can plug in their own package manager.
+pub trait PackageBackend {
+    /// The installed package with a name, if any.
+    fn get(&self, package_name: &str) -> Option<PackageInfo>;
+
+    /// The package with a name available from the repositories, if any.
+    fn search(&self, package_name: &str) -> Option<PackageInfo>;
+
+    /// Installs and removes packages in one transaction. Packages already installed
+    /// at the available version are left alone unless `reinstall` is set.
+    fn install(
+        &mut self,
+        packages_to_install: &[String],
+        packages_to_remove: &[String],
+        reinstall: bool,
+    ) -> Result<(), Error>;
+
+    /// Removes installed packages.
+    fn remove(&mut self, packages: &[String]) -> Result<(), Error> {
+        self.install(&[], packages, false)
+    }
+
+    /// The installed packages that depend on an installed package.
+    fn required_by(&self, package_name: &str) -> Vec<String>;
+
+    /// Resolves the dependency closure of the given packages.
+    fn dependency_tree(&self, packages: &[String]) -> Vec<DependencyTree>;
+
+    /// Overrides the number of parallel downloads, where the backend supports it.
+    fn set_parallel_downloads(&mut self, _parallel_downloads: u32) {}
+
+    /// Downloads fresh copies of the package databases, like `pacman -Sy`.
+    fn refresh_databases(&mut self) -> Result<(), Error> {
+        Ok(())
+    }
+
+    /// Makes repositories that are not enabled in the package manager configuration
+    /// available until the backend is dropped, by name, with their servers.
+    fn add_repositories(
+        &mut self,
+        _repositories: &BTreeMap<String, Vec<String>>,
+    ) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+/// The package managers that can be chosen with `--package-manager`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum PackageBackendKind {
+    /// pacman, through libalpm.
+    #[default]
+    Alpm,
+}
+
+/// Creates the chosen package backend.
+pub fn package_backend(kind: PackageBackendKind) -> Result<Box<dyn PackageBackend>, Error> {
+    match kind {
+        PackageBackendKind::Alpm => Ok(Box::new(AlpmBackend::new()?)),
+    }
+}
+
+/// Installs packages with pacman, through libalpm.
+pub struct AlpmBackend {
     handle: Alpm,
+    /// The databases downloaded for repositories added with `add_repositories`, which are
+    /// removed again once the backend is dropped.
+    temporary_databases: Vec<PathBuf>,
+}
+
+/// A package along with the packages that satisfy its dependencies, recursively.
+#[derive(Clone, Default, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DependencyTree {
+    /// The package name, or the unsatisfied dependency string when `missing` is set.
+    pub name: String,
+    pub version: Option<String>,
+    /// The package also appears among its own ancestors, so it is not expanded again.
+    pub cycle: bool,
+    /// The package was already expanded elsewhere in the tree.
+    pub repeated: bool,
+    /// No package in the sync or local databases satisfies the dependency.
+    pub missing: bool,
+    pub dependencies: Vec<DependencyTree>,
 }
 
-impl PackageManager {
-    pub fn new() -> Self {
+impl AlpmBackend {
+    pub fn new() -> Result<Self, Error> {
+        check_alpm_version(alpm::version())?;
         let pacman_conf = Config::from_file(PACMAN_CONFIG_PATH).unwrap();
         let alpm_handle = alpm_with_conf(&pacman_conf).unwrap();
-        Self {
+        Ok(Self {
             handle: alpm_handle,
+            temporary_databases: Vec::new(),
+        })
+    }
+
+    fn local_package(&self, package_name: &str) -> Option<Package<'_>> {
+        self.handle.localdb().pkg(package_name).ok()
+    }
+
+    /// The installed package that owns a file, given as an absolute path.
+    fn file_owner(&self, path: &str) -> Option<String> {
+        let relative_path = path.trim_start_matches('/');
+        self.handle
+            .localdb()
+            .pkgs()
+            .iter()
+            .find(|package| matches!(package.files().contains(relative_path), Ok(Some(_))))
+            .map(|package| package.name().to_owned())
+    }
+
+    fn sync_package(&self, package_name: &str) -> Option<Package<'_>> {
+        self.handle
+            .syncdbs()
+            .iter()
+            .find_map(|db| db.pkg(package_name).ok())
+    }
+
+    fn find_satisfier<S: AsRef<str>>(&self, dependency: S) -> Option<Package<'_>> {
+        self.handle
+            .syncdbs()
+            .find_satisfier(dependency.as_ref())
+            .or_else(|| {
+                self.handle
+                    .localdb()
+                    .pkgs()
+                    .find_satisfier(dependency.as_ref())
+            })
+    }
+
+    fn dependency_subtree(
+        &self,
+        dependency: &str,
+        ancestors: &mut Vec<String>,
+        expanded: &mut BTreeSet<String>,
+    ) -> DependencyTree {
+        let package = match self.find_satisfier(dependency) {
+            Some(package) => package,
+            None => {
+                return DependencyTree {
+                    name: dependency.to_owned(),
+                    missing: true,
+                    ..Default::default()
+                }
+            }
+        };
+        let mut tree = DependencyTree {
+            name: package.name().to_owned(),
+            version: Some(package.version().to_string()),
+            ..Default::default()
+        };
+        if ancestors.contains(&tree.name) {
+            tree.cycle = true;
+        } else if !expanded.insert(tree.name.clone()) {
+            tree.repeated = true;
+        } else {
+            ancestors.push(tree.name.clone());
+            tree.dependencies = package
+                .depends()
+                .iter()
+                .map(|dependency| {
+                    self.dependency_subtree(&dependency.to_string(), ancestors, expanded)
+                })
+                .collect();
+            ancestors.pop();
         }
+        tree
+    }
+}
+
+impl PackageBackend for AlpmBackend {
+    fn get(&self, package_name: &str) -> Option<PackageInfo> {
+        self.local_package(package_name).map(|package| PackageInfo {
+            name: package.name().to_owned(),
+            version: package.version().to_string(),
+            install_date: package.install_date(),
+            repository: None,
+            installed_size: Some(package.isize()),
+        })
+    }
+
+    fn search(&self, package_name: &str) -> Option<PackageInfo> {
+        self.sync_package(package_name).map(|package| PackageInfo {
+            name: package.name().to_owned(),
+            version: package.version().to_string(),
+            install_date: None,
+            repository: package.db().map(|db| db.name().to_owned()),
+            installed_size: Some(package.isize()),
+        })
+    }
+
+    fn required_by(&self, package_name: &str) -> Vec<String> {
+        self.local_package(package_name)
+            .map(|package| package.required_by().iter().map(String::from).collect())
+            .unwrap_or_default()
+    }
+
+    /// Resolves the dependency closure of the given packages over the sync databases,
+    /// falling back to the local database for packages that are not in any repository.
+    fn dependency_tree(&self, packages: &[String]) -> Vec<DependencyTree> {
+        let mut expanded = BTreeSet::<String>::new();
+        packages
+            .iter()
+            .map(|package_name| {
+                self.dependency_subtree(package_name, &mut Vec::new(), &mut expanded)
+            })
+            .collect()
+    }
+
+    /// Overrides the number of parallel downloads read from the pacman configuration.
+    fn set_parallel_downloads(&mut self, parallel_downloads: u32) {
+        self.handle.set_parallel_downloads(parallel_downloads);
+    }
+
+    fn refresh_databases(&mut self) -> Result<(), Error> {
+        self.handle
+            .syncdbs_mut()
+            .update(false)
+            .context(DatabaseRefreshSnafu)?;
+        Ok(())
     }
 
-    pub fn get<S: AsRef<str>>(&self, package_name: S) -> Option<Package> {
-        let db = self.handle.localdb();
-        db.pkg(package_name.as_ref()).ok()
+    /// Downloads the database of each repository that is not enabled yet, since libalpm
+    /// can only refresh all databases at once, and registers it for this handle only.
+    /// The database is written next to the ones of pacman, so an existing file, which
+    /// pacman may be using, is never replaced.
+    fn add_repositories(
+        &mut self,
+        repositories: &BTreeMap<String, Vec<String>>,
+    ) -> Result<(), Error> {
+        for (repository, servers) in repositories {
+            if self
+                .handle
+                .syncdbs()
+                .iter()
+                .any(|database| database.name() == repository)
+            {
+                continue;
+            }
+            let servers = repository_servers(repository, servers, std::env::consts::ARCH)?;
+            let database_path = Path::new(self.handle.dbpath())
+                .join("sync")
+                .join(format!("{}.db", repository));
+            if database_path.exists() {
+                return RepositoryDatabaseExistsSnafu {
+                    repository,
+                    path: database_path,
+                }
+                .fail();
+            }
+
+            let mut download_error = None;
+            let mut contents = None;
+            for server in servers.iter() {
+                let url = repository_database_url(server, repository);
+                match Client::new()
+                    .get(url.clone())
+                    .send()
+                    .and_then(|response| response.error_for_status())
+                    .and_then(|response| response.bytes())
+                {
+                    Ok(bytes) => {
+                        contents = Some(bytes);
+                        break;
+                    }
+                    Err(error) => download_error = Some((url, error)),
+                }
+            }
+            let contents = match contents {
+                Some(contents) => contents,
+                None => {
+                    let (url, source) = download_error.unwrap();
+                    return Err(source).context(RepositoryDownloadSnafu { repository, url });
+                }
+            };
+            let mut database_file = match fs::File::create_new(&database_path) {
+                Err(error) if error.kind() == ErrorKind::AlreadyExists => {
+                    return RepositoryDatabaseExistsSnafu {
+                        repository,
+                        path: database_path,
+                    }
+                    .fail();
+                }
+                database_file => database_file.context(RepositoryDatabaseWriteSnafu {
+                    path: &database_path,
+                })?,
+            };
+            self.temporary_databases.push(database_path.clone());
+            database_file
+                .write_all(&contents)
+                .context(RepositoryDatabaseWriteSnafu {
+                    path: &database_path,
+                })?;
+
+            let database = self
+                .handle
+                .register_syncdb_mut(repository.as_str(), SigLevel::USE_DEFAULT)
+                .context(RepositoryRegisterSnafu { repository })?;
+            for server in servers.iter() {
+                database
+                    .add_server(server.as_str())
+                    .context(RepositoryRegisterSnafu { repository })?;
+            }
+        }
+        Ok(())
     }
 
-    pub fn install<S: AsRef<str>, T: IntoIterator<Item = S>>(
+    fn install(
         &mut self,
-        packages_to_install: T,
-        packages_to_remove: T,
+        packages_to_install: &[String],
+        packages_to_remove: &[String],
+        reinstall: bool,
     ) -> Result<(), Error> {
         let flags = TransFlag::NONE;
         self.handle.trans_init(flags).unwrap();
 
         let mut actual_install_list = Vec::<String>::new();
         let mut actual_remove_list = Vec::<String>::new();
+        let mut up_to_date_list = Vec::<String>::new();
 
         for package_name in packages_to_install {
-            let package_name = package_name.as_ref();
-
-            let package = self
-                .handle
-                .syncdbs()
-                .iter()
-                .find_map(|db| db.pkg(package_name).ok());
+            let package = self.sync_package(package_name);
 
             if let Some(package) = package {
+                let up_to_date =
+                    self.local_package(package_name)
+                        .is_some_and(|installed_package| {
+                            installed_package.version() == package.version()
+                        });
+                if up_to_date && !reinstall {
+                    up_to_date_list.push(package_name.to_owned());
+                    continue;
+                }
                 self.handle.trans_add_pkg(package).unwrap();
                 actual_install_list.push(package_name.to_owned());
             } else {
@@ -53,9 +408,7 @@ impl PackageManager {
         }
 
         for package_name in packages_to_remove {
-            let package_name = package_name.as_ref();
-
-            let package = self.get(package_name);
+            let package = self.local_package(package_name);
 
             if let Some(package) = package {
                 self.handle.trans_remove_pkg(package).unwrap();
@@ -66,15 +419,373 @@ impl PackageManager {
             }
         }
 
-        self.handle.trans_prepare().unwrap();
-        println!("Packages to Install: {:?}", actual_install_list);
+        if !up_to_date_list.is_empty() {
+            println!(
+                "Packages already up to date (use --reinstall to reinstall them): {:?}",
+                up_to_date_list
+            );
+        }
+        if actual_install_list.is_empty() && actual_remove_list.is_empty() {
+            self.handle.trans_release().unwrap();
+            println!("Nothing to do.");
+            return Ok(());
+        }
+
+        let prepared = self.handle.trans_prepare().map_err(|(_, error)| error);
+        if let Err(error) = prepared {
+            self.handle.trans_release().unwrap();
+            return Err(error).context(TransactionSnafu);
+        }
+        if reinstall {
+            println!(
+                "Packages to Install or Reinstall: {:?}",
+                actual_install_list
+            );
+        } else {
+            println!("Packages to Install: {:?}", actual_install_list);
+        }
         println!("Packages to Remove: {:?}", actual_remove_list);
         println!("Please wait while packages are being installed...");
 
-        self.handle.trans_commit().unwrap();
+        // The conflicts borrow the handle, so they are copied out before looking up owners
+        let committed = self
+            .handle
+            .trans_commit()
+            .map_err(|(commit_result, error)| match commit_result {
+                CommitResult::FileConflict(file_conflicts) => (
+                    Some(
+                        file_conflicts
+                            .iter()
+                            .map(|file_conflict| {
+                                (
+                                    file_conflict.target().to_owned(),
+                                    file_conflict.file().to_owned(),
+                                    file_conflict.conflict_type(),
+                                )
+                            })
+                            .collect::<Vec<(String, String, FileConflictType)>>(),
+                    ),
+                    error,
+                ),
+                _ => (None, error),
+            });
+        match committed {
+            Ok(()) => {}
+            Err((Some(file_conflicts), _)) => {
+                self.handle.trans_release().unwrap();
+                return FileConflictSnafu {
+                    conflicts: file_conflicts
+                        .into_iter()
+                        .map(|(target, file, conflict_type)| {
+                            describe_file_conflict(
+                                &target,
+                                &file,
+                                conflict_type,
+                                self.file_owner(&file).as_deref(),
+                            )
+                        })
+                        .collect::<Vec<String>>(),
+                }
+                .fail();
+            }
+            Err((None, error)) => {
+                self.handle.trans_release().unwrap();
+                return Err(error).context(TransactionSnafu);
+            }
+        }
 
         println!("Transaction completed.");
 
         Ok(())
     }
 }
+
+/// Installs packages into an in-memory set instead of the system, from a fixed
+/// set of available packages.
+#[cfg(test)]
+#[derive(Clone, Default, Debug)]
+pub struct MockBackend {
+    /// The available packages, by name, with their versions.
+    pub available: BTreeMap<String, String>,
+    pub installed: BTreeMap<String, PackageInfo>,
+    /// The dependencies of packages, by package name.
+    pub depends: BTreeMap<String, BTreeSet<String>>,
+    /// The number of install transactions run.
+    pub transactions: usize,
+    /// The repositories of available packages, by package name.
+    pub repositories: BTreeMap<String, String>,
+    /// The installed sizes of available packages, by package name.
+    pub installed_sizes: BTreeMap<String, i64>,
+}
+
+#[cfg(test)]
+impl MockBackend {
+    pub fn new<S: Into<String>, T: IntoIterator<Item = (S, S)>>(available: T) -> Self {
+        MockBackend {
+            available: available
+                .into_iter()
+                .map(|(name, version)| (name.into(), version.into()))
+                .collect(),
+            installed: BTreeMap::new(),
+            depends: BTreeMap::new(),
+            transactions: 0,
+            repositories: BTreeMap::new(),
+            installed_sizes: BTreeMap::new(),
+        }
+    }
+
+    pub fn with_dependency<S: Into<String>>(mut self, package: S, dependency: S) -> Self {
+        self.depends
+            .entry(package.into())
+            .or_default()
+            .insert(dependency.into());
+        self
+    }
+
+    pub fn with_repository<S: Into<String>>(mut self, package: S, repository: S) -> Self {
+        self.repositories.insert(package.into(), repository.into());
+        self
+    }
+
+    pub fn with_installed_size<S: Into<String>>(mut self, package: S, installed_size: i64) -> Self {
+        self.installed_sizes.insert(package.into(), installed_size);
+        self
+    }
+
+    pub fn with_installed<S: AsRef<str>, T: IntoIterator<Item = S>>(mut self, packages: T) -> Self {
+        for package in packages {
+            let package = self
+                .search(package.as_ref())
+                .expect("Only available packages can be installed");
+            self.installed.insert(package.name.clone(), package);
+        }
+        self
+    }
+}
+
+#[cfg(test)]
+impl PackageBackend for MockBackend {
+    fn get(&self, package_name: &str) -> Option<PackageInfo> {
+        self.installed.get(package_name).cloned()
+    }
+
+    fn search(&self, package_name: &str) -> Option<PackageInfo> {
+        self.available.get(package_name).map(|version| PackageInfo {
+            name: package_name.to_owned(),
+            version: version.clone(),
+            install_date: None,
+            repository: self.repositories.get(package_name).cloned(),
+            installed_size: self.installed_sizes.get(package_name).copied(),
+        })
+    }
+
+    fn install(
+        &mut self,
+        packages_to_install: &[String],
+        packages_to_remove: &[String],
+        reinstall: bool,
+    ) -> Result<(), Error> {
+        let mut to_install = Vec::<PackageInfo>::new();
+        for (index, package_name) in packages_to_install.iter().enumerate() {
+            // Like alpm, which fails to add a package that is already a target
+            assert!(
+                !packages_to_install[..index].contains(package_name),
+                "{} was added to the transaction twice",
+                package_name
+            );
+            let package = self
+                .search(package_name)
+                .context(PackageNotFoundSnafu { name: package_name })?;
+            if reinstall || self.get(package_name).as_ref() != Some(&package) {
+                to_install.push(package);
+            }
+        }
+        if let Some(package_name) = packages_to_remove
+            .iter()
+            .find(|package_name| !self.installed.contains_key(*package_name))
+        {
+            PackageNotFoundSnafu { name: package_name }.fail()?;
+        }
+
+        for package_name in packages_to_remove {
+            self.installed.remove(package_name);
+        }
+        for package in to_install {
+            self.installed.insert(package.name.clone(), package);
+        }
+        self.transactions += 1;
+        Ok(())
+    }
+
+    fn required_by(&self, package_name: &str) -> Vec<String> {
+        self.installed
+            .keys()
+            .filter(|installed_package| {
+                self.depends
+                    .get(*installed_package)
+                    .is_some_and(|dependencies| dependencies.contains(package_name))
+            })
+            .cloned()
+            .collect()
+    }
+
+    fn dependency_tree(&self, packages: &[String]) -> Vec<DependencyTree> {
+        packages
+            .iter()
+            .map(|package_name| match self.search(package_name) {
+                Some(package) => DependencyTree {
+                    name: package.name,
+                    version: Some(package.version),
+                    ..Default::default()
+                },
+                None => DependencyTree {
+                    name: package_name.clone(),
+                    missing: true,
+                    ..Default::default()
+                },
+            })
+            .collect()
+    }
+}
+
+impl Drop for AlpmBackend {
+    fn drop(&mut self) {
+        for database_path in self.temporary_databases.iter() {
+            _ = fs::remove_file(database_path);
+        }
+    }
+}
+
+/// The servers of a repository, written like in the pacman configuration, where `$repo`
+/// and `$arch` stand for the name of the repository and the architecture. Only HTTP and
+/// HTTPS servers are accepted, since the database is downloaded without libalpm.
+pub fn repository_servers(
+    repository: &str,
+    servers: &[String],
+    architecture: &str,
+) -> Result<Vec<Url>, Error> {
+    if servers.is_empty() {
+        return InvalidRepositoryServerSnafu {
+            repository,
+            server: "",
+            reason: "no servers are listed",
+        }
+        .fail();
+    }
+    servers
+        .iter()
+        .map(|server| {
+            let url = Url::parse(
+                &server
+                    .replace("$repo", repository)
+                    .replace("$arch", architecture),
+            )
+            .map_err(|error| error.to_string())
+            .and_then(|url| match url.scheme() {
+                "http" | "https" => Ok(url),
+                scheme => Err(format!("{} servers are not supported", scheme)),
+            });
+            url.map_err(|reason| {
+                InvalidRepositoryServerSnafu {
+                    repository,
+                    server,
+                    reason,
+                }
+                .build()
+            })
+        })
+        .collect()
+}
+
+/// The URL of the database of a repository on one of its servers.
+fn repository_database_url(server: &Url, repository: &str) -> String {
+    format!(
+        "{}/{}.db",
+        server.as_str().trim_end_matches('/'),
+        repository
+    )
+}
+
+/// Describes a conflict found by libalpm before it writes any file. The bindings cannot read
+/// which other package a conflict is with, so the owner of a file on the system is passed in.
+fn describe_file_conflict(
+    target: &str,
+    file: &str,
+    conflict_type: FileConflictType,
+    owner: Option<&str>,
+) -> String {
+    match (conflict_type, owner) {
+        (FileConflictType::Target, _) => {
+            format!(
+                "{} is in both {} and another package to install",
+                file, target
+            )
+        }
+        (FileConflictType::Filesystem, Some(owner)) => {
+            format!("{} of {} is already owned by {}", file, target, owner)
+        }
+        (FileConflictType::Filesystem, None) => {
+            format!("{} of {} already exists in the filesystem", file, target)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    pub fn repository_servers_are_expanded_and_checked() {
+        let servers = repository_servers(
+            "chaotic-aur",
+            &["https://example.org/$repo/os/$arch/".to_string()],
+            "x86_64",
+        )
+        .unwrap();
+        assert_eq!(
+            repository_database_url(&servers[0], "chaotic-aur"),
+            "https://example.org/chaotic-aur/os/x86_64/chaotic-aur.db"
+        );
+        for servers in [vec![], vec!["ftp://example.org/$repo".to_string()]] {
+            assert!(matches!(
+                repository_servers("chaotic-aur", &servers, "x86_64"),
+                Err(Error::InvalidRepositoryServer { .. })
+            ));
+        }
+    }
+
+    #[test]
+    pub fn file_conflicts_name_the_owning_package() {
+        assert_eq!(
+            describe_file_conflict(
+                "nvidia-open",
+                "/usr/lib/modules/extramodules/nvidia.ko.xz",
+                FileConflictType::Filesystem,
+                Some("nvidia"),
+            ),
+            "/usr/lib/modules/extramodules/nvidia.ko.xz of nvidia-open is already owned by nvidia"
+        );
+        assert_eq!(
+            describe_file_conflict(
+                "nvidia-open",
+                "/etc/modprobe.d/nvidia.conf",
+                FileConflictType::Filesystem,
+                None,
+            ),
+            "/etc/modprobe.d/nvidia.conf of nvidia-open already exists in the filesystem"
+        );
+    }
+
+    #[test]
+    pub fn alpm_versions_outside_the_supported_range_are_rejected() {
+        assert!(check_alpm_version("13.0.2").is_ok());
+        match check_alpm_version("14.0.0") {
+            Err(Error::UnsupportedAlpm { found, supported }) => {
+                assert_eq!(found, "14.0.0");
+                assert_eq!(supported, "13.x to 13.x");
+            }
+            result => panic!("expected libalpm 14 to be rejected, got {:?}", result),
+        }
+        assert!(check_alpm_version("unknown").is_err());
+    }
+}
@@ -1,25 +1,149 @@
 pub use commandline_interface_template::*;
 
+#[cfg(feature = "serve")]
+use crate::actions::serve;
 use crate::{
-    actions::{generate_database, install, list, search},
-    data::database::convert_tag,
+    actions::{
+        coverage, deps, doctor, export, generate_database, hardware_kinds, history, install,
+        lint_database, list, list_tags, profile, prune, reapply, restore_database, search, version,
+    },
+    data::{config::Config, database::convert_tag, input_file::HardwareKind},
+    detection::VendorFilter,
+    paths,
 };
 use clap::Parser;
+use comfy_table::{presets::UTF8_FULL_CONDENSED, Table};
 use owo_colors::{OwoColorize, Stream::Stderr};
-use std::fmt::Display;
+use serde::Serialize;
+use std::{collections::BTreeSet, fmt::Display, process::ExitCode, sync::OnceLock};
 
 pub struct CommandlineInterface {}
 
+/// Adds the default tags configured for a hardware kind to the tags given on the command line.
+fn apply_default_tags(hardware: Option<HardwareKind>, tags: &mut Vec<String>) {
+    if let Some(hardware) = hardware {
+        match Config::load(&paths::config_file()) {
+            Ok(config) => config.apply_default_tags(hardware, tags),
+            Err(error) => eprintln!(
+                "{} {}",
+                "WARNING:".if_supports_color(Stderr, |text| text.yellow()),
+                error
+            ),
+        }
+    }
+}
+
+impl GlobalArguments {
+    /// The vendors whose hardware is considered. The lists given on the command line
+    /// replace the ones of the configuration file.
+    pub fn vendor_filter(&self) -> VendorFilter {
+        let config = Config::load(&paths::config_file()).unwrap_or_else(|error| {
+            eprintln!(
+                "{} {}",
+                "WARNING:".if_supports_color(Stderr, |text| text.yellow()),
+                error
+            );
+            Config::default()
+        });
+        let choose = |given: &[u16], configured: BTreeSet<u16>| {
+            if given.is_empty() {
+                configured
+            } else {
+                given.iter().copied().collect()
+            }
+        };
+        VendorFilter {
+            allow: choose(&self.vendor_allow, config.vendor_allow),
+            deny: choose(&self.vendor_deny, config.vendor_deny),
+        }
+    }
+}
+
+/// The exit status when `--fail-on-empty` is given and nothing was found.
+pub const EXIT_CODE_EMPTY: u8 = 2;
+
+/// Whether colors were forced on (`Some(true)`) or off (`Some(false)`) for this run,
+/// instead of being detected per stream.
+static COLOR_OVERRIDE: OnceLock<Option<bool>> = OnceLock::new();
+
+impl ColorChoice {
+    /// Resolves the choice against the `NO_COLOR` convention, which disables colors
+    /// unless they were asked for explicitly.
+    pub fn color_override(self) -> Option<bool> {
+        match self {
+            ColorChoice::Always => Some(true),
+            ColorChoice::Never => Some(false),
+            ColorChoice::Auto => std::env::var_os("NO_COLOR")
+                .filter(|value| !value.is_empty())
+                .map(|_| false),
+        }
+    }
+
+    /// Applies the choice to all colored output of this process.
+    pub fn apply(self) {
+        let color_override = self.color_override();
+        match color_override {
+            Some(enabled) => owo_colors::set_override(enabled),
+            None => owo_colors::unset_override(),
+        }
+        _ = COLOR_OVERRIDE.set(color_override);
+    }
+}
+
+/// Whether `--json-pretty` was given for this run.
+static PRETTY_JSON: OnceLock<bool> = OnceLock::new();
+
+/// Converts an output to a single JSON document, indented when `--json-pretty` was given.
+pub fn to_json<T: Serialize + ?Sized>(value: &T) -> serde_json::Result<String> {
+    if PRETTY_JSON.get().copied().unwrap_or(false) {
+        serde_json::to_string_pretty(value)
+    } else {
+        serde_json::to_string(value)
+    }
+}
+
+/// An empty table in the style shared by all table outputs, honoring `--color`.
+pub fn new_table() -> Table {
+    let mut table = Table::new();
+    table.load_preset(UTF8_FULL_CONDENSED);
+    match COLOR_OVERRIDE.get().copied().flatten() {
+        Some(true) => {
+            table.enforce_styling();
+        }
+        Some(false) => {
+            table.force_no_tty();
+        }
+        None => {}
+    }
+    table
+}
+
 pub trait CommandlinePrint {
     fn print(&self);
     fn print_json(&self);
     fn print_plain(&self);
     fn print_debug(&self);
+    fn print_table(&self) {
+        self.print();
+    }
+    /// Whether there is nothing to report, like a search that found no drivers.
+    fn is_empty_result(&self) -> bool {
+        false
+    }
+    /// The exit status of the program after printing this output.
+    fn exit_code(&self, fail_on_empty: bool) -> ExitCode {
+        if fail_on_empty && self.is_empty_result() {
+            ExitCode::from(EXIT_CODE_EMPTY)
+        } else {
+            ExitCode::SUCCESS
+        }
+    }
     fn print_select(&self, flags: impl CommandlineFlags) {
         match CommandlineFlags::output_kind(&flags) {
             CommandlineOutputKind::Regular => self.print(),
             CommandlineOutputKind::Json => self.print_json(),
             CommandlineOutputKind::Plain => self.print_plain(),
+            CommandlineOutputKind::Table => self.print_table(),
             CommandlineOutputKind::Debug => self.print_debug(),
         }
     }
@@ -28,12 +152,15 @@ pub trait CommandlinePrint {
 pub trait CommandlineFlags {
     fn json_flag(&self) -> bool;
     fn plain_flag(&self) -> bool;
+    fn table_flag(&self) -> bool;
     fn debug_flag(&self) -> bool;
     fn output_kind(&self) -> CommandlineOutputKind {
         if self.json_flag() {
             return CommandlineOutputKind::Json;
         } else if self.plain_flag() {
             return CommandlineOutputKind::Plain;
+        } else if self.table_flag() {
+            return CommandlineOutputKind::Table;
         } else if self.debug_flag() {
             return CommandlineOutputKind::Debug;
         } else {
@@ -42,10 +169,12 @@ pub trait CommandlineFlags {
     }
 }
 
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum CommandlineOutputKind {
     Regular,
     Json,
     Plain,
+    Table,
     Debug,
 }
 
@@ -77,7 +206,11 @@ where
                     inner,
                 );
                 eprintln!("{}", message);
-                println!("{{errors:[{}]}}", inner);
+                println!(
+                    "{}",
+                    to_json(&serde_json::json!({ "errors": [inner.to_string()] }))
+                        .unwrap_or_default()
+                );
             }
         }
     }
@@ -95,6 +228,19 @@ where
             }
         }
     }
+    fn print_table(&self) {
+        match self {
+            Ok(inner) => inner.print_table(),
+            Err(inner) => {
+                let message = format!(
+                    "{} {}",
+                    "ERROR:".if_supports_color(Stderr, |text| text.red()),
+                    inner,
+                );
+                eprintln!("{}", message);
+            }
+        }
+    }
     fn print_debug(&self) {
         match self {
             Ok(inner) => inner.print_debug(),
@@ -108,6 +254,18 @@ where
             }
         }
     }
+    fn is_empty_result(&self) -> bool {
+        match self {
+            Ok(inner) => inner.is_empty_result(),
+            Err(_) => false,
+        }
+    }
+    fn exit_code(&self, fail_on_empty: bool) -> ExitCode {
+        match self {
+            Ok(inner) => inner.exit_code(fail_on_empty),
+            Err(_) => ExitCode::FAILURE,
+        }
+    }
 }
 
 impl CommandlineInterface {
@@ -115,15 +273,26 @@ impl CommandlineInterface {
         CommandlineInterface {}
     }
 
-    pub fn run(self) {
+    pub fn run(self) -> ExitCode {
         let mut cli = Cli::parse();
+        cli.global_arguments.color.apply();
+        _ = PRETTY_JSON.set(cli.global_arguments.json_pretty_flag);
+        let fail_on_empty = cli.global_arguments.fail_on_empty;
 
         match cli.command {
             Some(ActionCommand::List(mut list_action_arguments)) => {
                 list_action_arguments.tags =
                     list_action_arguments.tags.iter().map(convert_tag).collect();
+                if !list_action_arguments.no_default_tags {
+                    apply_default_tags(
+                        list_action_arguments.hardware,
+                        &mut list_action_arguments.tags,
+                    );
+                }
 
-                list::list(list_action_arguments).print_select(cli.global_arguments);
+                let output = list::list(list_action_arguments, &cli.global_arguments);
+                output.print_select(cli.global_arguments);
+                output.exit_code(fail_on_empty)
             }
             Some(ActionCommand::Search(mut search_action_arguments)) => {
                 search_action_arguments.tags = search_action_arguments
@@ -131,8 +300,23 @@ impl CommandlineInterface {
                     .iter()
                     .map(convert_tag)
                     .collect();
+                // Searching every kind of hardware applies the same tags to all of them
+                if !search_action_arguments.no_default_tags && !search_action_arguments.all_kinds {
+                    apply_default_tags(
+                        search_action_arguments.hardware,
+                        &mut search_action_arguments.tags,
+                    );
+                }
 
-                search::search(search_action_arguments).print_select(cli.global_arguments);
+                let packages = search_action_arguments.packages;
+                let output = search::search(search_action_arguments, &cli.global_arguments);
+                if packages {
+                    let package_names = output.as_ref().map(|output| output.package_names());
+                    package_names.print_select(cli.global_arguments);
+                } else {
+                    output.print_select(cli.global_arguments);
+                }
+                output.exit_code(fail_on_empty)
             }
             Some(ActionCommand::Install(mut install_action_arguments)) => {
                 install_action_arguments.tags = install_action_arguments
@@ -140,27 +324,135 @@ impl CommandlineInterface {
                     .iter()
                     .map(convert_tag)
                     .collect();
+                if !install_action_arguments.no_default_tags {
+                    apply_default_tags(
+                        install_action_arguments.hardware,
+                        &mut install_action_arguments.tags,
+                    );
+                }
+
+                let packages = install_action_arguments.packages;
+                let output = install::install(install_action_arguments, &cli.global_arguments);
+                if packages {
+                    let package_names = output.as_ref().map(|output| output.package_names());
+                    package_names.print_select(cli.global_arguments);
+                } else {
+                    output.print_select(cli.global_arguments);
+                }
+                output.exit_code(fail_on_empty)
+            }
+            Some(ActionCommand::Deps(mut deps_action_arguments)) => {
+                deps_action_arguments.tags =
+                    deps_action_arguments.tags.iter().map(convert_tag).collect();
 
-                install::install(install_action_arguments).print_select(cli.global_arguments);
+                let output = deps::deps(deps_action_arguments, &cli.global_arguments);
+                output.print_select(cli.global_arguments);
+                output.exit_code(fail_on_empty)
             }
             Some(ActionCommand::GenerateDatabase(generate_database_action_arguments)) => {
-                generate_database::generate_database(generate_database_action_arguments)
-                    .print_select(cli.global_arguments);
+                let output =
+                    generate_database::generate_database(generate_database_action_arguments);
+                output.print_select(cli.global_arguments);
+                output.exit_code(fail_on_empty)
+            }
+            Some(ActionCommand::Version) => {
+                let output = version::version();
+                output.print_select(cli.global_arguments);
+                output.exit_code(fail_on_empty)
+            }
+            Some(ActionCommand::HardwareKinds) => {
+                let output = hardware_kinds::hardware_kinds();
+                output.print_select(cli.global_arguments);
+                output.exit_code(fail_on_empty)
+            }
+            Some(ActionCommand::ListTags(list_tags_action_arguments)) => {
+                let output =
+                    list_tags::list_tags(list_tags_action_arguments, &cli.global_arguments);
+                output.print_select(cli.global_arguments);
+                output.exit_code(fail_on_empty)
+            }
+            Some(ActionCommand::Coverage(coverage_action_arguments)) => {
+                let output = coverage::coverage(coverage_action_arguments, &cli.global_arguments);
+                output.print_select(cli.global_arguments);
+                output.exit_code(fail_on_empty)
+            }
+            Some(ActionCommand::Profile(profile_action_arguments)) => {
+                let output = profile::profile(profile_action_arguments, &cli.global_arguments);
+                output.print_select(cli.global_arguments);
+                output.exit_code(fail_on_empty)
+            }
+            Some(ActionCommand::RestoreDatabase(restore_database_action_arguments)) => {
+                let output = restore_database::restore_database(restore_database_action_arguments);
+                output.print_select(cli.global_arguments);
+                output.exit_code(fail_on_empty)
+            }
+            Some(ActionCommand::History(history_action_arguments)) => {
+                let output = history::history(history_action_arguments);
+                output.print_select(cli.global_arguments);
+                output.exit_code(fail_on_empty)
+            }
+            Some(ActionCommand::Export(export_action_arguments)) => {
+                let output = export::export(export_action_arguments, &cli.global_arguments);
+                output.print_select(cli.global_arguments);
+                output.exit_code(fail_on_empty)
+            }
+            Some(ActionCommand::Prune(prune_action_arguments)) => {
+                let output = prune::prune(prune_action_arguments, &cli.global_arguments);
+                output.print_select(cli.global_arguments);
+                output.exit_code(fail_on_empty)
+            }
+            Some(ActionCommand::Reapply(reapply_action_arguments)) => {
+                let output = reapply::reapply(reapply_action_arguments, &cli.global_arguments);
+                output.print_select(cli.global_arguments);
+                output.exit_code(fail_on_empty)
+            }
+            Some(ActionCommand::LintDatabase(lint_database_action_arguments)) => {
+                let output = lint_database::lint_database(
+                    lint_database_action_arguments,
+                    &cli.global_arguments,
+                );
+                output.print_select(cli.global_arguments);
+                output.exit_code(fail_on_empty)
+            }
+            Some(ActionCommand::Doctor(doctor_action_arguments)) => {
+                let output = doctor::doctor(doctor_action_arguments, &cli.global_arguments);
+                output.print_select(cli.global_arguments);
+                output.exit_code(fail_on_empty)
+            }
+            #[cfg(feature = "serve")]
+            Some(ActionCommand::Serve(serve_action_arguments)) => {
+                let output = serve::serve(serve_action_arguments, &cli.global_arguments);
+                output.print_select(cli.global_arguments);
+                output.exit_code(fail_on_empty)
             }
             None => {
                 cli.arguments.tags = cli.arguments.tags.iter().map(convert_tag).collect();
+                if !cli.arguments.no_default_tags {
+                    apply_default_tags(cli.arguments.hardware, &mut cli.arguments.tags);
+                }
 
-                list::list(cli.arguments).print_select(cli.global_arguments);
+                let output = list::list(cli.arguments, &cli.global_arguments);
+                output.print_select(cli.global_arguments);
+                output.exit_code(fail_on_empty)
             }
         }
     }
 }
 
 pub mod commandline_interface_template {
-    use crate::{data::input_file::HardwareKind, DB_PATH};
+    #[cfg(feature = "serve")]
+    use crate::paths::socket_path;
+    use crate::{
+        actions::list::parse_since,
+        arch::PackageBackendKind,
+        data::input_file::{parse_hex_id, FileFormat, HardwareKind, License},
+        detection::DetectorKind,
+        hooks::DEFAULT_SCRIPT_TIMEOUT_SECONDS,
+        paths::database_path,
+    };
 
     use super::CommandlineFlags;
-    use clap::{Args, Parser, Subcommand};
+    use clap::{Args, Parser, Subcommand, ValueEnum};
     use std::path::PathBuf;
 
     /// The big picture of the commandline-interface. This structure contains
@@ -181,24 +473,155 @@ pub mod commandline_interface_template {
     #[derive(Debug, Args)]
     #[clap(args_conflicts_with_subcommands = true)]
     pub struct GlobalArguments {
+        #[clap(
+                long = "offline",
+                help = "Do not access the network or enumerate hardware. Uses the hardware recorded by the last online run.",
+                num_args = 0,
+                global = true,
+                display_order = usize::MAX - 5,
+            )]
+        pub offline_flag: bool,
+
+        #[clap(
+                long = "fail-on-empty",
+                help = "Exit with status 2 when nothing was found, like a search without matching drivers.",
+                num_args = 0,
+                global = true,
+                display_order = usize::MAX - 6,
+            )]
+        pub fail_on_empty: bool,
+
+        #[clap(
+                long = "assume-hardware",
+                value_name = "HARDWARE_FILE",
+                help = "Match against the hardware IDs listed in a file, one like `PCI 10de:1b80` or `USB 0bda:8179` per line, instead of the detected hardware.",
+                global = true,
+                display_order = usize::MAX - 7,
+            )]
+        pub assume_hardware: Option<PathBuf>,
+
+        #[clap(
+                long = "package-manager",
+                value_enum,
+                help = "The package manager to query and install drivers with.",
+                default_value_t = PackageBackendKind::Alpm,
+                global = true,
+                display_order = usize::MAX - 8,
+            )]
+        pub package_manager: PackageBackendKind,
+
+        #[clap(
+                long = "detector",
+                value_enum,
+                help = "How to detect the hardware of the running system.",
+                default_value_t = DetectorKind::Native,
+                global = true,
+                display_order = usize::MAX - 9,
+            )]
+        pub detector: DetectorKind,
+
+        #[clap(
+                long = "only-free",
+                help = "Only search and install drivers that are free software, going by their license or tags like `foss`.",
+                num_args = 0,
+                conflicts_with = "only_proprietary",
+                global = true,
+                display_order = usize::MAX - 10,
+            )]
+        pub only_free: bool,
+
+        #[clap(
+                long = "only-proprietary",
+                help = "Only search and install proprietary drivers, going by their license or tags like `proprietary`.",
+                num_args = 0,
+                global = true,
+                display_order = usize::MAX - 11,
+            )]
+        pub only_proprietary: bool,
+
+        #[clap(
+                long = "no-scripts",
+                help = "Do not run hooks, verify commands or scripts of driver options when installing. Only packages are installed and removed.",
+                num_args = 0,
+                global = true,
+                display_order = usize::MAX - 12,
+            )]
+        pub no_scripts: bool,
+
+        #[clap(
+                long = "verify-checksum",
+                help = "Check the database against the `.sha256` file written next to it by generate-database before using it.",
+                num_args = 0,
+                global = true,
+                display_order = usize::MAX - 13,
+            )]
+        pub verify_checksum: bool,
+
+        #[clap(
+                long = "strict-tags",
+                help = "Fail when a tag given with --tag or --prefer is not carried by any driver option in the database, instead of finding nothing, so that misspelled tags are noticed.",
+                num_args = 0,
+                global = true,
+                display_order = usize::MAX - 14,
+            )]
+        pub strict_tags: bool,
+
+        #[clap(
+                long = "vendor-allow",
+                value_name = "VENDOR_ID",
+                value_parser = parse_hex_id,
+                help = "Only consider the detected hardware of this vendor, by hexadecimal PCI or USB vendor ID like `10de`. Can be given more than once. Replaces `vendor_allow` of the configuration file.",
+                global = true,
+                display_order = usize::MAX - 15,
+            )]
+        pub vendor_allow: Vec<u16>,
+
+        #[clap(
+                long = "vendor-deny",
+                value_name = "VENDOR_ID",
+                value_parser = parse_hex_id,
+                help = "Never consider the detected hardware of this vendor, by hexadecimal PCI or USB vendor ID like `046d`. Can be given more than once. Replaces `vendor_deny` of the configuration file.",
+                global = true,
+                display_order = usize::MAX - 16,
+            )]
+        pub vendor_deny: Vec<u16>,
+
         #[clap(
                 long = "json",
                 help = "Output in the JSON format for machine readability and scripting purposes.",
                 num_args = 0,
                 global = true,
-                display_order = usize::MAX - 3,
+                display_order = usize::MAX - 4,
             )]
         pub json_flag: bool,
 
+        #[clap(
+                long = "json-pretty",
+                help = "Output in the JSON format like --json, indented for reading.",
+                num_args = 0,
+                global = true,
+                display_order = usize::MAX - 4,
+            )]
+        pub json_pretty_flag: bool,
+
         #[clap(
                 long = "plain",
                 help = "Output as plain text without extra information, for machine readability and scripting purposes.",
                 num_args = 0,
                 global = true,
-                display_order = usize::MAX - 2,
+                display_order = usize::MAX - 3,
             )]
         pub plain_flag: bool,
 
+        #[clap(
+                long = "table",
+                help = "Output as a table with aligned columns.",
+                num_args = 0,
+                global = true,
+                display_order = usize::MAX - 2,
+            )]
+        pub table_flag: bool,
+
         #[clap(
                 long = "debug",
                 help = "Output debug messages.",
@@ -207,17 +630,53 @@ pub mod commandline_interface_template {
                 display_order = usize::MAX - 1,
             )]
         pub debug_flag: bool,
+
+        #[clap(
+                long = "color",
+                alias = "colour",
+                value_enum,
+                help = "When to use colors. `auto` also honors the `NO_COLOR` environment variable.",
+                default_value_t = ColorChoice::Auto,
+                global = true,
+                display_order = usize::MAX,
+            )]
+        pub color: ColorChoice,
+    }
+
+    #[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+    pub enum ColorChoice {
+        #[default]
+        Auto,
+        Always,
+        Never,
+    }
+
+    impl GlobalArguments {
+        /// The license that driver options are restricted to, if any.
+        pub fn license_filter(&self) -> Option<License> {
+            if self.only_free {
+                Some(License::Free)
+            } else if self.only_proprietary {
+                Some(License::Proprietary)
+            } else {
+                None
+            }
+        }
     }
 
     impl CommandlineFlags for GlobalArguments {
         fn json_flag(&self) -> bool {
-            return self.json_flag;
+            return self.json_flag || self.json_pretty_flag;
         }
 
         fn plain_flag(&self) -> bool {
             return self.plain_flag;
         }
 
+        fn table_flag(&self) -> bool {
+            return self.table_flag;
+        }
+
         fn debug_flag(&self) -> bool {
             return self.debug_flag;
         }
@@ -246,6 +705,106 @@ pub mod commandline_interface_template {
             display_order = 4
         )]
         GenerateDatabase(GenerateDatabaseActionArguments),
+
+        #[clap(
+            name = "deps",
+            about = "Show the dependency tree of a driver option.",
+            display_order = 5
+        )]
+        Deps(DepsActionArguments),
+
+        #[clap(
+            name = "version",
+            about = "Show version information of the program, libalpm, and the database format.",
+            display_order = 6
+        )]
+        Version,
+
+        #[clap(
+            name = "restore-database",
+            alias = "restoredb",
+            about = "Restore the database from a backup taken by generate-database.",
+            display_order = 7
+        )]
+        RestoreDatabase(RestoreDatabaseActionArguments),
+
+        #[clap(
+            name = "list-tags",
+            about = "List the tags that drivers can be filtered by.",
+            display_order = 8
+        )]
+        ListTags(ListTagsActionArguments),
+
+        #[clap(
+            name = "coverage",
+            about = "Show which driver options match each hardware ID listed in a file, or each one the database can match.",
+            display_order = 9
+        )]
+        Coverage(CoverageActionArguments),
+
+        #[clap(
+            name = "profile",
+            about = "Save the installed drivers as a named profile, or apply a saved profile.",
+            display_order = 10
+        )]
+        Profile(ProfileActionArguments),
+
+        #[clap(
+            name = "history",
+            about = "Show the driver installs recorded in the install log.",
+            display_order = 11
+        )]
+        History(HistoryActionArguments),
+
+        #[clap(
+            name = "prune",
+            about = "List the installed driver packages that no driver option lists anymore, and remove them with --yes.",
+            display_order = 12
+        )]
+        Prune(PruneActionArguments),
+
+        #[clap(
+            name = "export",
+            about = "Write the hardware setups of a database in the format of an input file.",
+            display_order = 13
+        )]
+        Export(ExportActionArguments),
+
+        #[clap(
+            name = "doctor",
+            about = "Check the system for common problems that keep drivers from being installed.",
+            display_order = 14
+        )]
+        Doctor(DoctorActionArguments),
+
+        #[cfg(feature = "serve")]
+        #[clap(
+            name = "serve",
+            about = "Answer search, list, detect and install-plan requests from frontends as JSON over a Unix socket.",
+            display_order = 15
+        )]
+        Serve(ServeActionArguments),
+
+        #[clap(
+            name = "lint-database",
+            about = "Report driver options and hardware setups in a database that are likely mistakes.",
+            display_order = 16
+        )]
+        LintDatabase(LintDatabaseActionArguments),
+
+        #[clap(
+            name = "hardware-kinds",
+            about = "List the supported hardware kinds and the names accepted for them.",
+            display_order = 17
+        )]
+        HardwareKinds,
+
+        #[clap(
+            name = "reapply",
+            about = "Install the drivers recorded by earlier installs again, like after a system refresh, and report which ones have drifted.",
+            display_order = 18
+        )]
+        Reapply(ReapplyActionArguments),
     }
 
     #[derive(Debug, Args)]
@@ -266,20 +825,42 @@ pub mod commandline_interface_template {
         )]
         pub tags: Vec<String>,
 
+        #[clap(
+            long = "no-default-tags",
+            help = "Do not add the default tags configured for the hardware in the configuration file.",
+            display_order = 15
+        )]
+        pub no_default_tags: bool,
+
         #[clap(
             long = "database",
-            help = "Path to the `ron` database file to use for recognizing drivers.",
-            default_value = *DB_PATH,
+            help = "Path to the database file to use for recognizing drivers.",
+            default_value_os_t = database_path(),
             display_order = 13
         )]
         pub database_file: PathBuf,
+
+        #[clap(
+            long = "since",
+            help = "Only list drivers installed within a duration (like `7d` or `12h`) or since a date (like `2023-06-01`).",
+            value_parser = parse_since,
+            display_order = 14
+        )]
+        pub since: Option<i64>,
+
+        #[clap(
+            long = "copy-db",
+            help = "Read from a private copy of the database instead of the database file itself, for example while it is being regenerated.",
+            display_order = 16
+        )]
+        pub copy_database: bool,
     }
 
     #[derive(Debug, Args)]
     pub struct SearchActionArguments {
         #[clap(
             value_enum,
-            help = "The hardware to search drivers for.",
+            help = "The hardware to search drivers for. Without it, or with --all-kinds, drivers for every kind of hardware are searched.",
             display_order = 21
         )]
         pub hardware: Option<HardwareKind>,
@@ -293,13 +874,56 @@ pub mod commandline_interface_template {
         )]
         pub tags: Vec<String>,
 
+        #[clap(
+            long = "no-default-tags",
+            help = "Do not add the default tags configured for the hardware in the configuration file.",
+            display_order = 26
+        )]
+        pub no_default_tags: bool,
+
+        #[clap(
+            long = "prefer",
+            value_name = "TAG",
+            help = "Rank drivers carrying any of these tags ahead of others of the same priority. Unlike --tag, which leaves out drivers without all of its tags, no driver is left out. Both can be combined: --tag decides which drivers are considered and --prefer how they are ordered.",
+            display_order = 28
+        )]
+        pub prefer: Vec<String>,
+
+        #[clap(
+            long = "packages",
+            help = "Print only the names of the packages of the drivers found, sorted and one per line, like for `pacman -S $(archlinux-driver-manager search graphics --packages)`.",
+            display_order = 29
+        )]
+        pub packages: bool,
+
         #[clap(
             long = "database",
-            help = "Path to the `ron` database file to use for searching drivers.",
-            default_value = *DB_PATH,
+            help = "Path to the database file to use for searching drivers.",
+            default_value_os_t = database_path(),
             display_order = 23
         )]
         pub database_file: PathBuf,
+
+        #[clap(
+            long = "explain",
+            help = "Show the hardware setup and detected hardware IDs that each driver matched on.",
+            display_order = 24
+        )]
+        pub explain: bool,
+
+        #[clap(
+            long = "all-kinds",
+            help = "Search drivers for every kind of hardware. This takes precedence over the hardware argument, which then only decides the kind listed first.",
+            display_order = 25
+        )]
+        pub all_kinds: bool,
+
+        #[clap(
+            long = "compare-installed",
+            help = "Show whether the packages of each driver option are installed, fully or partially.",
+            display_order = 27
+        )]
+        pub compare_installed: bool,
     }
 
     #[derive(Debug, Args)]
@@ -307,9 +931,10 @@ pub mod commandline_interface_template {
         #[clap(
             value_enum,
             help = "The hardware to install drivers for.",
+            required_unless_present = "from_file",
             display_order = 31
         )]
-        pub hardware: HardwareKind,
+        pub hardware: Option<HardwareKind>,
 
         #[clap(
             long = "tag",
@@ -320,26 +945,184 @@ pub mod commandline_interface_template {
         )]
         pub tags: Vec<String>,
 
+        #[clap(
+            long = "no-default-tags",
+            help = "Do not add the default tags configured for the hardware in the configuration file.",
+            display_order = 49
+        )]
+        pub no_default_tags: bool,
+
+        #[clap(
+            long = "prefer",
+            value_name = "TAG",
+            help = "Rank drivers carrying any of these tags ahead of others of the same priority when selecting one. Unlike --tag, which leaves out drivers without all of its tags, no driver is left out. Both can be combined: --tag decides which drivers are considered and --prefer how they are ordered.",
+            display_order = 53
+        )]
+        pub prefer: Vec<String>,
+
+        #[clap(
+            long = "packages",
+            help = "Print only the names of the packages installed, sorted and one per line, instead of the rest of the output.",
+            display_order = 54
+        )]
+        pub packages: bool,
+
         #[clap(
             long = "enable-aur",
-            help = "Enable installing from the Arch User Repository (AUR).",
+            help = "Enable installing from the Arch User Repository (AUR). Not supported yet, so packages that are only in the AUR are reported as not found.",
             display_order = 33
         )]
         pub enable_aur: bool,
 
         #[clap(
             long = "database",
-            help = "Path to the `ron` database file to use for searching drivers.",
-            default_value = *DB_PATH,
+            help = "Path to the database file to use for searching drivers.",
+            default_value_os_t = database_path(),
             display_order = 34
         )]
         pub database_file: PathBuf,
+
+        #[clap(
+            long = "reinstall",
+            help = "Reinstall packages of the driver that are already up to date.",
+            display_order = 35
+        )]
+        pub reinstall: bool,
+
+        #[clap(
+            long = "parallel-downloads",
+            value_name = "N",
+            value_parser = clap::value_parser!(u32).range(1..),
+            help = "Number of packages to download in parallel. Defaults to the ParallelDownloads setting in pacman.conf.",
+            display_order = 36
+        )]
+        pub parallel_downloads: Option<u32>,
+
+        #[clap(
+            long = "sign-modules",
+            help = "Sign the kernel modules built during the install with a Machine Owner Key, for Secure Boot. Needs --mok-key and --mok-certificate.",
+            requires_all = ["mok_key", "mok_certificate"],
+            display_order = 37
+        )]
+        pub sign_modules: bool,
+
+        #[clap(
+            long = "mok-key",
+            help = "Path to the private key of the Machine Owner Key used by --sign-modules.",
+            display_order = 38
+        )]
+        pub mok_key: Option<PathBuf>,
+
+        #[clap(
+            long = "mok-certificate",
+            help = "Path to the DER certificate of the Machine Owner Key used by --sign-modules.",
+            display_order = 39
+        )]
+        pub mok_certificate: Option<PathBuf>,
+
+        #[clap(
+            long = "no-confirm",
+            help = "Do not show the packages to change and ask for confirmation before installing, even for a driver option that carries a warning.",
+            display_order = 40
+        )]
+        pub no_confirm: bool,
+
+        #[clap(
+            long = "from-file",
+            value_name = "PLAN_FILE",
+            conflicts_with = "hardware",
+            help = "Install the driver options listed in a YAML file of `hardware` and `driver_option` pairs, without asking. The whole plan is checked against the database before anything is installed.",
+            display_order = 41
+        )]
+        pub from_file: Option<PathBuf>,
+
+        #[clap(
+            long = "keep-going",
+            requires = "from_file",
+            help = "Continue with the rest of the plan when installing one of its entries fails.",
+            display_order = 42
+        )]
+        pub keep_going: bool,
+
+        #[clap(
+            long = "explain-selection",
+            conflicts_with = "from_file",
+            help = "Show the driver options in the order they are ranked, with their priorities and tags, and why one was selected.",
+            display_order = 43
+        )]
+        pub explain_selection: bool,
+
+        #[clap(
+            long = "rank",
+            conflicts_with = "from_file",
+            help = "Install the driver option at this rank, where 1 is the one that would be installed anyway, instead of the highest ranked one. See --explain-selection for the ranks.",
+            display_order = 50
+        )]
+        pub rank: Option<usize>,
+
+        #[clap(
+            long = "continue-on-script-error",
+            help = "Only warn about post-install hooks that fail, instead of failing the install. Failing pre-install hooks still stop the install.",
+            display_order = 44
+        )]
+        pub continue_on_script_error: bool,
+
+        #[clap(
+            long = "with-optional",
+            help = "Also install the optional packages of the driver option, like utilities and settings apps.",
+            display_order = 45
+        )]
+        pub with_optional: bool,
+
+        #[clap(
+            long = "refresh",
+            short = 'y',
+            help = "Refresh the package databases before installing, like `pacman -Sy`. Without a full system upgrade this can leave the system partially upgraded.",
+            display_order = 46
+        )]
+        pub refresh: bool,
+
+        #[clap(
+            long = "include-disabled-repos",
+            value_name = "REPOSITORY",
+            value_delimiter = ',',
+            help = "Also install from these repositories, listed under `extra_repositories` in the configuration file, without enabling them in pacman.conf. Their databases are downloaded for this install only.",
+            display_order = 51
+        )]
+        pub include_disabled_repos: Vec<String>,
+
+        #[clap(
+            long = "enable-services",
+            value_name = "BOOL",
+            num_args = 0..=1,
+            default_missing_value = "true",
+            help = "Enable and start the systemd services of the installed driver option, and disable the ones of the driver options it replaces. On by default when systemd is running.",
+            display_order = 52
+        )]
+        pub enable_services: Option<bool>,
+
+        #[clap(
+            long = "edit",
+            conflicts_with = "from_file",
+            help = "Open the selected driver option in $EDITOR as YAML before installing, and install the edited version. The database is not changed.",
+            display_order = 47
+        )]
+        pub edit: bool,
+
+        #[clap(
+            long = "script-timeout",
+            value_name = "SECONDS",
+            help = "Kill an install hook or verify command that runs longer than this, failing the install. 0 lets them run for as long as they take.",
+            default_value_t = DEFAULT_SCRIPT_TIMEOUT_SECONDS,
+            display_order = 48
+        )]
+        pub script_timeout: u64,
     }
 
     #[derive(Debug, Args)]
     pub struct GenerateDatabaseActionArguments {
         #[clap(
-            help = "Path to the input file (Only YAML is currently supported).",
+            help = "Path to the input file, in YAML, or JSON when its extension is `.json`. Without --update, the database is regenerated to contain exactly the contents of this file. A directory stands for all of the YAML and JSON files directly in it.",
             display_order = 41
         )]
         pub input_file: PathBuf,
@@ -350,5 +1133,333 @@ pub mod commandline_interface_template {
             display_order = 42
         )]
         pub database_file: PathBuf,
+
+        #[clap(
+            long = "update",
+            help = "Only apply the hardware setups that changed since the database was last generated, and remove the ones no longer in the input file.",
+            display_order = 43
+        )]
+        pub update: bool,
+
+        #[clap(
+            long = "no-backup",
+            help = "Do not back up an existing database file before changing it.",
+            display_order = 44
+        )]
+        pub no_backup: bool,
+
+        #[clap(
+            long = "keep-backups",
+            value_name = "N",
+            value_parser = clap::value_parser!(u32).range(1..),
+            default_value_t = 5,
+            help = "Number of database backups to keep. Older ones are deleted.",
+            display_order = 45
+        )]
+        pub keep_backups: u32,
+
+        #[clap(
+            long = "watch",
+            help = "Keep running, and regenerate the database whenever the input file is saved. Stop with Ctrl-C.",
+            display_order = 46
+        )]
+        pub watch: bool,
+
+        #[clap(
+            long = "skip-errors",
+            help = "When the input is a directory, leave out the files that cannot be parsed, with a warning for each, instead of generating nothing.",
+            display_order = 47
+        )]
+        pub skip_errors: bool,
+    }
+
+    #[derive(Debug, Args)]
+    pub struct RestoreDatabaseActionArguments {
+        #[clap(
+            help = "Path to the backup to restore. Defaults to the newest backup of the database.",
+            display_order = 61
+        )]
+        pub backup: Option<PathBuf>,
+
+        #[clap(
+            long = "database",
+            help = "Path to the database file to restore.",
+            default_value_os_t = database_path(),
+            display_order = 62
+        )]
+        pub database_file: PathBuf,
+    }
+
+    #[derive(Debug, Args)]
+    pub struct DepsActionArguments {
+        #[clap(
+            value_enum,
+            help = "The hardware to show driver dependencies for.",
+            display_order = 51
+        )]
+        pub hardware: HardwareKind,
+
+        #[clap(
+            long = "driver",
+            help = "Name of the driver option. Defaults to the one that would be installed.",
+            display_order = 52
+        )]
+        pub driver: Option<String>,
+
+        #[clap(
+            long = "tag",
+            alias = "tags",
+            short = 't',
+            help = "Tags to filter drivers.",
+            display_order = 53
+        )]
+        pub tags: Vec<String>,
+
+        #[clap(
+            long = "database",
+            help = "Path to the database file to use for searching drivers.",
+            default_value_os_t = database_path(),
+            display_order = 54
+        )]
+        pub database_file: PathBuf,
+    }
+
+    #[derive(Debug, Args)]
+    pub struct ListTagsActionArguments {
+        #[clap(
+            value_enum,
+            help = "The hardware to list the tags of drivers for.",
+            display_order = 71
+        )]
+        pub hardware: Option<HardwareKind>,
+
+        #[clap(
+            long = "database",
+            help = "Path to the database file to read the tags from.",
+            default_value_os_t = database_path(),
+            display_order = 72
+        )]
+        pub database_file: PathBuf,
+    }
+
+    #[derive(Debug, Args)]
+    pub struct CoverageActionArguments {
+        #[clap(
+            help = "Path to a file listing hardware IDs, one like `PCI 10de:1b80` or `USB 0bda:8179` per line. Without it, every hardware ID of the database is listed.",
+            display_order = 81
+        )]
+        pub hardware_file: Option<PathBuf>,
+
+        #[clap(
+            long = "hardware",
+            value_enum,
+            help = "Only show the driver options for this hardware.",
+            display_order = 82
+        )]
+        pub hardware: Option<HardwareKind>,
+
+        #[clap(
+            long = "database",
+            help = "Path to the database file to match against.",
+            default_value_os_t = database_path(),
+            display_order = 83
+        )]
+        pub database_file: PathBuf,
+
+        #[clap(
+            long = "csv",
+            help = "Print one comma-separated row per hardware ID and driver option, with a header.",
+            display_order = 84
+        )]
+        pub csv: bool,
+    }
+
+    #[derive(Debug, Args)]
+    pub struct HistoryActionArguments {
+        #[clap(
+            value_enum,
+            help = "Only show the installs for this hardware.",
+            display_order = 101
+        )]
+        pub hardware: Option<HardwareKind>,
+
+        #[clap(
+            long = "limit",
+            value_name = "N",
+            help = "Only show the last N installs.",
+            display_order = 102
+        )]
+        pub limit: Option<usize>,
+    }
+
+    #[derive(Debug, Args)]
+    pub struct PruneActionArguments {
+        #[clap(
+            long = "database",
+            help = "Path to the database file to use for recognizing drivers.",
+            default_value_os_t = database_path(),
+            display_order = 111
+        )]
+        pub database_file: PathBuf,
+
+        #[clap(
+            long = "yes",
+            short = 'y',
+            help = "Remove the abandoned packages instead of only listing them.",
+            display_order = 112
+        )]
+        pub yes: bool,
+    }
+
+    #[derive(Debug, Args)]
+    pub struct ExportActionArguments {
+        #[clap(
+            long = "database",
+            help = "Path to the database file to export.",
+            default_value_os_t = database_path(),
+            display_order = 121
+        )]
+        pub database_file: PathBuf,
+
+        #[clap(
+            long = "format",
+            value_enum,
+            help = "The format to write the hardware setups in.",
+            default_value_t = FileFormat::Yaml,
+            display_order = 122
+        )]
+        pub format: FileFormat,
+    }
+
+    #[derive(Debug, Args)]
+    pub struct DoctorActionArguments {
+        #[clap(
+            long = "database",
+            help = "Path to the database file to check.",
+            default_value_os_t = database_path(),
+            display_order = 131
+        )]
+        pub database_file: PathBuf,
+    }
+
+    #[derive(Debug, Args)]
+    pub struct LintDatabaseActionArguments {
+        #[clap(
+            long = "database",
+            help = "Path to the database file to check.",
+            default_value_os_t = database_path(),
+            display_order = 151
+        )]
+        pub database_file: PathBuf,
+    }
+
+    #[derive(Debug, Args)]
+    pub struct ReapplyActionArguments {
+        #[clap(
+            long = "database",
+            help = "Path to the database file to read the driver options from.",
+            default_value_os_t = database_path(),
+            display_order = 181
+        )]
+        pub database_file: PathBuf,
+
+        #[clap(
+            long = "check",
+            help = "Only report how the installed packages differ from the recorded drivers, without installing anything.",
+            display_order = 182
+        )]
+        pub check: bool,
+
+        #[clap(
+            long = "no-confirm",
+            help = "Do not ask for confirmation before installing a driver option that carries a warning.",
+            display_order = 183
+        )]
+        pub no_confirm: bool,
+    }
+
+    #[cfg(feature = "serve")]
+    #[derive(Debug, Args)]
+    pub struct ServeActionArguments {
+        #[clap(
+            long = "database",
+            help = "Path to the database file to use for answering requests.",
+            default_value_os_t = database_path(),
+            display_order = 141
+        )]
+        pub database_file: PathBuf,
+
+        #[clap(
+            long = "socket",
+            help = "Path to the Unix socket to listen on.",
+            default_value_os_t = socket_path(),
+            display_order = 142
+        )]
+        pub socket: PathBuf,
+    }
+
+    #[derive(Debug, Args)]
+    pub struct ProfileActionArguments {
+        #[clap(subcommand)]
+        pub command: ProfileCommand,
+    }
+
+    #[derive(Debug, Subcommand)]
+    pub enum ProfileCommand {
+        #[clap(
+            name = "save",
+            about = "Save the driver options installed for each hardware kind as a profile.",
+            display_order = 1
+        )]
+        Save(ProfileSaveArguments),
+
+        #[clap(
+            name = "apply",
+            about = "Install the driver options of a saved profile.",
+            display_order = 2
+        )]
+        Apply(ProfileApplyArguments),
+    }
+
+    #[derive(Debug, Args)]
+    pub struct ProfileSaveArguments {
+        #[clap(help = "The name of the profile.", display_order = 91)]
+        pub name: String,
+
+        #[clap(
+            long = "database",
+            help = "Path to the database file to read the driver options from.",
+            default_value_os_t = database_path(),
+            display_order = 92
+        )]
+        pub database_file: PathBuf,
+    }
+
+    #[derive(Debug, Args)]
+    pub struct ProfileApplyArguments {
+        #[clap(help = "The name of the profile.", display_order = 91)]
+        pub name: String,
+
+        #[clap(
+            long = "database",
+            help = "Path to the database file to read the driver options from.",
+            default_value_os_t = database_path(),
+            display_order = 92
+        )]
+        pub database_file: PathBuf,
+
+        #[clap(
+            long = "reinstall",
+            help = "Reinstall packages that are already up to date.",
+            display_order = 93
+        )]
+        pub reinstall: bool,
+
+        #[clap(
+            long = "no-confirm",
+            help = "Do not ask for confirmation before installing a driver option that carries a warning.",
+            display_order = 94
+        )]
+        pub no_confirm: bool,
     }
 }
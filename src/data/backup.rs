@@ -0,0 +1,106 @@
+use crate::error::{DatabaseBackupSnafu, Error};
+use snafu::ResultExt;
+use std::{
+    ffi::OsString,
+    fs,
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// The path of a backup of a database file, taken at a number of milliseconds since the epoch.
+pub fn backup_path(database_file: &Path, timestamp: u128) -> PathBuf {
+    let mut file_name = database_file
+        .file_name()
+        .map(OsString::from)
+        .unwrap_or_default();
+    file_name.push(format!(".bak.{}", timestamp));
+    database_file.with_file_name(file_name)
+}
+
+/// The backups of a database file, from the oldest to the newest.
+pub fn database_backups(database_file: &Path) -> Result<Vec<PathBuf>, Error> {
+    let directory = match database_file.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+    let prefix = format!(
+        "{}.bak.",
+        database_file
+            .file_name()
+            .unwrap_or_default()
+            .to_string_lossy()
+    );
+    let mut backups = fs::read_dir(directory)
+        .context(DatabaseBackupSnafu { path: directory })?
+        .filter_map(|entry| {
+            let file_name = entry.ok()?.file_name();
+            let timestamp = file_name
+                .to_str()?
+                .strip_prefix(&prefix)?
+                .parse::<u128>()
+                .ok()?;
+            Some((timestamp, database_file.with_file_name(file_name)))
+        })
+        .collect::<Vec<(u128, PathBuf)>>();
+    backups.sort();
+    Ok(backups.into_iter().map(|(_timestamp, path)| path).collect())
+}
+
+/// Copies a database file next to itself under a timestamped name.
+/// Returns `None` if there is no database to back up yet.
+pub fn backup_database(database_file: &Path) -> Result<Option<PathBuf>, Error> {
+    if !database_file.exists() {
+        return Ok(None);
+    }
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis();
+    let path = backup_path(database_file, timestamp);
+    fs::copy(database_file, &path).context(DatabaseBackupSnafu { path: path.clone() })?;
+    Ok(Some(path))
+}
+
+/// Deletes all but the newest `keep` backups of a database file, and returns the deleted paths.
+pub fn prune_database_backups(database_file: &Path, keep: usize) -> Result<Vec<PathBuf>, Error> {
+    let mut backups = database_backups(database_file)?;
+    let excess = backups.len().saturating_sub(keep);
+    let pruned: Vec<PathBuf> = backups.drain(..excess).collect();
+    for path in pruned.iter() {
+        fs::remove_file(path).context(DatabaseBackupSnafu { path })?;
+    }
+    Ok(pruned)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::TestDirectory;
+    use std::{thread, time::Duration};
+
+    #[test]
+    pub fn backups_are_pruned_oldest_first() {
+        let directory = TestDirectory::new("backup");
+        let database_file = directory.join("database.db");
+
+        assert_eq!(backup_database(&database_file).unwrap(), None);
+
+        for generation in 1..=3 {
+            fs::write(&database_file, generation.to_string()).unwrap();
+            backup_database(&database_file).unwrap().unwrap();
+            thread::sleep(Duration::from_millis(2));
+        }
+        fs::write(directory.join("database.db.bak.unrelated"), "").unwrap();
+
+        let pruned = prune_database_backups(&database_file, 2).unwrap();
+        assert_eq!(pruned.len(), 1);
+        let backups = database_backups(&database_file).unwrap();
+        assert_eq!(
+            backups
+                .iter()
+                .map(|path| fs::read_to_string(path).unwrap())
+                .collect::<Vec<String>>(),
+            ["2", "3"]
+        );
+    }
+}
@@ -0,0 +1,142 @@
+use crate::{
+    data::{
+        database::convert_tag,
+        input_file::{parse_hex_id, HardwareKind},
+    },
+    error::{ConfigParseSnafu, ConfigReadSnafu, Error, UnknownRepositorySnafu},
+};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use snafu::ResultExt;
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fs,
+    io::ErrorKind,
+    path::Path,
+};
+
+/// Settings read from the configuration file, like
+///
+/// ```yaml
+/// default_tags:
+///   Graphics: [wayland]
+/// extra_repositories:
+///   chaotic-aur: [https://example.org/$repo/$arch]
+/// remote_index: https://example.org/drivers.yaml
+/// vendor_deny: [046d]
+/// ```
+#[derive(Clone, Default, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Config {
+    /// Tags added to the tags given on the command line when filtering drivers for a hardware kind.
+    #[serde(default)]
+    pub default_tags: BTreeMap<HardwareKind, Vec<String>>,
+
+    /// Repositories that `install --include-disabled-repos` can use without them being
+    /// enabled in the pacman configuration, by name, with their servers written like there.
+    #[serde(default)]
+    pub extra_repositories: BTreeMap<String, Vec<String>>,
+
+    /// The remote driver catalog merged into the database, unless
+    /// `ARCHLINUX_DRIVER_MANAGER_REMOTE_INDEX` names another one.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub remote_index: Option<String>,
+
+    /// The only vendors whose hardware is detected, by hexadecimal vendor ID, unless
+    /// `--vendor-allow` is given. Without any, the hardware of all vendors is.
+    #[serde(default, with = "hex_ids", skip_serializing_if = "BTreeSet::is_empty")]
+    pub vendor_allow: BTreeSet<u16>,
+
+    /// The vendors whose hardware is never detected, by hexadecimal vendor ID, unless
+    /// `--vendor-deny` is given.
+    #[serde(default, with = "hex_ids", skip_serializing_if = "BTreeSet::is_empty")]
+    pub vendor_deny: BTreeSet<u16>,
+}
+
+/// Vendor IDs written in hexadecimal, like in input files.
+mod hex_ids {
+    use super::*;
+
+    pub fn serialize<S: Serializer>(ids: &BTreeSet<u16>, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(ids.iter().map(|id| format!("{:04x}", id)))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<BTreeSet<u16>, D::Error> {
+        Vec::<String>::deserialize(deserializer)?
+            .iter()
+            .map(|id| parse_hex_id(id).map_err(serde::de::Error::custom))
+            .collect()
+    }
+}
+
+impl Config {
+    /// Reads the configuration file, or the default configuration if there is none.
+    pub fn load(path: &Path) -> Result<Self, Error> {
+        match fs::read(path) {
+            Ok(contents) => serde_yaml::from_slice(&contents).context(ConfigParseSnafu { path }),
+            Err(error) if error.kind() == ErrorKind::NotFound => Ok(Config::default()),
+            Err(error) => Err(error).context(ConfigReadSnafu { path }),
+        }
+    }
+
+    /// The servers of the extra repositories with these names.
+    pub fn selected_repositories(
+        &self,
+        names: &[String],
+        path: &Path,
+    ) -> Result<BTreeMap<String, Vec<String>>, Error> {
+        names
+            .iter()
+            .map(|name| match self.extra_repositories.get(name) {
+                Some(servers) => Ok((name.clone(), servers.clone())),
+                None => UnknownRepositorySnafu { name, path }.fail(),
+            })
+            .collect()
+    }
+
+    /// Adds the default tags of a hardware kind that are not given yet.
+    pub fn apply_default_tags(&self, hardware: HardwareKind, tags: &mut Vec<String>) {
+        for default_tag in self.default_tags.get(&hardware).into_iter().flatten() {
+            let default_tag = convert_tag(default_tag);
+            if !tags.contains(&default_tag) {
+                tags.push(default_tag);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    pub fn default_tags_only_apply_to_their_hardware_kind() {
+        let config: Config = serde_yaml::from_str(
+            r#"
+default_tags:
+  graphics: [wayland, open-source]
+"#,
+        )
+        .unwrap();
+
+        let mut tags = vec!["open source".to_string()];
+        config.apply_default_tags(HardwareKind::Graphics, &mut tags);
+        assert_eq!(tags, ["open source", "wayland"]);
+
+        let mut tags = Vec::new();
+        config.apply_default_tags(HardwareKind::Ethernet, &mut tags);
+        assert!(tags.is_empty());
+    }
+
+    #[test]
+    pub fn vendor_ids_are_hexadecimal() {
+        let config: Config = serde_yaml::from_str("vendor_allow: [10de, 0x8086]\n").unwrap();
+        assert_eq!(config.vendor_allow, BTreeSet::from([0x10de, 0x8086]));
+        assert!(config.vendor_deny.is_empty());
+        assert_eq!(
+            serde_yaml::from_str::<Config>(&serde_yaml::to_string(&config).unwrap()).unwrap(),
+            config
+        );
+        assert!(serde_yaml::from_str::<Config>("vendor_deny: [nvidia]\n").is_err());
+    }
+}
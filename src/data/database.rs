@@ -1,18 +1,107 @@
 use crate::{
-    error::{DatabaseSnafu, Error},
-    DB_PATH_TEMP,
+    data::input_file::parse_hex_id,
+    error::{
+        DatabaseChecksumMismatchSnafu, DatabaseChecksumSnafu, DatabaseNotWritableSnafu,
+        DatabaseOpenSnafu, DatabaseSchemaMismatchSnafu, DatabaseSnafu, Error, TemporaryCopySnafu,
+    },
+    paths,
 };
-use serde::{Deserialize, Serialize};
+use jammdb::{Bucket, Tx};
+use owo_colors::{OwoColorize, Stream::Stderr};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use sha2::{Digest, Sha256};
 use snafu::ResultExt;
 use std::{
-    fs,
+    cell::Cell,
+    fmt, fs, io,
     ops::{Deref, DerefMut},
-    path::{self, PathBuf},
+    path::{Path, PathBuf},
+    str::FromStr,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
 };
 
+/// The buckets mapping PCI and USB hardware IDs to the hardware setups that list them.
+pub const PCI_ID_BUCKET: &str = "pci_id_to_hardware_setup_id_bucket";
+pub const USB_ID_BUCKET: &str = "usb_id_to_hardware_setup_id_bucket";
+
+/// The version of the database layout written by `generate-database` and understood by the readers.
+/// Version 2 added the optional PCI revision to stored hardware lists.
+/// Version 3 added USB vendor wildcards.
+/// Version 4 added the optional warning of driver options.
+/// Version 5 added the optional DMI match of hardware setups.
+/// Version 6 added the optional post-install action of driver options.
+/// Version 7 stores every value with [`encode_value`] instead of a positional binary layout.
+/// Version 8 indexes runs of consecutive PCI devices under one [`pci_range_key`].
+/// Version 9 indexes single hardware IDs under their four byte [`hardware_id_key`].
+/// Version 10 keeps the PCI device ranges of hardware lists as they were written.
+pub const SCHEMA_VERSION: u32 = 10;
+
+/// The bucket of facts about the database itself, like the version of its layout.
+pub const METADATA_BUCKET: &str = "metadata_bucket";
+const SCHEMA_VERSION_KEY: &str = "schema_version";
+
+/// Records in a write transaction that the database has the layout of [`SCHEMA_VERSION`].
+pub fn write_schema_version(transaction: &Tx) -> Result<(), Error> {
+    transaction
+        .get_or_create_bucket(METADATA_BUCKET)
+        .context(DatabaseSnafu)?
+        .put(SCHEMA_VERSION_KEY, SCHEMA_VERSION.to_be_bytes().to_vec())
+        .context(DatabaseSnafu)?;
+    Ok(())
+}
+
+/// The layout version recorded when the database was generated, if any.
+pub fn stored_schema_version(transaction: &Tx) -> Option<u32> {
+    let metadata = transaction.get_bucket(METADATA_BUCKET).ok()?;
+    let data = metadata.get(SCHEMA_VERSION_KEY)?;
+    Some(u32::from_be_bytes(data.kv().value().try_into().ok()?))
+}
+
+/// The extension of the file written next to a generated database that holds its SHA-256 checksum.
+pub const CHECKSUM_EXTENSION: &str = "sha256";
+
+/// The version of the encoding of stored values, written as their first byte.
+pub const VALUE_ENCODING_VERSION: u8 = 1;
+
+/// Encodes a value to be stored in the database: [`VALUE_ENCODING_VERSION`] followed by
+/// the value as JSON. Since fields are stored by name, values written before a field was
+/// added still decode, with the default of that field, and older readers skip the fields
+/// they do not know.
+pub fn encode_value<T: Serialize>(value: &T) -> Vec<u8> {
+    let mut encoded = vec![VALUE_ENCODING_VERSION];
+    serde_json::to_writer(&mut encoded, value).expect("Stored values are always serializable");
+    encoded
+}
+
+/// Decodes a value stored with [`encode_value`].
+pub fn decode_value<T: DeserializeOwned>(encoded: &[u8]) -> Result<T, String> {
+    match encoded.split_first() {
+        Some((&VALUE_ENCODING_VERSION, json)) => {
+            serde_json::from_slice(json).map_err(|error| error.to_string())
+        }
+        Some((version, _)) => Err(format!("unsupported value encoding version {}", version)),
+        None => Err("empty value".to_string()),
+    }
+}
+
 #[derive(Clone)]
 pub struct DriverDatabase {
     pub db: jammdb::DB,
+    _temporary_copy: Option<Arc<TemporaryCopy>>,
+}
+
+/// A working copy of a database file, deleted once the last handle to it is dropped.
+struct TemporaryCopy {
+    path: PathBuf,
+}
+
+impl Drop for TemporaryCopy {
+    fn drop(&mut self) {
+        _ = fs::remove_file(&self.path);
+    }
 }
 
 #[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
@@ -31,6 +120,10 @@ pub struct PciId {
 
     #[serde(alias = "device-id")]
     pub device: u16,
+
+    /// The revision of the device, when known or required.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub revision: Option<u8>,
 }
 
 #[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
@@ -42,29 +135,258 @@ pub struct UsbId {
     pub device: u16,
 }
 
+impl HardwareId {
+    /// The vendor ID, of the PCI or USB vendor.
+    pub fn vendor(&self) -> u16 {
+        match self {
+            HardwareId::Pci(pci_id) => pci_id.vendor,
+            HardwareId::Usb(usb_id) => usb_id.vendor,
+        }
+    }
+
+    /// The lookup bucket and key under which hardware setups listing this ID are indexed.
+    /// PCI and USB IDs live in separate buckets, since their keys can coincide.
+    pub fn bucket_and_key(&self) -> (&'static str, Vec<u8>) {
+        match self {
+            HardwareId::Pci(pci_id) => {
+                (PCI_ID_BUCKET, hardware_id_key(pci_id.vendor, pci_id.device))
+            }
+            HardwareId::Usb(usb_id) => {
+                (USB_ID_BUCKET, hardware_id_key(usb_id.vendor, usb_id.device))
+            }
+        }
+    }
+
+    /// The bucket and keys to look up the hardware setups that can match this ID.
+    /// A USB ID is also looked up under the wildcard key of its vendor.
+    pub fn lookup_bucket_and_keys(&self) -> (&'static str, Vec<Vec<u8>>) {
+        let (bucket_name, key) = self.bucket_and_key();
+        match self {
+            HardwareId::Pci(_) => (bucket_name, vec![key]),
+            HardwareId::Usb(usb_id) => (
+                bucket_name,
+                vec![key, usb_vendor_wildcard_key(usb_id.vendor)],
+            ),
+        }
+    }
+}
+
+impl PciId {
+    /// Whether the device is within an inclusive range of device IDs.
+    pub fn matches_range(&self, start: u16, end: u16) -> bool {
+        (start..=end).contains(&self.device)
+    }
+}
+
+/// The key under which hardware setups listing a single hardware ID are indexed: the vendor
+/// and device as one big-endian `u32`, which sorts like the IDs themselves.
+pub fn hardware_id_key(vendor: u16, device: u16) -> Vec<u8> {
+    (((vendor as u32) << 16) | (device as u32))
+        .to_be_bytes()
+        .to_vec()
+}
+
+/// The key under which hardware setups listing a run of consecutive PCI devices of a
+/// vendor are indexed, instead of one key per device. The keys of a vendor share the
+/// prefix of the vendor, so they form an interval index that [`pci_range_keys`] scans.
+pub fn pci_range_key(vendor: u16, start: u16, end: u16) -> Vec<u8> {
+    format!("{:04x}:{:04x}-{:04x}", vendor, start, end).into_bytes()
+}
+
+/// The keys of the ranges of the vendor of a PCI ID that contain its device.
+pub fn pci_range_keys(bucket: &Bucket, pci_id: &PciId) -> Vec<Vec<u8>> {
+    // ';' follows ':', so this covers exactly the keys with the prefix of the vendor
+    let (first, last) = (
+        format!("{:04x}:", pci_id.vendor),
+        format!("{:04x};", pci_id.vendor),
+    );
+    bucket
+        .range(first.as_bytes()..last.as_bytes())
+        .filter_map(|data| {
+            let key = std::str::from_utf8(data.key()).ok()?;
+            let (start, end) = key.split_once(':')?.1.split_once('-')?;
+            let (start, end) = (
+                u16::from_str_radix(start, 16).ok()?,
+                u16::from_str_radix(end, 16).ok()?,
+            );
+            pci_id
+                .matches_range(start, end)
+                .then(|| data.key().to_vec())
+        })
+        .collect()
+}
+
+/// The key under which hardware setups matching every device of a USB vendor are indexed.
+/// Keys of single IDs are four bytes long, so the two never coincide.
+pub fn usb_vendor_wildcard_key(vendor: u16) -> Vec<u8> {
+    format!("{:04x}:*", vendor).into_bytes()
+}
+
+impl fmt::Display for HardwareId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            HardwareId::Pci(pci_id) => {
+                write!(f, "PCI {:04x}:{:04x}", pci_id.vendor, pci_id.device)?;
+                if let Some(revision) = pci_id.revision {
+                    write!(f, " rev {:02x}", revision)?;
+                }
+                Ok(())
+            }
+            HardwareId::Usb(usb_id) => write!(f, "USB {:04x}:{:04x}", usb_id.vendor, usb_id.device),
+        }
+    }
+}
+
+/// Parses a hardware ID the way it is displayed, like `PCI 10de:1b80 rev a1` or `USB 0bda:8179`.
+impl FromStr for HardwareId {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut words = s.split_whitespace();
+        let bus = words.next().unwrap_or_default();
+        let (vendor, device) = words
+            .next()
+            .and_then(|ids| ids.split_once(':'))
+            .ok_or_else(|| format!("expected vendor:device after {:?}", bus))?;
+        let (vendor, device) = (parse_hex_id(vendor)?, parse_hex_id(device)?);
+        let revision = match (words.next(), words.next()) {
+            (None, _) => None,
+            (Some(word), Some(revision)) if word.eq_ignore_ascii_case("rev") => Some(
+                u8::try_from(parse_hex_id(revision)?)
+                    .map_err(|error| format!("invalid revision {:?}: {}", revision, error))?,
+            ),
+            (Some(word), _) => return Err(format!("unexpected {:?}", word)),
+        };
+        if let Some(word) = words.next() {
+            return Err(format!("unexpected {:?}", word));
+        }
+        match bus.to_lowercase().as_str() {
+            "pci" => Ok(HardwareId::Pci(PciId {
+                vendor,
+                device,
+                revision,
+            })),
+            "usb" if revision.is_none() => Ok(HardwareId::Usb(UsbId { vendor, device })),
+            "usb" => Err("USB devices have no revision".to_string()),
+            _ => Err(format!("unknown bus {:?}, expected PCI or USB", bus)),
+        }
+    }
+}
+
 impl DriverDatabase {
     pub fn with_database_path(filepath: PathBuf) -> Result<Self, Error> {
         Ok(DriverDatabase {
             db: { jammdb::DB::open(filepath).context(DatabaseSnafu)? },
+            _temporary_copy: None,
         })
     }
 
+    /// Opens the database for reading where it is, or a private copy of it if `copy` is given.
+    /// jammdb opens every file for writing as well, so a database that cannot be written to,
+    /// like the system database for a regular user, or that does not exist is still copied.
+    pub fn for_reading(filepath: PathBuf, copy: bool) -> Result<Self, Error> {
+        let in_place = !copy
+            && fs::OpenOptions::new()
+                .read(true)
+                .write(true)
+                .open(&filepath)
+                .is_ok();
+        if in_place {
+            DriverDatabase::with_database_path(filepath.clone())?.checked_schema_version(&filepath)
+        } else {
+            DriverDatabase::cloned_from_database_path(filepath)
+        }
+    }
+
+    /// Fails unless the database was generated with the layout this version of the
+    /// program reads. Other layouts would silently match nothing. A database that
+    /// does not exist yet reads as empty.
+    fn checked_schema_version(self, filepath: &Path) -> Result<Self, Error> {
+        if !filepath.exists() {
+            return Ok(self);
+        }
+        let found = stored_schema_version(&self.tx(false).context(DatabaseSnafu)?);
+        if found != Some(SCHEMA_VERSION) {
+            return DatabaseSchemaMismatchSnafu {
+                path: filepath,
+                found,
+            }
+            .fail();
+        }
+        Ok(self)
+    }
+
+    /// Opens a private copy of the database in the temporary directory, so that
+    /// reading never contends with a regeneration of the original file.
     pub fn cloned_from_database_path(filepath: PathBuf) -> Result<Self, Error> {
-        let temp_db_path = path::PathBuf::from(*DB_PATH_TEMP);
-        std::fs::create_dir_all(temp_db_path.parent().unwrap()).unwrap();
-        _ = std::fs::remove_file(&temp_db_path).ok();
+        static COPY_SERIAL: AtomicUsize = AtomicUsize::new(1);
+        let file_name = format!(
+            "{}-{}",
+            std::process::id(),
+            COPY_SERIAL.fetch_add(1, Ordering::SeqCst)
+        );
+        let mut failure = None;
+        let usable_copy = paths::temp_directory_candidates()
+            .into_iter()
+            .find_map(|directory| {
+                let path = directory.join(&file_name);
+                _ = fs::remove_file(&path);
+                match paths::create_private_directory(&directory)
+                    .and_then(|_| fs::File::create(&path))
+                {
+                    Ok(file) => Some((path, file)),
+                    Err(error) => {
+                        failure = Some((path, error));
+                        None
+                    }
+                }
+            });
+        let (temp_db_path, mut temp_db_file) = match usable_copy {
+            Some(usable_copy) => usable_copy,
+            None => {
+                let (path, source) = failure.unwrap();
+                return Err(source).context(TemporaryCopySnafu { path });
+            }
+        };
+        let temporary_copy = Arc::new(TemporaryCopy {
+            path: temp_db_path.clone(),
+        });
         if filepath.exists() {
-            std::io::copy(
-                &mut std::fs::File::open(&filepath).expect("Could not open the database file."),
-                &mut fs::OpenOptions::new()
-                    .write(true)
-                    .create(true)
-                    .open(&temp_db_path)
-                    .unwrap(),
-            )
-            .unwrap();
+            let mut database_file = std::fs::File::open(&filepath).context(DatabaseOpenSnafu {
+                path: filepath.clone(),
+            })?;
+            std::io::copy(&mut database_file, &mut temp_db_file).context(TemporaryCopySnafu {
+                path: temp_db_path.clone(),
+            })?;
+        }
+        drop(temp_db_file);
+        DriverDatabase {
+            _temporary_copy: Some(temporary_copy),
+            ..DriverDatabase::with_database_path(temp_db_path)?
         }
-        DriverDatabase::with_database_path(temp_db_path)
+        .checked_schema_version(&filepath)
+    }
+
+    /// Fails with `DatabaseNotWritable` unless the database file can be written to,
+    /// or created if it does not exist yet.
+    pub fn ensure_writable(filepath: &Path) -> Result<(), Error> {
+        let writable = if filepath.exists() {
+            fs::OpenOptions::new().write(true).open(filepath).is_ok()
+        } else {
+            let created = fs::OpenOptions::new()
+                .write(true)
+                .create_new(true)
+                .open(filepath)
+                .is_ok();
+            if created {
+                _ = fs::remove_file(filepath);
+            }
+            created
+        };
+        if !writable {
+            return DatabaseNotWritableSnafu { path: filepath }.fail();
+        }
+        Ok(())
     }
 }
 
@@ -82,6 +404,263 @@ impl DerefMut for DriverDatabase {
     }
 }
 
+/// Decodes the entries read from the database, skipping the ones that cannot be decoded
+/// with a warning, so that one corrupt record does not fail a whole search. Counts the
+/// entries it skipped.
+#[derive(Debug, Default)]
+pub struct EntryDecoder {
+    skipped: Cell<usize>,
+}
+
+impl EntryDecoder {
+    pub fn new() -> Self {
+        EntryDecoder::default()
+    }
+
+    fn skip<E: fmt::Display>(&self, bucket_name: &str, key: &[u8], error: E) {
+        eprintln!(
+            "{} Skipping the entry {:?} of {} because it could not be decoded: {}",
+            "WARNING:".if_supports_color(Stderr, |text| text.yellow()),
+            String::from_utf8_lossy(key),
+            bucket_name,
+            error
+        );
+        self.skipped.set(self.skipped.get() + 1);
+    }
+
+    /// The value of a database entry, or `None` if it could not be decoded.
+    pub fn decode<T: DeserializeOwned>(
+        &self,
+        bucket_name: &str,
+        key: &[u8],
+        value: &[u8],
+    ) -> Option<T> {
+        decode_value(value)
+            .map_err(|error| self.skip(bucket_name, key, error))
+            .ok()
+    }
+
+    /// A value parsed from the key of a database entry, or `None` if it could not be parsed.
+    pub fn parse_key<T: FromStr>(&self, bucket_name: &str, key: &[u8]) -> Option<T>
+    where
+        T::Err: fmt::Display,
+    {
+        match std::str::from_utf8(key) {
+            Ok(text) => text
+                .parse()
+                .map_err(|error| self.skip(bucket_name, key, error))
+                .ok(),
+            Err(error) => {
+                self.skip(bucket_name, key, error);
+                None
+            }
+        }
+    }
+
+    /// The number of entries skipped so far.
+    pub fn skipped(&self) -> usize {
+        self.skipped.get()
+    }
+}
+
+/// The checksum file of a database, like `database.db.sha256` for `database.db`.
+pub fn checksum_path(database_file: &Path) -> PathBuf {
+    let mut checksum_path = database_file.as_os_str().to_owned();
+    checksum_path.push(".");
+    checksum_path.push(CHECKSUM_EXTENSION);
+    PathBuf::from(checksum_path)
+}
+
+fn file_checksum(path: &Path) -> io::Result<String> {
+    let mut hasher = Sha256::new();
+    io::copy(&mut fs::File::open(path)?, &mut hasher)?;
+    Ok(hex::encode(hasher.finalize()))
+}
+
+/// Writes the checksum of a database to its [`checksum_path`], in the format of `sha256sum`
+/// so that it can also be checked with `sha256sum --check`.
+pub fn write_checksum(database_file: &Path) -> Result<(), Error> {
+    let checksum = file_checksum(database_file).context(DatabaseChecksumSnafu {
+        path: database_file,
+    })?;
+    let file_name = database_file
+        .file_name()
+        .map(|file_name| file_name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let checksum_path = checksum_path(database_file);
+    fs::write(&checksum_path, format!("{}  {}\n", checksum, file_name)).context(
+        DatabaseChecksumSnafu {
+            path: checksum_path,
+        },
+    )
+}
+
+/// Fails with `DatabaseChecksumMismatch` unless the database matches the checksum written
+/// next to it when it was generated.
+pub fn verify_checksum(database_file: &Path) -> Result<(), Error> {
+    let checksum_path = checksum_path(database_file);
+    let expected = fs::read_to_string(&checksum_path)
+        .context(DatabaseChecksumSnafu {
+            path: &checksum_path,
+        })?
+        .split_whitespace()
+        .next()
+        .unwrap_or_default()
+        .to_lowercase();
+    let actual = file_checksum(database_file).context(DatabaseChecksumSnafu {
+        path: database_file,
+    })?;
+    if expected != actual {
+        return DatabaseChecksumMismatchSnafu {
+            path: database_file,
+            expected,
+            actual,
+        }
+        .fail();
+    }
+    Ok(())
+}
+
 pub fn convert_tag<S: AsRef<str>>(tag: S) -> String {
     tag.as_ref().trim().replace("-", " ").replace("_", " ")
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::input_file::HardwareKind;
+    use crate::test_support::TestDirectory;
+    use std::collections::BTreeSet;
+
+    #[test]
+    pub fn bucket_keys_keep_namespaces_apart() {
+        let pci_id = HardwareId::Pci(PciId {
+            vendor: 0x10de,
+            device: 0x1b80,
+            revision: None,
+        });
+        let usb_id = HardwareId::Usb(UsbId {
+            vendor: 0x10de,
+            device: 0x1b80,
+        });
+        let (pci_bucket, pci_key) = pci_id.bucket_and_key();
+        let (usb_bucket, usb_key) = usb_id.bucket_and_key();
+        assert_eq!(pci_key, [0x10, 0xde, 0x1b, 0x80]);
+        assert_eq!(pci_key, usb_key);
+        assert_ne!(pci_bucket, usb_bucket);
+    }
+
+    #[test]
+    pub fn hardware_ids_parse_as_displayed() {
+        for hardware_id in [
+            HardwareId::Pci(PciId {
+                vendor: 0x10de,
+                device: 0x1b80,
+                revision: Some(0xa1),
+            }),
+            HardwareId::Usb(UsbId {
+                vendor: 0x0bda,
+                device: 0x8179,
+            }),
+        ] {
+            assert_eq!(hardware_id.to_string().parse(), Ok(hardware_id));
+        }
+        assert_eq!(
+            "pci 0x10de:1B80".parse(),
+            Ok(HardwareId::Pci(PciId {
+                vendor: 0x10de,
+                device: 0x1b80,
+                revision: None,
+            }))
+        );
+        assert!("10de:1b80".parse::<HardwareId>().is_err());
+        assert!("ISA 10de:1b80".parse::<HardwareId>().is_err());
+        assert!("USB 0bda:8179 rev 01".parse::<HardwareId>().is_err());
+    }
+
+    #[test]
+    pub fn undecodable_entries_are_skipped() {
+        let entry_decoder = EntryDecoder::new();
+        let ids = BTreeSet::from(["1".to_string(), "2".to_string()]);
+        let encoded = encode_value(&ids);
+        assert_eq!(
+            entry_decoder.decode::<BTreeSet<String>>("bucket", b"good", &encoded),
+            Some(ids)
+        );
+        assert_eq!(
+            entry_decoder.decode::<BTreeSet<String>>("bucket", b"bad", &encoded[..5]),
+            None
+        );
+        assert_eq!(
+            entry_decoder.parse_key::<HardwareKind>("bucket", b"Graphics"),
+            Some(HardwareKind::Graphics)
+        );
+        assert_eq!(
+            entry_decoder.parse_key::<HardwareKind>("bucket", b"Floppy"),
+            None
+        );
+        assert_eq!(entry_decoder.skipped(), 2);
+    }
+
+    #[test]
+    pub fn database_writability() {
+        let directory = TestDirectory::new("writable");
+        let database_file = directory.join("database.db");
+
+        DriverDatabase::ensure_writable(&database_file).unwrap();
+        assert!(!database_file.exists());
+
+        let missing_directory = directory.join("missing").join("database.db");
+        assert!(matches!(
+            DriverDatabase::ensure_writable(&missing_directory),
+            Err(Error::DatabaseNotWritable { .. })
+        ));
+    }
+
+    #[test]
+    pub fn databases_are_only_copied_for_reading_on_request() {
+        let directory = TestDirectory::new("for-reading");
+        let database_file = directory.join("database.db");
+        drop(DriverDatabase::with_database_path(database_file.clone()).unwrap());
+        assert!(matches!(
+            DriverDatabase::for_reading(database_file.clone(), false),
+            Err(Error::DatabaseSchemaMismatch { found: None, .. })
+        ));
+        let driver_database = DriverDatabase::with_database_path(database_file.clone()).unwrap();
+        let transaction = driver_database.tx(true).unwrap();
+        write_schema_version(&transaction).unwrap();
+        transaction.commit().unwrap();
+        drop(driver_database);
+
+        let in_place = DriverDatabase::for_reading(database_file.clone(), false).unwrap();
+        assert!(in_place._temporary_copy.is_none());
+        drop(in_place);
+        let copied = DriverDatabase::for_reading(database_file, true).unwrap();
+        assert!(copied._temporary_copy.is_some());
+    }
+
+    #[test]
+    pub fn corrupted_databases_fail_the_checksum() {
+        let directory = TestDirectory::new("checksum");
+        let database_file = directory.join("database.db");
+        drop(DriverDatabase::with_database_path(database_file.clone()).unwrap());
+
+        assert!(matches!(
+            verify_checksum(&database_file),
+            Err(Error::DatabaseChecksum { .. })
+        ));
+        write_checksum(&database_file).unwrap();
+        assert!(fs::read_to_string(checksum_path(&database_file))
+            .unwrap()
+            .ends_with("  database.db\n"));
+        verify_checksum(&database_file).unwrap();
+
+        let mut contents = fs::read(&database_file).unwrap();
+        contents[0] ^= 0xff;
+        fs::write(&database_file, contents).unwrap();
+        assert!(matches!(
+            verify_checksum(&database_file),
+            Err(Error::DatabaseChecksumMismatch { .. })
+        ));
+    }
+}
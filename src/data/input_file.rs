@@ -1,19 +1,38 @@
+use crate::detection::{modalias_hardware_id, modalias_matches, DmiIdentity};
 use crate::error::Error;
-use crate::error::InputFileParseSnafu;
+use crate::error::{
+    InputDirectoryInvalidSnafu, InputFileIncludeCycleSnafu, InputFileIncludeInvalidSnafu,
+    InputFileIncludeOutsideSnafu, InputFileIncludeSnafu, InputFileJsonParseSnafu,
+    InputFileParseSnafu, InputFileReadSnafu, MissingInterpreterSnafu,
+};
+use clap::ValueEnum;
 use core::fmt;
-use serde::{Deserialize, Deserializer, Serialize};
+use serde::{
+    de::{SeqAccess, Visitor},
+    Deserialize, Deserializer, Serialize, Serializer,
+};
 use snafu::ResultExt;
-use speedy::{Readable, Writable};
 use std::str::FromStr;
-use std::{collections::BTreeSet, fs::File, path::PathBuf};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    env,
+    ffi::OsStr,
+    fs,
+    ops::{Deref, RangeInclusive},
+    os::unix::fs::PermissionsExt,
+    path::{Component, Path, PathBuf},
+};
 
+use super::database::convert_tag;
+use super::database::pci_range_key;
+use super::database::usb_vendor_wildcard_key;
 use super::database::HardwareId;
 use super::database::PciId;
 use super::database::UsbId;
+use super::database::PCI_ID_BUCKET;
+use super::database::USB_ID_BUCKET;
 
-#[derive(
-    Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize, Readable, Writable,
-)]
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
 /// Represents a particular type of hardware setup, like Intel+Nvidia Hybrid Graphics, or Nvidia Discrete Graphics, Intel+AMD Hybrid Graphics, etc.
 pub struct HardwareSetup {
     #[serde(default)]
@@ -26,6 +45,40 @@ pub struct HardwareSetup {
 
     pub hardware_list: HardwareList,
 
+    /// Restricts the setup to a system vendor and model, for model-specific quirks.
+    /// Without it, the setup applies to any system with the listed hardware.
+    #[serde(
+        default,
+        alias = "dmi-match",
+        alias = "dmi",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub dmi_match: Option<DmiMatch>,
+
+    /// Restricts the setup to virtual machines, for the virtual GPUs of hypervisors.
+    #[serde(
+        default,
+        alias = "virtual-only",
+        skip_serializing_if = "std::ops::Not::not"
+    )]
+    pub virtual_only: bool,
+
+    /// Restricts the setup to systems that are not virtual machines, so that a driver
+    /// for physical hardware is not offered for a device passed through to a guest.
+    #[serde(
+        default,
+        alias = "bare-metal-only",
+        skip_serializing_if = "std::ops::Not::not"
+    )]
+    pub bare_metal_only: bool,
+
+    /// Patterns of the kernel modaliases the setup applies to, with the `*` and `?`
+    /// wildcards of `modules.alias`, like `pci:v000010DEd*sv*sd*bc03sc*i*`. When the
+    /// detector reports modaliases, only the devices of the hardware list whose modalias
+    /// matches one of them count. Otherwise the hardware list alone decides.
+    #[serde(default, alias = "modaliases", skip_serializing_if = "Vec::is_empty")]
+    pub modalias: Vec<String>,
+
     pub driver_options: BTreeSet<DriverOption>,
 }
 
@@ -36,99 +89,216 @@ impl HardwareSetup {
         optional_hardware: &Option<HardwareKind>,
         tags: &BTreeSet<String>,
     ) -> Option<BTreeSet<&DriverOption>> {
+        self.matching_hardware_ids(hardware_ids, optional_hardware)?;
+        return Some(self.driver_options_with_tags(tags));
+    }
+
+    /// The detected hardware IDs that this setup matches on, or `None` if it does not apply.
+    pub fn matching_hardware_ids(
+        &self,
+        hardware_ids: &BTreeSet<HardwareId>,
+        optional_hardware: &Option<HardwareKind>,
+    ) -> Option<BTreeSet<HardwareId>> {
         if let Some(hardware_kind) = optional_hardware {
             if &self.hardware_kind != hardware_kind {
                 return None;
             }
         }
-        if !self.hardware_list.matches_with_hardware_ids(hardware_ids) {
-            return None;
+        return self.hardware_list.matching_hardware_ids(hardware_ids);
+    }
+
+    /// Whether the system matches the DMI restriction of this setup, if it has one.
+    pub fn matches_dmi(&self, dmi_identity: Option<&DmiIdentity>) -> bool {
+        match &self.dmi_match {
+            None => true,
+            Some(dmi_match) => {
+                dmi_identity.is_some_and(|dmi_identity| dmi_match.matches(dmi_identity))
+            }
         }
-        return Some({
-            self.driver_options
-                .iter()
-                .filter(|driver_option| tags.iter().all(|tag| driver_option.tags.contains(tag)))
-                .collect()
-        });
+    }
+
+    /// The matched hardware IDs whose modalias matches one of the `modalias` patterns of
+    /// this setup, or `None` if there are none. Without patterns or without modaliases
+    /// to compare with, all of them are kept.
+    pub fn matching_modaliases(
+        &self,
+        hardware_ids: BTreeSet<HardwareId>,
+        modaliases: Option<&BTreeSet<String>>,
+    ) -> Option<BTreeSet<HardwareId>> {
+        let modaliases = match modaliases {
+            Some(modaliases) if !self.modalias.is_empty() => modaliases,
+            _ => return Some(hardware_ids),
+        };
+        let matching_devices: BTreeSet<HardwareId> = modaliases
+            .iter()
+            .filter(|modalias| {
+                self.modalias
+                    .iter()
+                    .any(|pattern| modalias_matches(pattern, modalias))
+            })
+            .filter_map(|modalias| modalias_hardware_id(modalias))
+            .collect();
+        // Modaliases do not carry the PCI revision
+        let without_revision = |hardware_id: &HardwareId| match hardware_id {
+            HardwareId::Pci(pci_id) => HardwareId::Pci(PciId {
+                revision: None,
+                ..pci_id.clone()
+            }),
+            HardwareId::Usb(_) => hardware_id.clone(),
+        };
+        let hardware_ids: BTreeSet<HardwareId> = hardware_ids
+            .into_iter()
+            .filter(|hardware_id| matching_devices.contains(&without_revision(hardware_id)))
+            .collect();
+        (!hardware_ids.is_empty()).then_some(hardware_ids)
+    }
+
+    /// Whether this setup applies to a virtual machine, or to bare metal.
+    pub fn matches_virtualization(&self, is_virtual_machine: bool) -> bool {
+        if is_virtual_machine {
+            !self.bare_metal_only
+        } else {
+            !self.virtual_only
+        }
+    }
+
+    /// Problems with this setup that do not stop it from being used, but that likely
+    /// make it match differently than intended.
+    pub fn validation_warnings(&self) -> Vec<String> {
+        let mut warnings: Vec<String> = self
+            .hardware_list
+            .repeated_vendors()
+            .into_iter()
+            .map(|(bus, vendor)| {
+                format!(
+                    "The hardware setup {:?} lists the {} vendor {:04x} in more than one group of its `each` list, so a single device can satisfy several groups",
+                    self.name, bus, vendor
+                )
+            })
+            .collect();
+        if self.virtual_only && self.bare_metal_only {
+            warnings.push(format!(
+                "The hardware setup {:?} is both `virtual_only` and `bare_metal_only`, so it never matches",
+                self.name
+            ));
+        }
+        if self.driver_options.is_empty() {
+            warnings.push(format!(
+                "The hardware setup {:?} has no driver options, so searches leave it out",
+                self.name
+            ));
+        }
+        warnings
+    }
+
+    pub fn driver_options_with_tags(&self, tags: &BTreeSet<String>) -> BTreeSet<&DriverOption> {
+        return self
+            .driver_options
+            .iter()
+            .filter(|driver_option| tags.iter().all(|tag| driver_option.tags.contains(tag)))
+            .collect();
+    }
+}
+
+/// The system vendor and model a hardware setup is restricted to, compared with
+/// `/sys/class/dmi/id/sys_vendor` and `/sys/class/dmi/id/product_name` regardless
+/// of case. Fields left out match any value.
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct DmiMatch {
+    #[serde(default, alias = "sys-vendor", alias = "vendor")]
+    pub sys_vendor: Option<String>,
+
+    #[serde(default, alias = "product-name", alias = "product")]
+    pub product_name: Option<String>,
+}
+
+impl DmiMatch {
+    pub fn matches(&self, dmi_identity: &DmiIdentity) -> bool {
+        let field_matches = |expected: &Option<String>, actual: &str| {
+            expected
+                .as_ref()
+                .is_none_or(|expected| expected.trim().eq_ignore_ascii_case(actual.trim()))
+        };
+        field_matches(&self.sys_vendor, &dmi_identity.sys_vendor)
+            && field_matches(&self.product_name, &dmi_identity.product_name)
     }
 }
 
-#[derive(
-    Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize, Readable, Writable,
-)]
+/// A kind of hardware that drivers are installed for. Everywhere a hardware kind is read,
+/// on the command line and in files, its name and aliases are accepted in any case.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize)]
 pub enum HardwareKind {
-    #[serde(
-        alias = "graphics",
-        alias = "Graphics",
-        alias = "GRAPHICS",
-        alias = "gpu",
-        alias = "Gpu",
-        alias = "GPU"
-    )]
     Graphics,
-
-    #[serde(
-        alias = "ethernet",
-        alias = "Ethernet",
-        alias = "ETHERNET",
-        alias = "lan",
-        alias = "Lan",
-        alias = "LAN"
-    )]
     Ethernet,
-
-    #[serde(
-        alias = "wireless",
-        alias = "Wireless",
-        alias = "WIRELESS",
-        alias = "wifi",
-        alias = "Wifi",
-        alias = "WiFi",
-        alias = "WIFI"
-    )]
     Wireless,
-
-    #[serde(
-        alias = "sound",
-        alias = "Sound",
-        alias = "SOUND",
-        alias = "audio",
-        alias = "Audio",
-        alias = "AUDIO"
-    )]
     Audio,
 }
 
+impl HardwareKind {
+    /// Every hardware kind, in the order they are listed.
+    pub fn variants() -> &'static [HardwareKind] {
+        &[
+            HardwareKind::Graphics,
+            HardwareKind::Ethernet,
+            HardwareKind::Wireless,
+            HardwareKind::Audio,
+        ]
+    }
+
+    /// The name of the hardware kind, as used in plain output and hook environments.
+    pub fn name(&self) -> &'static str {
+        match self {
+            HardwareKind::Graphics => "graphics",
+            HardwareKind::Ethernet => "ethernet",
+            HardwareKind::Wireless => "wireless",
+            HardwareKind::Audio => "audio",
+        }
+    }
+
+    /// Other names accepted for the hardware kind.
+    pub fn aliases(&self) -> &'static [&'static str] {
+        match self {
+            HardwareKind::Graphics => &["gpu"],
+            HardwareKind::Ethernet => &["lan"],
+            HardwareKind::Wireless => &["wifi"],
+            HardwareKind::Audio => &["sound"],
+        }
+    }
+}
+
 impl FromStr for HardwareKind {
     type Err = Error;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        let user_string = String::from(s).to_lowercase();
-        match user_string.as_str() {
-            "graphics" | "Graphics" | "GRAPHICS" | "gpu" | "Gpu" | "GPU" => {
-                Ok(HardwareKind::Graphics)
-            }
-            "ethernet" | "Ethernet" | "ETHERNET" | "lan" | "Lan" | "LAN" => {
-                Ok(HardwareKind::Ethernet)
-            }
-            "wireless" | "Wireless" | "WIRELESS" | "wifi" | "Wifi" | "WiFi" | "WIFI" => {
-                Ok(HardwareKind::Wireless)
-            }
-            "sound" | "Sound" | "SOUND" | "audio" | "Audio" | "AUDIO" => Ok(HardwareKind::Audio),
-            _ => Err(Error::InvalidEnumValue {
+        let name = s.trim();
+        HardwareKind::variants()
+            .iter()
+            .find(|hardware_kind| {
+                std::iter::once(hardware_kind.name())
+                    .chain(hardware_kind.aliases().iter().copied())
+                    .any(|accepted| accepted.eq_ignore_ascii_case(name))
+            })
+            .copied()
+            .ok_or_else(|| Error::InvalidEnumValue {
                 value: s.into(),
                 enum_name: "HardwareKind".into(),
-                allowed_values: vec![
-                    "graphics", "Graphics", "GRAPHICS", "gpu", "Gpu", "GPU", "ethernet",
-                    "Ethernet", "ETHERNET", "lan", "Lan", "LAN", "wireless", "Wireless",
-                    "WIRELESS", "wifi", "Wifi", "WiFi", "WIFI", "sound", "Sound", "SOUND", "audio",
-                    "Audio", "AUDIO",
-                ]
-                .into_iter()
-                .map(|s| String::from(s))
-                .collect(),
-            }),
-        }
+                allowed_values: HardwareKind::variants()
+                    .iter()
+                    .flat_map(|hardware_kind| {
+                        std::iter::once(hardware_kind.name())
+                            .chain(hardware_kind.aliases().iter().copied())
+                    })
+                    .map(String::from)
+                    .collect(),
+            })
+    }
+}
+
+impl<'de> Deserialize<'de> for HardwareKind {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer)?
+            .parse()
+            .map_err(serde::de::Error::custom)
     }
 }
 
@@ -143,9 +313,7 @@ impl fmt::Display for HardwareKind {
     }
 }
 
-#[derive(
-    Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize, Readable, Writable,
-)]
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
 pub enum HardwareList {
     #[serde(alias = "each")]
     /// Represents the presence of devices from each of the child groups
@@ -160,48 +328,178 @@ pub enum HardwareList {
 
 impl HardwareList {
     pub fn matches_with_hardware_ids(&self, hardware_ids: &BTreeSet<HardwareId>) -> bool {
-        return match self {
+        return self.matching_hardware_ids(hardware_ids).is_some();
+    }
+
+    /// Whether any of the PCI devices listed is restricted to a revision.
+    pub fn specifies_revision(&self) -> bool {
+        match self {
+            HardwareList::Each(hardware_lists_inner) => {
+                hardware_lists_inner
+                    .iter()
+                    .any(|hardware_list_inner| match hardware_list_inner {
+                        HardwareListInner::Pci(pci_id_list) => pci_id_list.revision.is_some(),
+                        HardwareListInner::Usb(_) => false,
+                    })
+            }
+            HardwareList::Pci(pci_id_list) => pci_id_list.revision.is_some(),
+            HardwareList::Usb(_) => false,
+        }
+    }
+
+    /// The vendors, along with their bus, that appear in more than one group of an `Each`
+    /// list. Identical groups are already merged when the list is parsed.
+    pub fn repeated_vendors(&self) -> BTreeSet<(&'static str, u16)> {
+        let mut seen = BTreeSet::<(&'static str, u16)>::new();
+        match self {
+            HardwareList::Each(hardware_lists_inner) => hardware_lists_inner
+                .iter()
+                .map(|hardware_list_inner| match hardware_list_inner {
+                    HardwareListInner::Pci(pci_id_list) => ("PCI", pci_id_list.vendor),
+                    HardwareListInner::Usb(usb_id_list) => ("USB", usb_id_list.vendor),
+                })
+                .filter(|bus_and_vendor| !seen.insert(*bus_and_vendor))
+                .collect(),
+            HardwareList::Pci(_) | HardwareList::Usb(_) => BTreeSet::new(),
+        }
+    }
+
+    /// Every hardware ID listed, regardless of what is present.
+    pub fn hardware_ids(&self) -> BTreeSet<HardwareId> {
+        match self {
+            HardwareList::Each(hardware_lists_inner) => hardware_lists_inner
+                .iter()
+                .flat_map(|hardware_list_inner| match hardware_list_inner {
+                    HardwareListInner::Pci(pci_id_list) => {
+                        pci_id_list.hardware_ids().collect::<Vec<HardwareId>>()
+                    }
+                    HardwareListInner::Usb(usb_id_list) => {
+                        usb_id_list.hardware_ids().collect::<Vec<HardwareId>>()
+                    }
+                })
+                .collect(),
+            HardwareList::Pci(pci_id_list) => pci_id_list.hardware_ids().collect(),
+            HardwareList::Usb(usb_id_list) => usb_id_list.hardware_ids().collect(),
+        }
+    }
+
+    /// The USB vendors all of whose devices the list matches.
+    pub fn usb_vendor_wildcards(&self) -> BTreeSet<u16> {
+        let usb_id_lists: Vec<&UsbIdList> = match self {
+            HardwareList::Each(hardware_lists_inner) => hardware_lists_inner
+                .iter()
+                .filter_map(|hardware_list_inner| match hardware_list_inner {
+                    HardwareListInner::Pci(_) => None,
+                    HardwareListInner::Usb(usb_id_list) => Some(usb_id_list),
+                })
+                .collect(),
+            HardwareList::Pci(_) => Vec::new(),
+            HardwareList::Usb(usb_id_list) => vec![usb_id_list],
+        };
+        usb_id_lists
+            .into_iter()
+            .filter(|usb_id_list| matches!(usb_id_list.devices, UsbDevices::Any))
+            .map(|usb_id_list| usb_id_list.vendor)
+            .collect()
+    }
+
+    /// The buckets and keys under which this list is indexed in the database: one for each
+    /// run of consecutive PCI devices, one for each other listed hardware ID, and one for
+    /// each USB vendor whose devices all match.
+    pub fn bucket_and_keys(&self) -> BTreeSet<(&'static str, Vec<u8>)> {
+        let hardware_lists_inner: Vec<HardwareListInner> = match self {
             HardwareList::Each(hardware_lists_inner) => {
-                hardware_lists_inner.into_iter().all(|hardware_list_inner| {
-                    return match hardware_list_inner {
+                hardware_lists_inner.iter().cloned().collect()
+            }
+            HardwareList::Pci(pci_id_list) => vec![HardwareListInner::Pci(pci_id_list.clone())],
+            HardwareList::Usb(usb_id_list) => vec![HardwareListInner::Usb(usb_id_list.clone())],
+        };
+        hardware_lists_inner
+            .iter()
+            .flat_map(|hardware_list_inner| match hardware_list_inner {
+                HardwareListInner::Pci(pci_id_list) => pci_id_list.bucket_and_keys(),
+                HardwareListInner::Usb(usb_id_list) => match &usb_id_list.devices {
+                    UsbDevices::Any => {
+                        vec![(USB_ID_BUCKET, usb_vendor_wildcard_key(usb_id_list.vendor))]
+                    }
+                    UsbDevices::Listed(_) => usb_id_list
+                        .hardware_ids()
+                        .map(|hardware_id| hardware_id.bucket_and_key())
+                        .collect(),
+                },
+            })
+            .collect()
+    }
+
+    /// The hardware IDs that make this list match, or `None` if it does not match.
+    pub fn matching_hardware_ids(
+        &self,
+        hardware_ids: &BTreeSet<HardwareId>,
+    ) -> Option<BTreeSet<HardwareId>> {
+        return match self {
+            HardwareList::Each(hardware_lists_inner) => hardware_lists_inner.iter().try_fold(
+                BTreeSet::<HardwareId>::new(),
+                |mut matched, hardware_list_inner| {
+                    matched.extend(match hardware_list_inner {
                         HardwareListInner::Pci(pci_id_list) => {
-                            pci_id_list.devices.iter().any(|device| {
-                                hardware_ids.contains(&HardwareId::Pci(PciId {
-                                    vendor: pci_id_list.vendor,
-                                    device: *device,
-                                }))
-                            })
+                            pci_id_list.matching_hardware_ids(hardware_ids)?
                         }
                         HardwareListInner::Usb(usb_id_list) => {
-                            usb_id_list.devices.iter().any(|device| {
-                                hardware_ids.contains(&HardwareId::Usb(UsbId {
-                                    vendor: usb_id_list.vendor,
-                                    device: *device,
-                                }))
-                            })
+                            usb_id_list.matching_hardware_ids(hardware_ids)?
                         }
-                    };
-                })
-            }
-            HardwareList::Pci(pci_id_list) => pci_id_list.devices.iter().any(|device| {
-                hardware_ids.contains(&HardwareId::Pci(PciId {
-                    vendor: pci_id_list.vendor,
-                    device: *device,
-                }))
-            }),
-            HardwareList::Usb(usb_id_list) => usb_id_list.devices.iter().any(|device| {
-                hardware_ids.contains(&HardwareId::Usb(UsbId {
-                    vendor: usb_id_list.vendor,
-                    device: *device,
-                }))
-            }),
+                    });
+                    Some(matched)
+                },
+            ),
+            HardwareList::Pci(pci_id_list) => pci_id_list.matching_hardware_ids(hardware_ids),
+            HardwareList::Usb(usb_id_list) => usb_id_list.matching_hardware_ids(hardware_ids),
         };
     }
+
+    /// How specifically the list names the devices that make it match, or `None` if it
+    /// does not match. An `Each` list is only as specific as its loosest group.
+    pub fn match_specificity(
+        &self,
+        hardware_ids: &BTreeSet<HardwareId>,
+    ) -> Option<MatchSpecificity> {
+        match self {
+            HardwareList::Each(hardware_lists_inner) => hardware_lists_inner.iter().try_fold(
+                MatchSpecificity::ExactDevice,
+                |loosest, hardware_list_inner| {
+                    let specificity = match hardware_list_inner {
+                        HardwareListInner::Pci(pci_id_list) => {
+                            pci_id_list.match_specificity(hardware_ids)?
+                        }
+                        HardwareListInner::Usb(usb_id_list) => {
+                            usb_id_list.match_specificity(hardware_ids)?
+                        }
+                    };
+                    Some(loosest.min(specificity))
+                },
+            ),
+            HardwareList::Pci(pci_id_list) => pci_id_list.match_specificity(hardware_ids),
+            HardwareList::Usb(usb_id_list) => usb_id_list.match_specificity(hardware_ids),
+        }
+    }
+}
+
+/// How closely a hardware list names the devices it matched, from the loosest to the
+/// most specific. Driver options found through the most specific hardware setup are
+/// ranked ahead of the others, and among driver options of the same name, the one from
+/// the most specific hardware setup is kept.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum MatchSpecificity {
+    /// Every device of a USB vendor, written `"*"`.
+    #[default]
+    VendorWildcard,
+    /// A device in a run of consecutive devices, like `1b80-1b84`.
+    Range,
+    /// A device listed on its own.
+    ExactDevice,
 }
 
-#[derive(
-    Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize, Readable, Writable,
-)]
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
 pub enum HardwareListInner {
     #[serde(alias = "PCI", alias = "pci")]
     Pci(PciIdList),
@@ -210,46 +508,407 @@ pub enum HardwareListInner {
     Usb(UsbIdList),
 }
 
-#[derive(
-    Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize, Readable, Writable,
-)]
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
 pub struct PciIdList {
-    #[serde(alias = "vendor-id", alias = "vendor", deserialize_with = "from_hex")]
+    #[serde(
+        alias = "vendor-id",
+        alias = "vendor",
+        serialize_with = "to_hex",
+        deserialize_with = "from_hex"
+    )]
     pub vendor: u16,
 
     #[serde(
         alias = "device-ids",
         alias = "device-id",
         alias = "devices",
-        alias = "device",
-        deserialize_with = "from_hex_list"
+        alias = "device"
     )]
-    pub devices: BTreeSet<u16>,
+    pub devices: DeviceIds,
+
+    /// Restricts the match to one revision of the devices. Without it, any revision matches.
+    #[serde(
+        default,
+        serialize_with = "to_hex_revision",
+        deserialize_with = "from_hex_revision"
+    )]
+    pub revision: Option<u8>,
+}
+
+impl PciIdList {
+    /// The listed devices, as inclusive ranges of consecutive device IDs.
+    pub fn device_ranges(&self) -> Vec<RangeInclusive<u16>> {
+        id_ranges(&self.devices)
+    }
+
+    /// A range key for each run of consecutive devices, and the key of the device otherwise.
+    fn bucket_and_keys(&self) -> Vec<(&'static str, Vec<u8>)> {
+        self.device_ranges()
+            .into_iter()
+            .map(|range| {
+                if range.start() == range.end() {
+                    HardwareId::Pci(PciId {
+                        vendor: self.vendor,
+                        device: *range.start(),
+                        revision: self.revision,
+                    })
+                    .bucket_and_key()
+                } else {
+                    (
+                        PCI_ID_BUCKET,
+                        pci_range_key(self.vendor, *range.start(), *range.end()),
+                    )
+                }
+            })
+            .collect()
+    }
+
+    pub fn hardware_ids(&self) -> impl Iterator<Item = HardwareId> + '_ {
+        self.devices.iter().map(|device| {
+            HardwareId::Pci(PciId {
+                vendor: self.vendor,
+                device: *device,
+                revision: self.revision,
+            })
+        })
+    }
+
+    fn matching_hardware_ids(
+        &self,
+        hardware_ids: &BTreeSet<HardwareId>,
+    ) -> Option<BTreeSet<HardwareId>> {
+        let matched: BTreeSet<HardwareId> = hardware_ids
+            .iter()
+            .filter(|hardware_id| match hardware_id {
+                HardwareId::Pci(pci_id) => {
+                    pci_id.vendor == self.vendor
+                        && self.devices.contains(&pci_id.device)
+                        && self
+                            .revision
+                            .is_none_or(|revision| pci_id.revision == Some(revision))
+                }
+                HardwareId::Usb(_) => false,
+            })
+            .cloned()
+            .collect();
+        (!matched.is_empty()).then_some(matched)
+    }
+
+    /// The specificity of the best matching device: a device in a run of consecutive
+    /// devices only matches as a range.
+    fn match_specificity(&self, hardware_ids: &BTreeSet<HardwareId>) -> Option<MatchSpecificity> {
+        self.matching_hardware_ids(hardware_ids)?
+            .iter()
+            .filter_map(|hardware_id| match hardware_id {
+                HardwareId::Pci(pci_id) => Some(pci_id.device),
+                HardwareId::Usb(_) => None,
+            })
+            .map(|device| {
+                if self.devices.lists_on_its_own(device) {
+                    MatchSpecificity::ExactDevice
+                } else {
+                    MatchSpecificity::Range
+                }
+            })
+            .max()
+    }
+}
+
+/// The device IDs of a PCI ID list, remembering which of them were written on their own
+/// and which as part of a range like `1b80-1b84`, since consecutive IDs can be either.
+#[derive(Clone, Debug, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub struct DeviceIds {
+    ids: BTreeSet<u16>,
+    /// The IDs written on their own.
+    singles: BTreeSet<u16>,
+    /// The first and last ID of each range, as written.
+    ranges: BTreeSet<(u16, u16)>,
+}
+
+impl DeviceIds {
+    /// Whether a device is listed on its own, rather than only as part of a range.
+    pub fn lists_on_its_own(&self, device: u16) -> bool {
+        self.singles.contains(&device)
+    }
+}
+
+impl Deref for DeviceIds {
+    type Target = BTreeSet<u16>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.ids
+    }
 }
 
-#[derive(
-    Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize, Readable, Writable,
-)]
+impl From<BTreeSet<u16>> for DeviceIds {
+    fn from(ids: BTreeSet<u16>) -> Self {
+        DeviceIds {
+            singles: ids.clone(),
+            ids,
+            ranges: BTreeSet::new(),
+        }
+    }
+}
+
+impl Serialize for DeviceIds {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut items: Vec<(u16, String)> = self
+            .singles
+            .iter()
+            .map(|id| (*id, format!("{:04x}", id)))
+            .chain(
+                self.ranges
+                    .iter()
+                    .map(|(start, end)| (*start, format!("{:04x}-{:04x}", start, end))),
+            )
+            .collect();
+        items.sort();
+        serializer.collect_seq(items.into_iter().map(|(_, item)| item))
+    }
+}
+
+impl<'de> Deserialize<'de> for DeviceIds {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let items: BTreeSet<String> = Deserialize::deserialize(deserializer)?;
+        items
+            .iter()
+            .try_fold(
+                DeviceIds::default(),
+                |mut device_ids, item| -> Result<_, String> {
+                    let range = parse_hex_id_range(item)?;
+                    if range.start() == range.end() {
+                        device_ids.singles.insert(*range.start());
+                    } else {
+                        device_ids.ranges.insert((*range.start(), *range.end()));
+                    }
+                    device_ids.ids.extend(range);
+                    Ok(device_ids)
+                },
+            )
+            .map_err(serde::de::Error::custom)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
 pub struct UsbIdList {
-    #[serde(alias = "vendor-id", deserialize_with = "from_hex")]
+    #[serde(
+        alias = "vendor-id",
+        serialize_with = "to_hex",
+        deserialize_with = "from_hex"
+    )]
     pub vendor: u16,
 
     #[serde(
         alias = "device-ids",
         alias = "device-id",
-        deserialize_with = "from_hex_list"
+        deserialize_with = "from_usb_device_list"
     )]
-    pub devices: BTreeSet<u16>,
+    pub devices: UsbDevices,
+}
+
+impl UsbIdList {
+    /// The hardware IDs listed. A vendor wildcard lists none, since it stands for every device.
+    pub fn hardware_ids(&self) -> impl Iterator<Item = HardwareId> + '_ {
+        let devices = match &self.devices {
+            UsbDevices::Any => None,
+            UsbDevices::Listed(devices) => Some(devices),
+        };
+        devices.into_iter().flatten().map(|device| {
+            HardwareId::Usb(UsbId {
+                vendor: self.vendor,
+                device: *device,
+            })
+        })
+    }
+
+    fn matching_hardware_ids(
+        &self,
+        hardware_ids: &BTreeSet<HardwareId>,
+    ) -> Option<BTreeSet<HardwareId>> {
+        let matched: BTreeSet<HardwareId> = hardware_ids
+            .iter()
+            .filter(|hardware_id| match hardware_id {
+                HardwareId::Pci(_) => false,
+                HardwareId::Usb(usb_id) => {
+                    usb_id.vendor == self.vendor && self.devices.contains(usb_id.device)
+                }
+            })
+            .cloned()
+            .collect();
+        (!matched.is_empty()).then_some(matched)
+    }
+
+    fn match_specificity(&self, hardware_ids: &BTreeSet<HardwareId>) -> Option<MatchSpecificity> {
+        self.matching_hardware_ids(hardware_ids)?;
+        Some(match self.devices {
+            UsbDevices::Any => MatchSpecificity::VendorWildcard,
+            UsbDevices::Listed(_) => MatchSpecificity::ExactDevice,
+        })
+    }
+}
+
+/// The devices of a USB vendor that a list matches: every one of them, written
+/// `"*"` in the input file, or the listed ones.
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum UsbDevices {
+    Any,
+    Listed(BTreeSet<u16>),
+}
+
+impl UsbDevices {
+    pub fn contains(&self, device: u16) -> bool {
+        match self {
+            UsbDevices::Any => true,
+            UsbDevices::Listed(devices) => devices.contains(&device),
+        }
+    }
+}
+
+impl Serialize for UsbDevices {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            UsbDevices::Any => serializer.serialize_str("*"),
+            UsbDevices::Listed(devices) => to_hex_list(devices, serializer),
+        }
+    }
+}
+
+/// Parses a hexadecimal ID the way authors tend to write it, with or without
+/// a `0x` prefix and surrounding whitespace.
+pub(crate) fn parse_hex_id(token: &str) -> Result<u16, String> {
+    let trimmed = token.trim();
+    let digits = trimmed
+        .strip_prefix("0x")
+        .or_else(|| trimmed.strip_prefix("0X"))
+        .unwrap_or(trimmed);
+    // from_str_radix would accept a sign in front of the digits
+    if !digits.bytes().all(|digit| digit.is_ascii_hexdigit()) {
+        return Err(format!(
+            "invalid hexadecimal ID {:?}: only the digits 0-9 and a-f are allowed",
+            token
+        ));
+    }
+    u16::from_str_radix(digits, 16)
+        .map_err(|error| format!("invalid hexadecimal ID {:?}: {}", token, error))
+}
+
+/// Parses a hexadecimal ID, or an inclusive range of them written like `8176-8179`.
+fn parse_hex_id_range(token: &str) -> Result<RangeInclusive<u16>, String> {
+    match token.split_once('-') {
+        Some((start, end)) => {
+            let (start, end) = (parse_hex_id(start)?, parse_hex_id(end)?);
+            if start > end {
+                Err(format!(
+                    "inverted ID range {:?}: the first ID must not be greater than the last",
+                    token
+                ))
+            } else {
+                Ok(start..=end)
+            }
+        }
+        None => parse_hex_id(token).map(|id| id..=id),
+    }
+}
+
+/// Parses a list of hexadecimal IDs and ID ranges into the set of IDs they cover.
+fn parse_hex_list<I: IntoIterator<Item = String>>(items: I) -> Result<BTreeSet<u16>, String> {
+    items
+        .into_iter()
+        .try_fold(BTreeSet::new(), |mut ids, item| {
+            ids.extend(parse_hex_id_range(&item)?);
+            Ok(ids)
+        })
+}
+
+/// Accepts a list of USB device IDs and ID ranges, or `"*"` for every device of the vendor,
+/// either on its own or as an item of the list.
+fn from_usb_device_list<'de, D>(deserializer: D) -> Result<UsbDevices, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    struct DeviceListVisitor;
+
+    impl<'de> Visitor<'de> for DeviceListVisitor {
+        type Value = Vec<String>;
+
+        fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+            formatter.write_str("a list of hexadecimal device IDs, or \"*\"")
+        }
+
+        fn visit_str<E: serde::de::Error>(self, value: &str) -> Result<Self::Value, E> {
+            Ok(vec![value.to_owned()])
+        }
+
+        fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
+            let mut items = Vec::new();
+            while let Some(item) = seq.next_element::<String>()? {
+                items.push(item);
+            }
+            Ok(items)
+        }
+    }
+
+    let items = deserializer.deserialize_any(DeviceListVisitor)?;
+    if items.iter().any(|item| item.trim() == "*") {
+        Ok(UsbDevices::Any)
+    } else {
+        parse_hex_list(items)
+            .map(UsbDevices::Listed)
+            .map_err(serde::de::Error::custom)
+    }
 }
 
-fn from_hex_list<'de, D>(deserializer: D) -> Result<BTreeSet<u16>, D::Error>
+fn from_hex_revision<'de, D>(deserializer: D) -> Result<Option<u8>, D::Error>
 where
     D: Deserializer<'de>,
 {
-    let s: BTreeSet<String> = Deserialize::deserialize(deserializer)?;
-    s.into_iter()
-        .map(|item| u16::from_str_radix(&item, 16).map_err(serde::de::Error::custom))
-        .collect()
+    let s: Option<String> = Deserialize::deserialize(deserializer)?;
+    s.map(|item| {
+        parse_hex_id(&item)
+            .and_then(|revision| {
+                u8::try_from(revision)
+                    .map_err(|error| format!("invalid revision {:?}: {}", item, error))
+            })
+            .map_err(serde::de::Error::custom)
+    })
+    .transpose()
+}
+
+// IDs are written back the way they are read, so serialized lists parse again
+fn to_hex<S: Serializer>(id: &u16, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(&format!("{:04x}", id))
+}
+
+/// Groups a set of IDs into inclusive ranges of consecutive IDs.
+fn id_ranges(ids: &BTreeSet<u16>) -> Vec<RangeInclusive<u16>> {
+    let mut ranges = Vec::<RangeInclusive<u16>>::new();
+    for id in ids.iter().copied() {
+        match ranges.last_mut() {
+            Some(range) if range.end().checked_add(1) == Some(id) => {
+                *range = *range.start()..=id;
+            }
+            _ => ranges.push(id..=id),
+        }
+    }
+    ranges
+}
+
+// Runs of consecutive IDs are written as ranges, which parse back to the same IDs
+fn to_hex_list<S: Serializer>(ids: &BTreeSet<u16>, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.collect_seq(id_ranges(ids).into_iter().map(|range| {
+        if range.start() == range.end() {
+            format!("{:04x}", range.start())
+        } else {
+            format!("{:04x}-{:04x}", range.start(), range.end())
+        }
+    }))
+}
+
+fn to_hex_revision<S: Serializer>(revision: &Option<u8>, serializer: S) -> Result<S::Ok, S::Error> {
+    match revision {
+        Some(revision) => serializer.serialize_some(&format!("{:02x}", revision)),
+        None => serializer.serialize_none(),
+    }
 }
 
 fn from_hex<'de, D>(deserializer: D) -> Result<u16, D::Error>
@@ -257,12 +916,10 @@ where
     D: Deserializer<'de>,
 {
     let s: String = Deserialize::deserialize(deserializer)?;
-    u16::from_str_radix(&s, 16).map_err(serde::de::Error::custom)
+    parse_hex_id(&s).map_err(serde::de::Error::custom)
 }
 
-#[derive(
-    Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize, Readable, Writable,
-)]
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
 pub struct DriverOption {
     #[serde(
         default,
@@ -283,27 +940,135 @@ pub struct DriverOption {
     #[serde(default)]
     pub tags: BTreeSet<String>,
 
+    /// Whether the driver is free software. Without it, the tags decide, like `foss`
+    /// or `proprietary`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub license: Option<License>,
+
     #[serde(default, alias = "pre-install", alias = "preinstall")]
     pub pre_install: Option<Script>,
 
     #[serde(default)]
     pub packages: Vec<String>,
 
+    /// Extras like utilities and settings apps, only installed when asked for.
+    #[serde(
+        default,
+        alias = "optional-packages",
+        alias = "optional",
+        skip_serializing_if = "Vec::is_empty"
+    )]
+    pub optional_packages: Vec<String>,
+
     #[serde(default, alias = "post-install", alias = "postinstall")]
     pub post_install: Option<Script>,
+
+    /// Environment variables and the values they must have for this option to apply,
+    /// like `XDG_SESSION_TYPE: wayland`. Values are compared case-insensitively.
+    #[serde(default, alias = "requires-env", alias = "requires_environment")]
+    pub requires_env: BTreeMap<String, String>,
+
+    /// A caveat shown right before this option is installed, like "breaks suspend on some
+    /// laptops". Installing an option with a warning needs confirmation.
+    #[serde(default, alias = "notes", skip_serializing_if = "Option::is_none")]
+    pub warning: Option<String>,
+
+    /// What the user has to do after installing this option for the driver to be used.
+    #[serde(
+        default,
+        alias = "post-install-action",
+        alias = "after-install",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub post_install_action: Option<PostAction>,
+
+    /// A shell command run after the post-install hooks to check that the driver works,
+    /// like `nvidia-smi`. It fails when it exits with a non-zero status.
+    #[serde(
+        default,
+        alias = "verify",
+        alias = "verify-command",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub verify_command: Option<String>,
+
+    /// systemd units enabled and started after this option is installed, like
+    /// `nvidia-persistenced.service`, and disabled when it is replaced.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub services: Vec<String>,
+}
+
+impl DriverOption {
+    /// The license class of the driver option, from `license` or else from its tags.
+    pub fn license(&self) -> Option<License> {
+        self.license.or_else(|| {
+            self.tags
+                .iter()
+                .find_map(|tag| License::from_tag(&convert_tag(tag.to_lowercase())))
+        })
+    }
+
+    /// Checks that the interpreters of the install scripts are in a `PATH`-style list of
+    /// directories, so that a missing one is reported before anything is installed.
+    pub fn check_interpreters(&self, search_path: &OsStr) -> Result<(), Error> {
+        for script in self.pre_install.iter().chain(self.post_install.iter()) {
+            if script.language.find_interpreter(search_path).is_none() {
+                return MissingInterpreterSnafu {
+                    kind: script.language.clone(),
+                }
+                .fail();
+            }
+        }
+        Ok(())
+    }
+
+    /// The environment requirements that are not met, given a way to look up variables.
+    pub fn unmet_environment_requirements<F: Fn(&str) -> Option<String>>(
+        &self,
+        lookup: F,
+    ) -> BTreeMap<&String, &String> {
+        self.requires_env
+            .iter()
+            .filter(|(variable, required_value)| {
+                !lookup(variable).is_some_and(|value| value.eq_ignore_ascii_case(required_value))
+            })
+            .collect()
+    }
+
+    /// Whether the driver option carries any of the given tags.
+    pub fn has_any_tag(&self, tags: &BTreeSet<String>) -> bool {
+        !self.tags.is_disjoint(tags)
+    }
 }
 
-#[derive(
-    Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize, Readable, Writable,
-)]
+/// Driver options in the order they are ranked: the ones found through the most specific
+/// hardware setup first, going by the `specificities` of their names, then by priority,
+/// with the ones carrying any of the preferred tags ahead of the others of the same
+/// priority. Unlike filtering by tags, no driver option is left out.
+pub fn ranked_by_preference<'a, I: IntoIterator<Item = &'a DriverOption>>(
+    driver_options: I,
+    preferred_tags: &BTreeSet<String>,
+    specificities: &BTreeMap<String, MatchSpecificity>,
+) -> Vec<&'a DriverOption> {
+    let mut ranked_driver_options: Vec<&DriverOption> = driver_options.into_iter().collect();
+    // The sort is stable, so driver options keep their usual order otherwise
+    ranked_driver_options.sort_by_key(|driver_option| {
+        (
+            std::cmp::Reverse(specificities.get(&driver_option.name)),
+            driver_option.order_of_priority,
+            !driver_option.has_any_tag(preferred_tags),
+        )
+    });
+    ranked_driver_options
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
 pub struct Script {
     // pub path: PathBuf,
     pub language: ScriptKind,
 }
 
-#[derive(
-    Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize, Readable, Writable,
-)]
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
 pub enum ScriptKind {
     #[serde(alias = "PY", alias = "Py", alias = "py")]
     Python,
@@ -323,16 +1088,362 @@ pub enum ScriptKind {
     Shell,
 }
 
+impl fmt::Display for ScriptKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ScriptKind::Python => write!(f, "Python"),
+            ScriptKind::JavaScript => write!(f, "JavaScript"),
+            ScriptKind::Shell => write!(f, "shell"),
+        }
+    }
+}
+
+impl ScriptKind {
+    /// The program that runs scripts of this kind.
+    pub fn interpreter(&self) -> &'static str {
+        match self {
+            ScriptKind::Python => "python",
+            ScriptKind::JavaScript => "node",
+            ScriptKind::Shell => "sh",
+        }
+    }
+
+    /// Finds the interpreter among the directories of a `PATH`-style list.
+    pub fn find_interpreter(&self, search_path: &OsStr) -> Option<PathBuf> {
+        env::split_paths(search_path)
+            .map(|directory| directory.join(self.interpreter()))
+            .find(|candidate| {
+                fs::metadata(candidate).is_ok_and(|metadata| {
+                    metadata.is_file() && metadata.permissions().mode() & 0o111 != 0
+                })
+            })
+    }
+}
+
+/// Whether a driver is free software, for `--only-free` and `--only-proprietary`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize, ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum License {
+    #[serde(alias = "Free", alias = "foss", alias = "open-source", alias = "libre")]
+    Free,
+
+    #[serde(
+        alias = "Proprietary",
+        alias = "nonfree",
+        alias = "non-free",
+        alias = "closed-source"
+    )]
+    Proprietary,
+}
+
+impl License {
+    /// The license class that a conventional tag stands for, like `foss` or `non free`.
+    fn from_tag(tag: &str) -> Option<Self> {
+        match tag {
+            "free" | "foss" | "oss" | "libre" | "open source" => Some(License::Free),
+            "proprietary" | "nonfree" | "non free" | "closed source" => Some(License::Proprietary),
+            _ => None,
+        }
+    }
+}
+
+/// A step needed after an install before the new driver takes effect.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum PostAction {
+    #[serde(alias = "Reboot", alias = "restart")]
+    Reboot,
+
+    #[serde(alias = "Relogin", alias = "logout", alias = "log-out")]
+    Relogin,
+
+    #[serde(
+        alias = "RebuildInitramfs",
+        alias = "rebuild_initramfs",
+        alias = "initramfs",
+        alias = "mkinitcpio"
+    )]
+    RebuildInitramfs,
+
+    #[serde(alias = "None")]
+    None,
+}
+
+impl PostAction {
+    /// The instruction shown to the user after the install, if anything needs to be done.
+    pub fn hint(&self) -> Option<&'static str> {
+        match self {
+            PostAction::Reboot => Some("Reboot the system for the new driver to take effect."),
+            PostAction::Relogin => {
+                Some("Log out and back in for the new driver to take effect.")
+            }
+            PostAction::RebuildInitramfs => Some(
+                "Rebuild the initramfs (like with `mkinitcpio -P`) and reboot for the new driver to take effect.",
+            ),
+            PostAction::None => None,
+        }
+    }
+}
+
+impl fmt::Display for PostAction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PostAction::Reboot => write!(f, "reboot"),
+            PostAction::Relogin => write!(f, "relogin"),
+            PostAction::RebuildInitramfs => write!(f, "rebuild-initramfs"),
+            PostAction::None => write!(f, "none"),
+        }
+    }
+}
+
+/// The formats that hardware setups are read from and exported to.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum FileFormat {
+    #[default]
+    Yaml,
+    Json,
+}
+
+impl FileFormat {
+    /// The format of a file, going by its extension. Anything but `.json` is read as YAML.
+    pub fn from_path(path: &Path) -> Self {
+        match path.extension().and_then(|extension| extension.to_str()) {
+            Some(extension) if extension.eq_ignore_ascii_case("json") => FileFormat::Json,
+            _ => FileFormat::Yaml,
+        }
+    }
+
+    pub fn parse(self, path: &Path, contents: &str) -> Result<BTreeSet<HardwareSetup>, Error> {
+        self.parse_with_includes(path, contents, &mut BTreeSet::new())
+    }
+
+    /// Like [`FileFormat::parse`], adding the files pulled in with `!include` to `included_files`.
+    pub fn parse_with_includes(
+        self,
+        path: &Path,
+        contents: &str,
+        included_files: &mut BTreeSet<PathBuf>,
+    ) -> Result<BTreeSet<HardwareSetup>, Error> {
+        match self {
+            // Going through a `Value` loses the locations of errors, so only do it when needed
+            FileFormat::Yaml if contents.contains(INCLUDE_TAG) => {
+                let mut including =
+                    vec![fs::canonicalize(path).unwrap_or_else(|_| path.to_owned())];
+                let value =
+                    parse_yaml_with_includes(path, contents, &mut including, included_files)?;
+                serde_yaml::from_value(value).context(InputFileParseSnafu { path })
+            }
+            FileFormat::Yaml => {
+                serde_yaml::from_str(contents).context(InputFileParseSnafu { path })
+            }
+            FileFormat::Json => {
+                serde_json::from_str(contents).context(InputFileJsonParseSnafu { path })
+            }
+        }
+    }
+
+    pub fn serialize(self, hardware_setups: &[HardwareSetup]) -> String {
+        match self {
+            FileFormat::Yaml => serde_yaml::to_string(hardware_setups).unwrap(),
+            FileFormat::Json => serde_json::to_string_pretty(hardware_setups).unwrap(),
+        }
+    }
+}
+
+/// The tag of a YAML node that stands for the contents of another YAML file.
+pub const INCLUDE_TAG: &str = "!include";
+
+/// Whether a YAML value has an `!include` node anywhere in it.
+pub fn contains_include(value: &serde_yaml::Value) -> bool {
+    use serde_yaml::Value;
+
+    match value {
+        Value::Tagged(tagged) => tagged.tag == INCLUDE_TAG || contains_include(&tagged.value),
+        Value::Sequence(items) => items.iter().any(contains_include),
+        Value::Mapping(mapping) => mapping
+            .iter()
+            .any(|(key, value)| contains_include(key) || contains_include(value)),
+        _ => false,
+    }
+}
+
+/// Whether a relative path names something inside the directory it is relative to.
+fn stays_within_directory(path: &Path) -> bool {
+    let mut depth = 0usize;
+    path.components().all(|component| match component {
+        Component::Normal(_) => {
+            depth += 1;
+            true
+        }
+        Component::CurDir => true,
+        Component::ParentDir => depth.checked_sub(1).map(|parent| depth = parent).is_some(),
+        Component::RootDir | Component::Prefix(_) => false,
+    })
+}
+
+/// Parses YAML with every `!include path.yaml` node replaced by the contents of that file,
+/// found relative to the including file, in its directory or below. `including` holds the files being included,
+/// outermost first, to detect cycles. Every file named by an `!include`, even one that
+/// cannot be read, is added to `included_files`.
+fn parse_yaml_with_includes(
+    path: &Path,
+    contents: &str,
+    including: &mut Vec<PathBuf>,
+    included_files: &mut BTreeSet<PathBuf>,
+) -> Result<serde_yaml::Value, Error> {
+    let value: serde_yaml::Value =
+        serde_yaml::from_str(contents).context(InputFileParseSnafu { path })?;
+    resolve_includes(path, value, including, included_files)
+}
+
+/// Replaces the `!include` nodes of a YAML value. An included list that is an item of a
+/// list is spliced into it, so that shared driver options can be kept in a file of their
+/// own and included next to other ones.
+fn resolve_includes(
+    path: &Path,
+    value: serde_yaml::Value,
+    including: &mut Vec<PathBuf>,
+    included_files: &mut BTreeSet<PathBuf>,
+) -> Result<serde_yaml::Value, Error> {
+    use serde_yaml::Value;
+
+    let is_include =
+        |value: &Value| matches!(value, Value::Tagged(tagged) if tagged.tag == INCLUDE_TAG);
+    match value {
+        Value::Tagged(tagged) if tagged.tag == INCLUDE_TAG => {
+            let Value::String(included) = tagged.value else {
+                return InputFileIncludeInvalidSnafu { path }.fail();
+            };
+            if !stays_within_directory(Path::new(&included)) {
+                return InputFileIncludeOutsideSnafu { path, included }.fail();
+            }
+            let included = path.parent().unwrap_or(Path::new("")).join(included);
+            included_files.insert(included.clone());
+            let canonical = fs::canonicalize(&included).context(InputFileIncludeSnafu {
+                path,
+                included: &included,
+            })?;
+            if including.contains(&canonical) {
+                let mut chain = including.clone();
+                chain.push(canonical);
+                return InputFileIncludeCycleSnafu { chain }.fail();
+            }
+            let contents = fs::read_to_string(&canonical).context(InputFileIncludeSnafu {
+                path,
+                included: &included,
+            })?;
+            including.push(canonical);
+            let value = parse_yaml_with_includes(&included, &contents, including, included_files)?;
+            including.pop();
+            Ok(value)
+        }
+        Value::Tagged(mut tagged) => {
+            tagged.value = resolve_includes(path, tagged.value, including, included_files)?;
+            Ok(Value::Tagged(tagged))
+        }
+        Value::Sequence(items) => {
+            let mut resolved = Vec::<Value>::new();
+            for item in items {
+                let spliced = is_include(&item);
+                match resolve_includes(path, item, including, included_files)? {
+                    Value::Sequence(included_items) if spliced => resolved.extend(included_items),
+                    item => resolved.push(item),
+                }
+            }
+            Ok(Value::Sequence(resolved))
+        }
+        Value::Mapping(mapping) => mapping
+            .into_iter()
+            .map(|(key, value)| {
+                Ok((
+                    key,
+                    resolve_includes(path, value, including, included_files)?,
+                ))
+            })
+            .collect::<Result<serde_yaml::Mapping, Error>>()
+            .map(Value::Mapping),
+        value => Ok(value),
+    }
+}
+
+/// Reads the hardware setups of an input file, or of every input file in a directory.
+/// A directory fails as a whole when any of its files does, listing all of their errors.
 pub fn parse_input_file(path: PathBuf) -> Result<BTreeSet<HardwareSetup>, Error> {
-    let file = File::open(&path).unwrap();
-    Ok(serde_yaml::from_reader(&file).context(InputFileParseSnafu { path: path })?)
+    parse_input_file_with_includes(path, &mut BTreeSet::new())
+}
+
+/// Like [`parse_input_file`], adding the files pulled in with `!include` to `included_files`.
+pub fn parse_input_file_with_includes(
+    path: PathBuf,
+    included_files: &mut BTreeSet<PathBuf>,
+) -> Result<BTreeSet<HardwareSetup>, Error> {
+    if path.is_dir() {
+        let (hardware_setups, errors) = parse_input_directory(&path, included_files)?;
+        if !errors.is_empty() {
+            return InputDirectoryInvalidSnafu {
+                path,
+                errors: errors
+                    .iter()
+                    .map(|error| error.to_string())
+                    .collect::<Vec<String>>(),
+            }
+            .fail();
+        }
+        return Ok(hardware_setups);
+    }
+    let contents = fs::read_to_string(&path).context(InputFileReadSnafu { path: &path })?;
+    FileFormat::from_path(&path).parse_with_includes(&path, &contents, included_files)
+}
+
+/// Reads the hardware setups of the YAML and JSON files directly in a directory, in the
+/// order of their names. Each file is read on its own, so the ones that fail do not keep
+/// the others out, and their errors are returned next to the hardware setups of the rest.
+/// Files only meant to be included from others belong in a subdirectory. The files they
+/// pull in with `!include` are added to `included_files`.
+pub fn parse_input_directory(
+    directory: &Path,
+    included_files: &mut BTreeSet<PathBuf>,
+) -> Result<(BTreeSet<HardwareSetup>, Vec<Error>), Error> {
+    let mut input_files = fs::read_dir(directory)
+        .context(InputFileReadSnafu { path: directory })?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.is_file())
+        .filter(|path| {
+            path.extension()
+                .and_then(OsStr::to_str)
+                .is_some_and(|extension| {
+                    ["yaml", "yml", "json"]
+                        .iter()
+                        .any(|input_extension| extension.eq_ignore_ascii_case(input_extension))
+                })
+        })
+        .collect::<Vec<PathBuf>>();
+    input_files.sort();
+
+    let mut hardware_setups = BTreeSet::<HardwareSetup>::new();
+    let mut errors = Vec::<Error>::new();
+    for input_file in input_files {
+        match parse_input_file_with_includes(input_file, included_files) {
+            Ok(file_hardware_setups) => hardware_setups.extend(file_hardware_setups),
+            Err(error) => errors.push(error),
+        }
+    }
+    Ok((hardware_setups, errors))
 }
 
+/// An input file that uses every feature of the input format, for tests.
+#[cfg(test)]
+pub const FIXTURE_INPUT_DATA: &str = include_str!("fixture_input_data.yaml");
+
 #[cfg(test)]
 mod tests {
     use std::fs::File;
 
+    use crate::actions::generate_database::generate_database_inner;
+    use crate::data::database::{decode_value, encode_value};
+
     use super::*;
+    use crate::test_support::TestDirectory;
 
     #[test]
     pub fn deserialize_input_data() {
@@ -340,4 +1451,477 @@ mod tests {
         let deserialized_object: Vec<HardwareSetup> = serde_yaml::from_reader(&f).unwrap();
         println!("The deserialized object... \n {:#?}", deserialized_object);
     }
+
+    #[test]
+    pub fn fixture_uses_every_feature() {
+        let hardware_setups: Vec<HardwareSetup> = serde_yaml::from_str(FIXTURE_INPUT_DATA).unwrap();
+        let hardware_lists: Vec<&HardwareList> = hardware_setups
+            .iter()
+            .map(|hardware_setup| &hardware_setup.hardware_list)
+            .collect();
+        assert!(hardware_lists
+            .iter()
+            .any(|hardware_list| matches!(hardware_list, HardwareList::Each(_))));
+        assert!(hardware_lists.iter().any(|hardware_list| matches!(
+            hardware_list,
+            HardwareList::Pci(PciIdList {
+                revision: Some(_),
+                ..
+            })
+        )));
+        assert!(hardware_lists.iter().any(|hardware_list| matches!(
+            hardware_list,
+            HardwareList::Usb(UsbIdList {
+                devices: UsbDevices::Listed(_),
+                ..
+            })
+        )));
+        assert!(hardware_lists.iter().any(|hardware_list| matches!(
+            hardware_list,
+            HardwareList::Usb(UsbIdList {
+                devices: UsbDevices::Any,
+                ..
+            })
+        )));
+        assert!(hardware_setups
+            .iter()
+            .any(|hardware_setup| hardware_setup.dmi_match.is_some()));
+        assert!(hardware_setups
+            .iter()
+            .any(|hardware_setup| hardware_setup.virtual_only));
+        assert!(hardware_setups
+            .iter()
+            .any(|hardware_setup| hardware_setup.bare_metal_only));
+        assert!(hardware_setups
+            .iter()
+            .all(|hardware_setup| hardware_setup.validation_warnings().is_empty()));
+
+        let driver_options: Vec<&DriverOption> = hardware_setups
+            .iter()
+            .flat_map(|hardware_setup| hardware_setup.driver_options.iter())
+            .collect();
+        let uses = |feature: fn(&DriverOption) -> bool| {
+            driver_options
+                .iter()
+                .any(|driver_option| feature(driver_option))
+        };
+        assert!(uses(|driver_option| driver_option.pre_install.is_some()));
+        assert!(uses(|driver_option| driver_option.post_install.is_some()));
+        assert!(uses(|driver_option| !driver_option.tags.is_empty()));
+        assert!(uses(|driver_option| driver_option.license.is_some()));
+        assert!(uses(|driver_option| !driver_option
+            .optional_packages
+            .is_empty()));
+        assert!(uses(|driver_option| !driver_option.requires_env.is_empty()));
+        assert!(uses(|driver_option| driver_option.warning.is_some()));
+        assert!(uses(|driver_option| driver_option
+            .post_install_action
+            .is_some()));
+        assert!(uses(|driver_option| driver_option.verify_command.is_some()));
+        assert!(hardware_setups.iter().any(|hardware_setup| {
+            hardware_setup
+                .driver_options
+                .iter()
+                .map(|driver_option| driver_option.order_of_priority)
+                .collect::<BTreeSet<u32>>()
+                .len()
+                > 1
+        }));
+
+        let directory = TestDirectory::new("fixture");
+        let input_file = directory.join("input_data.yaml");
+        fs::write(&input_file, FIXTURE_INPUT_DATA).unwrap();
+        generate_database_inner(input_file, directory.join("database.db"), false).unwrap();
+    }
+
+    #[test]
+    pub fn hex_ids() {
+        assert_eq!(parse_hex_id("10de"), Ok(0x10de));
+        assert_eq!(parse_hex_id("0x10de"), Ok(0x10de));
+        assert_eq!(parse_hex_id("0X10DE"), Ok(0x10de));
+        assert_eq!(parse_hex_id(" 10de "), Ok(0x10de));
+        assert!(parse_hex_id("zzzz").unwrap_err().contains("\"zzzz\""));
+        assert!(parse_hex_id("+10de").is_err());
+        assert!(parse_hex_id("0x+10de").is_err());
+        assert!(parse_hex_id("").is_err());
+
+        let pci_id_list: PciIdList =
+            serde_yaml::from_str("vendor: \" 0x10de \"\ndevices: [\"0x1b80\", \" 1c03\"]\n")
+                .unwrap();
+        assert_eq!(pci_id_list.vendor, 0x10de);
+        assert_eq!(
+            pci_id_list.devices,
+            DeviceIds::from(BTreeSet::from([0x1b80, 0x1c03]))
+        );
+        let error = serde_yaml::from_str::<PciIdList>("vendor: zzzz\ndevices: [1b80]\n")
+            .unwrap_err()
+            .to_string();
+        assert!(error.contains("zzzz"), "{}", error);
+    }
+
+    #[test]
+    pub fn consecutive_devices_keep_how_they_were_listed() {
+        let pci_id_list: PciIdList =
+            serde_yaml::from_str("vendor: 10de\ndevices: [1b80, 1b81, 1c00-1c02]\n").unwrap();
+        let specificity = |device| {
+            pci_id_list.match_specificity(&BTreeSet::from([HardwareId::Pci(PciId {
+                vendor: 0x10de,
+                device,
+                revision: None,
+            })]))
+        };
+        assert_eq!(specificity(0x1b81), Some(MatchSpecificity::ExactDevice));
+        assert_eq!(specificity(0x1c01), Some(MatchSpecificity::Range));
+        assert_eq!(specificity(0x1c03), None);
+        assert_eq!(
+            *pci_id_list.devices,
+            BTreeSet::from([0x1b80, 0x1b81, 0x1c00, 0x1c01, 0x1c02])
+        );
+
+        let serialized = serde_yaml::to_string(&pci_id_list).unwrap();
+        assert!(
+            serialized.contains("- 1b80\n- 1b81\n- 1c00-1c02\n"),
+            "{}",
+            serialized
+        );
+        assert_eq!(
+            serde_yaml::from_str::<PciIdList>(&serialized).unwrap(),
+            pci_id_list
+        );
+    }
+
+    #[test]
+    pub fn usb_ranges_and_wildcards() {
+        let usb_id_list: UsbIdList =
+            serde_yaml::from_str("vendor: 0bda\ndevices: [8176-8179, \"0x8812\"]\n").unwrap();
+        assert_eq!(
+            usb_id_list.devices,
+            UsbDevices::Listed(BTreeSet::from([0x8176, 0x8177, 0x8178, 0x8179, 0x8812]))
+        );
+        assert_eq!(usb_id_list.hardware_ids().count(), 5);
+
+        let error = serde_yaml::from_str::<UsbIdList>("vendor: 0bda\ndevices: [8179-8176]\n")
+            .unwrap_err()
+            .to_string();
+        assert!(error.contains("inverted"), "{}", error);
+
+        let usb_id_list: UsbIdList =
+            serde_yaml::from_str("vendor: 0bda\ndevices: \"*\"\n").unwrap();
+        assert_eq!(usb_id_list.devices, UsbDevices::Any);
+        assert_eq!(
+            serde_yaml::from_str::<UsbIdList>("vendor: 0bda\ndevices: [\"*\"]\n").unwrap(),
+            usb_id_list
+        );
+        let hardware_list = HardwareList::Usb(usb_id_list);
+        assert_eq!(
+            hardware_list.bucket_and_keys(),
+            BTreeSet::from([(USB_ID_BUCKET, usb_vendor_wildcard_key(0x0bda))])
+        );
+        let realtek_device = HardwareId::Usb(UsbId {
+            vendor: 0x0bda,
+            device: 0x1234,
+        });
+        let other_device = HardwareId::Usb(UsbId {
+            vendor: 0x0b05,
+            device: 0x1234,
+        });
+        assert_eq!(
+            hardware_list
+                .matching_hardware_ids(&BTreeSet::from([realtek_device.clone(), other_device])),
+            Some(BTreeSet::from([realtek_device]))
+        );
+    }
+
+    #[test]
+    pub fn repeated_vendors_in_each_lists() {
+        let hardware_setup: HardwareSetup = serde_yaml::from_str(
+            r#"
+name: Nvidia dGPU + Intel iGPU
+hardware_kind: graphics
+hardware_list: !each
+  - !pci
+      vendor: 10de
+      devices: [1b80]
+  - !pci
+      vendor: 10de
+      devices: [1b80]
+  - !pci
+      vendor: 10de
+      devices: [1b81, 1b80]
+  - !pci
+      vendor: 8086
+      devices: [3e92]
+  - !usb
+      vendor: 8086
+      devices: [0b95]
+driver_options:
+  - name: Nvidia
+    packages: [nvidia]
+"#,
+        )
+        .unwrap();
+        match &hardware_setup.hardware_list {
+            HardwareList::Each(hardware_lists_inner) => assert_eq!(hardware_lists_inner.len(), 4),
+            hardware_list => panic!("expected an each list, got {:?}", hardware_list),
+        }
+        assert_eq!(
+            hardware_setup.hardware_list.repeated_vendors(),
+            BTreeSet::from([("PCI", 0x10de)])
+        );
+        assert_eq!(hardware_setup.validation_warnings().len(), 1);
+    }
+
+    #[test]
+    pub fn stored_values_round_trip() {
+        let hardware_setup: HardwareSetup = serde_yaml::from_str(
+            r#"
+name: Realtek wireless
+hardware_kind: wireless
+hardware_list: !each
+  - !pci
+      vendor: 10ec
+      devices: [b723]
+      revision: "01"
+  - !usb
+      vendor: 0bda
+      devices: [8176-8177]
+  - !usb
+      vendor: 0bda
+      devices: "*"
+driver_options:
+  - name: rtw88
+    packages: [linux-firmware]
+"#,
+        )
+        .unwrap();
+        assert_eq!(
+            decode_value::<HardwareSetup>(&encode_value(&hardware_setup)),
+            Ok(hardware_setup)
+        );
+
+        // Values stored before a field existed still decode, with the field defaulted
+        let mut stored = vec![1];
+        stored.extend_from_slice(br#"{"name":"Nvidia","packages":["nvidia"]}"#);
+        let driver_option = decode_value::<DriverOption>(&stored).unwrap();
+        assert_eq!(driver_option.packages, vec!["nvidia".to_string()]);
+        assert!(driver_option.requires_env.is_empty());
+        assert_eq!(driver_option.post_install_action, None);
+        assert!(driver_option.services.is_empty());
+        assert!(decode_value::<DriverOption>(&[2, b'{', b'}']).is_err());
+    }
+
+    #[test]
+    pub fn post_install_actions() {
+        let driver_option: DriverOption =
+            serde_yaml::from_str(
+                "name: Nvidia\npost-install-action: rebuild-initramfs\nservices: [nvidia-persistenced.service]\n",
+            )
+            .unwrap();
+        assert_eq!(
+            driver_option.post_install_action,
+            Some(PostAction::RebuildInitramfs)
+        );
+        let round_tripped = decode_value::<DriverOption>(&encode_value(&driver_option)).unwrap();
+        assert_eq!(round_tripped, driver_option);
+        assert_eq!(
+            serde_yaml::from_str::<DriverOption>(&serde_yaml::to_string(&driver_option).unwrap())
+                .unwrap(),
+            driver_option
+        );
+        assert_eq!(PostAction::None.hint(), None);
+    }
+
+    #[test]
+    pub fn environment_requirements() {
+        let driver_option: DriverOption = serde_yaml::from_str(
+            "name: Nvidia Wayland\nrequires-env:\n  XDG_SESSION_TYPE: wayland\nwarning: Breaks suspend on some laptops\n",
+        )
+        .unwrap();
+        assert_eq!(
+            driver_option.warning.as_deref(),
+            Some("Breaks suspend on some laptops")
+        );
+        let round_tripped = decode_value::<DriverOption>(&encode_value(&driver_option)).unwrap();
+        assert_eq!(round_tripped, driver_option);
+
+        let wayland = |_: &str| Some("Wayland".to_string());
+        let x11 = |_: &str| Some("x11".to_string());
+        let unset = |_: &str| None;
+        assert!(driver_option
+            .unmet_environment_requirements(wayland)
+            .is_empty());
+        assert_eq!(driver_option.unmet_environment_requirements(x11).len(), 1);
+        assert_eq!(driver_option.unmet_environment_requirements(unset).len(), 1);
+    }
+
+    #[test]
+    pub fn script_interpreters_are_looked_up_on_the_path() {
+        let directory = TestDirectory::new("interpreters");
+        for interpreter in ["python", "sh"] {
+            let path = directory.join(interpreter);
+            fs::write(&path, "").unwrap();
+            fs::set_permissions(&path, fs::Permissions::from_mode(0o755)).unwrap();
+        }
+        // Not executable, so not an interpreter
+        fs::write(directory.join("node"), "").unwrap();
+
+        let search_path =
+            env::join_paths([directory.join("missing"), directory.to_path_buf()]).unwrap();
+        assert_eq!(
+            ScriptKind::Python.find_interpreter(&search_path),
+            Some(directory.join("python"))
+        );
+        assert_eq!(
+            ScriptKind::Shell.find_interpreter(&search_path),
+            Some(directory.join("sh"))
+        );
+        assert_eq!(ScriptKind::JavaScript.find_interpreter(&search_path), None);
+
+        let driver_option: DriverOption = serde_yaml::from_str(
+            "name: Nvidia\npre_install:\n  language: sh\npost_install:\n  language: js\n",
+        )
+        .unwrap();
+        assert!(matches!(
+            driver_option.check_interpreters(&search_path),
+            Err(Error::MissingInterpreter {
+                kind: ScriptKind::JavaScript
+            })
+        ));
+        let driver_option = DriverOption {
+            post_install: None,
+            ..driver_option
+        };
+        assert!(driver_option.check_interpreters(&search_path).is_ok());
+    }
+
+    #[test]
+    pub fn included_files_are_resolved_relative_to_the_including_file() {
+        let directory = TestDirectory::new("includes");
+        fs::create_dir_all(directory.join("shared")).unwrap();
+        let input_file = directory.join("input_data.yaml");
+        fs::write(
+            &input_file,
+            r#"
+- name: Nvidia dGPU
+  hardware_kind: graphics
+  hardware_list: !include shared/nvidia_ids.yaml
+  driver_options:
+    - !include shared/nvidia_options.yaml
+    - name: Vesa
+"#,
+        )
+        .unwrap();
+        fs::write(
+            directory.join("shared/nvidia_ids.yaml"),
+            "!pci\nvendor: 10de\ndevices: [1b80]\n",
+        )
+        .unwrap();
+        fs::write(
+            directory.join("shared/nvidia_options.yaml"),
+            "- name: Nvidia\n  packages: [nvidia]\n- name: Nouveau\n",
+        )
+        .unwrap();
+
+        let mut included_files = BTreeSet::new();
+        let hardware_setups =
+            parse_input_file_with_includes(input_file.clone(), &mut included_files).unwrap();
+        assert_eq!(
+            included_files,
+            BTreeSet::from([
+                directory.join("shared/nvidia_ids.yaml"),
+                directory.join("shared/nvidia_options.yaml")
+            ])
+        );
+        let hardware_setup = hardware_setups.first().unwrap();
+        assert!(matches!(hardware_setup.hardware_list, HardwareList::Pci(_)));
+        assert_eq!(
+            hardware_setup
+                .driver_options
+                .iter()
+                .map(|driver_option| driver_option.name.as_str())
+                .collect::<BTreeSet<&str>>(),
+            BTreeSet::from(["Nvidia", "Nouveau", "Vesa"])
+        );
+
+        fs::write(
+            directory.join("shared/nvidia_options.yaml"),
+            "- !include nvidia_options.yaml\n",
+        )
+        .unwrap();
+        assert!(matches!(
+            parse_input_file(input_file.clone()),
+            Err(Error::InputFileIncludeCycle { chain }) if chain.len() == 3
+        ));
+        for outside in [
+            "../input_data.yaml",
+            "./../shared/../../x.yaml",
+            "/etc/passwd",
+        ] {
+            fs::write(
+                directory.join("shared/nvidia_options.yaml"),
+                format!("- name: !include {}\n", outside),
+            )
+            .unwrap();
+            assert!(matches!(
+                parse_input_file(input_file.clone()),
+                Err(Error::InputFileIncludeOutside { .. })
+            ));
+        }
+        fs::create_dir_all(directory.join("shared/nested")).unwrap();
+        fs::write(
+            directory.join("shared/nvidia_options.yaml"),
+            "- !include ./nested/../nvidia_options.yaml\n",
+        )
+        .unwrap();
+        assert!(matches!(
+            parse_input_file(input_file.clone()),
+            Err(Error::InputFileIncludeCycle { .. })
+        ));
+        fs::remove_file(directory.join("shared/nvidia_ids.yaml")).unwrap();
+        assert!(matches!(
+            parse_input_file(input_file),
+            Err(Error::InputFileInclude { .. })
+        ));
+    }
+
+    #[test]
+    pub fn input_directories_isolate_errors_per_file() {
+        let directory = TestDirectory::new("input-directory");
+        fs::write(
+            directory.join("graphics.yaml"),
+            "- name: Nvidia dGPU\n  hardware_kind: graphics\n  hardware_list: !pci\n    vendor: 10de\n    devices: [1b80]\n  driver_options:\n    - name: Nvidia\n",
+        )
+        .unwrap();
+        fs::write(
+            directory.join("ethernet.json"),
+            r#"[{"name": "Realtek Ethernet", "hardware_kind": "ethernet", "hardware_list": {"pci": {"vendor": "10ec", "devices": ["8168"]}}, "driver_options": [{"name": "r8168"}]}]"#,
+        )
+        .unwrap();
+        fs::write(directory.join("README.md"), "Not an input file").unwrap();
+        fs::write(directory.join("wireless.yaml"), "- name: [unclosed\n").unwrap();
+
+        let (hardware_setups, errors) =
+            parse_input_directory(&directory, &mut BTreeSet::new()).unwrap();
+        assert_eq!(
+            hardware_setups
+                .iter()
+                .map(|hardware_setup| hardware_setup.name.as_str())
+                .collect::<Vec<&str>>(),
+            ["Nvidia dGPU", "Realtek Ethernet"]
+        );
+        assert!(matches!(
+            errors.as_slice(),
+            [Error::InputFileParse { path, .. }] if path.ends_with("wireless.yaml")
+        ));
+        assert!(matches!(
+            parse_input_file(directory.to_path_buf()),
+            Err(Error::InputDirectoryInvalid { errors, .. }) if errors.len() == 1
+        ));
+
+        fs::remove_file(directory.join("wireless.yaml")).unwrap();
+        assert_eq!(
+            parse_input_file(directory.to_path_buf()).unwrap(),
+            hardware_setups
+        );
+    }
 }
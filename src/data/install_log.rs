@@ -0,0 +1,151 @@
+use crate::{
+    data::input_file::HardwareKind,
+    error::{Error, InstallLogReadSnafu, InstallLogWriteSnafu},
+};
+use owo_colors::{OwoColorize, Stream::Stderr};
+use serde::{Deserialize, Serialize};
+use snafu::ResultExt;
+use std::{
+    fs::{self, OpenOptions},
+    io::{ErrorKind, Write},
+    path::Path,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// One install transaction, as recorded in the install log. The log holds one entry
+/// per line, as JSON.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct InstallLogEntry {
+    /// When the transaction finished, in seconds since the Unix epoch.
+    pub timestamp: i64,
+    pub hardware: HardwareKind,
+    pub driver_option: String,
+    pub packages_installed: Vec<String>,
+    /// The installed packages that were not installed before the transaction. Unlike
+    /// packages the user already had, only these are removed by `prune`.
+    #[serde(default)]
+    pub packages_added: Vec<String>,
+    pub packages_removed: Vec<String>,
+    /// Why the transaction failed, or `None` if it succeeded.
+    pub error: Option<String>,
+}
+
+impl InstallLogEntry {
+    /// An entry for a transaction that just finished.
+    pub fn now(
+        hardware: HardwareKind,
+        driver_option: &str,
+        packages_installed: &[String],
+        packages_added: &[String],
+        packages_removed: &[String],
+        error: Option<String>,
+    ) -> Self {
+        InstallLogEntry {
+            timestamp: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |duration| duration.as_secs() as i64),
+            hardware,
+            driver_option: driver_option.to_owned(),
+            packages_installed: packages_installed.to_vec(),
+            packages_added: packages_added.to_vec(),
+            packages_removed: packages_removed.to_vec(),
+            error,
+        }
+    }
+}
+
+pub fn append_install_log(path: &Path, entry: &InstallLogEntry) -> Result<(), Error> {
+    if let Some(directory) = path.parent() {
+        fs::create_dir_all(directory).context(InstallLogWriteSnafu { path })?;
+    }
+    let mut line = serde_json::to_string(entry).unwrap();
+    line.push('\n');
+    OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .and_then(|mut file| file.write_all(line.as_bytes()))
+        .context(InstallLogWriteSnafu { path })
+}
+
+/// The entries of the install log, oldest first. A missing log has no entries, and
+/// lines that cannot be parsed are skipped with a warning.
+pub fn read_install_log(path: &Path) -> Result<Vec<InstallLogEntry>, Error> {
+    let contents = match fs::read_to_string(path) {
+        Ok(contents) => contents,
+        Err(error) if error.kind() == ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(error) => return Err(error).context(InstallLogReadSnafu { path }),
+    };
+    Ok(contents
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .filter_map(|(index, line)| match serde_json::from_str(line) {
+            Ok(entry) => Some(entry),
+            Err(error) => {
+                eprintln!(
+                    "{} Skipping line {} of the install log {}. {}",
+                    "WARNING:".if_supports_color(Stderr, |text| text.yellow()),
+                    index + 1,
+                    path.to_string_lossy(),
+                    error
+                );
+                None
+            }
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::TestDirectory;
+
+    #[test]
+    pub fn install_log_round_trip() {
+        let directory = TestDirectory::new("install-log");
+        let log_path = directory.join("install.log");
+        assert_eq!(read_install_log(&log_path).unwrap(), []);
+
+        let installed = InstallLogEntry::now(
+            HardwareKind::Graphics,
+            "Nvidia",
+            &["nvidia".to_string(), "nvidia-utils".to_string()],
+            &["nvidia".to_string()],
+            &["xf86-video-nouveau".to_string()],
+            None,
+        );
+        let failed = InstallLogEntry {
+            error: Some("The package nvidia-open was not found...".to_string()),
+            ..installed.clone()
+        };
+        append_install_log(&log_path, &installed).unwrap();
+        fs::write(
+            &log_path,
+            fs::read_to_string(&log_path).unwrap() + "not json\n",
+        )
+        .unwrap();
+        append_install_log(&log_path, &failed).unwrap();
+        assert_eq!(
+            read_install_log(&log_path).unwrap(),
+            [installed.clone(), failed]
+        );
+
+        // Entries logged before the added packages were recorded added none
+        fs::write(
+            &log_path,
+            r#"{"timestamp":0,"hardware":"Graphics","driver_option":"Nvidia","packages_installed":["nvidia"],"packages_removed":[],"error":null}"#,
+        )
+        .unwrap();
+        assert_eq!(
+            read_install_log(&log_path).unwrap(),
+            [InstallLogEntry {
+                timestamp: 0,
+                packages_installed: vec!["nvidia".to_string()],
+                packages_added: Vec::new(),
+                packages_removed: Vec::new(),
+                ..installed
+            }]
+        );
+    }
+}
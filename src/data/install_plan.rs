@@ -0,0 +1,38 @@
+use crate::{
+    data::input_file::HardwareKind,
+    error::{Error, InstallPlanParseSnafu, InstallPlanReadSnafu},
+};
+use serde::{Deserialize, Serialize};
+use snafu::ResultExt;
+use std::{fs, path::Path};
+
+/// Driver options to install one after the other without asking, for provisioning
+/// identical machines, like
+///
+/// ```yaml
+/// - hardware: graphics
+///   driver_option: Nvidia
+/// - hardware: wireless
+///   driver_option: Broadcom wl
+/// ```
+#[derive(Clone, Default, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct InstallPlan {
+    pub entries: Vec<InstallPlanEntry>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct InstallPlanEntry {
+    #[serde(alias = "hardware_kind", alias = "hardware-kind")]
+    pub hardware: HardwareKind,
+
+    #[serde(alias = "driver-option", alias = "driver")]
+    pub driver_option: String,
+}
+
+impl InstallPlan {
+    pub fn load(path: &Path) -> Result<Self, Error> {
+        let contents = fs::read(path).context(InstallPlanReadSnafu { path })?;
+        serde_yaml::from_slice(&contents).context(InstallPlanParseSnafu { path })
+    }
+}
@@ -0,0 +1,50 @@
+use crate::{
+    data::input_file::HardwareKind,
+    error::{Error, ManifestParseSnafu, ManifestReadSnafu, ManifestWriteSnafu},
+};
+use serde::{Deserialize, Serialize};
+use snafu::ResultExt;
+use std::{collections::BTreeMap, fs, io::ErrorKind, path::Path};
+
+/// The driver options that the machine should have, one per hardware kind. Every install
+/// records the driver option it selected, so that `reapply` can install them again, like
+/// after a system refresh. It is stored like a profile:
+///
+/// ```yaml
+/// driver_options:
+///   Graphics: Nvidia
+///   Wireless: Broadcom wl
+/// ```
+#[derive(Clone, Default, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Manifest {
+    #[serde(default)]
+    pub driver_options: BTreeMap<HardwareKind, String>,
+}
+
+impl Manifest {
+    /// Loads the manifest, which is empty while nothing has been installed yet.
+    pub fn load(path: &Path) -> Result<Self, Error> {
+        let contents = match fs::read(path) {
+            Ok(contents) => contents,
+            Err(error) if error.kind() == ErrorKind::NotFound => return Ok(Manifest::default()),
+            Err(error) => return Err(error).context(ManifestReadSnafu { path }),
+        };
+        serde_yaml::from_slice(&contents).context(ManifestParseSnafu { path })
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), Error> {
+        if let Some(directory) = path.parent() {
+            fs::create_dir_all(directory).context(ManifestWriteSnafu { path })?;
+        }
+        fs::write(path, serde_yaml::to_string(self).unwrap()).context(ManifestWriteSnafu { path })
+    }
+
+    /// Records the driver option installed for a hardware kind, replacing the one before.
+    pub fn record(path: &Path, hardware: HardwareKind, driver_option: &str) -> Result<(), Error> {
+        let mut manifest = Manifest::load(path)?;
+        manifest
+            .driver_options
+            .insert(hardware, driver_option.to_owned());
+        manifest.save(path)
+    }
+}
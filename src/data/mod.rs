@@ -1,2 +1,9 @@
+pub mod backup;
+pub mod config;
 pub mod database;
 pub mod input_file;
+pub mod install_log;
+pub mod install_plan;
+pub mod manifest;
+pub mod profile;
+pub mod remote_index;
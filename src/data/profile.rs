@@ -0,0 +1,48 @@
+use crate::{
+    data::input_file::HardwareKind,
+    error::{
+        Error, InvalidProfileNameSnafu, ProfileParseSnafu, ProfileReadSnafu, ProfileWriteSnafu,
+    },
+};
+use serde::{Deserialize, Serialize};
+use snafu::ResultExt;
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::{Path, PathBuf},
+};
+
+/// A named set of driver options to install together, one per hardware kind, like
+///
+/// ```yaml
+/// driver_options:
+///   Graphics: Nvidia
+///   Wireless: Broadcom wl
+/// ```
+#[derive(Clone, Default, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Profile {
+    #[serde(default)]
+    pub driver_options: BTreeMap<HardwareKind, String>,
+}
+
+/// The file a profile is stored in, within a directory of profiles.
+pub fn profile_path(profiles_directory: &Path, name: &str) -> Result<PathBuf, Error> {
+    if name.trim().is_empty() || name.contains('/') || name.starts_with('.') {
+        return InvalidProfileNameSnafu { name }.fail();
+    }
+    Ok(profiles_directory.join(format!("{}.yaml", name)))
+}
+
+impl Profile {
+    pub fn load(path: &Path) -> Result<Self, Error> {
+        let contents = fs::read(path).context(ProfileReadSnafu { path })?;
+        serde_yaml::from_slice(&contents).context(ProfileParseSnafu { path })
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), Error> {
+        if let Some(directory) = path.parent() {
+            fs::create_dir_all(directory).context(ProfileWriteSnafu { path })?;
+        }
+        fs::write(path, serde_yaml::to_string(self).unwrap()).context(ProfileWriteSnafu { path })
+    }
+}
@@ -0,0 +1,433 @@
+use crate::{
+    actions::generate_database::merge_hardware_setups,
+    data::{
+        config::Config,
+        database,
+        input_file::{self, HardwareSetup},
+    },
+    error::{
+        Error, RemoteIndexCacheSnafu, RemoteIndexIncludeSnafu, RemoteIndexParseSnafu,
+        RemoteIndexSnafu,
+    },
+    paths,
+};
+use owo_colors::{OwoColorize, Stream::Stderr};
+use reqwest::{
+    blocking::Client,
+    header::{ETAG, IF_MODIFIED_SINCE, IF_NONE_MATCH, LAST_MODIFIED},
+    StatusCode,
+};
+use serde::{Deserialize, Serialize};
+use snafu::ResultExt;
+use std::{
+    collections::BTreeSet,
+    fs,
+    path::PathBuf,
+    sync::atomic::{AtomicUsize, Ordering},
+    time::{Duration, SystemTime},
+};
+
+/// How long a cached catalog is used before it is checked for changes again, so that
+/// commands that only read do not wait on the network every time.
+pub const REFRESH_INTERVAL: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// The validators a cached catalog was served with, for conditional requests.
+#[derive(Clone, Default, Debug, PartialEq, Eq, Serialize, Deserialize)]
+struct CacheMetadata {
+    url: String,
+    etag: Option<String>,
+    last_modified: Option<String>,
+}
+
+/// A driver catalog published at a URL, in the same format as the input file
+/// of `generate-database`, along with the directory it is cached in.
+#[derive(Clone, Debug)]
+pub struct RemoteIndex {
+    pub url: String,
+    pub cache_directory: PathBuf,
+}
+
+/// A database file to read from, deleted once dropped if it is a merged copy.
+#[derive(Debug)]
+pub struct CatalogDatabase {
+    pub path: PathBuf,
+    temporary: bool,
+}
+
+impl CatalogDatabase {
+    fn local(path: PathBuf) -> Self {
+        CatalogDatabase {
+            path,
+            temporary: false,
+        }
+    }
+}
+
+impl Drop for CatalogDatabase {
+    fn drop(&mut self) {
+        if self.temporary {
+            _ = fs::remove_file(&self.path);
+        }
+    }
+}
+
+impl RemoteIndex {
+    pub fn new(url: String, cache_directory: PathBuf) -> Self {
+        RemoteIndex {
+            url,
+            cache_directory,
+        }
+    }
+
+    /// The remote catalog configured through `ARCHLINUX_DRIVER_MANAGER_REMOTE_INDEX`, or
+    /// else through `remote_index` in the configuration file, if any.
+    pub fn configured() -> Option<Self> {
+        let url = paths::remote_index_url().or_else(|| {
+            Config::load(&paths::config_file())
+                .unwrap_or_else(|error| {
+                    eprintln!(
+                        "{} {}",
+                        "WARNING:".if_supports_color(Stderr, |text| text.yellow()),
+                        error
+                    );
+                    Config::default()
+                })
+                .remote_index
+                .filter(|url| !url.is_empty())
+        })?;
+        Some(RemoteIndex::new(url, paths::cache_directory()))
+    }
+
+    pub fn catalog_path(&self) -> PathBuf {
+        self.cache_directory
+            .join(paths::REMOTE_INDEX_CACHE_FILENAME)
+    }
+
+    fn metadata_path(&self) -> PathBuf {
+        self.cache_directory
+            .join(paths::REMOTE_INDEX_METADATA_FILENAME)
+    }
+
+    /// The metadata of the cached catalog, if one was cached for this URL.
+    fn cached_metadata(&self) -> Option<CacheMetadata> {
+        let metadata: CacheMetadata =
+            serde_json::from_slice(&fs::read(self.metadata_path()).ok()?).ok()?;
+        (metadata.url == self.url && self.catalog_path().is_file()).then_some(metadata)
+    }
+
+    /// Whether the cached catalog was last checked for changes less than `interval` ago.
+    fn checked_within(&self, interval: Duration) -> bool {
+        self.cached_metadata().is_some()
+            && fs::metadata(self.metadata_path())
+                .and_then(|metadata| metadata.modified())
+                .ok()
+                .and_then(|checked| SystemTime::now().duration_since(checked).ok())
+                .is_some_and(|age| age < interval)
+    }
+
+    fn write_metadata(&self, metadata: &CacheMetadata) -> Result<(), Error> {
+        fs::write(self.metadata_path(), serde_json::to_vec(metadata).unwrap()).context(
+            RemoteIndexCacheSnafu {
+                path: self.metadata_path(),
+            },
+        )
+    }
+
+    /// Downloads the catalog unless the cached copy is still current, which is checked
+    /// with a conditional request. Returns whether the cached copy was replaced.
+    pub fn refresh(&self) -> Result<bool, Error> {
+        let cached_metadata = self.cached_metadata();
+        let mut request = Client::new().get(&self.url);
+        if let Some(cached_metadata) = &cached_metadata {
+            if let Some(etag) = &cached_metadata.etag {
+                request = request.header(IF_NONE_MATCH, etag);
+            }
+            if let Some(last_modified) = &cached_metadata.last_modified {
+                request = request.header(IF_MODIFIED_SINCE, last_modified);
+            }
+        }
+        let response = request
+            .send()
+            .and_then(|response| response.error_for_status())
+            .context(RemoteIndexSnafu { url: &self.url })?;
+        if let Some(cached_metadata) = cached_metadata
+            .as_ref()
+            .filter(|_| response.status() == StatusCode::NOT_MODIFIED)
+        {
+            // Rewritten to note when the catalog was last checked
+            self.write_metadata(cached_metadata)?;
+            return Ok(false);
+        }
+
+        let header_value = |name| {
+            response
+                .headers()
+                .get(name)
+                .and_then(|value| value.to_str().ok())
+                .map(String::from)
+        };
+        let metadata = CacheMetadata {
+            url: self.url.clone(),
+            etag: header_value(ETAG),
+            last_modified: header_value(LAST_MODIFIED),
+        };
+        let catalog = response
+            .bytes()
+            .context(RemoteIndexSnafu { url: &self.url })?;
+        // Keep the last good copy if the new one is broken
+        self.parse_catalog(&catalog)?;
+
+        fs::create_dir_all(&self.cache_directory).context(RemoteIndexCacheSnafu {
+            path: &self.cache_directory,
+        })?;
+        fs::write(self.catalog_path(), &catalog).context(RemoteIndexCacheSnafu {
+            path: self.catalog_path(),
+        })?;
+        self.write_metadata(&metadata)?;
+        Ok(true)
+    }
+
+    /// The cached catalog, or `None` if nothing was cached for this URL yet.
+    pub fn cached_hardware_setups(&self) -> Result<Option<BTreeSet<HardwareSetup>>, Error> {
+        if self.cached_metadata().is_none() {
+            return Ok(None);
+        }
+        let catalog = fs::read(self.catalog_path()).context(RemoteIndexCacheSnafu {
+            path: self.catalog_path(),
+        })?;
+        Ok(Some(self.parse_catalog(&catalog)?))
+    }
+
+    /// Parses a catalog, which unlike a local input file cannot `!include` other files,
+    /// as those would be read from the local file system.
+    fn parse_catalog(&self, catalog: &[u8]) -> Result<BTreeSet<HardwareSetup>, Error> {
+        let mentions_include = catalog
+            .windows(input_file::INCLUDE_TAG.len())
+            .any(|window| window == input_file::INCLUDE_TAG.as_bytes());
+        if !mentions_include {
+            return serde_yaml::from_slice(catalog)
+                .context(RemoteIndexParseSnafu { url: &self.url });
+        }
+        // Going through a `Value` loses the locations of errors, so only do it when needed
+        let value: serde_yaml::Value =
+            serde_yaml::from_slice(catalog).context(RemoteIndexParseSnafu { url: &self.url })?;
+        if input_file::contains_include(&value) {
+            return RemoteIndexIncludeSnafu { url: &self.url }.fail();
+        }
+        serde_yaml::from_value(value).context(RemoteIndexParseSnafu { url: &self.url })
+    }
+
+    /// Merges the cached catalog into a private copy of a database, refreshing the
+    /// cache first when it was last checked more than [`REFRESH_INTERVAL`] ago, unless
+    /// running offline. Problems with the catalog are only warned about, so that the
+    /// local database keeps working on its own.
+    pub fn merged_database(
+        &self,
+        database_file: PathBuf,
+        offline: bool,
+    ) -> Result<CatalogDatabase, Error> {
+        if !offline && !self.checked_within(REFRESH_INTERVAL) {
+            if let Err(error) = self.refresh() {
+                eprintln!(
+                    "{} Using the cached remote driver catalog. {}",
+                    "WARNING:".if_supports_color(Stderr, |text| text.yellow()),
+                    error
+                );
+            }
+        }
+        let hardware_setups = match self.cached_hardware_setups() {
+            Ok(Some(hardware_setups)) => hardware_setups,
+            Ok(None) => return Ok(CatalogDatabase::local(database_file)),
+            Err(error) => {
+                eprintln!(
+                    "{} Ignoring the remote driver catalog. {}",
+                    "WARNING:".if_supports_color(Stderr, |text| text.yellow()),
+                    error
+                );
+                return Ok(CatalogDatabase::local(database_file));
+            }
+        };
+
+        static COPY_SERIAL: AtomicUsize = AtomicUsize::new(1);
+        let temp_directory = paths::temp_directory();
+        paths::create_private_directory(&temp_directory).context(RemoteIndexCacheSnafu {
+            path: &temp_directory,
+        })?;
+        let catalog_database = CatalogDatabase {
+            path: temp_directory.join(format!(
+                "{}-catalog-{}",
+                std::process::id(),
+                COPY_SERIAL.fetch_add(1, Ordering::SeqCst)
+            )),
+            temporary: true,
+        };
+        // Without a local database, the remote catalog is used on its own
+        if database_file.exists() {
+            fs::copy(&database_file, &catalog_database.path).context(RemoteIndexCacheSnafu {
+                path: &catalog_database.path,
+            })?;
+        }
+        merge_hardware_setups(catalog_database.path.clone(), &hardware_setups)?;
+        Ok(catalog_database)
+    }
+}
+
+/// The database to read from: the given one, merged with the remote catalog when
+/// one is configured. With `verify_checksum`, the given
+/// database is first checked against the checksum written when it was generated.
+pub fn database_with_remote_index(
+    database_file: PathBuf,
+    offline: bool,
+    verify_checksum: bool,
+) -> Result<CatalogDatabase, Error> {
+    if verify_checksum {
+        database::verify_checksum(&database_file)?;
+    }
+    match RemoteIndex::configured() {
+        Some(remote_index) => remote_index.merged_database(database_file, offline),
+        None => Ok(CatalogDatabase::local(database_file)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::TestDirectory;
+    use crate::{
+        actions::{generate_database::generate_database_inner, search::search_inner},
+        data::{
+            database::{HardwareId, PciId},
+            input_file::HardwareKind,
+        },
+        detection::MockDetector,
+    };
+
+    const LOCAL_DATA: &str = r#"
+- name: Nvidia dGPU
+  hardware_kind: graphics
+  hardware_list: !pci
+      vendor: 10de
+      devices: [1b80]
+  driver_options:
+    - name: Nvidia
+      packages: [nvidia]
+"#;
+
+    const REMOTE_DATA: &str = r#"
+- name: Nvidia dGPU
+  hardware_kind: graphics
+  hardware_list: !pci
+      vendor: 10de
+      devices: [1b80]
+  driver_options:
+    - name: Nvidia
+      packages: [nvidia]
+- name: Nvidia dGPU (Open)
+  hardware_kind: graphics
+  hardware_list: !pci
+      vendor: 10de
+      devices: [1b80]
+  driver_options:
+    - name: Nvidia Open
+      packages: [nvidia-open]
+"#;
+
+    #[test]
+    pub fn cached_catalog_is_merged_offline() {
+        let directory = TestDirectory::new("remote");
+        let input_file = directory.join("input_data.yaml");
+        let database_file = directory.join("database.db");
+        fs::write(&input_file, LOCAL_DATA).unwrap();
+        generate_database_inner(input_file, database_file.clone(), false).unwrap();
+
+        let remote_index = RemoteIndex::new(
+            "https://example.invalid/index.yaml".to_string(),
+            directory.join("cache"),
+        );
+        let detector = MockDetector::new([HardwareId::Pci(PciId {
+            vendor: 0x10de,
+            device: 0x1b80,
+            revision: None,
+        })]);
+        let option_names = |database_file: PathBuf| {
+            search_inner(
+                database_file,
+                &None,
+                Vec::new().into_iter(),
+                &detector,
+                None,
+            )
+            .unwrap()[&HardwareKind::Graphics]
+                .iter()
+                .map(|driver_option| driver_option.name.clone())
+                .collect::<Vec<String>>()
+        };
+
+        let unmerged = remote_index
+            .merged_database(database_file.clone(), true)
+            .unwrap();
+        assert_eq!(unmerged.path, database_file);
+
+        fs::create_dir_all(&remote_index.cache_directory).unwrap();
+        fs::write(remote_index.catalog_path(), REMOTE_DATA).unwrap();
+        fs::write(
+            remote_index.metadata_path(),
+            serde_json::to_vec(&CacheMetadata {
+                url: remote_index.url.clone(),
+                ..Default::default()
+            })
+            .unwrap(),
+        )
+        .unwrap();
+        assert!(remote_index.checked_within(REFRESH_INTERVAL));
+        assert!(!remote_index.checked_within(Duration::ZERO));
+
+        // A catalog checked recently is used without going to the network
+        let merged = remote_index
+            .merged_database(database_file.clone(), false)
+            .unwrap();
+        assert_eq!(option_names(merged.path.clone()), ["Nvidia", "Nvidia Open"]);
+        assert_eq!(option_names(database_file), ["Nvidia"]);
+
+        let merged_path = merged.path.clone();
+        drop(merged);
+        assert!(!merged_path.exists());
+    }
+
+    #[test]
+    pub fn catalogs_cannot_include_local_files() {
+        let directory = TestDirectory::new("remote-include");
+        fs::write(directory.join("secret.yaml"), "Nvidia").unwrap();
+        let remote_index = RemoteIndex::new(
+            "https://example.invalid/index.yaml".to_string(),
+            directory.to_path_buf(),
+        );
+        let included_name = REMOTE_DATA.replace("name: Nvidia Open", "name: !include secret.yaml");
+        assert!(matches!(
+            remote_index.parse_catalog(included_name.as_bytes()),
+            Err(Error::RemoteIndexInclude { .. })
+        ));
+        assert_eq!(
+            remote_index
+                .parse_catalog(REMOTE_DATA.as_bytes())
+                .unwrap()
+                .len(),
+            2
+        );
+
+        fs::write(remote_index.catalog_path(), &included_name).unwrap();
+        fs::write(
+            remote_index.metadata_path(),
+            serde_json::to_vec(&CacheMetadata {
+                url: remote_index.url.clone(),
+                ..Default::default()
+            })
+            .unwrap(),
+        )
+        .unwrap();
+        assert!(matches!(
+            remote_index.cached_hardware_setups(),
+            Err(Error::RemoteIndexInclude { .. })
+        ));
+    }
+}
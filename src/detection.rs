@@ -0,0 +1,920 @@
+use crate::{
+    data::database::{HardwareId, PciId, UsbId},
+    error::{AssumedHardwareParseSnafu, AssumedHardwareReadSnafu, DetectionCacheSnafu, Error},
+    paths,
+};
+use clap::ValueEnum;
+use owo_colors::{OwoColorize, Stream::Stderr};
+use snafu::ResultExt;
+use std::{
+    collections::BTreeSet,
+    fs,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+/// A source of the hardware IDs present on a system. The actions match driver
+/// options against whatever a detector reports, so swapping the detector makes
+/// the matching logic testable without real hardware.
+pub trait HardwareDetector {
+    fn detect(&self) -> BTreeSet<HardwareId>;
+
+    /// The DMI identity of the system, when known.
+    fn dmi_identity(&self) -> Option<DmiIdentity> {
+        None
+    }
+
+    /// The kernel modaliases of the devices, when known. Without them, hardware setups
+    /// are matched by their hardware IDs alone.
+    fn modaliases(&self) -> Option<BTreeSet<String>> {
+        None
+    }
+}
+
+/// The vendor and model of the system, as reported by its firmware through DMI.
+#[derive(Clone, Default, Debug, PartialEq, Eq)]
+pub struct DmiIdentity {
+    pub sys_vendor: String,
+    pub product_name: String,
+}
+
+impl DmiIdentity {
+    /// Reads the DMI identity of the running system from sysfs.
+    pub fn from_sysfs() -> Option<Self> {
+        let read = |name: &str| {
+            fs::read_to_string(Path::new(DMI_DIRECTORY).join(name))
+                .ok()
+                .map(|value| value.trim().to_owned())
+        };
+        Some(DmiIdentity {
+            sys_vendor: read("sys_vendor")?,
+            product_name: read("product_name")?,
+        })
+    }
+}
+
+pub const DMI_DIRECTORY: &str = "/sys/class/dmi/id";
+
+/// The PCI vendor and device IDs of the GPUs that hypervisors emulate.
+pub const VIRTUAL_GPU_IDS: [(u16, u16); 7] = [
+    // QEMU standard VGA
+    (0x1234, 0x1111),
+    // QEMU Cirrus VGA
+    (0x1013, 0x00b8),
+    // Red Hat QXL
+    (0x1b36, 0x0100),
+    // virtio-gpu
+    (0x1af4, 0x1050),
+    // VMware SVGA II
+    (0x15ad, 0x0405),
+    // VirtualBox Graphics Adapter
+    (0x80ee, 0xbeef),
+    // Hyper-V synthetic video
+    (0x1414, 0x5353),
+];
+
+/// The DMI system vendors and product names that hypervisors report.
+const VIRTUAL_MACHINE_DMI_NAMES: [&str; 7] = [
+    "QEMU",
+    "KVM",
+    "VMware",
+    "innotek GmbH",
+    "VirtualBox",
+    "Xen",
+    "Virtual Machine",
+];
+
+/// Whether the system is a virtual machine, judged by an emulated GPU among its
+/// hardware or by the DMI identity its hypervisor reports.
+pub fn is_virtual_machine(
+    hardware_ids: &BTreeSet<HardwareId>,
+    dmi_identity: Option<&DmiIdentity>,
+) -> bool {
+    let has_virtual_gpu = hardware_ids.iter().any(|hardware_id| match hardware_id {
+        HardwareId::Pci(pci_id) => VIRTUAL_GPU_IDS.contains(&(pci_id.vendor, pci_id.device)),
+        HardwareId::Usb(_) => false,
+    });
+    let has_virtual_dmi_identity = dmi_identity.is_some_and(|dmi_identity| {
+        VIRTUAL_MACHINE_DMI_NAMES.iter().any(|name| {
+            dmi_identity.sys_vendor.starts_with(name) || dmi_identity.product_name.starts_with(name)
+        })
+    });
+    has_virtual_gpu || has_virtual_dmi_identity
+}
+
+/// Detects the PCI and USB devices connected to the running system.
+#[derive(Clone, Copy, Default, Debug)]
+pub struct DevicesDetector {}
+
+impl DevicesDetector {
+    pub fn new() -> Self {
+        DevicesDetector {}
+    }
+}
+
+impl HardwareDetector for DevicesDetector {
+    fn detect(&self) -> BTreeSet<HardwareId> {
+        let connected_devices = match devices::Devices::get() {
+            Ok(connected_devices) if !connected_devices.is_empty() => connected_devices,
+            result => {
+                eprintln!(
+                    "{} The connected devices could not be enumerated{}, so they are listed with lspci and lsusb instead",
+                    "WARNING:".if_supports_color(Stderr, |text| text.yellow()),
+                    result
+                        .err()
+                        .map(|error| format!(" ({:?})", error))
+                        .unwrap_or_default()
+                );
+                return LspciDetector::new().detect();
+            }
+        };
+        let hardware_ids: BTreeSet<HardwareId> = connected_devices
+            .into_iter()
+            .map(|item| match item.path() {
+                devices::DevicePath::PCI {
+                    bus,
+                    slot,
+                    function,
+                } => HardwareId::Pci(PciId {
+                    vendor: item.vendor_id(),
+                    device: item.product_id(),
+                    revision: pci_revision(
+                        Path::new(PCI_DEVICES_DIRECTORY),
+                        (*bus, *slot, *function),
+                        (item.vendor_id(), item.product_id()),
+                    ),
+                }),
+                devices::DevicePath::USB { bus: _, device: _ } => HardwareId::Usb(UsbId {
+                    vendor: item.vendor_id(),
+                    device: item.product_id(),
+                }),
+            })
+            .collect();
+        with_sysfs_usb_ids(hardware_ids, Path::new(USB_DEVICES_DIRECTORY))
+    }
+
+    fn dmi_identity(&self) -> Option<DmiIdentity> {
+        DmiIdentity::from_sysfs()
+    }
+}
+
+/// The directory in sysfs listing the connected USB devices and their interfaces.
+pub const USB_DEVICES_DIRECTORY: &str = "/sys/bus/usb/devices";
+
+/// The USB IDs of the devices listed in a sysfs directory like [`USB_DEVICES_DIRECTORY`].
+/// Interfaces are listed there too, but only devices have an `idVendor` and `idProduct`.
+fn sysfs_usb_ids(usb_devices_directory: &Path) -> std::io::Result<BTreeSet<HardwareId>> {
+    let read_id = |device_directory: &Path, name: &str| {
+        fs::read_to_string(device_directory.join(name))
+            .ok()
+            .and_then(|id| u16::from_str_radix(id.trim(), 16).ok())
+    };
+    Ok(fs::read_dir(usb_devices_directory)?
+        .filter_map(|entry| {
+            let device_directory = entry.ok()?.path();
+            Some(HardwareId::Usb(UsbId {
+                vendor: read_id(&device_directory, "idVendor")?,
+                device: read_id(&device_directory, "idProduct")?,
+            }))
+        })
+        .collect())
+}
+
+/// Adds the USB devices listed in sysfs when a detection found none, since lsusb can be
+/// missing or denied access to the devices, which would make every USB driver go unfound.
+/// Warns when that happens, or when sysfs cannot be read either.
+fn with_sysfs_usb_ids(
+    mut hardware_ids: BTreeSet<HardwareId>,
+    usb_devices_directory: &Path,
+) -> BTreeSet<HardwareId> {
+    if hardware_ids
+        .iter()
+        .any(|hardware_id| matches!(hardware_id, HardwareId::Usb(_)))
+    {
+        return hardware_ids;
+    }
+    match sysfs_usb_ids(usb_devices_directory) {
+        Ok(usb_ids) if !usb_ids.is_empty() => {
+            eprintln!(
+                "{} No USB devices were reported by lsusb, though {} lists {}, so those are used instead",
+                "WARNING:".if_supports_color(Stderr, |text| text.yellow()),
+                usb_devices_directory.to_string_lossy(),
+                usb_ids.len()
+            );
+            hardware_ids.extend(usb_ids);
+        }
+        // A system without USB, or without sysfs like some containers
+        Ok(_) => {}
+        Err(error) if error.kind() == std::io::ErrorKind::NotFound => {}
+        Err(error) => eprintln!(
+            "{} No USB devices were detected and {} could not be read ({}), so drivers for USB hardware may not be found",
+            "WARNING:".if_supports_color(Stderr, |text| text.yellow()),
+            usb_devices_directory.to_string_lossy(),
+            error
+        ),
+    }
+    hardware_ids
+}
+
+/// Detects the PCI and USB devices from the output of `lspci -nn` and `lsusb`, for
+/// systems where enumerating them directly fails.
+#[derive(Clone, Copy, Default, Debug)]
+pub struct LspciDetector {}
+
+impl LspciDetector {
+    pub fn new() -> Self {
+        LspciDetector {}
+    }
+}
+
+impl HardwareDetector for LspciDetector {
+    fn detect(&self) -> BTreeSet<HardwareId> {
+        // A missing tool only leaves out the devices of its bus
+        let output_of = |program: &str, arguments: &[&str]| {
+            Command::new(program)
+                .args(arguments)
+                .output()
+                .ok()
+                .filter(|output| output.status.success())
+                .map(|output| String::from_utf8_lossy(&output.stdout).into_owned())
+                .unwrap_or_default()
+        };
+        let hardware_ids: BTreeSet<HardwareId> = parse_lspci(&output_of("lspci", &["-nn"]))
+            .chain(parse_lsusb(&output_of("lsusb", &[])))
+            .collect();
+        with_sysfs_usb_ids(hardware_ids, Path::new(USB_DEVICES_DIRECTORY))
+    }
+
+    fn dmi_identity(&self) -> Option<DmiIdentity> {
+        DmiIdentity::from_sysfs()
+    }
+}
+
+/// Parses a `vendor:device` pair of hexadecimal IDs, like `10de:1b80`.
+fn parse_id_pair(pair: &str) -> Option<(u16, u16)> {
+    let (vendor, device) = pair.split_once(':')?;
+    if vendor.len() != 4 || device.len() != 4 {
+        return None;
+    }
+    Some((
+        u16::from_str_radix(vendor, 16).ok()?,
+        u16::from_str_radix(device, 16).ok()?,
+    ))
+}
+
+/// The PCI IDs in the output of `lspci -nn`, where each line ends with the IDs in
+/// brackets and an optional revision, like
+/// `01:00.0 VGA compatible controller [0300]: NVIDIA Corporation GP104 [10de:1b80] (rev a1)`.
+fn parse_lspci(output: &str) -> impl Iterator<Item = HardwareId> + '_ {
+    output.lines().filter_map(|line| {
+        let (vendor, device) = line
+            .split('[')
+            .filter_map(|part| part.split_once(']'))
+            .filter_map(|(inside, _)| parse_id_pair(inside))
+            .next_back()?;
+        let revision = line
+            .rsplit_once("(rev ")
+            .and_then(|(_, rest)| rest.split_once(')'))
+            .and_then(|(revision, _)| u8::from_str_radix(revision.trim(), 16).ok());
+        Some(HardwareId::Pci(PciId {
+            vendor,
+            device,
+            revision,
+        }))
+    })
+}
+
+/// The USB IDs in the output of `lsusb`, like
+/// `Bus 001 Device 002: ID 0bda:8179 Realtek Semiconductor Corp. RTL8188EUS`.
+fn parse_lsusb(output: &str) -> impl Iterator<Item = HardwareId> + '_ {
+    output.lines().filter_map(|line| {
+        let (_, rest) = line.split_once(" ID ")?;
+        let (vendor, device) = parse_id_pair(rest.split_whitespace().next()?)?;
+        Some(HardwareId::Usb(UsbId { vendor, device }))
+    })
+}
+
+/// Reads the revision of a PCI device from a sysfs directory like [`PCI_DEVICES_DIRECTORY`],
+/// since the device enumeration does not report it. The enumeration leaves out the PCI
+/// domain of the address too, so the device is looked for in every domain, and told apart
+/// from devices at the same address in other domains by its vendor and device IDs.
+fn pci_revision(
+    pci_devices_directory: &Path,
+    (bus, slot, function): (u8, u8, u8),
+    (vendor, device): (u16, u16),
+) -> Option<u8> {
+    let address = format!(":{:02x}:{:02x}.{:x}", bus, slot, function);
+    let read_hex = |device_directory: &Path, name: &str| {
+        let value = fs::read_to_string(device_directory.join(name)).ok()?;
+        u16::from_str_radix(value.trim().trim_start_matches("0x"), 16).ok()
+    };
+    fs::read_dir(pci_devices_directory)
+        .ok()?
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.file_name().to_string_lossy().ends_with(&address))
+        .map(|entry| entry.path())
+        .find(|device_directory| {
+            read_hex(device_directory, "vendor") == Some(vendor)
+                && read_hex(device_directory, "device") == Some(device)
+        })
+        .and_then(|device_directory| read_hex(&device_directory, "revision"))
+        .and_then(|revision| u8::try_from(revision).ok())
+}
+
+/// The directory in sysfs listing the PCI devices.
+pub const PCI_DEVICES_DIRECTORY: &str = "/sys/bus/pci/devices";
+
+/// The hardware ID in a kernel modalias, like `pci:v000010DEd00001B80sv00001458sd00003702bc03sc00i00`
+/// or `usb:v0BDAp8179d0000dc00dsc00dp00icFFiscFFipFFin00`.
+pub fn modalias_hardware_id(modalias: &str) -> Option<HardwareId> {
+    let hex_after = |text: &str, marker: char, length: usize| {
+        let (_, rest) = text.split_once(marker)?;
+        u16::from_str_radix(rest.get(..length)?, 16).ok()
+    };
+    if let Some(fields) = modalias.strip_prefix("pci:") {
+        Some(HardwareId::Pci(PciId {
+            vendor: hex_after(fields, 'v', 8)?,
+            device: hex_after(fields, 'd', 8)?,
+            revision: None,
+        }))
+    } else if let Some(fields) = modalias.strip_prefix("usb:") {
+        Some(HardwareId::Usb(UsbId {
+            vendor: hex_after(fields, 'v', 4)?,
+            device: hex_after(fields, 'p', 4)?,
+        }))
+    } else {
+        None
+    }
+}
+
+/// Whether a modalias matches a pattern with the `*` and `?` wildcards of `modules.alias`,
+/// regardless of case.
+pub fn modalias_matches(pattern: &str, modalias: &str) -> bool {
+    let pattern = pattern.to_ascii_lowercase().into_bytes();
+    let modalias = modalias.to_ascii_lowercase().into_bytes();
+    // Where the last `*` was, and the position in the modalias it currently stands for
+    let (mut pattern_index, mut modalias_index) = (0, 0);
+    let mut backtrack: Option<(usize, usize)> = None;
+    while modalias_index < modalias.len() {
+        match pattern.get(pattern_index) {
+            Some(b'*') => {
+                backtrack = Some((pattern_index, modalias_index));
+                pattern_index += 1;
+            }
+            Some(&character) if character == b'?' || character == modalias[modalias_index] => {
+                pattern_index += 1;
+                modalias_index += 1;
+            }
+            _ => match backtrack {
+                Some((star_index, star_modalias_index)) => {
+                    backtrack = Some((star_index, star_modalias_index + 1));
+                    pattern_index = star_index + 1;
+                    modalias_index = star_modalias_index + 1;
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[pattern_index..]
+        .iter()
+        .all(|&character| character == b'*')
+}
+
+/// The modaliases of the devices listed in sysfs directories like [`PCI_DEVICES_DIRECTORY`].
+/// USB devices only have them on their interfaces, which are listed along with them.
+fn sysfs_modaliases(devices_directories: &[&Path]) -> BTreeSet<String> {
+    devices_directories
+        .iter()
+        .filter_map(|devices_directory| fs::read_dir(devices_directory).ok())
+        .flatten()
+        .filter_map(|entry| fs::read_to_string(entry.ok()?.path().join("modalias")).ok())
+        .map(|modalias| modalias.trim().to_owned())
+        .filter(|modalias| modalias_hardware_id(modalias).is_some())
+        .collect()
+}
+
+/// Detects the PCI and USB devices from the modaliases in sysfs, which lets hardware
+/// setups also match on them. Falls back to [`DevicesDetector`] when sysfs lists none.
+#[derive(Clone, Debug)]
+pub struct ModaliasDetector {
+    modaliases: BTreeSet<String>,
+}
+
+impl ModaliasDetector {
+    pub fn new() -> Self {
+        ModaliasDetector {
+            modaliases: sysfs_modaliases(&[
+                Path::new(PCI_DEVICES_DIRECTORY),
+                Path::new(USB_DEVICES_DIRECTORY),
+            ]),
+        }
+    }
+}
+
+impl Default for ModaliasDetector {
+    fn default() -> Self {
+        ModaliasDetector::new()
+    }
+}
+
+impl HardwareDetector for ModaliasDetector {
+    fn detect(&self) -> BTreeSet<HardwareId> {
+        if self.modaliases.is_empty() {
+            eprintln!(
+                "{} No modaliases were found in sysfs, so hardware setups are matched by their hardware IDs alone",
+                "WARNING:".if_supports_color(Stderr, |text| text.yellow()),
+            );
+            return DevicesDetector::new().detect();
+        }
+        self.modaliases
+            .iter()
+            .filter_map(|modalias| modalias_hardware_id(modalias))
+            .collect()
+    }
+
+    fn dmi_identity(&self) -> Option<DmiIdentity> {
+        DmiIdentity::from_sysfs()
+    }
+
+    fn modaliases(&self) -> Option<BTreeSet<String>> {
+        (!self.modaliases.is_empty()).then(|| self.modaliases.clone())
+    }
+}
+
+/// Where a [`CachedDetector`] gets the hardware it reports from.
+enum CacheSource {
+    /// The hardware IDs recorded by the last live detection.
+    Recorded(BTreeSet<HardwareId>),
+    /// A live detector, whose detections refresh the cache.
+    Refreshing {
+        live_detector: Box<dyn HardwareDetector>,
+        cache_path: PathBuf,
+    },
+}
+
+/// Reports the hardware IDs recorded by the last live detection without enumerating
+/// devices, or detects them live and records them for later offline runs.
+pub struct CachedDetector {
+    source: CacheSource,
+}
+
+impl CachedDetector {
+    /// Reports the hardware IDs recorded in the cache, for offline runs.
+    pub fn from_cache_path(path: PathBuf) -> Result<Self, Error> {
+        let contents = fs::read(&path).context(DetectionCacheSnafu { path: path.clone() })?;
+        Ok(CachedDetector {
+            source: CacheSource::Recorded(
+                serde_json::from_slice(&contents)
+                    .map_err(std::io::Error::from)
+                    .context(DetectionCacheSnafu { path })?,
+            ),
+        })
+    }
+
+    /// Reports what a live detector detects, and records it in the cache.
+    pub fn refreshing(live_detector: Box<dyn HardwareDetector>, cache_path: PathBuf) -> Self {
+        CachedDetector {
+            source: CacheSource::Refreshing {
+                live_detector,
+                cache_path,
+            },
+        }
+    }
+}
+
+impl HardwareDetector for CachedDetector {
+    fn detect(&self) -> BTreeSet<HardwareId> {
+        match &self.source {
+            CacheSource::Recorded(hardware_ids) => hardware_ids.clone(),
+            CacheSource::Refreshing {
+                live_detector,
+                cache_path,
+            } => {
+                let hardware_ids = live_detector.detect();
+                // The cache is only an aid for offline runs, so failing to write it is not an error
+                if let Some(cache_directory) = cache_path.parent() {
+                    _ = fs::create_dir_all(cache_directory);
+                }
+                if let Ok(serialized) = serde_json::to_vec(&hardware_ids) {
+                    _ = fs::write(cache_path, serialized);
+                }
+                hardware_ids
+            }
+        }
+    }
+
+    fn dmi_identity(&self) -> Option<DmiIdentity> {
+        match &self.source {
+            // The firmware identity does not change between runs, and reading it enumerates nothing
+            CacheSource::Recorded(_) => DmiIdentity::from_sysfs(),
+            CacheSource::Refreshing { live_detector, .. } => live_detector.dmi_identity(),
+        }
+    }
+
+    fn modaliases(&self) -> Option<BTreeSet<String>> {
+        match &self.source {
+            CacheSource::Recorded(_) => None,
+            CacheSource::Refreshing { live_detector, .. } => live_detector.modaliases(),
+        }
+    }
+}
+
+/// Reports the hardware IDs listed in a file instead of the ones present, to see what
+/// would match on other systems. The file has one ID per line, written like
+/// `PCI 10de:1b80`, `PCI 10de:1b80 rev a1` or `USB 0bda:8179`. Blank lines and
+/// lines starting with `#` are skipped.
+#[derive(Clone, Default, Debug)]
+pub struct AssumedDetector {
+    /// The listed hardware IDs, in the order of the file.
+    pub hardware_ids: Vec<HardwareId>,
+}
+
+impl AssumedDetector {
+    pub fn from_path(path: &Path) -> Result<Self, Error> {
+        let contents = fs::read_to_string(path).context(AssumedHardwareReadSnafu { path })?;
+        let hardware_ids = contents
+            .lines()
+            .enumerate()
+            .map(|(index, line)| (index + 1, line.trim()))
+            .filter(|(_, line)| !line.is_empty() && !line.starts_with('#'))
+            .map(|(line_number, line)| {
+                line.parse::<HardwareId>().map_err(|reason| {
+                    AssumedHardwareParseSnafu {
+                        path,
+                        line_number,
+                        reason,
+                    }
+                    .build()
+                })
+            })
+            .collect::<Result<Vec<HardwareId>, Error>>()?;
+        Ok(AssumedDetector { hardware_ids })
+    }
+}
+
+impl HardwareDetector for AssumedDetector {
+    fn detect(&self) -> BTreeSet<HardwareId> {
+        self.hardware_ids.iter().cloned().collect()
+    }
+}
+
+/// The ways of detecting the hardware of the running system that can be chosen with `--detector`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum DetectorKind {
+    /// Enumerate the devices directly, falling back to lspci and lsusb if that fails.
+    #[default]
+    Native,
+    /// Parse the output of `lspci -nn` and `lsusb`.
+    Lspci,
+    /// Read the modaliases of the devices from sysfs, so that hardware setups with
+    /// `modalias` patterns are matched on them too.
+    Modalias,
+}
+
+/// The vendors whose hardware is considered, by vendor ID. PCI and USB vendors are
+/// compared alike.
+#[derive(Clone, Default, Debug, PartialEq, Eq)]
+pub struct VendorFilter {
+    /// Only the hardware of these vendors is considered, or of all vendors when empty.
+    pub allow: BTreeSet<u16>,
+    /// The hardware of these vendors is never considered.
+    pub deny: BTreeSet<u16>,
+}
+
+impl VendorFilter {
+    pub fn is_empty(&self) -> bool {
+        self.allow.is_empty() && self.deny.is_empty()
+    }
+
+    pub fn permits(&self, hardware_id: &HardwareId) -> bool {
+        let vendor = hardware_id.vendor();
+        (self.allow.is_empty() || self.allow.contains(&vendor)) && !self.deny.contains(&vendor)
+    }
+}
+
+/// Leaves out the hardware of the vendors that a [`VendorFilter`] does not permit from
+/// what another detector reports.
+pub struct VendorFilteredDetector {
+    inner: Box<dyn HardwareDetector>,
+    vendor_filter: VendorFilter,
+}
+
+impl HardwareDetector for VendorFilteredDetector {
+    fn detect(&self) -> BTreeSet<HardwareId> {
+        self.inner
+            .detect()
+            .into_iter()
+            .filter(|hardware_id| self.vendor_filter.permits(hardware_id))
+            .collect()
+    }
+
+    fn dmi_identity(&self) -> Option<DmiIdentity> {
+        self.inner.dmi_identity()
+    }
+
+    fn modaliases(&self) -> Option<BTreeSet<String>> {
+        self.inner.modaliases()
+    }
+}
+
+/// Chooses the hardware listed in a file when one is assumed, or else live detection,
+/// which is recorded in the detection cache, or that cache when running offline. Either way, only the hardware of the
+/// vendors that `vendor_filter` permits is reported.
+pub fn hardware_detector(
+    offline: bool,
+    assumed_hardware: Option<&Path>,
+    detector_kind: DetectorKind,
+    vendor_filter: VendorFilter,
+) -> Result<Box<dyn HardwareDetector>, Error> {
+    let hardware_detector: Box<dyn HardwareDetector> =
+        if let Some(assumed_hardware) = assumed_hardware {
+            Box::new(AssumedDetector::from_path(assumed_hardware)?)
+        } else if offline {
+            Box::new(CachedDetector::from_cache_path(
+                paths::detection_cache_path(),
+            )?)
+        } else {
+            let live_detector: Box<dyn HardwareDetector> = match detector_kind {
+                DetectorKind::Native => Box::new(DevicesDetector::new()),
+                DetectorKind::Lspci => Box::new(LspciDetector::new()),
+                DetectorKind::Modalias => Box::new(ModaliasDetector::new()),
+            };
+            Box::new(CachedDetector::refreshing(
+                live_detector,
+                paths::detection_cache_path(),
+            ))
+        };
+    if vendor_filter.is_empty() {
+        Ok(hardware_detector)
+    } else {
+        Ok(Box::new(VendorFilteredDetector {
+            inner: hardware_detector,
+            vendor_filter,
+        }))
+    }
+}
+
+/// Reports a fixed, synthetic set of hardware IDs instead of enumerating the system.
+#[derive(Clone, Default, Debug)]
+pub struct MockDetector {
+    pub hardware_ids: BTreeSet<HardwareId>,
+    pub dmi_identity: Option<DmiIdentity>,
+    pub modaliases: Option<BTreeSet<String>>,
+}
+
+impl MockDetector {
+    pub fn new<T: IntoIterator<Item = HardwareId>>(hardware_ids: T) -> Self {
+        MockDetector {
+            hardware_ids: hardware_ids.into_iter().collect(),
+            dmi_identity: None,
+            modaliases: None,
+        }
+    }
+
+    pub fn with_dmi_identity(self, dmi_identity: DmiIdentity) -> Self {
+        MockDetector {
+            dmi_identity: Some(dmi_identity),
+            ..self
+        }
+    }
+
+    pub fn with_modaliases<T: IntoIterator<Item = &'static str>>(self, modaliases: T) -> Self {
+        MockDetector {
+            modaliases: Some(modaliases.into_iter().map(String::from).collect()),
+            ..self
+        }
+    }
+}
+
+impl HardwareDetector for MockDetector {
+    fn detect(&self) -> BTreeSet<HardwareId> {
+        self.hardware_ids.clone()
+    }
+
+    fn dmi_identity(&self) -> Option<DmiIdentity> {
+        self.dmi_identity.clone()
+    }
+
+    fn modaliases(&self) -> Option<BTreeSet<String>> {
+        self.modaliases.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::TestDirectory;
+
+    #[test]
+    pub fn lspci_and_lsusb_output() {
+        let lspci = "\
+00:02.0 VGA compatible controller [0300]: Intel Corporation HD Graphics 630 [8086:5912] (rev 04)
+01:00.0 VGA compatible controller [0300]: NVIDIA Corporation GP104 [GeForce GTX 1080] [10de:1b80] (rev a1)
+02:00.0 Network controller [0280]: Intel Corporation Wireless 8265 / 8275 [8086:24fd]
+garbage
+";
+        assert_eq!(
+            parse_lspci(lspci).collect::<Vec<HardwareId>>(),
+            [
+                HardwareId::Pci(PciId {
+                    vendor: 0x8086,
+                    device: 0x5912,
+                    revision: Some(0x04),
+                }),
+                HardwareId::Pci(PciId {
+                    vendor: 0x10de,
+                    device: 0x1b80,
+                    revision: Some(0xa1),
+                }),
+                HardwareId::Pci(PciId {
+                    vendor: 0x8086,
+                    device: 0x24fd,
+                    revision: None,
+                }),
+            ]
+        );
+
+        let lsusb = "\
+Bus 002 Device 001: ID 1d6b:0003 Linux Foundation 3.0 root hub
+Bus 001 Device 002: ID 0bda:8179 Realtek Semiconductor Corp. RTL8188EUS 802.11n Wireless Network Adapter
+";
+        assert_eq!(
+            parse_lsusb(lsusb).collect::<Vec<HardwareId>>(),
+            [
+                HardwareId::Usb(UsbId {
+                    vendor: 0x1d6b,
+                    device: 0x0003,
+                }),
+                HardwareId::Usb(UsbId {
+                    vendor: 0x0bda,
+                    device: 0x8179,
+                }),
+            ]
+        );
+    }
+
+    #[test]
+    pub fn modaliases() {
+        assert_eq!(
+            modalias_hardware_id("pci:v000010DEd00001B80sv00001458sd00003702bc03sc00i00"),
+            Some(HardwareId::Pci(PciId {
+                vendor: 0x10de,
+                device: 0x1b80,
+                revision: None,
+            }))
+        );
+        assert_eq!(
+            modalias_hardware_id("usb:v0BDAp8179d0000dc00dsc00dp00icFFiscFFipFFin00"),
+            Some(HardwareId::Usb(UsbId {
+                vendor: 0x0bda,
+                device: 0x8179,
+            }))
+        );
+        assert_eq!(modalias_hardware_id("acpi:PNP0C0A:"), None);
+
+        let modalias = "pci:v000010DEd00001B80sv00001458sd00003702bc03sc00i00";
+        assert!(modalias_matches("pci:v000010DEd*sv*sd*bc03sc*i*", modalias));
+        assert!(modalias_matches("pci:v000010ded00001b80*", modalias));
+        assert!(modalias_matches("pci:v000010DEd0000????sv*", modalias));
+        assert!(!modalias_matches("pci:v000010DEd*bc02*", modalias));
+        assert!(!modalias_matches("pci:v000010DEd00001B80", modalias));
+    }
+
+    #[test]
+    pub fn usb_devices_are_read_from_sysfs_when_lsusb_finds_none() {
+        let directory = TestDirectory::new("sysfs-usb");
+        for (device, ids) in [
+            ("usb1", Some(("1d6b", "0002"))),
+            ("1-1", Some(("0bda", "8179"))),
+            ("1-1:1.0", None),
+        ] {
+            let device_directory = directory.join(device);
+            fs::create_dir_all(&device_directory).unwrap();
+            if let Some((vendor, product)) = ids {
+                fs::write(device_directory.join("idVendor"), format!("{}\n", vendor)).unwrap();
+                fs::write(device_directory.join("idProduct"), format!("{}\n", product)).unwrap();
+            }
+        }
+        let gpu = HardwareId::Pci(PciId {
+            vendor: 0x10de,
+            device: 0x1b80,
+            revision: None,
+        });
+        let wireless_adapter = HardwareId::Usb(UsbId {
+            vendor: 0x0bda,
+            device: 0x8179,
+        });
+
+        let completed = with_sysfs_usb_ids(BTreeSet::from([gpu.clone()]), &directory);
+        assert_eq!(completed.len(), 3);
+        assert!(completed.contains(&wireless_adapter));
+
+        // USB devices that were detected are trusted as they are
+        let detected = BTreeSet::from([
+            gpu.clone(),
+            HardwareId::Usb(UsbId {
+                vendor: 0x046d,
+                device: 0xc52b,
+            }),
+        ]);
+        assert_eq!(with_sysfs_usb_ids(detected.clone(), &directory), detected);
+        assert_eq!(
+            with_sysfs_usb_ids(BTreeSet::from([gpu.clone()]), &directory.join("missing")),
+            BTreeSet::from([gpu])
+        );
+    }
+
+    #[test]
+    pub fn vendor_filters_leave_out_hardware() {
+        let nvidia = HardwareId::Pci(PciId {
+            vendor: 0x10de,
+            device: 0x1b80,
+            revision: None,
+        });
+        let intel = HardwareId::Pci(PciId {
+            vendor: 0x8086,
+            device: 0x3e92,
+            revision: None,
+        });
+        let logitech = HardwareId::Usb(UsbId {
+            vendor: 0x046d,
+            device: 0xc52b,
+        });
+        let detected = |vendor_filter: VendorFilter| {
+            VendorFilteredDetector {
+                inner: Box::new(MockDetector::new([
+                    nvidia.clone(),
+                    intel.clone(),
+                    logitech.clone(),
+                ])),
+                vendor_filter,
+            }
+            .detect()
+        };
+        assert_eq!(
+            detected(VendorFilter {
+                allow: BTreeSet::from([0x10de, 0x8086]),
+                deny: BTreeSet::from([0x8086]),
+            }),
+            BTreeSet::from([nvidia.clone()])
+        );
+        assert_eq!(
+            detected(VendorFilter {
+                deny: BTreeSet::from([0x046d]),
+                ..Default::default()
+            }),
+            BTreeSet::from([nvidia, intel])
+        );
+    }
+
+    #[test]
+    pub fn pci_revisions_are_read_from_the_domain_of_the_device() {
+        let directory = TestDirectory::new("pci-revision");
+        let write_device = |name: &str, device: &str, revision: &str| {
+            let device_directory = directory.join(name);
+            fs::create_dir_all(&device_directory).unwrap();
+            fs::write(device_directory.join("vendor"), "0x10de\n").unwrap();
+            fs::write(device_directory.join("device"), device).unwrap();
+            fs::write(device_directory.join("revision"), revision).unwrap();
+        };
+        write_device("0000:01:00.0", "0x1b80\n", "0xa1\n");
+        write_device("0001:01:00.0", "0x2684\n", "0xa2\n");
+
+        assert_eq!(
+            pci_revision(&directory, (1, 0, 0), (0x10de, 0x2684)),
+            Some(0xa2)
+        );
+        assert_eq!(
+            pci_revision(&directory, (1, 0, 0), (0x10de, 0x1b80)),
+            Some(0xa1)
+        );
+        assert_eq!(pci_revision(&directory, (2, 0, 0), (0x10de, 0x1b80)), None);
+    }
+
+    #[test]
+    pub fn live_detections_are_recorded_for_offline_runs() {
+        let directory = TestDirectory::new("detection-cache");
+        let cache_path = directory
+            .join("cache")
+            .join(paths::DETECTION_CACHE_FILENAME);
+        let nvidia = HardwareId::Pci(PciId {
+            vendor: 0x10de,
+            device: 0x1b80,
+            revision: None,
+        });
+        assert!(CachedDetector::from_cache_path(cache_path.clone()).is_err());
+
+        let refreshing = CachedDetector::refreshing(
+            Box::new(MockDetector::new([nvidia.clone()])),
+            cache_path.clone(),
+        );
+        assert!(!cache_path.exists());
+        assert_eq!(refreshing.detect(), BTreeSet::from([nvidia.clone()]));
+        assert_eq!(
+            CachedDetector::from_cache_path(cache_path)
+                .unwrap()
+                .detect(),
+            BTreeSet::from([nvidia])
+        );
+    }
+}
@@ -2,20 +2,138 @@ use std::path::PathBuf;
 
 use snafu::Snafu;
 
+use crate::data::{
+    database::SCHEMA_VERSION,
+    input_file::{HardwareKind, ScriptKind},
+};
+
+/// An error of a library the program uses, kept opaque so that the libraries are not
+/// part of the public interface and can be upgraded without breaking it.
+pub type ExternalError = Box<dyn std::error::Error + Send + Sync>;
+
+/// The errors of all actions. New variants are added as features grow, so matching
+/// on it needs a wildcard arm.
 #[derive(Debug, Snafu)]
 #[snafu(visibility(pub))]
+#[non_exhaustive]
 pub enum Error {
     #[snafu(display("The driver database encountered an error. More details: {source}"))]
-    Database { source: jammdb::Error },
+    Database {
+        #[snafu(source(from(jammdb::Error, Box::new)))]
+        source: ExternalError,
+    },
+
+    #[snafu(display(
+        "The database at {} is not writable. Choose a writable path for the database file, or run with superuser privileges...",
+        path.to_string_lossy()
+    ))]
+    DatabaseNotWritable { path: PathBuf },
+
+    #[snafu(
+        display("A working copy of the database could not be created at {}. More details: {}", path.to_string_lossy(), source)
+    )]
+    TemporaryCopy {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+
+    #[snafu(
+        display("The database file {} could not be opened for reading. More details: {}", path.to_string_lossy(), source)
+    )]
+    DatabaseOpen {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+
+    #[snafu(
+        display("The database backup at {} could not be accessed. More details: {}", path.to_string_lossy(), source)
+    )]
+    DatabaseBackup {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+
+    #[snafu(display("No backups of the database at {} were found...", path.to_string_lossy()))]
+    NoDatabaseBackup { path: PathBuf },
+
+    #[snafu(
+        display("The checksum of the database at {} could not be read or written. More details: {}", path.to_string_lossy(), source)
+    )]
+    DatabaseChecksum {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+
+    #[snafu(display(
+        "The database at {} {}, but this version of the program reads layout {}. Regenerate it with `generate-database`...",
+        path.to_string_lossy(),
+        match found {
+            Some(found) => format!("has layout version {}", found),
+            None => String::from("records no layout version, so it was written by an older version of the program or is not a driver database"),
+        },
+        SCHEMA_VERSION
+    ))]
+    DatabaseSchemaMismatch { path: PathBuf, found: Option<u32> },
+
+    #[snafu(display(
+        "The database at {} does not match its checksum, expected {expected} but found {actual}. It may be corrupted or have been tampered with, so regenerate or restore it...",
+        path.to_string_lossy()
+    ))]
+    DatabaseChecksumMismatch {
+        path: PathBuf,
+        expected: String,
+        actual: String,
+    },
+
+    #[snafu(
+        display("The input file at {} could not be read. More details: {}", path.to_string_lossy(), source)
+    )]
+    InputFileRead {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+
+    #[snafu(
+        display("{} of the input files in {} could not be parsed, so nothing was generated. Fix them, or leave them out with --skip-errors:\n{}", errors.len(), path.to_string_lossy(), errors.join("\n"))
+    )]
+    InputDirectoryInvalid { path: PathBuf, errors: Vec<String> },
 
     #[snafu(
         display("The input file at {} could not be parsed for driver data. More details: {}", path.to_string_lossy(), source)
     )]
     InputFileParse {
         path: PathBuf,
-        source: serde_yaml::Error,
+        #[snafu(source(from(serde_yaml::Error, Box::new)))]
+        source: ExternalError,
     },
 
+    #[snafu(
+        display("The input file at {} could not be parsed for driver data. More details: {}", path.to_string_lossy(), source)
+    )]
+    InputFileJsonParse {
+        path: PathBuf,
+        #[snafu(source(from(serde_json::Error, Box::new)))]
+        source: ExternalError,
+    },
+
+    #[snafu(
+        display("The file {} included from {} could not be read. More details: {}", included.to_string_lossy(), path.to_string_lossy(), source)
+    )]
+    InputFileInclude {
+        path: PathBuf,
+        included: PathBuf,
+        source: std::io::Error,
+    },
+
+    #[snafu(display("An `!include` in {} does not name a file. Write it like `!include options.yaml`...", path.to_string_lossy()))]
+    InputFileIncludeInvalid { path: PathBuf },
+
+    #[snafu(display("The file {} included from {} is outside of its directory. Only files next to the including file or in its subdirectories can be included...", included.to_string_lossy(), path.to_string_lossy()))]
+    InputFileIncludeOutside { path: PathBuf, included: PathBuf },
+
+    #[snafu(display("The input files include each other in a cycle: {}", chain.iter().map(|path| path.to_string_lossy()).collect::<Vec<_>>().join(" -> ")))]
+    InputFileIncludeCycle { chain: Vec<PathBuf> },
+
     #[snafu(
         display("The value {value} could not be converted to the enumeration {enum_name}. The allowed values are {allowed_values:?}")
     )]
@@ -25,6 +143,316 @@ pub enum Error {
         allowed_values: Vec<String>,
     },
 
+    #[snafu(display(
+        "The value {value} is neither a duration like `7d` nor a date like `2023-06-01`."
+    ))]
+    InvalidSince { value: String },
+
+    #[snafu(
+        display("The hardware detected earlier could not be read from {}. Run once without `--offline` to record it. More details: {}", path.to_string_lossy(), source)
+    )]
+    DetectionCache {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+
+    #[snafu(display("The assumed hardware could not be read from {}. More details: {}", path.to_string_lossy(), source))]
+    AssumedHardwareRead {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+
+    #[snafu(display("Line {line_number} of {} is not a hardware ID like `PCI 10de:1b80`: {reason}", path.to_string_lossy()))]
+    AssumedHardwareParse {
+        path: PathBuf,
+        line_number: usize,
+        reason: String,
+    },
+
+    #[snafu(display(
+        "The remote driver catalog at {url} could not be fetched. More details: {source}"
+    ))]
+    RemoteIndex {
+        url: String,
+        #[snafu(source(from(reqwest::Error, Box::new)))]
+        source: ExternalError,
+    },
+
+    #[snafu(display(
+        "The remote driver catalog at {url} could not be parsed for driver data. More details: {source}"
+    ))]
+    RemoteIndexParse {
+        url: String,
+        #[snafu(source(from(serde_yaml::Error, Box::new)))]
+        source: ExternalError,
+    },
+
+    #[snafu(display(
+        "The remote driver catalog at {url} uses `!include`, which only local input files can..."
+    ))]
+    RemoteIndexInclude { url: String },
+
+    #[snafu(
+        display("The cached remote driver catalog at {} could not be accessed. More details: {}", path.to_string_lossy(), source)
+    )]
+    RemoteIndexCache {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+
+    #[snafu(display("{operation} needs network access, which `--offline` disables..."))]
+    OfflineRequired { operation: String },
+
+    #[snafu(display("Could not get superuser privileges, because {reason}..."))]
+    PrivilegeEscalationFailed { reason: String },
+
+    #[snafu(
+        display("The hook {} could not be run. More details: {}", path.to_string_lossy(), source)
+    )]
+    HookExecution {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+
+    #[snafu(display("The hook {} failed with {status}, so the install was stopped...{}", path.to_string_lossy(), output.iter().map(|line| format!("\n    {}", line)).collect::<String>()))]
+    HookFailed {
+        path: PathBuf,
+        status: String,
+        /// The last lines the hook wrote, when its output was captured.
+        output: Vec<String>,
+    },
+
+    #[snafu(display("The post-install hook {} failed with {status}. The packages are installed, but the driver may not be set up completely. Pass --continue-on-script-error to only warn about failing post-install hooks...{}", path.to_string_lossy(), output.iter().map(|line| format!("\n    {}", line)).collect::<String>()))]
+    PostInstallHookFailed {
+        path: PathBuf,
+        status: String,
+        /// The last lines the hook wrote, when its output was captured.
+        output: Vec<String>,
+    },
+
+    #[snafu(display("The hook {} was killed after running for {seconds} seconds. Pass a longer --script-timeout if it needs more time...", path.to_string_lossy()))]
+    ScriptTimeout { path: PathBuf, seconds: u64 },
+
+    #[snafu(display("The kernel module {} could not be signed: {reason}", path.to_string_lossy()))]
+    ModuleSigningFailed { path: PathBuf, reason: String },
+
+    #[snafu(display("The Machine Owner Key file {} does not exist, so the kernel modules could not be signed. Nothing was installed...", path.to_string_lossy()))]
+    SigningKeyMissing { path: PathBuf },
+
+    #[snafu(display("libalpm {found} is not supported. This build works with libalpm {supported}. Please update archlinux-driver-manager..."))]
+    UnsupportedAlpm { found: String, supported: String },
+
     #[snafu(display("Package {name} was not found..."))]
     PackageNotFound { name: String },
+
+    #[snafu(display("The package databases could not be refreshed. More details: {source}"))]
+    DatabaseRefresh {
+        #[snafu(source(from(alpm::Error, Box::new)))]
+        source: ExternalError,
+    },
+
+    #[snafu(display("The install would overwrite files that are already on the system, so nothing was changed:\n\t{}", conflicts.join("\n\t")))]
+    FileConflict { conflicts: Vec<String> },
+
+    #[snafu(display(
+        "The package transaction failed, so nothing was changed. More details: {source}"
+    ))]
+    Transaction {
+        #[snafu(source(from(alpm::Error, Box::new)))]
+        source: ExternalError,
+    },
+
+    #[snafu(display("The repository {name} is not listed under extra_repositories in the configuration file {}...", path.to_string_lossy()))]
+    UnknownRepository { name: String, path: PathBuf },
+
+    #[snafu(display(
+        "The server {server} of the repository {repository} cannot be used: {reason}..."
+    ))]
+    InvalidRepositoryServer {
+        repository: String,
+        server: String,
+        reason: String,
+    },
+
+    #[snafu(display("The database of the repository {repository} could not be downloaded from {url}. More details: {source}"))]
+    RepositoryDownload {
+        repository: String,
+        url: String,
+        #[snafu(source(from(reqwest::Error, Box::new)))]
+        source: ExternalError,
+    },
+
+    #[snafu(display(
+        "The database of the repository {repository} could not be set up. More details: {source}"
+    ))]
+    RepositoryRegister {
+        repository: String,
+        #[snafu(source(from(alpm::Error, Box::new)))]
+        source: ExternalError,
+    },
+
+    #[snafu(display("The database of the repository could not be saved to {}. More details: {}", path.to_string_lossy(), source))]
+    RepositoryDatabaseWrite {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+
+    #[snafu(display("The database of the repository {repository} already exists at {}, so it is left alone. Enable the repository in {} instead, or remove the file if it is left over from when the repository was enabled...", path.to_string_lossy(), crate::arch::PACMAN_CONFIG_PATH))]
+    RepositoryDatabaseExists { repository: String, path: PathBuf },
+
+    #[snafu(display("No driver option named {name} matches the detected hardware..."))]
+    DriverOptionNotFound { name: String },
+
+    #[snafu(display(
+        "No driver option in the database has the tags {}, so they may be misspelled. See `list-tags` for the tags in use...",
+        tags.join(", ")
+    ))]
+    UnknownTags { tags: Vec<String> },
+
+    #[snafu(display("The database has no driver option named {name} for {hardware}..."))]
+    UnknownDriverOption {
+        hardware: HardwareKind,
+        name: String,
+    },
+
+    #[snafu(display("No driver options for {hardware} match the detected hardware..."))]
+    NoDriverOption { hardware: HardwareKind },
+
+    #[snafu(display("There is no driver option at rank {rank} for {hardware}, only ranks 1 to {available} match the detected hardware..."))]
+    RankOutOfRange {
+        hardware: HardwareKind,
+        rank: usize,
+        available: usize,
+    },
+
+    #[snafu(display("The installation of {driver_option} was cancelled..."))]
+    InstallCancelled { driver_option: String },
+
+    #[snafu(display(
+        "{name:?} is not a valid profile name. Profile names cannot be empty or contain `/`..."
+    ))]
+    InvalidProfileName { name: String },
+
+    #[snafu(display("The configuration file {} could not be read. More details: {}", path.to_string_lossy(), source))]
+    ConfigRead {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+
+    #[snafu(display("The configuration file {} could not be parsed. More details: {}", path.to_string_lossy(), source))]
+    ConfigParse {
+        path: PathBuf,
+        #[snafu(source(from(serde_yaml::Error, Box::new)))]
+        source: ExternalError,
+    },
+
+    #[snafu(display("The profile {} could not be read. More details: {}", path.to_string_lossy(), source))]
+    ProfileRead {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+
+    #[snafu(display("The profile {} could not be parsed. More details: {}", path.to_string_lossy(), source))]
+    ProfileParse {
+        path: PathBuf,
+        #[snafu(source(from(serde_yaml::Error, Box::new)))]
+        source: ExternalError,
+    },
+
+    #[snafu(display("The profile {} could not be saved. More details: {}", path.to_string_lossy(), source))]
+    ProfileWrite {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+
+    #[snafu(display("The install manifest {} could not be read. More details: {}", path.to_string_lossy(), source))]
+    ManifestRead {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+
+    #[snafu(display("The install manifest {} could not be parsed. More details: {}", path.to_string_lossy(), source))]
+    ManifestParse {
+        path: PathBuf,
+        #[snafu(source(from(serde_yaml::Error, Box::new)))]
+        source: ExternalError,
+    },
+
+    #[snafu(display("The install manifest {} could not be saved. More details: {}", path.to_string_lossy(), source))]
+    ManifestWrite {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+
+    #[snafu(display(
+        "The profile selects {driver_option} for {hardware}, which is not in the database..."
+    ))]
+    ProfileDriverOptionMissing {
+        hardware: HardwareKind,
+        driver_option: String,
+    },
+
+    #[snafu(display("The install plan {} could not be read. More details: {}", path.to_string_lossy(), source))]
+    InstallPlanRead {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+
+    #[snafu(display("The driver option could not be edited at {}. More details: {}", path.to_string_lossy(), source))]
+    DriverOptionEdit {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+
+    #[snafu(display(
+        "The {kind} interpreter `{}` is not installed, so the install scripts of the driver option cannot be run...",
+        kind.interpreter()
+    ))]
+    MissingInterpreter { kind: ScriptKind },
+
+    #[snafu(display("The editor exited with {status}, so nothing was installed..."))]
+    EditorFailed { status: String },
+
+    #[snafu(display(
+        "The edited driver option is not valid, so nothing was installed. More details: {source}"
+    ))]
+    EditedDriverOptionParse {
+        #[snafu(source(from(serde_yaml::Error, Box::new)))]
+        source: ExternalError,
+    },
+
+    #[snafu(display("The install plan {} could not be parsed. More details: {}", path.to_string_lossy(), source))]
+    InstallPlanParse {
+        path: PathBuf,
+        #[snafu(source(from(serde_yaml::Error, Box::new)))]
+        source: ExternalError,
+    },
+
+    #[snafu(display(
+        "The install plan {} does not fit this system, so nothing was installed: {}",
+        path.to_string_lossy(),
+        problems.join("; ")
+    ))]
+    InstallPlanInvalid {
+        path: PathBuf,
+        problems: Vec<String>,
+    },
+
+    #[snafu(display("The install log {} could not be read. More details: {}", path.to_string_lossy(), source))]
+    InstallLogRead {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+
+    #[snafu(display("The install log {} could not be written. More details: {}", path.to_string_lossy(), source))]
+    InstallLogWrite {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+
+    #[snafu(display("The socket {} could not be listened on. More details: {}", path.to_string_lossy(), source))]
+    ServeSocket {
+        path: PathBuf,
+        source: std::io::Error,
+    },
 }
@@ -0,0 +1,562 @@
+use crate::{
+    cli::CommandlineOutputKind,
+    data::input_file::HardwareKind,
+    error::{
+        Error, HookExecutionSnafu, HookFailedSnafu, PostInstallHookFailedSnafu, ScriptTimeoutSnafu,
+    },
+};
+use owo_colors::{OwoColorize, Stream::Stderr};
+use serde::{Deserialize, Serialize};
+use snafu::ResultExt;
+use std::{
+    fmt, fs,
+    io::{self, Read},
+    os::unix::{fs::PermissionsExt, process::CommandExt},
+    path::{Path, PathBuf},
+    process::{Command, ExitStatus, Stdio},
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+
+/// The number of trailing lines of captured hook output kept when a hook fails.
+pub const CAPTURED_OUTPUT_LINES: usize = 20;
+
+/// How long a hook or verify command may run before it is killed, unless
+/// `--script-timeout` says otherwise.
+pub const DEFAULT_SCRIPT_TIMEOUT_SECONDS: u64 = 600;
+
+/// How often a running hook is checked on for its timeout.
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// When a hook runs relative to the package transaction of an install.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum HookPhase {
+    PreInstall,
+    PostInstall,
+}
+
+impl fmt::Display for HookPhase {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            HookPhase::PreInstall => write!(f, "pre-install"),
+            HookPhase::PostInstall => write!(f, "post-install"),
+        }
+    }
+}
+
+/// Where the output of hooks goes.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum HookOutput {
+    /// Hooks write to the terminal as they run.
+    #[default]
+    Stream,
+    /// Hook output is captured, so that it does not corrupt machine-readable output,
+    /// and only its last lines are reported if the hook fails.
+    Capture,
+}
+
+impl From<CommandlineOutputKind> for HookOutput {
+    fn from(output_kind: CommandlineOutputKind) -> Self {
+        match output_kind {
+            CommandlineOutputKind::Json | CommandlineOutputKind::Plain => HookOutput::Capture,
+            CommandlineOutputKind::Regular
+            | CommandlineOutputKind::Table
+            | CommandlineOutputKind::Debug => HookOutput::Stream,
+        }
+    }
+}
+
+/// The last lines of the standard output and standard error of a process, in that order.
+fn last_lines(stdout: &[u8], stderr: &[u8], count: usize) -> Vec<String> {
+    let lines: Vec<String> = [stdout, stderr]
+        .iter()
+        .flat_map(|output| {
+            String::from_utf8_lossy(output)
+                .lines()
+                .map(String::from)
+                .collect::<Vec<String>>()
+        })
+        .collect();
+    lines[lines.len().saturating_sub(count)..].to_vec()
+}
+
+/// Reads a pipe of a child process to the end on another thread, so that the child
+/// does not block on a full pipe while it is waited for.
+fn read_in_background<R: Read + Send + 'static>(pipe: Option<R>) -> JoinHandle<Vec<u8>> {
+    thread::spawn(move || {
+        let mut output = Vec::new();
+        if let Some(mut pipe) = pipe {
+            _ = pipe.read_to_end(&mut output);
+        }
+        output
+    })
+}
+
+/// Runs a hook command, and kills it along with the processes it started if it is
+/// still running after the timeout. Returns `None` when it was killed.
+fn run_with_timeout(
+    command: &mut Command,
+    hook_output: HookOutput,
+    timeout: Option<Duration>,
+) -> io::Result<Option<(ExitStatus, Vec<String>)>> {
+    if hook_output == HookOutput::Capture {
+        command
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped());
+    }
+    // A process group of its own lets the processes the hook starts be killed with it
+    let mut child = command.process_group(0).spawn()?;
+    let stdout = read_in_background(child.stdout.take());
+    let stderr = read_in_background(child.stderr.take());
+    let started = Instant::now();
+    let status = loop {
+        if let Some(status) = child.try_wait()? {
+            break status;
+        }
+        if timeout.is_some_and(|timeout| started.elapsed() >= timeout) {
+            // SAFETY: killpg has no preconditions, and the group is the one of the hook
+            unsafe { libc::killpg(child.id() as libc::pid_t, libc::SIGKILL) };
+            _ = child.wait();
+            // Processes that left the group of the hook may still hold the pipes open,
+            // so the readers are left to finish on their own
+            return Ok(None);
+        }
+        thread::sleep(POLL_INTERVAL);
+    };
+    let output = match hook_output {
+        HookOutput::Stream => Vec::new(),
+        HookOutput::Capture => last_lines(
+            &stdout.join().unwrap_or_default(),
+            &stderr.join().unwrap_or_default(),
+            CAPTURED_OUTPUT_LINES,
+        ),
+    };
+    Ok(Some((status, output)))
+}
+
+/// What a hook is told about the install, through `ADM_*` environment variables.
+#[derive(Clone, Debug)]
+pub struct HookEnvironment<'a> {
+    pub hardware: HardwareKind,
+    pub driver_option: &'a str,
+    pub packages_to_install: &'a [String],
+    pub packages_to_remove: &'a [String],
+}
+
+impl HookEnvironment<'_> {
+    fn variables(&self, phase: HookPhase) -> [(&'static str, String); 5] {
+        [
+            ("ADM_PHASE", phase.to_string()),
+            ("ADM_HARDWARE", self.hardware.name().to_string()),
+            ("ADM_DRIVER", self.driver_option.to_owned()),
+            ("ADM_PACKAGES", self.packages_to_install.join(" ")),
+            ("ADM_REMOVED_PACKAGES", self.packages_to_remove.join(" ")),
+        ]
+    }
+}
+
+/// The executable files in the directory of a phase, in lexical order.
+pub fn hooks(hooks_directory: &Path, phase: HookPhase) -> Vec<PathBuf> {
+    let mut hooks: Vec<PathBuf> = fs::read_dir(hooks_directory.join(phase.to_string()))
+        .map(|entries| {
+            entries
+                .filter_map(|entry| entry.ok())
+                .map(|entry| entry.path())
+                .filter(|path| {
+                    fs::metadata(path).is_ok_and(|metadata| {
+                        metadata.is_file() && metadata.permissions().mode() & 0o111 != 0
+                    })
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+    hooks.sort();
+    hooks
+}
+
+/// Runs the hooks of a phase one after another, and stops at the first one that fails
+/// or runs longer than the timeout. Failing post-install hooks can instead be only
+/// warned about, since the packages are installed by then, in which case they are
+/// returned as descriptions of the failures.
+pub fn run_hooks(
+    hooks_directory: &Path,
+    phase: HookPhase,
+    environment: &HookEnvironment,
+    hook_output: HookOutput,
+    timeout: Option<Duration>,
+    continue_on_post_install_error: bool,
+) -> Result<Vec<String>, Error> {
+    let mut failures = Vec::<String>::new();
+    for hook in hooks(hooks_directory, phase) {
+        let mut command = Command::new(&hook);
+        command.envs(environment.variables(phase));
+        let seconds = timeout.unwrap_or_default().as_secs();
+        let (status, output) = match run_with_timeout(&mut command, hook_output, timeout)
+            .context(HookExecutionSnafu { path: &hook })?
+        {
+            Some(finished) => finished,
+            None if phase == HookPhase::PostInstall && continue_on_post_install_error => {
+                let failure = format!(
+                    "The post-install hook {} was killed after running for {} seconds",
+                    hook.to_string_lossy(),
+                    seconds
+                );
+                eprintln!(
+                    "{} {}",
+                    "WARNING:".if_supports_color(Stderr, |text| text.yellow()),
+                    failure
+                );
+                failures.push(failure);
+                continue;
+            }
+            None => {
+                return ScriptTimeoutSnafu {
+                    path: hook,
+                    seconds,
+                }
+                .fail()
+            }
+        };
+        if status.success() {
+            continue;
+        }
+        match phase {
+            HookPhase::PreInstall => {
+                return HookFailedSnafu {
+                    path: hook,
+                    status: status.to_string(),
+                    output,
+                }
+                .fail();
+            }
+            HookPhase::PostInstall if continue_on_post_install_error => {
+                let failure = format!(
+                    "The post-install hook {} failed with {}",
+                    hook.to_string_lossy(),
+                    status
+                );
+                eprintln!(
+                    "{} {}",
+                    "WARNING:".if_supports_color(Stderr, |text| text.yellow()),
+                    failure
+                );
+                for line in output {
+                    eprintln!("    {}", line);
+                }
+                failures.push(failure);
+            }
+            HookPhase::PostInstall => {
+                return PostInstallHookFailedSnafu {
+                    path: hook,
+                    status: status.to_string(),
+                    output,
+                }
+                .fail();
+            }
+        }
+    }
+    Ok(failures)
+}
+
+/// The outcome of the verification command of an installed driver option.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Verification {
+    pub command: String,
+    pub passed: bool,
+    /// The exit status, or why the command could not be run.
+    pub status: String,
+    /// The last lines of output, when hook output is captured.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub output: Vec<String>,
+}
+
+/// Runs the verification command of a driver option with `sh -c`, with the same
+/// environment as the post-install hooks and `ADM_PHASE` set to `verify`. A failing
+/// command does not fail the install, since the packages are installed by then.
+pub fn run_verify_command(
+    verify_command: &str,
+    environment: &HookEnvironment,
+    hook_output: HookOutput,
+    timeout: Option<Duration>,
+) -> Verification {
+    let mut command = Command::new("sh");
+    command
+        .arg("-c")
+        .arg(verify_command)
+        .envs(environment.variables(HookPhase::PostInstall))
+        .env("ADM_PHASE", "verify");
+    let (passed, status, output) = match run_with_timeout(&mut command, hook_output, timeout) {
+        Ok(Some((status, output))) => (status.success(), status.to_string(), output),
+        Ok(None) => (
+            false,
+            format!(
+                "killed after running for {} seconds",
+                timeout.unwrap_or_default().as_secs()
+            ),
+            Vec::new(),
+        ),
+        Err(error) => (false, format!("could not be run: {}", error), Vec::new()),
+    };
+    Verification {
+        command: verify_command.to_owned(),
+        passed,
+        status,
+        output,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::TestDirectory;
+
+    fn write_hook(path: PathBuf, script: &str) {
+        fs::write(&path, format!("#!/bin/sh\n{}\n", script)).unwrap();
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o755)).unwrap();
+    }
+
+    #[test]
+    pub fn hooks_run_in_order_and_pre_install_failures_abort() {
+        let directory = TestDirectory::new("hooks");
+        let pre_install_directory = directory.join("pre-install");
+        fs::create_dir_all(&pre_install_directory).unwrap();
+        let log_file = directory.join("log");
+
+        let log_line = format!(
+            "echo \"$ADM_PHASE $ADM_DRIVER $ADM_PACKAGES\" >> {}",
+            log_file.to_string_lossy()
+        );
+        write_hook(pre_install_directory.join("20-second"), &log_line);
+        write_hook(pre_install_directory.join("10-first"), &log_line);
+        fs::write(pre_install_directory.join("00-not-executable"), "exit 1").unwrap();
+
+        let packages_to_install = vec!["nvidia".to_string(), "nvidia-utils".to_string()];
+        let environment = HookEnvironment {
+            hardware: HardwareKind::Graphics,
+            driver_option: "Nvidia",
+            packages_to_install: &packages_to_install,
+            packages_to_remove: &[],
+        };
+        run_hooks(
+            &directory,
+            HookPhase::PreInstall,
+            &environment,
+            HookOutput::Stream,
+            None,
+            false,
+        )
+        .unwrap();
+        run_hooks(
+            &directory,
+            HookPhase::PostInstall,
+            &environment,
+            HookOutput::Stream,
+            None,
+            false,
+        )
+        .unwrap();
+        assert_eq!(
+            fs::read_to_string(&log_file).unwrap(),
+            "pre-install Nvidia nvidia nvidia-utils\n".repeat(2)
+        );
+
+        write_hook(pre_install_directory.join("15-veto"), "exit 3");
+        assert!(matches!(
+            run_hooks(
+                &directory,
+                HookPhase::PreInstall,
+                &environment,
+                HookOutput::Stream,
+                None,
+                false
+            ),
+            Err(Error::HookFailed { .. })
+        ));
+        assert_eq!(fs::read_to_string(&log_file).unwrap().lines().count(), 3);
+    }
+
+    #[test]
+    pub fn captured_output_is_reported_on_failure() {
+        let directory = TestDirectory::new("hook-output");
+        let pre_install_directory = directory.join("pre-install");
+        fs::create_dir_all(&pre_install_directory).unwrap();
+        write_hook(
+            pre_install_directory.join("10-noisy"),
+            "seq 1 30\necho 'no space left' >&2\nexit 1",
+        );
+
+        let environment = HookEnvironment {
+            hardware: HardwareKind::Graphics,
+            driver_option: "Nvidia",
+            packages_to_install: &[],
+            packages_to_remove: &[],
+        };
+        match run_hooks(
+            &directory,
+            HookPhase::PreInstall,
+            &environment,
+            HookOutput::Capture,
+            None,
+            false,
+        ) {
+            Err(Error::HookFailed { output, .. }) => {
+                assert_eq!(output.len(), CAPTURED_OUTPUT_LINES);
+                assert_eq!(output.first().map(String::as_str), Some("12"));
+                assert_eq!(output.last().map(String::as_str), Some("no space left"));
+            }
+            result => panic!("expected the hook to fail, got {:?}", result),
+        }
+    }
+
+    #[test]
+    pub fn verify_commands_report_without_failing() {
+        let environment = HookEnvironment {
+            hardware: HardwareKind::Graphics,
+            driver_option: "Nvidia",
+            packages_to_install: &[],
+            packages_to_remove: &[],
+        };
+        let verification = run_verify_command(
+            "test \"$ADM_PHASE $ADM_DRIVER\" = 'verify Nvidia'",
+            &environment,
+            HookOutput::Capture,
+            None,
+        );
+        assert!(verification.passed);
+
+        let verification = run_verify_command(
+            "echo 'NVIDIA-SMI has failed' >&2; exit 9",
+            &environment,
+            HookOutput::Capture,
+            None,
+        );
+        assert!(!verification.passed);
+        assert_eq!(verification.output, ["NVIDIA-SMI has failed"]);
+    }
+
+    #[test]
+    pub fn post_install_failures_abort_unless_continuing() {
+        let directory = TestDirectory::new("post-install");
+        let post_install_directory = directory.join("post-install");
+        fs::create_dir_all(&post_install_directory).unwrap();
+        let log_file = directory.join("log");
+        write_hook(post_install_directory.join("10-cosmetic"), "exit 2");
+        write_hook(
+            post_install_directory.join("20-after"),
+            &format!("echo ran >> {}", log_file.to_string_lossy()),
+        );
+
+        let environment = HookEnvironment {
+            hardware: HardwareKind::Graphics,
+            driver_option: "Nvidia",
+            packages_to_install: &[],
+            packages_to_remove: &[],
+        };
+        assert!(matches!(
+            run_hooks(
+                &directory,
+                HookPhase::PostInstall,
+                &environment,
+                HookOutput::Capture,
+                None,
+                false
+            ),
+            Err(Error::PostInstallHookFailed { .. })
+        ));
+        assert!(!log_file.exists());
+
+        let failures = run_hooks(
+            &directory,
+            HookPhase::PostInstall,
+            &environment,
+            HookOutput::Capture,
+            None,
+            true,
+        )
+        .unwrap();
+        assert_eq!(failures.len(), 1);
+        assert!(failures[0].contains("10-cosmetic"), "{}", failures[0]);
+        assert_eq!(fs::read_to_string(&log_file).unwrap(), "ran\n");
+
+        // Continuing only applies after the packages are installed
+        let pre_install_directory = directory.join("pre-install");
+        fs::create_dir_all(&pre_install_directory).unwrap();
+        write_hook(pre_install_directory.join("10-veto"), "exit 1");
+        assert!(matches!(
+            run_hooks(
+                &directory,
+                HookPhase::PreInstall,
+                &environment,
+                HookOutput::Capture,
+                None,
+                true
+            ),
+            Err(Error::HookFailed { .. })
+        ));
+    }
+
+    #[test]
+    pub fn hung_hooks_are_killed_after_the_timeout() {
+        let directory = TestDirectory::new("hook-timeout");
+        let pre_install_directory = directory.join("pre-install");
+        fs::create_dir_all(&pre_install_directory).unwrap();
+        let sleep_pid_file = directory.join("sleep.pid");
+        write_hook(
+            pre_install_directory.join("10-hung"),
+            &format!("sleep 30 &\necho $! > {}\nwait", sleep_pid_file.display()),
+        );
+
+        let environment = HookEnvironment {
+            hardware: HardwareKind::Graphics,
+            driver_option: "Nvidia",
+            packages_to_install: &[],
+            packages_to_remove: &[],
+        };
+        let started = Instant::now();
+        for hook_output in [HookOutput::Stream, HookOutput::Capture] {
+            assert!(matches!(
+                run_hooks(
+                    &directory,
+                    HookPhase::PreInstall,
+                    &environment,
+                    hook_output,
+                    Some(Duration::from_millis(200)),
+                    false
+                ),
+                Err(Error::ScriptTimeout { .. })
+            ));
+        }
+        let verification = run_verify_command(
+            "sleep 30",
+            &environment,
+            HookOutput::Capture,
+            Some(Duration::from_millis(200)),
+        );
+        assert!(!verification.passed);
+        assert!(started.elapsed() < Duration::from_secs(10));
+        // The processes started by the hook are killed along with it
+        let sleep_pid = fs::read_to_string(&sleep_pid_file).unwrap();
+        thread::sleep(Duration::from_millis(100));
+        let sleep_state =
+            fs::read_to_string(format!("/proc/{}/stat", sleep_pid.trim())).map(|stat| {
+                stat.rsplit(") ")
+                    .next()
+                    .unwrap_or_default()
+                    .starts_with('Z')
+            });
+        assert!(sleep_state.unwrap_or(true));
+
+        // Hooks that finish in time are not affected
+        write_hook(pre_install_directory.join("10-hung"), "exit 0");
+        assert!(run_hooks(
+            &directory,
+            HookPhase::PreInstall,
+            &environment,
+            HookOutput::Capture,
+            Some(Duration::from_secs(10)),
+            false
+        )
+        .is_ok());
+    }
+}
@@ -1,12 +1,13 @@
-use lazy_static::lazy_static;
-
-lazy_static! {
-    pub static ref DB_PATH: &'static str = "/var/lib/archlinux-driver-manager/database.db";
-    pub static ref DB_PATH_TEMP: &'static str = "/tmp/archlinux-driver-manager/database.db";
-}
-
 pub mod actions;
 pub mod arch;
 pub mod cli;
 pub mod data;
+pub mod detection;
 pub mod error;
+pub mod hooks;
+pub mod module_signing;
+pub mod paths;
+pub mod privileges;
+pub mod services;
+#[cfg(test)]
+mod test_support;
@@ -1,6 +1,7 @@
 use archlinux_driver_manager::cli::*;
+use std::process::ExitCode;
 
-fn main() {
+fn main() -> ExitCode {
     let archlinux_driver_manager_app = CommandlineInterface::new();
-    archlinux_driver_manager_app.run();
+    archlinux_driver_manager_app.run()
 }
@@ -0,0 +1,216 @@
+use crate::error::{Error, ModuleSigningFailedSnafu, SigningKeyMissingSnafu};
+use std::{
+    fs,
+    io::ErrorKind,
+    path::{Path, PathBuf},
+    process::Command,
+    time::SystemTime,
+};
+
+pub const MODULES_DIRECTORY: &str = "/usr/lib/modules";
+
+/// A Machine Owner Key, enrolled with shim, to sign kernel modules with.
+#[derive(Clone, Debug)]
+pub struct SigningKey {
+    pub private_key: PathBuf,
+    pub certificate: PathBuf,
+}
+
+impl SigningKey {
+    /// Fails with `SigningKeyMissing` unless both files of the key exist, so that a wrong
+    /// path is caught before anything is installed. Files that cannot be looked at without
+    /// superuser privileges are checked again by the run through sudo.
+    pub fn check_exists(&self) -> Result<(), Error> {
+        for path in [&self.private_key, &self.certificate] {
+            if fs::metadata(path).is_err_and(|error| error.kind() == ErrorKind::NotFound) {
+                return SigningKeyMissingSnafu { path }.fail();
+            }
+        }
+        Ok(())
+    }
+}
+
+fn is_module(path: &Path) -> bool {
+    let file_name = path.to_string_lossy();
+    file_name.ends_with(".ko") || file_name.ends_with(".ko.zst")
+}
+
+/// The kernel modules under a directory that were written at or after a point in time,
+/// like the ones DKMS builds while packages are being installed.
+pub fn modules_changed_since(directory: &Path, since: SystemTime) -> Vec<PathBuf> {
+    let mut modules = Vec::<PathBuf>::new();
+    let Ok(entries) = fs::read_dir(directory) else {
+        return modules;
+    };
+    for entry in entries.filter_map(|entry| entry.ok()) {
+        let path = entry.path();
+        let Ok(metadata) = fs::symlink_metadata(&path) else {
+            continue;
+        };
+        if metadata.is_dir() {
+            modules.extend(modules_changed_since(&path, since));
+        } else if metadata.is_file()
+            && is_module(&path)
+            && metadata.modified().is_ok_and(|modified| modified >= since)
+        {
+            modules.push(path);
+        }
+    }
+    modules.sort();
+    modules
+}
+
+/// The `sign-file` tool of the kernel a module under `modules_directory` belongs to.
+fn sign_file_tool(modules_directory: &Path, module: &Path) -> Option<PathBuf> {
+    let kernel_version = module
+        .strip_prefix(modules_directory)
+        .ok()?
+        .components()
+        .next()?;
+    Some(
+        modules_directory
+            .join(kernel_version)
+            .join("build")
+            .join("scripts")
+            .join("sign-file"),
+    )
+}
+
+fn run(command: &mut Command, module: &Path) -> Result<(), Error> {
+    let program = command.get_program().to_string_lossy().into_owned();
+    let status = command.status().map_err(|error| {
+        ModuleSigningFailedSnafu {
+            path: module,
+            reason: format!("{} could not be run: {}", program, error),
+        }
+        .build()
+    })?;
+    if !status.success() {
+        return ModuleSigningFailedSnafu {
+            path: module,
+            reason: format!("{} failed with {}", program, status),
+        }
+        .fail();
+    }
+    Ok(())
+}
+
+/// Signs a module in place with the `sign-file` tool of its kernel. Compressed modules
+/// are decompressed for signing and compressed again afterwards.
+pub fn sign_module(
+    modules_directory: &Path,
+    module: &Path,
+    signing_key: &SigningKey,
+) -> Result<(), Error> {
+    let sign_file = sign_file_tool(modules_directory, module)
+        .filter(|sign_file| sign_file.is_file())
+        .ok_or_else(|| {
+            ModuleSigningFailedSnafu {
+                path: module,
+                reason: "the sign-file tool of its kernel was not found. Are the kernel headers installed?",
+            }
+            .build()
+        })?;
+
+    let compressed = module.to_string_lossy().ends_with(".zst");
+    let uncompressed_module = if compressed {
+        let uncompressed_module = module.with_extension("");
+        run(
+            Command::new("zstd")
+                .args(["-q", "-d", "-f", "-o"])
+                .arg(&uncompressed_module)
+                .arg(module),
+            module,
+        )?;
+        uncompressed_module
+    } else {
+        module.to_path_buf()
+    };
+
+    run(
+        Command::new(&sign_file)
+            .arg("sha256")
+            .arg(&signing_key.private_key)
+            .arg(&signing_key.certificate)
+            .arg(&uncompressed_module),
+        module,
+    )?;
+
+    if compressed {
+        run(
+            Command::new("zstd")
+                .args(["-q", "-f", "--rm", "-o"])
+                .arg(module)
+                .arg(&uncompressed_module),
+            module,
+        )?;
+    }
+    Ok(())
+}
+
+/// Signs every module written since a point in time, and returns the signed modules.
+pub fn sign_modules_changed_since(
+    modules_directory: &Path,
+    since: SystemTime,
+    signing_key: &SigningKey,
+) -> Result<Vec<PathBuf>, Error> {
+    let modules = modules_changed_since(modules_directory, since);
+    for module in modules.iter() {
+        sign_module(modules_directory, module, signing_key)?;
+    }
+    Ok(modules)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::TestDirectory;
+    use std::{os::unix::fs::PermissionsExt, time::Duration};
+
+    #[test]
+    pub fn new_modules_are_signed_with_their_kernels_tool() {
+        let modules_directory = TestDirectory::new("modules");
+        let kernel_directory = modules_directory.join("6.5.0-arch1-1");
+        let dkms_directory = kernel_directory.join("updates").join("dkms");
+        let scripts_directory = kernel_directory.join("build").join("scripts");
+        fs::create_dir_all(&dkms_directory).unwrap();
+        fs::create_dir_all(&scripts_directory).unwrap();
+
+        // Stands in for the kernel's sign-file by appending its arguments to the module
+        let sign_file = scripts_directory.join("sign-file");
+        fs::write(&sign_file, "#!/bin/sh\necho \"$1 $2 $3\" >> \"$4\"\n").unwrap();
+        fs::set_permissions(&sign_file, fs::Permissions::from_mode(0o755)).unwrap();
+
+        let old_module = kernel_directory.join("old.ko");
+        fs::write(&old_module, "").unwrap();
+        let since = SystemTime::now() + Duration::from_millis(10);
+        std::thread::sleep(Duration::from_millis(20));
+        let new_module = dkms_directory.join("nvidia.ko");
+        fs::write(&new_module, "").unwrap();
+        fs::write(dkms_directory.join("nvidia.conf"), "").unwrap();
+
+        let signing_key = SigningKey {
+            private_key: PathBuf::from("MOK.priv"),
+            certificate: PathBuf::from("MOK.der"),
+        };
+        let signed_modules =
+            sign_modules_changed_since(&modules_directory, since, &signing_key).unwrap();
+        assert_eq!(signed_modules, std::slice::from_ref(&new_module));
+        assert_eq!(
+            fs::read_to_string(&new_module).unwrap(),
+            "sha256 MOK.priv MOK.der\n"
+        );
+        assert_eq!(fs::read_to_string(&old_module).unwrap(), "");
+
+        assert!(matches!(
+            signing_key.check_exists(),
+            Err(Error::SigningKeyMissing { path }) if path == Path::new("MOK.priv")
+        ));
+
+        fs::remove_file(&sign_file).unwrap();
+        assert!(matches!(
+            sign_module(&modules_directory, &new_module, &signing_key),
+            Err(Error::ModuleSigningFailed { .. })
+        ));
+    }
+}
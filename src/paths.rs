@@ -0,0 +1,198 @@
+use std::{
+    env, fs, io,
+    os::unix::fs::{DirBuilderExt, MetadataExt},
+    path::{Path, PathBuf},
+};
+
+pub const APPLICATION_NAME: &str = "archlinux-driver-manager";
+pub const DATABASE_FILENAME: &str = "database.db";
+
+/// Overrides the default location of the driver database when set.
+pub const DATABASE_PATH_VARIABLE: &str = "ARCHLINUX_DRIVER_MANAGER_DATABASE";
+
+/// The file holding the hardware IDs found by the last live detection.
+pub const DETECTION_CACHE_FILENAME: &str = "detected_hardware.json";
+
+/// The URL of a remote driver catalog to merge with the local database, when set.
+pub const REMOTE_INDEX_VARIABLE: &str = "ARCHLINUX_DRIVER_MANAGER_REMOTE_INDEX";
+
+/// The last fetched copy of the remote driver catalog, and the validators it was served with.
+pub const REMOTE_INDEX_CACHE_FILENAME: &str = "remote_index.yaml";
+pub const REMOTE_INDEX_METADATA_FILENAME: &str = "remote_index.json";
+
+/// The socket that `serve` listens on, in the temporary directory.
+pub const SOCKET_FILENAME: &str = "serve.sock";
+
+/// The log of install transactions, one JSON object per line.
+pub const INSTALL_LOG_PATH: &str = "/var/log/archlinux-driver-manager.log";
+
+/// The driver options that the machine should have, as recorded by every install.
+pub const MANIFEST_PATH: &str = "/var/lib/archlinux-driver-manager/manifest.yaml";
+
+fn non_empty_variable(name: &str) -> Option<PathBuf> {
+    env::var_os(name)
+        .filter(|value| !value.is_empty())
+        .map(PathBuf::from)
+}
+
+/// The system-wide configuration directory, `/etc/archlinux-driver-manager`.
+pub fn config_directory() -> PathBuf {
+    PathBuf::from("/etc").join(APPLICATION_NAME)
+}
+
+/// The configuration file, `/etc/archlinux-driver-manager/config.yaml`.
+pub fn config_file() -> PathBuf {
+    config_directory().join("config.yaml")
+}
+
+/// The directory holding the `pre-install` and `post-install` hook directories.
+pub fn hooks_directory() -> PathBuf {
+    config_directory().join("hooks.d")
+}
+
+/// The directory holding the saved driver profiles, one YAML file per profile.
+pub fn profiles_directory() -> PathBuf {
+    config_directory().join("profiles")
+}
+
+fn effective_user_id() -> u32 {
+    // SAFETY: geteuid has no preconditions and cannot fail
+    unsafe { libc::geteuid() }
+}
+
+/// The system-wide driver database, `/var/lib/archlinux-driver-manager/database.db`.
+pub fn system_database_path() -> PathBuf {
+    PathBuf::from("/var/lib")
+        .join(APPLICATION_NAME)
+        .join(DATABASE_FILENAME)
+}
+
+/// The driver database used when `--database` is not given: the value of
+/// `ARCHLINUX_DRIVER_MANAGER_DATABASE` if set, otherwise the system-wide database.
+/// Users other than root fall back to `database.db` in the data directory while
+/// there is no system-wide database.
+pub fn database_path() -> PathBuf {
+    non_empty_variable(DATABASE_PATH_VARIABLE).unwrap_or_else(|| {
+        let system_database_path = system_database_path();
+        if system_database_path.exists() || effective_user_id() == 0 {
+            system_database_path
+        } else {
+            data_directory().join(DATABASE_FILENAME)
+        }
+    })
+}
+
+/// The directory for data of one user that is not a cache, like a driver database
+/// generated without superuser privileges: `$XDG_DATA_HOME/archlinux-driver-manager`,
+/// falling back to `~/.local/share` and then to the cache directory.
+pub fn data_directory() -> PathBuf {
+    non_empty_variable("XDG_DATA_HOME")
+        .or_else(|| non_empty_variable("HOME").map(|home| home.join(".local").join("share")))
+        .map(|directory| directory.join(APPLICATION_NAME))
+        .unwrap_or_else(cache_directory)
+}
+
+/// A directory of the current user in the system temporary directory (which honors
+/// `TMPDIR`), like `/tmp/archlinux-driver-manager-1000`.
+fn user_temp_directory() -> PathBuf {
+    env::temp_dir().join(format!("{}-{}", APPLICATION_NAME, effective_user_id()))
+}
+
+/// The directory for short-lived files like working copies of the database:
+/// `$XDG_RUNTIME_DIR/archlinux-driver-manager` if set, otherwise a directory of the
+/// current user in the system temporary directory. Create it with
+/// `create_private_directory`.
+pub fn temp_directory() -> PathBuf {
+    non_empty_variable("XDG_RUNTIME_DIR")
+        .map(|directory| directory.join(APPLICATION_NAME))
+        .unwrap_or_else(user_temp_directory)
+}
+
+/// The directories to try in order for short-lived files, falling back to ones the
+/// user can write to when the preferred temporary directory is not usable.
+pub fn temp_directory_candidates() -> Vec<PathBuf> {
+    let mut candidates = vec![
+        temp_directory(),
+        user_temp_directory(),
+        cache_directory().join("tmp"),
+    ];
+    candidates.dedup();
+    candidates
+}
+
+/// Creates a directory that only the current user can access, or checks that an
+/// existing one is a directory of the current user. Otherwise another user sharing
+/// the temporary directory could create it first and swap the files in it.
+pub fn create_private_directory(directory: &Path) -> io::Result<()> {
+    if let Some(parent) = directory.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    match fs::DirBuilder::new().mode(0o700).create(directory) {
+        Ok(()) => return Ok(()),
+        Err(error) if error.kind() == io::ErrorKind::AlreadyExists => {}
+        Err(error) => return Err(error),
+    }
+    let metadata = fs::symlink_metadata(directory)?;
+    if !metadata.is_dir() || metadata.uid() != effective_user_id() {
+        return Err(io::Error::new(
+            io::ErrorKind::PermissionDenied,
+            format!(
+                "{} is not a directory of the current user",
+                directory.display()
+            ),
+        ));
+    }
+    Ok(())
+}
+
+/// The directory for data that can be regenerated, like the detection cache:
+/// `$XDG_CACHE_HOME/archlinux-driver-manager`, falling back to `~/.cache` and then
+/// to the temporary directory.
+pub fn cache_directory() -> PathBuf {
+    non_empty_variable("XDG_CACHE_HOME")
+        .or_else(|| non_empty_variable("HOME").map(|home| home.join(".cache")))
+        .map(|directory| directory.join(APPLICATION_NAME))
+        .unwrap_or_else(temp_directory)
+}
+
+pub fn socket_path() -> PathBuf {
+    temp_directory().join(SOCKET_FILENAME)
+}
+
+pub fn detection_cache_path() -> PathBuf {
+    cache_directory().join(DETECTION_CACHE_FILENAME)
+}
+
+/// The remote driver catalog to use, from `ARCHLINUX_DRIVER_MANAGER_REMOTE_INDEX`.
+pub fn remote_index_url() -> Option<String> {
+    non_empty_variable(REMOTE_INDEX_VARIABLE).map(|url| url.to_string_lossy().into_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::TestDirectory;
+    use std::os::unix::fs::{symlink, PermissionsExt};
+
+    #[test]
+    pub fn private_directories_are_only_used_when_owned() {
+        let directory = TestDirectory::new("private");
+
+        let private_directory = directory.join("private");
+        create_private_directory(&private_directory).unwrap();
+        assert_eq!(
+            fs::metadata(&private_directory)
+                .unwrap()
+                .permissions()
+                .mode()
+                & 0o777,
+            0o700
+        );
+        // Already there, and owned by the current user
+        create_private_directory(&private_directory).unwrap();
+
+        let planted = directory.join("planted");
+        symlink(&private_directory, &planted).unwrap();
+        assert!(create_private_directory(&planted).is_err());
+    }
+}
@@ -0,0 +1,206 @@
+use crate::{
+    error::{Error, PrivilegeEscalationFailedSnafu},
+    paths::{self, APPLICATION_NAME, DATABASE_PATH_VARIABLE, REMOTE_INDEX_VARIABLE},
+};
+use std::{
+    env,
+    ffi::OsString,
+    path::{Path, PathBuf},
+    process::Command,
+};
+use sudo::RunningAs;
+
+pub const SUDO_PATH: &str = "/usr/bin/sudo";
+
+/// The variables that choose the editor, kept as they are when running again through sudo.
+const EDITOR_VARIABLES: [&str; 2] = ["VISUAL", "EDITOR"];
+
+/// The parent of a directory of this program, like `~/.cache` for
+/// `~/.cache/archlinux-driver-manager`, to pass on as the `XDG_*` variable it came from.
+fn application_directory_parent(directory: PathBuf) -> Option<OsString> {
+    if directory.file_name()? != APPLICATION_NAME {
+        return None;
+    }
+    directory
+        .parent()
+        .map(|parent| parent.as_os_str().to_owned())
+}
+
+/// The environment that decides which database, catalog, cache and editor are used.
+/// sudo resets the environment, and root would resolve the default paths to its own
+/// directories, so the paths are resolved for the calling user and passed on as they are.
+fn preserved_environment() -> Vec<(&'static str, OsString)> {
+    let mut environment = vec![(
+        DATABASE_PATH_VARIABLE,
+        paths::database_path().into_os_string(),
+    )];
+    environment.extend(
+        application_directory_parent(paths::data_directory())
+            .map(|directory| ("XDG_DATA_HOME", directory)),
+    );
+    environment.extend(
+        application_directory_parent(paths::cache_directory())
+            .map(|directory| ("XDG_CACHE_HOME", directory)),
+    );
+    for variable in std::iter::once(REMOTE_INDEX_VARIABLE).chain(EDITOR_VARIABLES) {
+        if let Some(value) = env::var_os(variable).filter(|value| !value.is_empty()) {
+            environment.push((variable, value));
+        }
+    }
+    environment
+}
+
+/// The command that runs the program again through sudo with the same arguments,
+/// keeping the variables of `environment`.
+fn rerun_command(
+    sudo_path: &Path,
+    arguments: Vec<OsString>,
+    environment: Vec<(&'static str, OsString)>,
+) -> Command {
+    let mut command = Command::new(sudo_path);
+    command
+        .arg(format!(
+            "--preserve-env={}",
+            environment
+                .iter()
+                .map(|(variable, _)| *variable)
+                .collect::<Vec<&str>>()
+                .join(",")
+        ))
+        .arg("--")
+        .args(arguments)
+        .envs(environment);
+    command
+}
+
+/// Runs the program again through sudo unless it already has superuser privileges.
+/// Returns only when running with superuser privileges; otherwise the program exits
+/// with the status of the run through sudo.
+pub fn escalate_if_needed() -> Result<(), Error> {
+    escalate_with(sudo::check(), Path::new(SUDO_PATH))
+}
+
+fn escalate_with(running_as: RunningAs, sudo_path: &Path) -> Result<(), Error> {
+    match running_as {
+        RunningAs::Root => return Ok(()),
+        RunningAs::Suid => {
+            return sudo::escalate_if_needed().map(|_| ()).map_err(|error| {
+                PrivilegeEscalationFailedSnafu {
+                    reason: error.to_string(),
+                }
+                .build()
+            })
+        }
+        RunningAs::User => {}
+    }
+
+    // Ask for the password on its own first, so that a cancelled prompt is told apart
+    // from the program failing when run again
+    let validated = Command::new(sudo_path)
+        .arg("--validate")
+        .status()
+        .map_err(|error| {
+            PrivilegeEscalationFailedSnafu {
+                reason: format!("{} could not be run: {}", sudo_path.display(), error),
+            }
+            .build()
+        })?;
+    if !validated.success() {
+        return PrivilegeEscalationFailedSnafu {
+            reason: "the authentication was cancelled or failed",
+        }
+        .fail();
+    }
+
+    // Arguments like paths need not be valid UTF-8, and are passed on as they are
+    let mut arguments: Vec<OsString> = env::args_os().collect();
+    if let Ok(executable) = env::current_exe() {
+        arguments[0] = executable.into_os_string();
+    }
+    let status = rerun_command(sudo_path, arguments, preserved_environment())
+        .status()
+        .map_err(|error| {
+            PrivilegeEscalationFailedSnafu {
+                reason: format!("{} could not be run: {}", sudo_path.display(), error),
+            }
+            .build()
+        })?;
+    std::process::exit(status.code().unwrap_or(1));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::TestDirectory;
+    use std::{
+        fs,
+        os::unix::{ffi::OsStringExt, fs::PermissionsExt},
+    };
+
+    #[test]
+    pub fn failed_escalations_are_errors() {
+        let directory = TestDirectory::new("privileges");
+
+        assert!(escalate_with(RunningAs::Root, &directory.join("sudo")).is_ok());
+        assert!(matches!(
+            escalate_with(RunningAs::User, &directory.join("sudo")),
+            Err(Error::PrivilegeEscalationFailed { .. })
+        ));
+
+        // Like sudo when the password prompt is cancelled
+        let cancelling_sudo = directory.join("cancelling-sudo");
+        fs::write(&cancelling_sudo, "#!/bin/sh\nexit 1\n").unwrap();
+        fs::set_permissions(&cancelling_sudo, fs::Permissions::from_mode(0o755)).unwrap();
+        match escalate_with(RunningAs::User, &cancelling_sudo) {
+            Err(Error::PrivilegeEscalationFailed { reason }) => {
+                assert!(reason.contains("cancelled"), "{}", reason)
+            }
+            result => panic!("expected the escalation to fail, got {:?}", result),
+        }
+    }
+
+    #[test]
+    pub fn reruns_keep_the_paths_of_the_caller() {
+        let command = rerun_command(
+            Path::new(SUDO_PATH),
+            vec![
+                OsString::from("/usr/bin/archlinux-driver-manager"),
+                OsString::from("install"),
+                OsString::from("graphics"),
+                OsString::from("--database"),
+                OsString::from_vec(b"/home/user/\xff.db".to_vec()),
+            ],
+            vec![
+                (
+                    DATABASE_PATH_VARIABLE,
+                    OsString::from("/home/user/database.db"),
+                ),
+                ("XDG_CACHE_HOME", OsString::from("/home/user/.cache")),
+            ],
+        );
+        assert_eq!(
+            command.get_args().collect::<Vec<_>>(),
+            [
+                OsString::from("--preserve-env=ARCHLINUX_DRIVER_MANAGER_DATABASE,XDG_CACHE_HOME"),
+                OsString::from("--"),
+                OsString::from("/usr/bin/archlinux-driver-manager"),
+                OsString::from("install"),
+                OsString::from("graphics"),
+                OsString::from("--database"),
+                OsString::from_vec(b"/home/user/\xff.db".to_vec()),
+            ]
+        );
+        assert!(command.get_envs().any(|(variable, value)| {
+            variable == "XDG_CACHE_HOME" && value == Some("/home/user/.cache".as_ref())
+        }));
+
+        assert_eq!(
+            application_directory_parent(PathBuf::from("/home/user/.cache").join(APPLICATION_NAME)),
+            Some(OsString::from("/home/user/.cache"))
+        );
+        assert_eq!(
+            application_directory_parent(PathBuf::from("/tmp/archlinux-driver-manager-1000")),
+            None
+        );
+    }
+}
@@ -0,0 +1,120 @@
+use serde::{Deserialize, Serialize};
+use std::{fmt, path::Path, process::Command};
+
+/// The program that services are enabled and disabled with.
+pub const SYSTEMCTL: &str = "systemctl";
+
+/// Whether the system was booted with systemd, checked the same way as `sd_booted(3)`.
+pub fn systemd_is_running() -> bool {
+    Path::new("/run/systemd/system").is_dir()
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ServiceAction {
+    Enable,
+    Disable,
+}
+
+impl fmt::Display for ServiceAction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            ServiceAction::Enable => "enable",
+            ServiceAction::Disable => "disable",
+        })
+    }
+}
+
+/// A service of a driver option that could not be enabled or disabled.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ServiceFailure {
+    pub service: String,
+    pub action: ServiceAction,
+    /// The exit status and last line of output, or why `systemctl` could not be run.
+    pub status: String,
+}
+
+/// Runs `systemctl enable --now` or `systemctl disable --now` for each service. Services
+/// are changed one at a time, so that a failing one does not keep the others from being
+/// changed, and failures are returned instead of failing the install, since the packages
+/// are installed by then.
+pub fn change_services(
+    systemctl: &str,
+    action: ServiceAction,
+    services: &[String],
+) -> Vec<ServiceFailure> {
+    services
+        .iter()
+        .filter_map(|service| {
+            let status = match Command::new(systemctl)
+                .arg(action.to_string())
+                .arg("--now")
+                .arg(service)
+                .output()
+            {
+                Ok(output) if output.status.success() => return None,
+                Ok(output) => {
+                    let stderr = String::from_utf8_lossy(&output.stderr);
+                    match stderr.lines().rfind(|line| !line.trim().is_empty()) {
+                        Some(line) => format!("{}: {}", output.status, line.trim()),
+                        None => output.status.to_string(),
+                    }
+                }
+                Err(error) => format!("could not be run: {}", error),
+            };
+            Some(ServiceFailure {
+                service: service.clone(),
+                action,
+                status,
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::TestDirectory;
+    use std::{fs, os::unix::fs::PermissionsExt};
+
+    #[test]
+    pub fn failing_services_are_reported_one_by_one() {
+        let directory = TestDirectory::new("services");
+        let systemctl = directory.join("systemctl");
+        let calls = directory.join("calls");
+        fs::write(
+            &systemctl,
+            format!(
+                "#!/bin/sh\necho \"$@\" >> {}\nif [ \"$3\" = broken.service ]; then\n    echo 'Unit broken.service does not exist.' >&2\n    exit 1\nfi\n",
+                calls.display()
+            ),
+        )
+        .unwrap();
+        fs::set_permissions(&systemctl, fs::Permissions::from_mode(0o755)).unwrap();
+
+        let failures = change_services(
+            systemctl.to_str().unwrap(),
+            ServiceAction::Enable,
+            &[
+                "broken.service".to_string(),
+                "nvidia-persistenced.service".to_string(),
+            ],
+        );
+        assert_eq!(
+            fs::read_to_string(&calls).unwrap(),
+            "enable --now broken.service\nenable --now nvidia-persistenced.service\n"
+        );
+        assert_eq!(failures.len(), 1);
+        assert_eq!(failures[0].service, "broken.service");
+        assert!(failures[0]
+            .status
+            .ends_with(": Unit broken.service does not exist."));
+
+        let failures = change_services(
+            directory.join("missing").to_str().unwrap(),
+            ServiceAction::Disable,
+            &["nvidia-persistenced.service".to_string()],
+        );
+        assert!(failures[0].status.starts_with("could not be run"));
+    }
+}
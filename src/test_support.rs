@@ -0,0 +1,42 @@
+use std::{
+    fs,
+    ops::Deref,
+    path::{Path, PathBuf},
+};
+
+/// An empty directory of its own for a test, under the temporary directory, which is
+/// removed again with everything in it once dropped.
+pub struct TestDirectory {
+    path: PathBuf,
+}
+
+impl TestDirectory {
+    /// Creates the directory for the test with this name. What an earlier run that did
+    /// not finish may have left there is removed first.
+    pub fn new(name: &str) -> Self {
+        let path = std::env::temp_dir().join(format!("adm-test-{}-{}", std::process::id(), name));
+        _ = fs::remove_dir_all(&path);
+        fs::create_dir_all(&path).unwrap();
+        TestDirectory { path }
+    }
+}
+
+impl Deref for TestDirectory {
+    type Target = Path;
+
+    fn deref(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl AsRef<Path> for TestDirectory {
+    fn as_ref(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for TestDirectory {
+    fn drop(&mut self) {
+        _ = fs::remove_dir_all(&self.path);
+    }
+}